@@ -1,9 +1,47 @@
+pub(crate) mod app_scheme;
+pub(crate) mod bitmap;
+pub(crate) mod bookmarks;
+pub(crate) mod box_painter;
+pub(crate) mod browser;
+pub(crate) mod browser_window;
+pub(crate) mod chrome;
 pub(crate) mod color;
+pub(crate) mod config_page;
+pub(crate) mod cookie_page;
+pub(crate) mod cookies;
 pub(crate) mod dom;
+pub(crate) mod error_page;
+pub(crate) mod fetcher;
 pub(crate) mod geometry;
 pub(crate) mod gfxbuffer;
+pub(crate) mod gradient;
+pub(crate) mod history_db;
+pub(crate) mod http;
+pub(crate) mod http_cache;
+pub(crate) mod image_animation;
+pub(crate) mod image_cache;
+pub(crate) mod loader;
+pub(crate) mod media;
+pub(crate) mod path;
 pub mod platform_window;
+pub(crate) mod profile;
+pub(crate) mod proxy;
+pub(crate) mod rasterizer;
+pub(crate) mod region;
+pub(crate) mod session_history;
+pub(crate) mod settings;
+pub(crate) mod sidebar;
 pub(crate) mod simple_error;
+pub(crate) mod source_view;
+pub(crate) mod srcset;
+pub(crate) mod tab_strip;
+pub(crate) mod style;
+pub(crate) mod text;
+pub(crate) mod text_field;
+pub(crate) mod tls;
+pub(crate) mod web_view;
+pub(crate) mod window_manager;
+pub(crate) mod zoom;
 
 
 #[macro_export]
@@ -0,0 +1,214 @@
+#![allow(dead_code)]
+#![allow(unused_imports)]
+
+use std::fmt;
+use std::io::{Cursor, Read};
+use std::sync::Arc;
+
+use log::{debug, error, info, warn};
+use url::Url;
+
+use crate::fixme;
+
+use super::http::{Headers, Request, Response};
+use super::http_cache::CacheMode;
+use super::loader::{Loader, SchemeHandler};
+use super::simple_error::SimpleError;
+
+/// Schemes the browser loads itself, which applications can't take over.
+const BUILTIN_SCHEMES: [&str; 7] = ["about", "blob", "data", "file", "http", "https", "view-source"];
+
+/// A request for a URL of a scheme served by the application.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SchemeRequest {
+    pub method: String,
+    pub url: Url,
+    /// Header fields in order
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl SchemeRequest {
+    /// The first field named `name`, compared without case.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// What the application answers to a `SchemeRequest`: the body is read to its end.
+pub struct SchemeResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Box<dyn Read + Send>,
+}
+
+impl SchemeResponse {
+    pub fn new(status: u16, body: impl Read + Send + 'static) -> Self {
+        Self {
+            status,
+            headers: vec![],
+            body: Box::new(body),
+        }
+    }
+
+    /// A `200 OK` response of `bytes` with the `Content-Type` `content_type`.
+    pub fn ok(content_type: &str, bytes: impl Into<Vec<u8>>) -> Self {
+        Self::new(200, Cursor::new(bytes.into())).with_header("Content-Type", content_type)
+    }
+
+    /// An empty `404 Not Found` response.
+    pub fn not_found() -> Self {
+        Self::new(404, std::io::empty())
+    }
+
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((String::from(name), String::from(value)));
+        self
+    }
+}
+
+impl fmt::Debug for SchemeResponse {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SchemeResponse")
+            .field("status", &self.status)
+            .field("headers", &self.headers)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Serves the URLs of a scheme with a callback of the application, called on the thread loading them.
+pub type SchemeCallback = dyn Fn(&SchemeRequest) -> SchemeResponse + Send + Sync;
+
+struct CallbackHandler {
+    callback: Box<SchemeCallback>,
+}
+
+impl fmt::Debug for CallbackHandler {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CallbackHandler").finish_non_exhaustive()
+    }
+}
+
+impl SchemeHandler for CallbackHandler {
+    fn load(&self, _: &Loader, request: Request, _: CacheMode) -> Result<Response, SimpleError> {
+        let scheme_request = SchemeRequest {
+            method: request.method,
+            url: request.url,
+            headers: request
+                .headers
+                .iter()
+                .map(|(name, value)| (String::from(name), String::from(value)))
+                .collect(),
+            body: request.body,
+        };
+        let mut scheme_response = (self.callback)(&scheme_request);
+        fixme!("Stream bodies to documents as they arrive");
+        let mut body = vec![];
+        scheme_response
+            .body
+            .read_to_end(&mut body)
+            .map_err(|err| SimpleError::new(format!("Error while reading {}: {}", scheme_request.url, err).as_str()))?;
+        debug!(
+            "{} {} {} ({} bytes)",
+            scheme_request.method,
+            scheme_request.url,
+            scheme_response.status,
+            body.len()
+        );
+        let mut response = Response::new(scheme_request.url, scheme_response.status, "", body);
+        response.headers = Headers::new();
+        for (name, value) in scheme_response.headers.iter() {
+            response.headers.add(name, value);
+        }
+        response.headers.set("Content-Length", &response.body.len().to_string());
+        Ok(response)
+    }
+}
+
+/// Load the URLs of `scheme` with `callback`, instead of the callback it had if any.
+pub fn register<F>(loader: &mut Loader, scheme: &str, callback: F) -> Result<(), SimpleError>
+where
+    F: Fn(&SchemeRequest) -> SchemeResponse + Send + Sync + 'static,
+{
+    let scheme = scheme.to_ascii_lowercase();
+    let valid = scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+        && scheme
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'));
+    if !valid {
+        return Err(SimpleError::new(format!("Invalid scheme '{}'", scheme).as_str()));
+    }
+    if BUILTIN_SCHEMES.contains(&scheme.as_str()) {
+        return Err(SimpleError::new(
+            format!("The scheme '{}' is loaded by the browser", scheme).as_str(),
+        ));
+    }
+    info!("Register scheme {}", scheme);
+    loader.register(
+        &scheme,
+        Arc::new(CallbackHandler {
+            callback: Box::new(callback),
+        }),
+    );
+    Ok(())
+}
+
+/// Stop loading the URLs of `scheme` with a callback. Returns false if it had none.
+pub fn unregister(loader: &mut Loader, scheme: &str) -> bool {
+    !BUILTIN_SCHEMES.contains(&scheme.to_ascii_lowercase().as_str()) && loader.unregister(scheme)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use assert2::check;
+
+    use super::super::http::HttpClient;
+    use super::*;
+
+    #[test]
+    fn application_schemes() {
+        let mut loader = Loader::new(Arc::new(Mutex::new(HttpClient::new())));
+        let received = Arc::new(Mutex::new(vec![]));
+        let log = received.clone();
+        check!(register(&mut loader, "App", move |request| {
+            log.lock().unwrap().push(request.clone());
+            match request.url.path() {
+                "/index.html" => SchemeResponse::ok("text/html", "<p>app</p>").with_header("X-App", "1"),
+                _ => SchemeResponse::not_found(),
+            }
+        })
+        .is_ok());
+
+        let request = Request::get(Url::parse("app://main/index.html").unwrap()).with_header("Accept", "text/html");
+        let response = loader.load(request, CacheMode::Default).unwrap();
+        check!(response.status == 200);
+        check!(response.reason == "OK");
+        check!(response.mime_type().as_deref() == Some("text/html"));
+        check!(response.headers.get("x-app") == Some("1"));
+        check!(response.headers.get("Content-Length") == Some("10"));
+        check!(response.body == b"<p>app</p>");
+        check!(received.lock().unwrap()[0].method == "GET");
+        check!(received.lock().unwrap()[0].header("accept") == Some("text/html"));
+
+        let response = loader.get(&Url::parse("app://main/missing").unwrap()).unwrap();
+        check!(response.status == 404);
+        check!(response.headers.get("Content-Type") == None);
+        // Built-in handlers see the application's resources too
+        let response = loader
+            .get(&Url::parse("view-source:app://main/index.html").unwrap())
+            .unwrap();
+        check!(response.body == b"<p>app</p>");
+
+        check!(register(&mut loader, "https", |_| SchemeResponse::not_found()).is_err());
+        check!(register(&mut loader, "1app", |_| SchemeResponse::not_found()).is_err());
+        check!(!unregister(&mut loader, "data"));
+        check!(loader.handles("data"));
+        check!(unregister(&mut loader, "app"));
+        check!(loader.get(&Url::parse("app://main/index.html").unwrap()).is_err());
+    }
+}
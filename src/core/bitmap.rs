@@ -0,0 +1,486 @@
+#![allow(dead_code)]
+#![allow(unused_imports)]
+
+use std::io::Cursor;
+
+use image::{AnimationDecoder, ImageFormat};
+use log::{debug, error, info, warn};
+
+use super::geometry::Size;
+use super::simple_error::SimpleError;
+
+/// How a bitmap is sampled when painted at a size different from its own.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScaleFilter {
+    Nearest,
+    Bilinear,
+}
+
+/// Straight (non premultiplied) RGBA pixels, row by row.
+#[derive(Debug, Clone)]
+pub struct Bitmap {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ImageFrame {
+    pub bitmap: Bitmap,
+    pub delay_ms: u32,
+}
+
+/// How many times an animation is played before stopping on its last frame.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LoopCount {
+    Infinite,
+    Finite(u32),
+}
+
+/// A decoded image. Still images have exactly one frame.
+///   Animation frames are already composited on the full canvas by the decoder,
+///   so GIF disposal methods and APNG dispose/blend operations are resolved here.
+#[derive(Debug)]
+pub struct DecodedImage {
+    pub format: ImageFormat,
+    pub width: u32,
+    pub height: u32,
+    pub frames: Vec<ImageFrame>,
+    pub loop_count: LoopCount,
+}
+
+impl Bitmap {
+    /// Fails if `pixels` are not `width` x `height` RGBA pixels, or if so many don't fit in memory.
+    pub fn new(width: u32, height: u32, pixels: Vec<u8>) -> Result<Self, SimpleError> {
+        let len = Self::byte_len(width, height)
+            .ok_or_else(|| SimpleError::new(format!("Bitmap {}x{} too large", width, height).as_str()))?;
+        if pixels.len() != len {
+            let found = pixels.len();
+            return Err(SimpleError::new(
+                format!("Bitmap {}x{} needs {} bytes, found {}", width, height, len, found).as_str(),
+            ));
+        }
+        Ok(Self {
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    /// A bitmap of `width` x `height` transparent pixels.
+    pub fn transparent(width: u32, height: u32) -> Result<Self, SimpleError> {
+        // Too large for memory, `new` tells why
+        let pixels = Self::byte_len(width, height).map_or_else(Vec::new, |len| vec![0; len]);
+        Self::new(width, height, pixels)
+    }
+
+    /// Bytes of `width` x `height` RGBA pixels, None if they overflow.
+    fn byte_len(width: u32, height: u32) -> Option<usize> {
+        (width as usize)
+            .checked_mul(height as usize)
+            .and_then(|pixels| pixels.checked_mul(4))
+    }
+
+    pub fn pixel(&self, x: u32, y: u32) -> [u8; 4] {
+        let i = (y as usize * self.width as usize + x as usize) * 4;
+        [
+            self.pixels[i],
+            self.pixels[i + 1],
+            self.pixels[i + 2],
+            self.pixels[i + 3],
+        ]
+    }
+
+    /// Sample at (u, v) expressed in source pixels, where pixel centers are at `i + 0.5`.
+    pub fn sample(&self, u: f64, v: f64, filter: ScaleFilter) -> [u8; 4] {
+        let max_x = self.width as f64 - 1.0;
+        let max_y = self.height as f64 - 1.0;
+        match filter {
+            ScaleFilter::Nearest => self.pixel(
+                u.floor().max(0.0).min(max_x) as u32,
+                v.floor().max(0.0).min(max_y) as u32,
+            ),
+            ScaleFilter::Bilinear => {
+                let fx = (u - 0.5).max(0.0).min(max_x);
+                let fy = (v - 0.5).max(0.0).min(max_y);
+                let x0 = fx.floor();
+                let y0 = fy.floor();
+                let tx = fx - x0;
+                let ty = fy - y0;
+                let x1 = (x0 + 1.0).min(max_x);
+                let y1 = (y0 + 1.0).min(max_y);
+
+                // Interpolate premultiplied values, so transparent texels don't bleed their color.
+                let mut acc = [0.0f64; 4];
+                for (x, y, w) in [
+                    (x0, y0, (1.0 - tx) * (1.0 - ty)),
+                    (x1, y0, tx * (1.0 - ty)),
+                    (x0, y1, (1.0 - tx) * ty),
+                    (x1, y1, tx * ty),
+                ] {
+                    let p = self.pixel(x as u32, y as u32);
+                    let a = p[3] as f64 * w;
+                    acc[0] += p[0] as f64 * a;
+                    acc[1] += p[1] as f64 * a;
+                    acc[2] += p[2] as f64 * a;
+                    acc[3] += a;
+                }
+                if acc[3] <= 0.0 {
+                    return [0, 0, 0, 0];
+                }
+                [
+                    (acc[0] / acc[3]).round() as u8,
+                    (acc[1] / acc[3]).round() as u8,
+                    (acc[2] / acc[3]).round() as u8,
+                    acc[3].round().min(255.0) as u8,
+                ]
+            }
+        }
+    }
+}
+
+impl DecodedImage {
+    pub fn intrinsic_size(&self) -> Size {
+        Size::new(self.width as f64, self.height as f64)
+    }
+    pub fn is_animated(&self) -> bool {
+        self.frames.len() > 1
+    }
+    pub fn first_frame(&self) -> &Bitmap {
+        &self.frames[0].bitmap
+    }
+}
+
+fn to_simple_error(err: image::ImageError) -> SimpleError {
+    SimpleError::new(format!("{}", err).as_str())
+}
+
+fn collect_frames<'a>(decoder: impl AnimationDecoder<'a>) -> Result<Vec<ImageFrame>, SimpleError> {
+    decoder
+        .into_frames()
+        .collect_frames()
+        .map_err(to_simple_error)?
+        .into_iter()
+        .map(|frame| {
+            let (numer, denom) = frame.delay().numer_denom_ms();
+            let buffer = frame.into_buffer();
+            Ok(ImageFrame {
+                delay_ms: numer.checked_div(denom).unwrap_or(0),
+                bitmap: Bitmap::new(buffer.width(), buffer.height(), buffer.into_raw())?,
+            })
+        })
+        .collect()
+}
+
+/// Skip the data sub-blocks starting at `i`, each prefixed by its size, up to the empty one ending them.
+///   Returns the offset after it, None if the data ends first.
+fn skip_gif_sub_blocks(data: &[u8], mut i: usize) -> Option<usize> {
+    loop {
+        let size = *data.get(i)? as usize;
+        i += 1 + size;
+        if size == 0 {
+            return Some(i);
+        }
+    }
+}
+
+/// Size of the color table announced by the `packed` fields of a GIF descriptor.
+fn gif_color_table_size(packed: u8) -> usize {
+    if packed & 0x80 == 0 {
+        0
+    } else {
+        3 << ((packed & 0x07) + 1)
+    }
+}
+
+/// Read the NETSCAPE2.0 application extension, walking the blocks of the GIF.
+///   Its value counts the repetitions after the first play.
+fn gif_loop_count(data: &[u8]) -> LoopCount {
+    gif_repeat(data).map_or(LoopCount::Finite(1), |repeat| match repeat {
+        0 => LoopCount::Infinite,
+        repeat => LoopCount::Finite(repeat as u32 + 1),
+    })
+}
+
+fn gif_repeat(data: &[u8]) -> Option<u16> {
+    // Header and logical screen descriptor
+    let mut i = 13 + gif_color_table_size(*data.get(10)?);
+    loop {
+        match *data.get(i)? {
+            // Extension: label, then sub-blocks
+            0x21 => {
+                let label = *data.get(i + 1)?;
+                let application = data.get(i + 2..i + 14);
+                if label == 0xff && application.is_some_and(|block| block == b"\x0bNETSCAPE2.0") {
+                    if let Some([3, 1, low, high]) = data.get(i + 14..i + 18) {
+                        return Some(u16::from_le_bytes([*low, *high]));
+                    }
+                }
+                i = skip_gif_sub_blocks(data, i + 2)?;
+            }
+            // Image descriptor, local color table, LZW code size, then the image sub-blocks
+            0x2c => {
+                let packed = *data.get(i + 9)?;
+                i = skip_gif_sub_blocks(data, i + 10 + gif_color_table_size(packed) + 1)?;
+            }
+            // Trailer, or not a GIF block
+            _ => return None,
+        }
+    }
+}
+
+/// Read `num_plays` from the APNG acTL chunk, walking the chunks of the PNG up to the image data.
+fn apng_loop_count(data: &[u8]) -> LoopCount {
+    match apng_plays(data) {
+        Some(0) => LoopCount::Infinite,
+        Some(plays) => LoopCount::Finite(plays),
+        None => LoopCount::Finite(1),
+    }
+}
+
+fn apng_plays(data: &[u8]) -> Option<u32> {
+    const SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+    if !data.starts_with(SIGNATURE) {
+        return None;
+    }
+    let mut i = SIGNATURE.len();
+    loop {
+        let length = u32::from_be_bytes(data.get(i..i + 4)?.try_into().ok()?) as usize;
+        // Type and data are covered by the CRC
+        let checked = data.get(i + 4..(i + 8).checked_add(length)?)?;
+        let end = i + 8 + length;
+        let crc = u32::from_be_bytes(data.get(end..end + 4)?.try_into().ok()?);
+        let mut computed = flate2::Crc::new();
+        computed.update(checked);
+        if computed.sum() != crc {
+            warn!("Bad CRC of PNG chunk at {}", i);
+            return None;
+        }
+        match (&checked[..4], &checked[4..]) {
+            (b"acTL", [_, _, _, _, plays @ ..]) => return Some(u32::from_be_bytes(plays.try_into().ok()?)),
+            // The animation control comes before the image data
+            (b"IDAT", _) | (b"IEND", _) => return None,
+            _ => i = end + 4,
+        }
+    }
+}
+
+fn single_frame(data: &[u8], format: ImageFormat) -> Result<Vec<ImageFrame>, SimpleError> {
+    let buffer = image::load_from_memory_with_format(data, format)
+        .map_err(to_simple_error)?
+        .to_rgba8();
+    Ok(vec![ImageFrame {
+        delay_ms: 0,
+        bitmap: Bitmap::new(buffer.width(), buffer.height(), buffer.into_raw())?,
+    }])
+}
+
+/// Decode PNG, JPEG, GIF or BMP data into RGBA frames. The format is sniffed from the data itself.
+pub fn decode_image(data: &[u8]) -> Result<DecodedImage, SimpleError> {
+    let format = image::guess_format(data).map_err(to_simple_error)?;
+
+    let (frames, loop_count) = match format {
+        ImageFormat::Gif => {
+            let decoder =
+                image::codecs::gif::GifDecoder::new(Cursor::new(data)).map_err(to_simple_error)?;
+            (collect_frames(decoder)?, gif_loop_count(data))
+        }
+        ImageFormat::Png => {
+            let decoder =
+                image::codecs::png::PngDecoder::new(Cursor::new(data)).map_err(to_simple_error)?;
+            if decoder.is_apng().map_err(to_simple_error)? {
+                let decoder = decoder.apng().map_err(to_simple_error)?;
+                (collect_frames(decoder)?, apng_loop_count(data))
+            } else {
+                (single_frame(data, format)?, LoopCount::Finite(1))
+            }
+        }
+        ImageFormat::Jpeg | ImageFormat::Bmp => (single_frame(data, format)?, LoopCount::Finite(1)),
+        _ => {
+            return Err(SimpleError::new(
+                format!("Unsupported image format {:?}", format).as_str(),
+            ))
+        }
+    };
+
+    if frames.is_empty() {
+        return Err(SimpleError::new("Image has no frames"));
+    }
+
+    let first = &frames[0].bitmap;
+    debug!(
+        "Decoded {:?} image {}x{} with {} frame(s)",
+        format,
+        first.width,
+        first.height,
+        frames.len()
+    );
+
+    Ok(DecodedImage {
+        format,
+        width: first.width,
+        height: first.height,
+        frames,
+        loop_count,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use assert2::check;
+    use image::{Delay, DynamicImage, Frame, RgbaImage};
+
+    use super::*;
+
+    fn sample_pixels() -> Vec<u8> {
+        vec![
+            255, 0, 0, 255, /**/ 0, 255, 0, 255, //
+            0, 0, 255, 255, /**/ 255, 255, 255, 255,
+        ]
+    }
+
+    fn encode(format: ImageFormat) -> Vec<u8> {
+        let img = RgbaImage::from_raw(2, 2, sample_pixels()).unwrap();
+        let mut data = Vec::new();
+        DynamicImage::ImageRgba8(img)
+            .write_to(&mut Cursor::new(&mut data), format)
+            .unwrap();
+        data
+    }
+
+    #[test]
+    fn decode_lossless_formats() {
+        for format in [ImageFormat::Png, ImageFormat::Bmp] {
+            let decoded = decode_image(&encode(format)).unwrap();
+            check!(decoded.format == format);
+            check!(decoded.intrinsic_size() == (2, 2));
+            check!(!decoded.is_animated());
+            check!(decoded.first_frame().pixels == sample_pixels());
+        }
+    }
+
+    #[test]
+    fn decode_jpeg() {
+        let img = DynamicImage::ImageRgba8(RgbaImage::from_raw(2, 2, sample_pixels()).unwrap());
+        let mut data = Vec::new();
+        // JPEG has no alpha channel.
+        DynamicImage::ImageRgb8(img.to_rgb8())
+            .write_to(&mut Cursor::new(&mut data), ImageFormat::Jpeg)
+            .unwrap();
+        let decoded = decode_image(&data).unwrap();
+        check!(decoded.format == ImageFormat::Jpeg);
+        check!(decoded.intrinsic_size() == (2, 2));
+        check!(decoded.first_frame().pixel(0, 0)[3] == 255);
+    }
+
+    #[test]
+    fn decode_gif_frames() {
+        let mut data = Vec::new();
+        {
+            let mut encoder = image::codecs::gif::GifEncoder::new(&mut data);
+            let frames = (0..3).map(|i| {
+                let img = RgbaImage::from_pixel(2, 2, image::Rgba([i * 100, 0, 0, 255]));
+                Frame::from_parts(img, 0, 0, Delay::from_numer_denom_ms(100, 1))
+            });
+            encoder.encode_frames(frames).unwrap();
+        }
+        let decoded = decode_image(&data).unwrap();
+        check!(decoded.format == ImageFormat::Gif);
+        check!(decoded.frames.len() == 3);
+        check!(decoded.frames[1].delay_ms == 100);
+        check!(decoded.loop_count == LoopCount::Finite(1));
+    }
+
+    #[test]
+    fn gif_loop_counts() {
+        use image::codecs::gif::{GifEncoder, Repeat};
+        for (repeat, expected) in [
+            (Repeat::Infinite, LoopCount::Infinite),
+            (Repeat::Finite(2), LoopCount::Finite(3)),
+        ] {
+            let mut data = Vec::new();
+            {
+                let mut encoder = GifEncoder::new(&mut data);
+                encoder.set_repeat(repeat).unwrap();
+                let frames = (0..2).map(|_| {
+                    Frame::from_parts(RgbaImage::new(1, 1), 0, 0, Delay::from_numer_denom_ms(50, 1))
+                });
+                encoder.encode_frames(frames).unwrap();
+            }
+            check!(decode_image(&data).unwrap().loop_count == expected);
+        }
+    }
+
+    #[test]
+    fn gif_loop_count_from_blocks() {
+        let netscape = b"\x21\xff\x0bNETSCAPE2.0\x03\x01\x05\x00\x00";
+        // Screen of 1x1 with a global color table of 2 colors
+        let mut data = b"GIF89a\x01\x00\x01\x00\x80\x00\x00".to_vec();
+        data.extend_from_slice(&[0; 6]);
+        // The bytes of the extension in a comment don't count
+        data.extend_from_slice(b"\x21\xfe\x10");
+        data.extend_from_slice(&netscape[2..18]);
+        data.push(0);
+        // An image with a local color table of 4 colors and one sub-block
+        data.extend_from_slice(b"\x2c\x00\x00\x00\x00\x01\x00\x01\x00\x81");
+        data.extend_from_slice(&[0; 12]);
+        data.extend_from_slice(b"\x02\x02\x44\x01\x00");
+        check!(gif_loop_count(&[&data[..], b"\x3b"].concat()) == LoopCount::Finite(1));
+        data.extend_from_slice(netscape);
+        data.push(0x3b);
+        check!(gif_loop_count(&data) == LoopCount::Finite(6));
+        check!(gif_loop_count(&data[..data.len() - 4]) == LoopCount::Finite(1));
+        check!(gif_loop_count(b"GIF") == LoopCount::Finite(1));
+    }
+
+    fn png_chunk(kind: &[u8], data: &[u8]) -> Vec<u8> {
+        let mut crc = flate2::Crc::new();
+        crc.update(kind);
+        crc.update(data);
+        let length = (data.len() as u32).to_be_bytes();
+        [&length[..], kind, data, &crc.sum().to_be_bytes()].concat()
+    }
+
+    #[test]
+    fn apng_loop_count_from_actl() {
+        let actl = |plays: u32| png_chunk(b"acTL", &[4u32.to_be_bytes(), plays.to_be_bytes()].concat());
+        let png = |chunks: &[Vec<u8>]| [&b"\x89PNG\r\n\x1a\n"[..], &chunks.concat()].concat();
+        let header = png_chunk(b"IHDR", &[0, 0, 0, 1, 0, 0, 0, 1, 8, 6, 0, 0, 0]);
+        let idat = png_chunk(b"IDAT", &[]);
+        check!(apng_loop_count(&png(&[header.clone(), actl(0), idat.clone()])) == LoopCount::Infinite);
+        check!(apng_loop_count(&png(&[header.clone(), actl(2), idat.clone()])) == LoopCount::Finite(2));
+        // The bytes of the chunk in a text chunk don't count
+        let text = png_chunk(b"tEXt", &actl(3));
+        check!(apng_loop_count(&png(&[header.clone(), text, idat.clone()])) == LoopCount::Finite(1));
+        let mut corrupt = actl(3);
+        corrupt[15] ^= 1;
+        check!(apng_loop_count(&png(&[header.clone(), corrupt, idat.clone()])) == LoopCount::Finite(1));
+        check!(apng_loop_count(&png(&[header, idat, actl(3)])) == LoopCount::Finite(1));
+        check!(apng_loop_count(b"no chunk") == LoopCount::Finite(1));
+    }
+
+    #[test]
+    fn bitmap_sizes() {
+        check!(Bitmap::new(2, 2, sample_pixels()).unwrap().pixel(1, 1) == [255, 255, 255, 255]);
+        check!(Bitmap::new(2, 3, sample_pixels()).is_err());
+        check!(Bitmap::new(u32::MAX, u32::MAX, vec![]).is_err());
+        check!(Bitmap::transparent(3, 2).unwrap().pixels == vec![0; 24]);
+        check!(Bitmap::transparent(u32::MAX, u32::MAX).is_err());
+    }
+
+    #[test]
+    fn reject_garbage() {
+        check!(decode_image(b"definitely not an image").is_err());
+    }
+
+    #[test]
+    fn sample_nearest_and_bilinear() {
+        let bitmap = Bitmap::new(2, 2, sample_pixels()).unwrap();
+        check!(bitmap.sample(0.2, 0.9, ScaleFilter::Nearest) == [255, 0, 0, 255]);
+        check!(bitmap.sample(1.7, 1.1, ScaleFilter::Nearest) == [255, 255, 255, 255]);
+        // Exactly on a pixel center bilinear gives the pixel itself
+        check!(bitmap.sample(0.5, 0.5, ScaleFilter::Bilinear) == [255, 0, 0, 255]);
+        // Halfway between red and green
+        check!(bitmap.sample(1.0, 0.5, ScaleFilter::Bilinear) == [128, 128, 0, 255]);
+    }
+}
@@ -0,0 +1,620 @@
+#![allow(dead_code)]
+#![allow(unused_imports)]
+
+use std::path::{Path, PathBuf};
+
+use log::{debug, error, info, warn};
+use url::Url;
+
+use super::simple_error::SimpleError;
+
+/// A bookmarked page. `keyword` opens it from the address bar, with `%s` in the URL replaced by
+///   what follows the keyword.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bookmark {
+    pub title: String,
+    pub url: Url,
+    pub tags: Vec<String>,
+    pub keyword: Option<String>,
+    /// Seconds since the Unix epoch
+    pub added: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct BookmarkFolder {
+    pub title: String,
+    pub children: Vec<BookmarkItem>,
+    pub added: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BookmarkItem {
+    Bookmark(Bookmark),
+    Folder(BookmarkFolder),
+}
+
+impl BookmarkFolder {
+    pub fn new(title: &str, added: u64) -> Self {
+        Self {
+            title: String::from(title),
+            children: vec![],
+            added,
+        }
+    }
+
+    /// The folder at `path`, child indices from this one.
+    fn folder_mut(&mut self, path: &[usize]) -> Option<&mut BookmarkFolder> {
+        match path.split_first() {
+            None => Some(self),
+            Some((index, rest)) => match self.children.get_mut(*index)? {
+                BookmarkItem::Folder(folder) => folder.folder_mut(rest),
+                BookmarkItem::Bookmark(_) => None,
+            },
+        }
+    }
+
+    fn collect<'b>(&'b self, bookmarks: &mut Vec<&'b Bookmark>) {
+        for child in self.children.iter() {
+            match child {
+                BookmarkItem::Bookmark(bookmark) => bookmarks.push(bookmark),
+                BookmarkItem::Folder(folder) => folder.collect(bookmarks),
+            }
+        }
+    }
+
+    fn find_mut(&mut self, url: &Url) -> Option<&mut Bookmark> {
+        self.children.iter_mut().find_map(|child| match child {
+            BookmarkItem::Bookmark(bookmark) if bookmark.url == *url => Some(bookmark),
+            BookmarkItem::Bookmark(_) => None,
+            BookmarkItem::Folder(folder) => folder.find_mut(url),
+        })
+    }
+
+    /// Remove the bookmarks of `url` from this folder and its subfolders. Returns how many.
+    fn remove_url(&mut self, url: &Url) -> usize {
+        let count = self.children.len();
+        self.children
+            .retain(|child| !matches!(child, BookmarkItem::Bookmark(bookmark) if bookmark.url == *url));
+        let mut removed = count - self.children.len();
+        for child in self.children.iter_mut() {
+            if let BookmarkItem::Folder(folder) = child {
+                removed += folder.remove_url(url);
+            }
+        }
+        removed
+    }
+}
+
+/// The bookmarks of the profile, saved in the Netscape bookmark file format other browsers
+///   import and export, so the profile file itself can be copied between browsers.
+#[derive(Debug, Default)]
+pub struct Bookmarks {
+    root: BookmarkFolder,
+    path: Option<PathBuf>,
+}
+
+impl Bookmarks {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load from `path` and save there on every change. A missing file is no bookmarks.
+    pub fn load(path: &Path) -> Result<Self, SimpleError> {
+        let mut bookmarks = Self::new();
+        match std::fs::read_to_string(path) {
+            Ok(html) => bookmarks.root.children = parse_html(&html).children,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => (),
+            Err(err) => return Err(SimpleError::new(format!("{:?}: {}", path, err).as_str())),
+        }
+        bookmarks.path = Some(path.to_path_buf());
+        Ok(bookmarks)
+    }
+
+    pub fn root(&self) -> &BookmarkFolder {
+        &self.root
+    }
+
+    /// Every bookmark, depth first.
+    pub fn bookmarks(&self) -> Vec<&Bookmark> {
+        let mut bookmarks = vec![];
+        self.root.collect(&mut bookmarks);
+        bookmarks
+    }
+
+    pub fn find(&self, url: &Url) -> Option<&Bookmark> {
+        self.bookmarks().into_iter().find(|bookmark| bookmark.url == *url)
+    }
+
+    pub fn tagged(&self, tag: &str) -> Vec<&Bookmark> {
+        self.bookmarks()
+            .into_iter()
+            .filter(|bookmark| bookmark.tags.iter().any(|t| t.eq_ignore_ascii_case(tag)))
+            .collect()
+    }
+
+    /// Add `item` at the end of the folder at `folder`, child indices from the root.
+    pub fn add(&mut self, folder: &[usize], item: BookmarkItem) -> Result<(), SimpleError> {
+        let folder = self
+            .root
+            .folder_mut(folder)
+            .ok_or_else(|| SimpleError::new(format!("No bookmark folder at {:?}", folder).as_str()))?;
+        folder.children.push(item);
+        self.save_or_log();
+        Ok(())
+    }
+
+    /// Remove the item at `path`, child indices from the root.
+    pub fn remove(&mut self, path: &[usize]) -> Option<BookmarkItem> {
+        let (index, folder) = path.split_last()?;
+        let folder = self.root.folder_mut(folder)?;
+        if *index >= folder.children.len() {
+            return None;
+        }
+        let removed = folder.children.remove(*index);
+        self.save_or_log();
+        Some(removed)
+    }
+
+    /// Bookmark `url` at the root, or remove its bookmarks if it has some. Returns true if added.
+    pub fn toggle(&mut self, url: &Url, title: &str, now: u64) -> bool {
+        if self.root.remove_url(url) > 0 {
+            self.save_or_log();
+            return false;
+        }
+        self.root.children.push(BookmarkItem::Bookmark(Bookmark {
+            title: String::from(title),
+            url: url.clone(),
+            tags: vec![],
+            keyword: None,
+            added: now,
+        }));
+        self.save_or_log();
+        true
+    }
+
+    pub fn set_tags(&mut self, url: &Url, tags: &[&str]) -> Result<(), SimpleError> {
+        let bookmark = self.find_mut(url)?;
+        bookmark.tags = tags.iter().map(|tag| String::from(tag.trim())).collect();
+        self.save_or_log();
+        Ok(())
+    }
+
+    pub fn set_keyword(&mut self, url: &Url, keyword: Option<&str>) -> Result<(), SimpleError> {
+        let bookmark = self.find_mut(url)?;
+        bookmark.keyword = keyword.map(String::from);
+        self.save_or_log();
+        Ok(())
+    }
+
+    fn find_mut(&mut self, url: &Url) -> Result<&mut Bookmark, SimpleError> {
+        self.root
+            .find_mut(url)
+            .ok_or_else(|| SimpleError::new(format!("{} is not bookmarked", url).as_str()))
+    }
+
+    /// URL for `input` typed in the address bar if it starts with a bookmark keyword.
+    pub fn expand_keyword(&self, input: &str) -> Option<Url> {
+        let input = input.trim();
+        let (keyword, argument) = input.split_once(' ').unwrap_or((input, ""));
+        let bookmark = self
+            .bookmarks()
+            .into_iter()
+            .find(|bookmark| bookmark.keyword.as_deref() == Some(keyword))?;
+        let template = bookmark.url.as_str();
+        if !template.contains("%s") {
+            return Some(bookmark.url.clone());
+        }
+        let argument: String = url::form_urlencoded::byte_serialize(argument.trim().as_bytes()).collect();
+        Url::parse(&template.replace("%s", &argument)).ok()
+    }
+
+    /// Add the bookmarks of a Netscape bookmark file in a new folder named `title`.
+    ///   Returns how many bookmarks were imported.
+    pub fn import_html(&mut self, html: &str, title: &str, now: u64) -> usize {
+        let mut imported = parse_html(html);
+        imported.title = String::from(title);
+        imported.added = now;
+        let mut bookmarks = vec![];
+        imported.collect(&mut bookmarks);
+        let count = bookmarks.len();
+        info!("Import {} bookmarks in {:?}", count, title);
+        self.root.children.push(BookmarkItem::Folder(imported));
+        self.save_or_log();
+        count
+    }
+
+    /// Import the bookmark file at `path` in a folder named after it.
+    pub fn import_file(&mut self, path: &Path, now: u64) -> Result<usize, SimpleError> {
+        let html =
+            std::fs::read_to_string(path).map_err(|err| SimpleError::new(format!("{:?}: {}", path, err).as_str()))?;
+        let name = path.file_name().map_or_else(String::new, |name| name.to_string_lossy().into_owned());
+        Ok(self.import_html(&html, &format!("Imported from {}", name), now))
+    }
+
+    pub fn export_file(&self, path: &Path) -> Result<(), SimpleError> {
+        std::fs::write(path, self.export_html()).map_err(|err| SimpleError::new(format!("{:?}: {}", path, err).as_str()))
+    }
+
+    pub fn export_html(&self) -> String {
+        let mut html = String::from(
+            "<!DOCTYPE NETSCAPE-Bookmark-file-1>\n\
+             <!-- This is an automatically generated file.\n     It will be read and overwritten.\n     DO NOT EDIT! -->\n\
+             <META HTTP-EQUIV=\"Content-Type\" CONTENT=\"text/html; charset=UTF-8\">\n\
+             <TITLE>Bookmarks</TITLE>\n\
+             <H1>Bookmarks</H1>\n",
+        );
+        write_folder(&mut html, &self.root, 0);
+        html
+    }
+
+    fn save_or_log(&self) {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return,
+        };
+        if let Err(err) = std::fs::write(path, self.export_html()) {
+            error!("Error while saving bookmarks: {:?}: {}", path, err);
+        }
+    }
+}
+
+fn write_folder(html: &mut String, folder: &BookmarkFolder, depth: usize) {
+    let indent = "    ".repeat(depth);
+    html.push_str(&format!("{}<DL><p>\n", indent));
+    for child in folder.children.iter() {
+        match child {
+            BookmarkItem::Bookmark(bookmark) => {
+                html.push_str(&format!(
+                    "{}    <DT><A HREF=\"{}\" ADD_DATE=\"{}\"",
+                    indent,
+                    escape(bookmark.url.as_str()),
+                    bookmark.added
+                ));
+                if !bookmark.tags.is_empty() {
+                    html.push_str(&format!(" TAGS=\"{}\"", escape(&bookmark.tags.join(","))));
+                }
+                if let Some(keyword) = &bookmark.keyword {
+                    html.push_str(&format!(" SHORTCUTURL=\"{}\"", escape(keyword)));
+                }
+                html.push_str(&format!(">{}</A>\n", escape(&bookmark.title)));
+            }
+            BookmarkItem::Folder(child) => {
+                html.push_str(&format!(
+                    "{}    <DT><H3 ADD_DATE=\"{}\">{}</H3>\n",
+                    indent,
+                    child.added,
+                    escape(&child.title)
+                ));
+                write_folder(html, child, depth + 1);
+            }
+        }
+    }
+    html.push_str(&format!("{}</DL><p>\n", indent));
+}
+
+/// `text` escaped for HTML text and attribute values.
+pub fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn unescape(text: &str) -> String {
+    let mut unescaped = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        unescaped.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let end = match rest.find(';').filter(|end| *end <= 10) {
+            Some(end) => end,
+            None => {
+                unescaped.push('&');
+                rest = &rest[1..];
+                continue;
+            }
+        };
+        let entity = &rest[1..end];
+        let c = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some('\u{a0}'),
+            _ => entity
+                .strip_prefix("#x")
+                .or_else(|| entity.strip_prefix("#X"))
+                .map(|hex| u32::from_str_radix(hex, 16).ok())
+                .unwrap_or_else(|| entity.strip_prefix('#').and_then(|dec| dec.parse().ok()))
+                .and_then(char::from_u32),
+        };
+        match c {
+            Some(c) => {
+                unescaped.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                unescaped.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    unescaped.push_str(rest);
+    unescaped
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    /// Lowercase name, closing tag flag and attributes with lowercase names
+    Tag(String, bool, Vec<(String, String)>),
+    Text(String),
+}
+
+/// Split the loose HTML of bookmark files into tags and text. Comments and doctypes are skipped.
+fn tokenize(html: &str) -> Vec<Token> {
+    let mut tokens = vec![];
+    let mut rest = html;
+    while !rest.is_empty() {
+        let start = rest.find('<').unwrap_or(rest.len());
+        if start > 0 {
+            tokens.push(Token::Text(unescape(&rest[..start])));
+        }
+        rest = &rest[start..];
+        if rest.is_empty() {
+            break;
+        }
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            rest = comment.find("-->").map_or("", |end| &comment[end + 3..]);
+            continue;
+        }
+        let (tag, after) = parse_tag(&rest[1..]);
+        tokens.extend(tag);
+        rest = after;
+    }
+    tokens
+}
+
+/// Parse a tag from after its `<`, returning what follows it.
+fn parse_tag(text: &str) -> (Option<Token>, &str) {
+    let (closing, text) = match text.strip_prefix('/') {
+        Some(text) => (true, text),
+        None => (false, text),
+    };
+    let name_end = text
+        .find(|c: char| c.is_whitespace() || c == '>' || c == '/')
+        .unwrap_or(text.len());
+    let name = text[..name_end].to_ascii_lowercase();
+    let mut attributes = vec![];
+    let mut rest = &text[name_end..];
+    loop {
+        rest = rest.trim_start_matches(|c: char| c.is_whitespace() || c == '/');
+        if rest.is_empty() {
+            break;
+        }
+        if let Some(after) = rest.strip_prefix('>') {
+            rest = after;
+            break;
+        }
+        let attribute_end = rest
+            .find(|c: char| c.is_whitespace() || c == '=' || c == '>')
+            .unwrap_or(rest.len())
+            .max(1);
+        let attribute = rest[..attribute_end].to_ascii_lowercase();
+        rest = rest[attribute_end..].trim_start();
+        let value = match rest.strip_prefix('=') {
+            None => String::new(),
+            Some(after) => {
+                let after = after.trim_start();
+                let (value, after) = match after.chars().next() {
+                    Some(quote @ ('"' | '\'')) => {
+                        let end = after[1..].find(quote).map_or(after.len(), |end| end + 1);
+                        (&after[1..end], after.get(end + 1..).unwrap_or(""))
+                    }
+                    _ => {
+                        let end = after.find(|c: char| c.is_whitespace() || c == '>').unwrap_or(after.len());
+                        (&after[..end], &after[end..])
+                    }
+                };
+                rest = after;
+                unescape(value)
+            }
+        };
+        attributes.push((attribute, value));
+    }
+    if name.is_empty() || name.starts_with('!') {
+        return (None, rest);
+    }
+    (Some(Token::Tag(name, closing, attributes)), rest)
+}
+
+fn attribute<'a>(attributes: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    attributes.iter().find(|(n, _)| n == name).map(|(_, value)| value.as_str())
+}
+
+/// Folders and bookmarks of a Netscape bookmark file, as exported by every browser:
+///   `<DT><H3>` names the folder whose `<DL>` follows, `<DT><A HREF>` is a bookmark.
+fn parse_html(html: &str) -> BookmarkFolder {
+    let mut folders = vec![BookmarkFolder::default()];
+    // For every open `<DL>`, whether it opened a folder
+    let mut lists: Vec<bool> = vec![];
+    let mut heading: Option<BookmarkFolder> = None;
+    let mut link: Option<Bookmark> = None;
+    let mut in_heading = false;
+    let date = |attributes: &[(String, String)]| attribute(attributes, "add_date").and_then(|d| d.parse().ok());
+
+    for token in tokenize(html) {
+        // `</A>` is often left out before the next item
+        if let Token::Tag(name, _, _) = &token {
+            if ["a", "dt", "dl", "h3"].contains(&name.as_str()) {
+                if let Some(bookmark) = link.take() {
+                    folders.last_mut().unwrap().children.push(BookmarkItem::Bookmark(bookmark));
+                }
+            }
+        }
+        match token {
+            Token::Tag(name, false, attributes) if name == "h3" => {
+                heading = Some(BookmarkFolder::new("", date(&attributes).unwrap_or(0)));
+                in_heading = true;
+            }
+            Token::Tag(name, true, _) if name == "h3" => in_heading = false,
+            Token::Tag(name, false, attributes) if name == "a" => {
+                let href = attribute(&attributes, "href").unwrap_or("");
+                link = match Url::parse(href) {
+                    Ok(url) => Some(Bookmark {
+                        title: String::new(),
+                        url,
+                        tags: attribute(&attributes, "tags")
+                            .map(|tags| {
+                                tags.split(',')
+                                    .map(str::trim)
+                                    .filter(|t| !t.is_empty())
+                                    .map(String::from)
+                                    .collect()
+                            })
+                            .unwrap_or_default(),
+                        keyword: attribute(&attributes, "shortcuturl")
+                            .filter(|k| !k.is_empty())
+                            .map(String::from),
+                        added: date(&attributes).unwrap_or(0),
+                    }),
+                    Err(err) => {
+                        warn!("Ignoring bookmark of {:?}: {}", href, err);
+                        None
+                    }
+                };
+            }
+            Token::Tag(name, false, _) if name == "dl" => {
+                let folder = heading.take();
+                lists.push(folder.is_some());
+                folders.extend(folder);
+            }
+            // Every list end pops its list, folder or not
+            Token::Tag(name, true, _) if name == "dl" && lists.pop() == Some(true) && folders.len() > 1 => {
+                let folder = folders.pop().unwrap();
+                folders.last_mut().unwrap().children.push(BookmarkItem::Folder(folder));
+            }
+            Token::Text(text) => {
+                let text = text.trim();
+                if let Some(bookmark) = link.as_mut() {
+                    bookmark.title.push_str(text);
+                } else if let Some(folder) = heading.as_mut().filter(|_| in_heading) {
+                    folder.title.push_str(text);
+                }
+            }
+            _ => (),
+        }
+    }
+    // Files cut short: close the folders still open
+    while folders.len() > 1 {
+        let folder = folders.pop().unwrap();
+        folders.last_mut().unwrap().children.push(BookmarkItem::Folder(folder));
+    }
+    folders.pop().unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use assert2::check;
+
+    use super::*;
+
+    const FIREFOX_EXPORT: &str = r#"<!DOCTYPE NETSCAPE-Bookmark-file-1>
+<!-- This is an automatically generated file.
+     It will be read and overwritten.
+     DO NOT EDIT! -->
+<META HTTP-EQUIV="Content-Type" CONTENT="text/html; charset=UTF-8">
+<TITLE>Bookmarks</TITLE>
+<H1>Bookmarks Menu</H1>
+
+<DL><p>
+    <DT><H3 ADD_DATE="1600000000" LAST_MODIFIED="1600000001" PERSONAL_TOOLBAR_FOLDER="true">Bookmarks Toolbar</H3>
+    <DL><p>
+        <DT><A HREF="https://www.rust-lang.org/" ADD_DATE="1600000002" TAGS="rust,lang">Rust &amp; friends</A>
+        <DT><H3>Empty</H3>
+        <DL><p>
+        </DL><p>
+    </DL><p>
+    <DT><A HREF="https://en.wikipedia.org/wiki/Special:Search?search=%s" SHORTCUTURL="w">Wikipedia</A>
+    <DD>Search the encyclopedia
+    <DT><A HREF="not a url">Broken</A>
+    <DT><A HREF="https://unclosed.org/">Unclosed
+</DL>
+"#;
+
+    fn url(text: &str) -> Url {
+        Url::parse(text).unwrap()
+    }
+
+    #[test]
+    fn netscape_import() {
+        let mut bookmarks = Bookmarks::new();
+        check!(bookmarks.import_html(FIREFOX_EXPORT, "Imported", 10) == 3);
+        let imported = match &bookmarks.root().children[0] {
+            BookmarkItem::Folder(folder) => folder,
+            item => panic!("Expected a folder, found {:?}", item),
+        };
+        check!(imported.title == "Imported");
+        check!(imported.children.len() == 3);
+        check!(bookmarks.find(&url("https://unclosed.org/")).unwrap().title == "Unclosed");
+        let toolbar = match &imported.children[0] {
+            BookmarkItem::Folder(folder) => folder,
+            item => panic!("Expected a folder, found {:?}", item),
+        };
+        check!(toolbar.title == "Bookmarks Toolbar");
+        check!(toolbar.added == 1600000000);
+        check!(toolbar.children.len() == 2);
+        let rust = bookmarks.find(&url("https://www.rust-lang.org/")).unwrap();
+        check!(rust.title == "Rust & friends");
+        check!(rust.tags == vec!["rust", "lang"]);
+        check!(bookmarks.tagged("RUST").len() == 1);
+        check!(
+            bookmarks.expand_keyword("w rust lang").map(String::from)
+                == Some(String::from("https://en.wikipedia.org/wiki/Special:Search?search=rust+lang"))
+        );
+        check!(bookmarks.expand_keyword("x rust") == None);
+    }
+
+    #[test]
+    fn export_round_trip() {
+        let mut bookmarks = Bookmarks::new();
+        bookmarks.add(&[], BookmarkItem::Folder(BookmarkFolder::new("Work <3", 5))).unwrap();
+        check!(bookmarks.toggle(&url("https://a.com/?q=\"x\""), "A & B", 6));
+        bookmarks.set_keyword(&url("https://a.com/?q=\"x\""), Some("a")).unwrap();
+        bookmarks
+            .add(
+                &[0],
+                BookmarkItem::Bookmark(Bookmark {
+                    title: String::from("Docs"),
+                    url: url("https://docs.rs/"),
+                    tags: vec![String::from("rust")],
+                    keyword: None,
+                    added: 7,
+                }),
+            )
+            .unwrap();
+        check!(bookmarks.add(&[1], BookmarkItem::Folder(BookmarkFolder::new("x", 0))).is_err());
+        let parsed = parse_html(&bookmarks.export_html());
+        check!(parsed.children == bookmarks.root().children);
+
+        check!(!bookmarks.toggle(&url("https://a.com/?q=\"x\""), "", 8));
+        check!(bookmarks.bookmarks().len() == 1);
+        check!(matches!(bookmarks.remove(&[0, 0]), Some(BookmarkItem::Bookmark(_))));
+        check!(bookmarks.bookmarks().is_empty());
+        check!(bookmarks.remove(&[3]) == None);
+    }
+
+    #[test]
+    fn persistence() {
+        let path = std::env::temp_dir().join(format!("rowser-bookmarks-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        {
+            let mut bookmarks = Bookmarks::load(&path).unwrap();
+            bookmarks.toggle(&url("https://a.com/"), "A", 1);
+            bookmarks.set_tags(&url("https://a.com/"), &["x", " y"]).unwrap();
+        }
+        let bookmarks = Bookmarks::load(&path).unwrap();
+        check!(bookmarks.find(&url("https://a.com/")).unwrap().tags == vec!["x", "y"]);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,884 @@
+#![allow(dead_code)]
+#![allow(unused_imports)]
+
+use log::{debug, error, info, warn};
+
+use crate::fixme;
+
+use super::bitmap::ScaleFilter;
+use super::color::Color;
+use super::geometry::{Position, Rect, Size};
+use super::gfxbuffer::Surface;
+use super::gradient::dither;
+use super::style::{
+    BackgroundBox, BackgroundImage, BackgroundLayer, BackgroundRepeat, BackgroundSize, BorderSide,
+    BorderStyle, Borders, BoxShadow, BoxStyle, CornerRadii, LengthPercentage,
+};
+
+/// Shade applied to the darker half of groove, ridge, inset and outset borders.
+const DARK_SHADE: f64 = 2.0 / 3.0;
+
+/// A rectangle with elliptical corners.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RoundedRect {
+    pub rect: Rect,
+    pub radii: CornerRadii,
+}
+
+impl RoundedRect {
+    /// Radii that don't fit are scaled down all together, as CSS asks.
+    pub fn new(rect: Rect, radii: CornerRadii) -> Self {
+        let mut factor: f64 = 1.0;
+        for (length, a, b) in [
+            (rect.width(), radii.top_left.width, radii.top_right.width),
+            (rect.width(), radii.bottom_left.width, radii.bottom_right.width),
+            (rect.height(), radii.top_left.height, radii.bottom_left.height),
+            (rect.height(), radii.top_right.height, radii.bottom_right.height),
+        ] {
+            if a + b > 0.0 {
+                factor = factor.min(length.max(0.0) / (a + b));
+            }
+        }
+        let scale = |r: Size| Size::new(r.width.max(0.0) * factor, r.height.max(0.0) * factor);
+        Self {
+            rect,
+            radii: CornerRadii {
+                top_left: scale(radii.top_left),
+                top_right: scale(radii.top_right),
+                bottom_right: scale(radii.bottom_right),
+                bottom_left: scale(radii.bottom_left),
+            },
+        }
+    }
+
+    /// Shrink by the given amounts. Corner radii shrink along, never below zero.
+    pub fn inset(&self, top: f64, right: f64, bottom: f64, left: f64) -> Self {
+        let shrink = |r: Size, dx: f64, dy: f64| Size::new((r.width - dx).max(0.0), (r.height - dy).max(0.0));
+        Self::new(
+            Rect::new(
+                self.rect.left() + left,
+                self.rect.top() + top,
+                (self.rect.width() - left - right).max(0.0),
+                (self.rect.height() - top - bottom).max(0.0),
+            ),
+            CornerRadii {
+                top_left: shrink(self.radii.top_left, left, top),
+                top_right: shrink(self.radii.top_right, right, top),
+                bottom_right: shrink(self.radii.bottom_right, right, bottom),
+                bottom_left: shrink(self.radii.bottom_left, left, bottom),
+            },
+        )
+    }
+
+    /// Grow (or shrink with a negative `amount`) on every side. Sharp corners stay sharp.
+    pub fn outset(&self, amount: f64) -> Self {
+        let grow = |r: Size| {
+            if r.width <= 0.0 || r.height <= 0.0 {
+                r
+            } else {
+                Size::new((r.width + amount).max(0.0), (r.height + amount).max(0.0))
+            }
+        };
+        Self::new(
+            Rect::new(
+                self.rect.left() - amount,
+                self.rect.top() - amount,
+                (self.rect.width() + amount * 2.0).max(0.0),
+                (self.rect.height() + amount * 2.0).max(0.0),
+            ),
+            CornerRadii {
+                top_left: grow(self.radii.top_left),
+                top_right: grow(self.radii.top_right),
+                bottom_right: grow(self.radii.bottom_right),
+                bottom_left: grow(self.radii.bottom_left),
+            },
+        )
+    }
+
+    pub fn translate(&self, offset: &Position) -> Self {
+        Self {
+            rect: self.rect.add_pos(offset),
+            radii: self.radii,
+        }
+    }
+
+    /// Approximate signed distance from the outline: negative inside, positive outside.
+    pub fn signed_distance(&self, x: f64, y: f64) -> f64 {
+        let r = &self.rect;
+        if r.is_empty() {
+            return f64::MAX;
+        }
+        let corners = [
+            (self.radii.top_left, r.left(), r.top(), 1.0, 1.0),
+            (self.radii.top_right, r.right(), r.top(), -1.0, 1.0),
+            (self.radii.bottom_right, r.right(), r.bottom(), -1.0, -1.0),
+            (self.radii.bottom_left, r.left(), r.bottom(), 1.0, -1.0),
+        ];
+        for (radius, corner_x, corner_y, sx, sy) in corners {
+            if radius.width <= 0.0 || radius.height <= 0.0 {
+                continue;
+            }
+            let center_x = corner_x + sx * radius.width;
+            let center_y = corner_y + sy * radius.height;
+            let dx = x - center_x;
+            let dy = y - center_y;
+            // Inside the corner box, on the outer side of the ellipse center
+            if dx * sx < 0.0 && dy * sy < 0.0 {
+                return ellipse_distance(dx, dy, radius.width, radius.height);
+            }
+        }
+        (r.left() - x)
+            .max(x - r.right())
+            .max(r.top() - y)
+            .max(y - r.bottom())
+    }
+
+    /// Fraction of the pixel centered at (x, y) covered by the shape.
+    pub fn coverage(&self, x: f64, y: f64) -> f64 {
+        (0.5 - self.signed_distance(x, y)).clamp(0.0, 1.0)
+    }
+}
+
+fn ellipse_distance(dx: f64, dy: f64, rx: f64, ry: f64) -> f64 {
+    let f = dx * dx / (rx * rx) + dy * dy / (ry * ry) - 1.0;
+    let gx = 2.0 * dx / (rx * rx);
+    let gy = 2.0 * dy / (ry * ry);
+    let g = (gx * gx + gy * gy).sqrt();
+    if g < 1e-9 {
+        return -rx.min(ry);
+    }
+    f / g
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Edge {
+    Top,
+    Right,
+    Bottom,
+    Left,
+}
+
+/// Paint a full element box in CSS order: outer shadows, background, inset shadows, borders, outline.
+pub fn paint_box(surface: &mut Surface, border_box: &Rect, style: &BoxStyle, clip: &Rect) {
+    paint_box_clips(surface, border_box, style, std::slice::from_ref(clip));
+}
+
+/// `paint_box` in each of `clips`, blurring the shadows once for all of them.
+pub fn paint_box_clips(surface: &mut Surface, border_box: &Rect, style: &BoxStyle, clips: &[Rect]) {
+    let outer = RoundedRect::new(*border_box, style.border_radius);
+    let borders = &style.borders;
+    let padding_box = outer.inset(
+        borders.top.used_width(),
+        borders.right.used_width(),
+        borders.bottom.used_width(),
+        borders.left.used_width(),
+    );
+
+    let outer_shadows: Vec<ShadowMask> = style
+        .box_shadows
+        .iter()
+        .rev()
+        .filter(|s| !s.inset)
+        .filter_map(|shadow| outer_shadow_mask(surface, &outer, shadow, clips))
+        .collect();
+    let inset_shadows: Vec<ShadowMask> = style
+        .box_shadows
+        .iter()
+        .rev()
+        .filter(|s| s.inset)
+        .filter_map(|shadow| inset_shadow_mask(surface, &padding_box, shadow, clips))
+        .collect();
+    for clip in clips {
+        paint_box_clip(
+            surface,
+            style,
+            &outer,
+            &padding_box,
+            &outer_shadows,
+            &inset_shadows,
+            clip,
+        );
+    }
+}
+
+fn paint_box_clip(
+    surface: &mut Surface,
+    style: &BoxStyle,
+    outer: &RoundedRect,
+    padding_box: &RoundedRect,
+    outer_shadows: &[ShadowMask],
+    inset_shadows: &[ShadowMask],
+    clip: &Rect,
+) {
+    let borders = &style.borders;
+    for shadow in outer_shadows {
+        paint_outer_shadow(surface, outer, shadow, clip);
+    }
+    if let Some(color) = style.background_color {
+        // The background color is clipped like the bottom layer
+        let color_clip = style
+            .background_layers
+            .last()
+            .map_or(BackgroundBox::Border, |layer| layer.clip);
+        fill_rounded_rect(surface, background_box(color_clip, outer, padding_box), color, clip);
+    }
+    for layer in style.background_layers.iter().rev() {
+        paint_background_layer(surface, layer, outer, padding_box, clip);
+    }
+    for shadow in inset_shadows {
+        paint_inset_shadow(surface, padding_box, shadow, clip);
+    }
+    paint_border_ring(surface, outer, padding_box, borders, clip);
+
+    if let Some(outline) = &style.outline {
+        let width = outline.side.used_width();
+        if width > 0.0 {
+            let inner = outer.outset(outline.offset);
+            let ring = inner.outset(width);
+            paint_border_ring(surface, &ring, &inner, &Borders::all(outline.side), clip);
+        }
+    }
+}
+
+pub fn fill_rounded_rect(surface: &mut Surface, shape: &RoundedRect, color: Color, clip: &Rect) {
+    let (x_begin, x_end, y_begin, y_end) = match surface.pixel_span(&shape.rect, clip) {
+        Some(span) => span,
+        None => return,
+    };
+    let rgba = color.as_u8_ref();
+    for y in y_begin..y_end {
+        for x in x_begin..x_end {
+            let coverage = shape.coverage(x as f64 + 0.5, y as f64 + 0.5);
+            if coverage > 0.0 {
+                surface.blend(x, y, &rgba, coverage);
+            }
+        }
+    }
+}
+
+fn background_box<'r>(kind: BackgroundBox, outer: &'r RoundedRect, padding_box: &'r RoundedRect) -> &'r RoundedRect {
+    match kind {
+        BackgroundBox::Border => outer,
+        // There is no padding yet: the content box is the padding box
+        BackgroundBox::Padding | BackgroundBox::Content => padding_box,
+    }
+}
+
+/// Placement of background tiles along one axis, relative to the origin box.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct AxisTiling {
+    tile: f64,
+    offset: f64,
+    period: f64,
+    repeat: bool,
+}
+
+impl AxisTiling {
+    fn new(repeat: BackgroundRepeat, tile: f64, area: f64, position: LengthPercentage) -> Self {
+        let positioned = |tile: f64, repeat: bool| Self {
+            tile,
+            offset: position.resolve(area - tile),
+            period: tile,
+            repeat,
+        };
+        match repeat {
+            BackgroundRepeat::Repeat => positioned(tile, true),
+            BackgroundRepeat::NoRepeat => positioned(tile, false),
+            BackgroundRepeat::Round => {
+                let count = (area / tile).round().max(1.0);
+                positioned(area / count, true)
+            }
+            BackgroundRepeat::Space => {
+                let count = (area / tile).floor();
+                if count < 2.0 {
+                    return positioned(tile, false);
+                }
+                Self {
+                    tile,
+                    offset: 0.0,
+                    period: tile + (area - count * tile) / (count - 1.0),
+                    repeat: true,
+                }
+            }
+        }
+    }
+
+    /// Position inside the tile covering `p`, if any.
+    fn local(&self, p: f64) -> Option<f64> {
+        let local = if self.repeat {
+            (p - self.offset).rem_euclid(self.period)
+        } else {
+            p - self.offset
+        };
+        if local >= 0.0 && local < self.tile {
+            Some(local)
+        } else {
+            None
+        }
+    }
+}
+
+/// Size of one background tile, from `background-size` and the intrinsic size of the image, if it has one.
+fn tile_size(size: &BackgroundSize, area: &Size, intrinsic: Option<Size>) -> Size {
+    let intrinsic = intrinsic.filter(|i| i.width > 0.0 && i.height > 0.0);
+    match (size, intrinsic) {
+        (BackgroundSize::Cover, Some(i)) => {
+            let scale = (area.width / i.width).max(area.height / i.height);
+            Size::new(i.width * scale, i.height * scale)
+        }
+        (BackgroundSize::Contain, Some(i)) => {
+            let scale = (area.width / i.width).min(area.height / i.height);
+            Size::new(i.width * scale, i.height * scale)
+        }
+        (BackgroundSize::Cover, None) | (BackgroundSize::Contain, None) => *area,
+        (BackgroundSize::Explicit(width, height), intrinsic) => {
+            let width = width.map(|w| w.resolve(area.width));
+            let height = height.map(|h| h.resolve(area.height));
+            match (width, height, intrinsic) {
+                (Some(w), Some(h), _) => Size::new(w, h),
+                (Some(w), None, Some(i)) => Size::new(w, w * i.height / i.width),
+                (None, Some(h), Some(i)) => Size::new(h * i.width / i.height, h),
+                (Some(w), None, None) => Size::new(w, area.height),
+                (None, Some(h), None) => Size::new(area.width, h),
+                (None, None, Some(i)) => i,
+                (None, None, None) => *area,
+            }
+        }
+    }
+}
+
+fn paint_background_layer(
+    surface: &mut Surface,
+    layer: &BackgroundLayer,
+    outer: &RoundedRect,
+    padding_box: &RoundedRect,
+    clip: &Rect,
+) {
+    let origin = background_box(layer.origin, outer, padding_box).rect;
+    let clip_shape = background_box(layer.clip, outer, padding_box);
+    let intrinsic = match &layer.image {
+        BackgroundImage::Gradient(_) => None,
+        BackgroundImage::Image(image) => Some(image.intrinsic_size()),
+    };
+    let tile = tile_size(&layer.size, &origin.size, intrinsic);
+    if tile.width <= 0.0 || tile.height <= 0.0 {
+        return;
+    }
+    let tiling_x = AxisTiling::new(layer.repeat.0, tile.width, origin.width(), layer.position.0);
+    let tiling_y = AxisTiling::new(layer.repeat.1, tile.height, origin.height(), layer.position.1);
+    // Round may have stretched the tile
+    let tile = Size::new(tiling_x.tile, tiling_y.tile);
+
+    let (x_begin, x_end, y_begin, y_end) = match surface.pixel_span(&clip_shape.rect, clip) {
+        Some(span) => span,
+        None => return,
+    };
+    let stops = match &layer.image {
+        BackgroundImage::Gradient(gradient) => gradient.resolved_stops(),
+        BackgroundImage::Image(image) => {
+            if image.is_animated() {
+                fixme!("Animated background images");
+            }
+            vec![]
+        }
+    };
+    for y in y_begin..y_end {
+        let cy = y as f64 + 0.5;
+        let v = match tiling_y.local(cy - origin.top()) {
+            Some(v) => v,
+            None => continue,
+        };
+        for x in x_begin..x_end {
+            let cx = x as f64 + 0.5;
+            let u = match tiling_x.local(cx - origin.left()) {
+                Some(u) => u,
+                None => continue,
+            };
+            let coverage = clip_shape.coverage(cx, cy);
+            if coverage <= 0.0 {
+                continue;
+            }
+            let rgba = match &layer.image {
+                BackgroundImage::Gradient(gradient) => {
+                    dither(&gradient.color_at(&stops, gradient.parameter(u, v, &tile)), x, y)
+                }
+                BackgroundImage::Image(image) => {
+                    let bitmap = image.first_frame();
+                    bitmap.sample(
+                        u * bitmap.width as f64 / tile.width,
+                        v * bitmap.height as f64 / tile.height,
+                        ScaleFilter::Bilinear,
+                    )
+                }
+            };
+            surface.blend(x, y, &rgba, coverage);
+        }
+    }
+}
+
+/// Paint the area between `outer` and `inner`, each pixel taking the style of the nearest side.
+fn paint_border_ring(
+    surface: &mut Surface,
+    outer: &RoundedRect,
+    inner: &RoundedRect,
+    borders: &Borders,
+    clip: &Rect,
+) {
+    let (x_begin, x_end, y_begin, y_end) = match surface.pixel_span(&outer.rect, clip) {
+        Some(span) => span,
+        None => return,
+    };
+    let rect = &outer.rect;
+    for y in y_begin..y_end {
+        for x in x_begin..x_end {
+            let (cx, cy) = (x as f64 + 0.5, y as f64 + 0.5);
+            let coverage = outer.coverage(cx, cy) * (1.0 - inner.coverage(cx, cy));
+            if coverage <= 0.0 {
+                continue;
+            }
+            // Distance from each outer edge relative to that side width: corners split along the diagonal
+            let mut nearest: Option<(Edge, f64)> = None;
+            for (edge, side, distance) in [
+                (Edge::Top, &borders.top, cy - rect.top()),
+                (Edge::Right, &borders.right, rect.right() - cx),
+                (Edge::Bottom, &borders.bottom, rect.bottom() - cy),
+                (Edge::Left, &borders.left, cx - rect.left()),
+            ] {
+                let width = side.used_width();
+                if width <= 0.0 {
+                    continue;
+                }
+                let depth = distance / width;
+                if nearest.is_none_or(|(_, d)| depth < d) {
+                    nearest = Some((edge, depth));
+                }
+            }
+            let (edge, depth) = match nearest {
+                Some(nearest) => nearest,
+                None => continue,
+            };
+            let (side, along, length) = match edge {
+                Edge::Top => (&borders.top, cx - rect.left(), rect.width()),
+                Edge::Bottom => (&borders.bottom, cx - rect.left(), rect.width()),
+                Edge::Left => (&borders.left, cy - rect.top(), rect.height()),
+                Edge::Right => (&borders.right, cy - rect.top(), rect.height()),
+            };
+            if let Some((color, pattern)) = border_pixel(side, edge, depth.clamp(0.0, 1.0), along, length) {
+                surface.blend(x, y, &color.as_u8_ref(), coverage * pattern);
+            }
+        }
+    }
+}
+
+/// Color and pattern coverage of a border pixel. `depth` goes from 0 on the outer edge to 1 on the inner one,
+///   `along` is the position on a side of `length` pixels.
+fn border_pixel(
+    side: &BorderSide,
+    edge: Edge,
+    depth: f64,
+    along: f64,
+    length: f64,
+) -> Option<(Color, f64)> {
+    let width = side.used_width();
+    let top_left = edge == Edge::Top || edge == Edge::Left;
+    let dark = side.color.shade(DARK_SHADE);
+    let light = side.color;
+    match side.style {
+        BorderStyle::None => None,
+        BorderStyle::Solid => Some((side.color, 1.0)),
+        BorderStyle::Double => {
+            if width < 3.0 || depth <= 1.0 / 3.0 || depth >= 2.0 / 3.0 {
+                Some((side.color, 1.0))
+            } else {
+                None
+            }
+        }
+        BorderStyle::Dashed => {
+            // Dashes of 3 widths, with gaps stretched so the pattern fits the side exactly.
+            let count = (length / (width * 6.0)).round().max(1.0);
+            let period = length / count;
+            if along.rem_euclid(period) < period / 2.0 {
+                Some((side.color, 1.0))
+            } else {
+                None
+            }
+        }
+        BorderStyle::Dotted => {
+            let count = (length / (width * 2.0)).round().max(1.0);
+            let period = length / count;
+            let center = (along / period).floor() * period + period / 2.0;
+            let dx = along - center;
+            let dy = (depth - 0.5) * width;
+            let coverage = (width / 2.0 - (dx * dx + dy * dy).sqrt() + 0.5).clamp(0.0, 1.0);
+            if coverage > 0.0 {
+                Some((side.color, coverage))
+            } else {
+                None
+            }
+        }
+        BorderStyle::Inset => Some((if top_left { dark } else { light }, 1.0)),
+        BorderStyle::Outset => Some((if top_left { light } else { dark }, 1.0)),
+        BorderStyle::Groove | BorderStyle::Ridge => {
+            let outer_half = depth < 0.5;
+            let groove = side.style == BorderStyle::Groove;
+            if outer_half == (top_left == groove) {
+                Some((dark, 1.0))
+            } else {
+                Some((light, 1.0))
+            }
+        }
+    }
+}
+
+/// Gaussian blur in place on a `width` x `height` mask, horizontal then vertical pass.
+fn gaussian_blur(mask: &mut [f64], width: usize, height: usize, sigma: f64) {
+    if sigma < 0.1 || width == 0 || height == 0 {
+        return;
+    }
+    let radius = (sigma * 3.0).ceil() as isize;
+    let mut kernel: Vec<f64> = (-radius..=radius)
+        .map(|i| (-((i * i) as f64) / (2.0 * sigma * sigma)).exp())
+        .collect();
+    let total: f64 = kernel.iter().sum();
+    kernel.iter_mut().for_each(|k| *k /= total);
+
+    let mut tmp = vec![0.0; mask.len()];
+    // Samples outside the mask repeat the edge value
+    for y in 0..height {
+        for x in 0..width {
+            let mut acc = 0.0;
+            for (k, weight) in kernel.iter().enumerate() {
+                let sx = (x as isize + k as isize - radius).max(0).min(width as isize - 1) as usize;
+                acc += mask[y * width + sx] * weight;
+            }
+            tmp[y * width + x] = acc;
+        }
+    }
+    for y in 0..height {
+        for x in 0..width {
+            let mut acc = 0.0;
+            for (k, weight) in kernel.iter().enumerate() {
+                let sy = (y as isize + k as isize - radius).max(0).min(height as isize - 1) as usize;
+                acc += tmp[sy * width + x] * weight;
+            }
+            mask[y * width + x] = acc;
+        }
+    }
+}
+
+/// Blurred coverage mask of `area` (whole pixels), with `value` giving the unblurred coverage of each pixel center.
+fn blurred_mask(area: &Rect, blur: f64, value: impl Fn(f64, f64) -> f64) -> (Vec<f64>, usize, usize) {
+    let width = area.width().max(0.0) as usize;
+    let height = area.height().max(0.0) as usize;
+    let mut mask = vec![0.0; width * height];
+    for y in 0..height {
+        for x in 0..width {
+            mask[y * width + x] = value(
+                area.left() + x as f64 + 0.5,
+                area.top() + y as f64 + 0.5,
+            );
+        }
+    }
+    // CSS: the blur radius is twice the standard deviation
+    gaussian_blur(&mut mask, width, height, blur / 2.0);
+    (mask, width, height)
+}
+
+fn pixel_aligned(rect: &Rect, margin: f64) -> Rect {
+    let left = (rect.left() - margin).floor();
+    let top = (rect.top() - margin).floor();
+    Rect::new(
+        left,
+        top,
+        (rect.right() + margin).ceil() - left,
+        (rect.bottom() + margin).ceil() - top,
+    )
+}
+
+/// A shadow blurred over `area` (whole pixels), painted in each clip rect.
+struct ShadowMask {
+    area: Rect,
+    mask: Vec<f64>,
+    width: usize,
+    color: [u8; 4],
+}
+
+impl ShadowMask {
+    /// Blur `value`, the unblurred coverage of each pixel center, unless no clip shows some of `area`.
+    fn new(
+        surface: &Surface,
+        area: Rect,
+        shadow: &BoxShadow,
+        clips: &[Rect],
+        value: impl Fn(f64, f64) -> f64,
+    ) -> Option<Self> {
+        if !clips.iter().any(|clip| surface.pixel_span(&area, clip).is_some()) {
+            return None;
+        }
+        let (mask, width, _) = blurred_mask(&area, shadow.blur, value);
+        Some(Self {
+            area,
+            mask,
+            width,
+            color: shadow.color.as_u8_ref(),
+        })
+    }
+
+    /// Blend the shadow in `clip`, its coverage multiplied by `visible` at each pixel center.
+    fn paint(&self, surface: &mut Surface, clip: &Rect, visible: impl Fn(f64, f64) -> f64) {
+        let (x_begin, x_end, y_begin, y_end) = match surface.pixel_span(&self.area, clip) {
+            Some(span) => span,
+            None => return,
+        };
+        for y in y_begin..y_end {
+            for x in x_begin..x_end {
+                let mx = (x as f64 - self.area.left()) as usize;
+                let my = (y as f64 - self.area.top()) as usize;
+                let coverage = self.mask[my * self.width + mx] * visible(x as f64 + 0.5, y as f64 + 0.5);
+                if coverage > 0.0 {
+                    surface.blend(x, y, &self.color, coverage);
+                }
+            }
+        }
+    }
+}
+
+fn outer_shadow_mask(
+    surface: &Surface,
+    border_box: &RoundedRect,
+    shadow: &BoxShadow,
+    clips: &[Rect],
+) -> Option<ShadowMask> {
+    let shape = border_box.outset(shadow.spread).translate(&shadow.offset);
+    let area = pixel_aligned(&shape.rect, (shadow.blur * 1.5).ceil());
+    ShadowMask::new(surface, area, shadow, clips, |x, y| shape.coverage(x, y))
+}
+
+fn paint_outer_shadow(surface: &mut Surface, border_box: &RoundedRect, shadow: &ShadowMask, clip: &Rect) {
+    // The outer shadow never shows below the box itself
+    shadow.paint(surface, clip, |x, y| 1.0 - border_box.coverage(x, y));
+}
+
+fn inset_shadow_mask(
+    surface: &Surface,
+    padding_box: &RoundedRect,
+    shadow: &BoxShadow,
+    clips: &[Rect],
+) -> Option<ShadowMask> {
+    let hole = padding_box.outset(-shadow.spread).translate(&shadow.offset);
+    let area = pixel_aligned(&padding_box.rect, 0.0);
+    ShadowMask::new(surface, area, shadow, clips, |x, y| 1.0 - hole.coverage(x, y))
+}
+
+fn paint_inset_shadow(surface: &mut Surface, padding_box: &RoundedRect, shadow: &ShadowMask, clip: &Rect) {
+    shadow.paint(surface, clip, |x, y| padding_box.coverage(x, y));
+}
+
+#[cfg(test)]
+mod tests {
+    use assert2::check;
+
+    use super::super::color::Colors;
+    use super::*;
+
+    const RED: Color = Color {
+        r: 255,
+        g: 0,
+        b: 0,
+        a: 255,
+    };
+
+    fn paint(width: usize, height: usize, border_box: Rect, style: &BoxStyle) -> Vec<u8> {
+        let mut data = vec![0u8; width * height * 4];
+        {
+            let mut surface = Surface::new(&mut data, width, height);
+            let clip = Rect::from_u32(0, 0, width as u32, height as u32);
+            paint_box(&mut surface, &border_box, style, &clip);
+        }
+        data
+    }
+
+    fn pixel(data: &[u8], width: usize, x: usize, y: usize) -> [u8; 4] {
+        let i = (y * width + x) * 4;
+        [data[i], data[i + 1], data[i + 2], data[i + 3]]
+    }
+
+    #[test]
+    fn rounded_rect_distance() {
+        let shape = RoundedRect::new(Rect::from_u32(0, 0, 20, 20), CornerRadii::uniform(5.0));
+        check!(shape.signed_distance(10.0, 10.0) < 0.0);
+        check!(shape.signed_distance(10.0, -1.0) == 1.0);
+        // The very corner of the box is outside the rounded shape
+        check!(shape.coverage(0.5, 0.5) == 0.0);
+        let on_curve = shape.signed_distance(5.0 - 5.0 / 2f64.sqrt(), 5.0 - 5.0 / 2f64.sqrt());
+        check!(on_curve.abs() < 0.01);
+    }
+
+    #[test]
+    fn oversized_radii_are_scaled() {
+        let shape = RoundedRect::new(Rect::from_u32(0, 0, 10, 20), CornerRadii::uniform(10.0));
+        check!(shape.radii.top_left == Size::new(5.0, 5.0));
+    }
+
+    #[test]
+    fn background_clipped_to_radius() {
+        let mut style = BoxStyle::with_background(RED);
+        style.border_radius = CornerRadii::uniform(6.0);
+        let data = paint(12, 12, Rect::from_u32(0, 0, 12, 12), &style);
+        check!(pixel(&data, 12, 0, 0)[3] == 0);
+        check!(pixel(&data, 12, 6, 6) == [255, 0, 0, 255]);
+        // Anti-aliased edge on the curve
+        let edge = pixel(&data, 12, 1, 2)[3];
+        check!(edge > 0 && edge < 255);
+    }
+
+    #[test]
+    fn background_tiling() {
+        let area = Size::new(100.0, 40.0);
+        let cover = tile_size(&BackgroundSize::Cover, &area, Some(Size::new(10.0, 10.0)));
+        check!(cover == Size::new(100.0, 100.0));
+        let contain = tile_size(&BackgroundSize::Contain, &area, Some(Size::new(10.0, 10.0)));
+        check!(contain == Size::new(40.0, 40.0));
+        let half_width = BackgroundSize::Explicit(Some(LengthPercentage::Percent(0.5)), None);
+        check!(tile_size(&half_width, &area, Some(Size::new(10.0, 20.0))) == Size::new(50.0, 100.0));
+
+        let centered = AxisTiling::new(BackgroundRepeat::NoRepeat, 10.0, 100.0, LengthPercentage::Percent(0.5));
+        check!(centered.local(44.0) == None);
+        check!(centered.local(46.0) == Some(1.0));
+        let repeat = AxisTiling::new(BackgroundRepeat::Repeat, 30.0, 100.0, LengthPercentage::Px(5.0));
+        check!(repeat.local(4.0) == Some(29.0));
+        let round = AxisTiling::new(BackgroundRepeat::Round, 30.0, 100.0, LengthPercentage::Px(0.0));
+        check!((round.tile - 100.0 / 3.0).abs() < 1e-9);
+        let space = AxisTiling::new(BackgroundRepeat::Space, 30.0, 100.0, LengthPercentage::Px(0.0));
+        check!(space.local(30.0) == None);
+        check!(space.local(35.0) == Some(0.0));
+        check!(space.local(99.0) == Some(29.0));
+    }
+
+    #[test]
+    fn gradient_layer_over_color() {
+        use super::super::gradient::{ColorStop, Gradient};
+        let mut style = BoxStyle::with_background(Colors::WHITE);
+        let gradient = Gradient::linear(
+            90.0,
+            vec![ColorStop::new(RED, None), ColorStop::new(Colors::TRANSPARENT, None)],
+        );
+        style
+            .background_layers
+            .push(BackgroundLayer::new(BackgroundImage::Gradient(gradient)));
+        let data = paint(100, 1, Rect::from_u32(0, 0, 100, 1), &style);
+        let left = pixel(&data, 100, 0, 0);
+        let right = pixel(&data, 100, 99, 0);
+        check!(left[0] == 255 && left[1] <= 3);
+        check!(right[1] >= 250 && right[3] == 255);
+        // Halfway the red is half transparent over white
+        let middle = pixel(&data, 100, 50, 0);
+        check!(middle[1] > 120 && middle[1] < 135);
+    }
+
+    #[test]
+    fn solid_border_ring() {
+        let mut style = BoxStyle::with_background(Colors::WHITE);
+        style.borders = Borders::all(BorderSide::new(2.0, BorderStyle::Solid, RED));
+        let data = paint(10, 10, Rect::from_u32(0, 0, 10, 10), &style);
+        check!(pixel(&data, 10, 0, 5) == [255, 0, 0, 255]);
+        check!(pixel(&data, 10, 1, 5) == [255, 0, 0, 255]);
+        check!(pixel(&data, 10, 2, 5) == [255, 255, 255, 255]);
+        check!(pixel(&data, 10, 9, 9) == [255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn dashed_border_has_gaps() {
+        let mut style = BoxStyle::default();
+        style.borders.top = BorderSide::new(2.0, BorderStyle::Dashed, RED);
+        let data = paint(48, 4, Rect::from_u32(0, 0, 48, 4), &style);
+        let painted = (0..48).filter(|x| pixel(&data, 48, *x, 0)[3] > 0).count();
+        check!(painted == 24);
+    }
+
+    #[test]
+    fn inset_border_shades() {
+        let style = BoxStyle {
+            borders: Borders::all(BorderSide::new(3.0, BorderStyle::Inset, RED)),
+            ..BoxStyle::default()
+        };
+        let data = paint(12, 12, Rect::from_u32(0, 0, 12, 12), &style);
+        check!(pixel(&data, 12, 6, 0) == [170, 0, 0, 255]);
+        check!(pixel(&data, 12, 6, 11) == [255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn outer_shadow_stays_outside() {
+        let mut style = BoxStyle::default();
+        style.box_shadows.push(BoxShadow {
+            offset: Position { left: 0.0, top: 0.0 },
+            blur: 4.0,
+            spread: 0.0,
+            color: Colors::BLACK,
+            inset: false,
+        });
+        let data = paint(30, 30, Rect::from_u32(10, 10, 10, 10), &style);
+        check!(pixel(&data, 30, 15, 15)[3] == 0);
+        let near = pixel(&data, 30, 9, 15)[3];
+        let far = pixel(&data, 30, 6, 15)[3];
+        check!(near > far);
+        check!(far > 0);
+        check!(pixel(&data, 30, 0, 0)[3] == 0);
+    }
+
+    #[test]
+    fn shadows_in_several_clips() {
+        let mut style = BoxStyle::with_background(RED);
+        for inset in [false, true] {
+            style.box_shadows.push(BoxShadow {
+                offset: Position { left: 2.0, top: 1.0 },
+                blur: 4.0,
+                spread: 1.0,
+                color: Colors::BLACK,
+                inset,
+            });
+        }
+        let border_box = Rect::from_u32(10, 10, 10, 10);
+        let whole = paint(30, 30, border_box, &style);
+        let clips = [
+            Rect::from_u32(0, 0, 30, 12),
+            Rect::from_u32(0, 12, 14, 18),
+            Rect::from_u32(14, 12, 16, 18),
+        ];
+        let mut data = vec![0u8; 30 * 30 * 4];
+        {
+            let mut surface = Surface::new(&mut data, 30, 30);
+            paint_box_clips(&mut surface, &border_box, &style, &clips);
+        }
+        check!(data == whole);
+    }
+
+    #[test]
+    fn inset_shadow_stays_inside() {
+        let mut style = BoxStyle::default();
+        style.box_shadows.push(BoxShadow {
+            offset: Position { left: 0.0, top: 0.0 },
+            blur: 0.0,
+            spread: 2.0,
+            color: Colors::BLACK,
+            inset: true,
+        });
+        let data = paint(20, 20, Rect::from_u32(5, 5, 10, 10), &style);
+        check!(pixel(&data, 20, 4, 10)[3] == 0);
+        check!(pixel(&data, 20, 5, 10)[3] == 255);
+        check!(pixel(&data, 20, 10, 10)[3] == 0);
+    }
+
+    #[test]
+    fn outline_outside_border_box() {
+        let mut style = BoxStyle::default();
+        style.outline = Some(super::super::style::Outline {
+            side: BorderSide::new(1.0, BorderStyle::Solid, RED),
+            offset: 1.0,
+        });
+        let data = paint(12, 12, Rect::from_u32(2, 2, 8, 8), &style);
+        check!(pixel(&data, 12, 0, 5) == [255, 0, 0, 255]);
+        check!(pixel(&data, 12, 1, 5)[3] == 0);
+        check!(pixel(&data, 12, 2, 5)[3] == 0);
+    }
+}
@@ -0,0 +1,89 @@
+#![allow(dead_code)]
+#![allow(unused_imports)]
+
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+use log::{debug, error, info, warn};
+use url::Url;
+
+use super::app_scheme::{self, SchemeRequest, SchemeResponse};
+use super::history_db;
+use super::platform_window::MainWindow;
+use super::profile::Profile;
+use super::window_manager::SharedState;
+
+/// The browser as a library: the profile, network and URL schemes its windows share.
+#[derive(Debug, Clone)]
+pub struct Browser {
+    shared: SharedState,
+}
+
+impl Browser {
+    /// A browser remembering settings, history and bookmarks in `profile_dir`, nothing without one.
+    pub fn new(profile_dir: Option<&Path>) -> Self {
+        let profile = profile_dir.map(Profile::new);
+        match &profile {
+            Some(profile) => info!("Profile in {:?}", profile.dir()),
+            None => warn!("No profile directory: nothing will be remembered"),
+        }
+        Self {
+            shared: SharedState::load(profile.as_ref()),
+        }
+    }
+
+    pub(crate) fn shared(&self) -> &SharedState {
+        &self.shared
+    }
+
+    /// `$XDG_DATA_HOME/rowser`, falling back to `~/.local/share/rowser`.
+    pub fn default_profile_dir() -> Option<PathBuf> {
+        Profile::default_dir()
+    }
+
+    /// Add the bookmarks of a bookmark HTML file exported by another browser. Returns how many were added.
+    pub fn import_bookmarks(&self, path: &Path) -> Result<usize, Box<dyn Error>> {
+        Ok(self
+            .shared
+            .bookmarks
+            .borrow_mut()
+            .import_file(path, history_db::now())?)
+    }
+
+    pub fn export_bookmarks(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        Ok(self.shared.bookmarks.borrow().export_file(path)?)
+    }
+
+    /// Load pages only from the HTTP cache, never from the network.
+    pub fn set_offline(&self, offline: bool) {
+        self.shared.http.lock().unwrap().offline = offline;
+    }
+
+    /// The page new windows open, None for `about:blank`.
+    pub fn home_page(&self) -> Option<Url> {
+        Some(self.shared.settings.borrow().home_page.clone()).filter(|url| url.as_str() != "about:blank")
+    }
+
+    /// Serve the URLs of `scheme`, as `app://`, with `callback`, called on the threads loading them.
+    ///   The schemes the browser loads itself can't be registered.
+    pub fn register_scheme<F>(&self, scheme: &str, callback: F) -> Result<(), Box<dyn Error>>
+    where
+        F: Fn(&SchemeRequest) -> SchemeResponse + Send + Sync + 'static,
+    {
+        Ok(app_scheme::register(
+            &mut self.shared.loader.borrow_mut(),
+            scheme,
+            callback,
+        )?)
+    }
+
+    /// Stop serving the URLs of `scheme`. Returns false if no callback served them.
+    pub fn unregister_scheme(&self, scheme: &str) -> bool {
+        app_scheme::unregister(&mut self.shared.loader.borrow_mut(), scheme)
+    }
+
+    /// Open a window at `url` and handle events until the last window closes.
+    pub fn run(&self, url: Option<Url>) -> Result<(), Box<dyn Error>> {
+        MainWindow::new(url, self.shared.clone()).run()
+    }
+}
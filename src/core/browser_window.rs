@@ -0,0 +1,1349 @@
+#![allow(dead_code)]
+#![allow(unused_imports)]
+
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+
+use log::{debug, error, info, warn};
+use url::Url;
+
+use crate::fixme;
+
+use super::bookmarks::Bookmarks;
+use super::chrome::{
+    self, ChromeHit, Suggestion, ToolbarLayout, ToolbarState, MAX_SUGGESTIONS, STATUS_BAR_HEIGHT, TOOLBAR_HEIGHT,
+};
+use super::config_page::ConfigPage;
+use super::cookie_page::{CookieHit, CookiePage};
+use super::dom::window_events::{Event, EventHandler, Key, Modifiers, MouseButton, WheelDelta};
+use super::dom::{self, DomRoot};
+use super::error_page::ErrorPage;
+use super::fetcher::Fetcher;
+use super::geometry::{IntSize, Position, Rect};
+use super::gfxbuffer::GfxBuffer;
+use super::history_db::{self, ClearRange, HistoryDb};
+use super::http::{HttpClient, Request, Response};
+use super::http_cache::CacheMode;
+use super::loader::Loader;
+use super::proxy::ProxyConfig;
+use super::region::Region;
+use super::simple_error::SimpleError;
+use super::session_history::{self, SessionHistory};
+use super::settings::Settings;
+use super::sidebar::{self, SidebarLayout, SIDEBAR_WIDTH};
+use super::source_view::SourceView;
+use super::tab_strip::{TabStripHit, TabStripLayout, TAB_STRIP_HEIGHT};
+use super::text_field::TextField;
+use super::window_manager::SharedState;
+use super::zoom::{self, ZoomMap};
+
+/// Documents kept alive per tab for instant back and forward.
+const BFCACHE_CAPACITY: usize = 3;
+/// CSS pixels scrolled per wheel line on `view-source:` pages, as in documents
+const SOURCE_SCROLL_LINE: f64 = 40.0;
+
+/// A load of a tab: its resource is fetched off the event loop, then the document is built on the frame
+///   after it arrives.
+#[derive(Debug, Clone, PartialEq)]
+enum Load {
+    Navigate(Url),
+    /// Go to this entry of the tab history
+    History(usize),
+    /// Load the document again, revalidating the cached copy, or ignoring it for a hard reload
+    Reload { bypass_cache: bool },
+}
+
+impl Load {
+    fn cache_mode(&self) -> CacheMode {
+        match self {
+            Load::Navigate(_) => CacheMode::Default,
+            // Back and forward show what was seen, even stale
+            Load::History(_) => CacheMode::ForceCache,
+            Load::Reload { bypass_cache: false } => CacheMode::NoCache,
+            Load::Reload { bypass_cache: true } => CacheMode::Reload,
+        }
+    }
+}
+
+/// A tab: the document it shows and its session history.
+#[derive(Debug)]
+pub struct Tab {
+    pub document: DomRoot,
+    history: SessionHistory<DomRoot>,
+    loading: Option<Load>,
+    /// Number of the fetch of `loading`, once started
+    fetch: Option<u64>,
+    /// Shown instead of the document when it could not be fetched
+    error: Option<ErrorPage>,
+    /// Shown instead of the document at `view-source:` URLs
+    source: Option<SourceView>,
+}
+
+impl Tab {
+    fn new(document: DomRoot) -> Self {
+        let mut history = SessionHistory::new(BFCACHE_CAPACITY);
+        if let Some(url) = document.url() {
+            history.push(url.clone());
+        }
+        Self {
+            document,
+            history,
+            loading: None,
+            fetch: None,
+            error: None,
+            source: None,
+        }
+    }
+
+    pub fn can_go_back(&self) -> bool {
+        self.history.can_go_back()
+    }
+
+    pub fn can_go_forward(&self) -> bool {
+        self.history.can_go_forward()
+    }
+
+    /// `history.pushState`: a new entry for the current document, without loading.
+    pub fn push_state(&mut self, state: Option<String>, url: Option<Url>) -> Result<(), SimpleError> {
+        let url = match url.or_else(|| self.document.url().cloned()) {
+            Some(url) => url,
+            None => return Err(SimpleError::new("No document to push a state for")),
+        };
+        self.history.set_scroll(self.document.scroll_position());
+        self.history.push_same_document(url.clone(), state)?;
+        self.document.set_url(url);
+        Ok(())
+    }
+
+    /// `history.replaceState`
+    pub fn replace_state(&mut self, state: Option<String>, url: Option<Url>) -> Result<(), SimpleError> {
+        let url = match url.or_else(|| self.document.url().cloned()) {
+            Some(url) => url,
+            None => return Err(SimpleError::new("No document to replace the state of")),
+        };
+        self.history.replace_current(url.clone(), state)?;
+        self.document.set_url(url);
+        Ok(())
+    }
+
+    /// `history.state` of the current entry.
+    pub fn state(&self) -> Option<&str> {
+        self.history.current().and_then(|entry| entry.state.as_deref())
+    }
+
+    pub fn is_loading(&self) -> bool {
+        self.loading.is_some()
+    }
+
+    /// URL of the address bar: the one being loaded, or the one of the document.
+    pub fn display_url(&self) -> Option<Url> {
+        match &self.loading {
+            Some(Load::Navigate(url)) => Some(url.clone()),
+            Some(Load::History(index)) => self.history.entry(*index).map(|entry| entry.url.clone()),
+            _ => self.document.url().cloned(),
+        }
+    }
+
+    pub fn title(&self) -> String {
+        if self.is_loading() {
+            return String::from("Loading...");
+        }
+        if let Some(title) = self.document.title() {
+            return String::from(title);
+        }
+        match self.document.url() {
+            None => String::from("New Tab"),
+            Some(url) => match url.host_str() {
+                Some(host) => String::from(host),
+                None => url
+                    .path_segments()
+                    .and_then(|mut segments| segments.next_back())
+                    .filter(|name| !name.is_empty())
+                    .map_or_else(|| String::from(url.as_str()), String::from),
+            },
+        }
+    }
+}
+
+/// Everything painted in one platform window: the tab strip, the toolbar, the document of the active tab
+///   and the status bar.
+#[derive(Debug)]
+pub struct BrowserWindow {
+    window: Rc<winit::window::Window>,
+    gfx_buffer: GfxBuffer,
+    tabs: Vec<Tab>,
+    active_tab: usize,
+    /// Shared by all the windows
+    zoom_map: Rc<RefCell<ZoomMap>>,
+    history: Rc<RefCell<HistoryDb>>,
+    bookmarks: Rc<RefCell<Bookmarks>>,
+    settings: Rc<RefCell<Settings>>,
+    /// Shared by every document, keeping connections alive across them
+    http: Arc<Mutex<HttpClient>>,
+    loader: Rc<RefCell<Loader>>,
+    /// Fetches the documents of the tabs, numbered by `next_fetch`
+    fetcher: Fetcher<(u64, Result<Response, SimpleError>)>,
+    next_fetch: u64,
+    /// Shown instead of the document of tabs at `about:config`. `about:cookies` is built from the jar when painted.
+    config_page: ConfigPage,
+    /// The bookmarks sidebar is shown on the left of the document
+    sidebar_open: bool,
+    scale_factor: f64,
+    window_size: IntSize,
+    /// Damage of the browser UI, in device pixels
+    invalidated_ui: Region,
+    /// Cursor position in device pixels
+    cursor_position: Option<Position>,
+    window_focused: bool,
+    close_requested: bool,
+    /// URLs to open in new windows, taken by the window manager
+    window_requests: Vec<Option<Url>>,
+    address: TextField,
+    /// Keyboard input goes to the address bar instead of the document
+    address_focused: bool,
+    /// History pages matching the address bar, shown under it while typing
+    suggestions: Vec<Suggestion>,
+    selected_suggestion: Option<usize>,
+    /// Shown in the status bar when no link is hovered and nothing loads
+    message: Option<String>,
+    status: String,
+    /// The next key chooses the time range of history to clear
+    clearing_history: bool,
+}
+
+impl BrowserWindow {
+    pub fn new(window: Rc<winit::window::Window>, url: Option<Url>, shared: SharedState) -> Self {
+        let size = window.inner_size();
+        let mut browser = Self {
+            gfx_buffer: GfxBuffer::new(window.clone()),
+            tabs: vec![],
+            active_tab: 0,
+            zoom_map: shared.zoom_map,
+            history: shared.history,
+            bookmarks: shared.bookmarks,
+            settings: shared.settings,
+            http: shared.http,
+            loader: shared.loader,
+            fetcher: Fetcher::new(),
+            next_fetch: 0,
+            config_page: ConfigPage::new(),
+            sidebar_open: false,
+            scale_factor: window.scale_factor(),
+            window_size: IntSize::new(size.width, size.height),
+            invalidated_ui: Region::new(),
+            cursor_position: None,
+            window_focused: true,
+            close_requested: false,
+            window_requests: vec![],
+            window,
+            address: TextField::new(),
+            address_focused: false,
+            suggestions: vec![],
+            selected_suggestion: None,
+            message: None,
+            status: String::new(),
+            clearing_history: false,
+        };
+        browser.open_tab(url);
+        browser
+    }
+
+    pub fn window_id(&self) -> winit::window::WindowId {
+        self.window.id()
+    }
+
+    /// Ask for `url` to be shown in a new window, e.g. for `target=_blank` or Ctrl+N.
+    pub fn request_window(&mut self, url: Option<Url>) {
+        self.window_requests.push(url);
+    }
+
+    pub fn take_window_requests(&mut self) -> Vec<Option<Url>> {
+        std::mem::take(&mut self.window_requests)
+    }
+
+    /// True once the last tab is closed or the window close button is pressed.
+    pub fn close_requested(&self) -> bool {
+        self.close_requested
+    }
+
+    pub fn tab_count(&self) -> usize {
+        self.tabs.len()
+    }
+
+    pub fn active_tab(&self) -> &Tab {
+        &self.tabs[self.active_tab]
+    }
+
+    fn active_document(&mut self) -> &mut DomRoot {
+        &mut self.tabs[self.active_tab].document
+    }
+
+    /// Tab strip height in device pixels.
+    fn strip_height(&self) -> f64 {
+        (TAB_STRIP_HEIGHT * self.scale_factor).round()
+    }
+
+    /// Tab strip and toolbar height in device pixels.
+    fn chrome_height(&self) -> f64 {
+        ((TAB_STRIP_HEIGHT + TOOLBAR_HEIGHT) * self.scale_factor).round()
+    }
+
+    fn status_bar_height(&self) -> f64 {
+        (STATUS_BAR_HEIGHT * self.scale_factor).round()
+    }
+
+    /// Area of the window showing the active document, in device pixels.
+    fn content_viewport(&self) -> Rect {
+        let top = self.chrome_height();
+        let left = if self.sidebar_open {
+            (SIDEBAR_WIDTH * self.scale_factor).round().min(self.window_size.width as f64)
+        } else {
+            0.0
+        };
+        Rect::new(
+            left,
+            top,
+            self.window_size.width as f64 - left,
+            (self.window_size.height as f64 - top - self.status_bar_height()).max(0.0),
+        )
+    }
+
+    fn css_width(&self) -> f64 {
+        self.window_size.width as f64 / self.scale_factor
+    }
+
+    fn strip_layout(&self) -> TabStripLayout {
+        TabStripLayout::new(self.tabs.len(), self.css_width())
+    }
+
+    fn toolbar_layout(&self) -> ToolbarLayout {
+        ToolbarLayout::new(TAB_STRIP_HEIGHT, self.css_width())
+    }
+
+    fn sidebar_layout(&self) -> SidebarLayout {
+        let content = self.content_viewport().scale(1.0 / self.scale_factor);
+        SidebarLayout::new(content.top(), content.height())
+    }
+
+    /// Status bar area in CSS pixels.
+    fn status_bar_bounds(&self) -> Rect {
+        let height = self.status_bar_height();
+        Rect::new(
+            0.0,
+            (self.window_size.height as f64 - height).max(0.0),
+            self.window_size.width as f64,
+            height,
+        )
+        .scale(1.0 / self.scale_factor)
+    }
+
+    fn invalidate_ui(&mut self) {
+        let width = self.window_size.width as f64;
+        let chrome = Rect::new(0.0, 0.0, width, self.chrome_height());
+        let status = self.status_bar_bounds().scale(self.scale_factor);
+        self.invalidated_ui.union_rect(&chrome);
+        self.invalidated_ui.union_rect(&status);
+        if self.sidebar_open {
+            let sidebar = self.sidebar_layout().bounds.scale(self.scale_factor);
+            self.invalidated_ui.union_rect(&sidebar);
+        }
+        if self.showing_config() || self.showing_cookies() || self.showing_error() || self.showing_source() {
+            let content = self.content_viewport();
+            self.invalidated_ui.union_rect(&content);
+        }
+        if !self.suggestions.is_empty() {
+            let suggestions = self.suggestions_bounds();
+            self.invalidated_ui.union_rect(&suggestions);
+        }
+    }
+
+    /// Suggestions drop-down area in device pixels.
+    fn suggestions_bounds(&self) -> Rect {
+        self.toolbar_layout()
+            .suggestions_bounds(self.suggestions.len())
+            .scale(self.scale_factor)
+    }
+
+    fn create_document(&self, url: Option<Url>) -> DomRoot {
+        let zoom = url.as_ref().map_or(1.0, |url| self.zoom_map.borrow().get(url));
+        DomRoot::new(self.content_viewport(), self.scale_factor, url, zoom, self.settings.clone(), self.loader.clone())
+    }
+
+    /// Open a tab after the active one and select it. The URL loads on the next frame.
+    pub fn open_tab(&mut self, url: Option<Url>) -> usize {
+        let mut tab = Tab::new(self.create_document(None));
+        let blank = url.is_none();
+        tab.loading = url.map(Load::Navigate);
+        let index = if self.tabs.is_empty() {
+            0
+        } else {
+            self.active_tab + 1
+        };
+        self.tabs.insert(index, tab);
+        self.activate_tab(index);
+        if blank {
+            self.focus_address();
+        }
+        index
+    }
+
+    /// Close a tab. Closing the last one closes the window.
+    pub fn close_tab(&mut self, index: usize) {
+        if index >= self.tabs.len() {
+            return;
+        }
+        self.tabs.remove(index);
+        if self.tabs.is_empty() {
+            self.close_requested = true;
+            return;
+        }
+        let active = if index < self.active_tab || self.active_tab >= self.tabs.len() {
+            self.active_tab.saturating_sub(1)
+        } else {
+            self.active_tab
+        };
+        self.activate_tab(active);
+    }
+
+    pub fn select_tab(&mut self, index: usize) {
+        if index >= self.tabs.len() || index == self.active_tab {
+            return;
+        }
+        self.activate_tab(index);
+    }
+
+    fn activate_tab(&mut self, index: usize) {
+        debug!("Select tab {} of {}", index, self.tabs.len());
+        self.active_tab = index;
+        let viewport = self.content_viewport();
+        let focused = self.window_focused;
+        let document = self.active_document();
+        // Background tabs don't track the window: catch up before painting
+        document.set_viewport(viewport);
+        document.on_window_event(&Event::Focused { focus: focused });
+        document.redraw_requested();
+        self.blur_address();
+    }
+
+    /// Show `url` in the active tab, dropping the forward history.
+    ///   Changing only the fragment scrolls the current document instead of loading.
+    pub fn navigate(&mut self, url: Url) {
+        debug!("Navigate to {}", url);
+        let tab = &mut self.tabs[self.active_tab];
+        let same_document = tab
+            .document
+            .url()
+            .is_some_and(|current| session_history::is_fragment_navigation(current, &url));
+        if same_document && !tab.is_loading() {
+            tab.history.set_scroll(tab.document.scroll_position());
+            if let Err(err) = tab.history.push_same_document(url.clone(), None) {
+                error!("{}", err);
+                return;
+            }
+            tab.document.set_url(url.clone());
+            tab.document.scroll_to_fragment(url.fragment().unwrap_or(""));
+            self.message = None;
+            self.blur_address();
+            return;
+        }
+        self.start_load(Load::Navigate(url));
+    }
+
+    /// Move in the history of the active tab by `delta` entries.
+    ///   Entries of the current document and documents still in the back-forward cache show at once.
+    pub fn go(&mut self, delta: isize) {
+        let tab = &mut self.tabs[self.active_tab];
+        let index = match tab.history.offset(delta) {
+            Some(index) => index,
+            None => return,
+        };
+        tab.loading = None;
+        tab.fetch = None;
+        tab.history.set_scroll(tab.document.scroll_position());
+        if tab.history.is_same_document(index) {
+            tab.history.go_to(index);
+            let entry = tab.history.current().unwrap().clone();
+            tab.document.set_url(entry.url);
+            tab.document.scroll_to(entry.scroll);
+            fixme!("Fire popstate and hashchange");
+            self.blur_address();
+            return;
+        }
+        // Source views are painted from a response the cache does not keep
+        let cacheable = tab
+            .history
+            .entry(index)
+            .is_some_and(|entry| entry.url.scheme() != "view-source");
+        if let Some(cached) = tab.history.take_cached(index).filter(|_| cacheable) {
+            debug!("Back-forward cache hit for entry {}", index);
+            let previous = std::mem::replace(&mut tab.document, cached);
+            tab.history.cache_current(previous);
+            tab.error = None;
+            tab.source = None;
+            tab.history.go_to(index);
+            // The window may have changed while the document was cached
+            tab.document.set_scale_factor(self.scale_factor);
+            let url = tab.document.url().cloned();
+            let title = String::from(tab.document.title().unwrap_or(""));
+            self.activate_tab(self.active_tab);
+            if let Some(url) = url {
+                self.record_visit(&url, &title);
+            }
+            return;
+        }
+        self.start_load(Load::History(index));
+    }
+
+    pub fn reload(&mut self) {
+        if self.active_tab().document.url().is_some() {
+            self.start_load(Load::Reload { bypass_cache: false });
+        }
+    }
+
+    /// Reload without using the cached copy of the document.
+    pub fn hard_reload(&mut self) {
+        if self.active_tab().document.url().is_some() {
+            self.start_load(Load::Reload { bypass_cache: true });
+        }
+    }
+
+    /// Cancel the load of the active tab, keeping the current document.
+    pub fn stop(&mut self) {
+        let tab = &mut self.tabs[self.active_tab];
+        tab.loading = None;
+        tab.fetch = None;
+        self.blur_address();
+    }
+
+    fn start_load(&mut self, load: Load) {
+        let tab = &mut self.tabs[self.active_tab];
+        tab.loading = Some(load);
+        tab.fetch = None;
+        self.message = None;
+        self.blur_address();
+    }
+
+    /// Start fetching the documents of the tabs waiting for one, and build the documents fetched.
+    ///   Returns true if some tab loaded.
+    fn load_pending(&mut self) -> bool {
+        let mut loaded = false;
+        let mut visited = vec![];
+        let mut finished = self.fetcher.take_finished();
+        for index in 0..self.tabs.len() {
+            let tab = &self.tabs[index];
+            let load = match &tab.loading {
+                Some(load) => load.clone(),
+                None => continue,
+            };
+            let url = match &load {
+                Load::Navigate(url) => url.clone(),
+                Load::History(history_index) => tab.history.entry(*history_index).unwrap().url.clone(),
+                Load::Reload { .. } => match tab.document.url() {
+                    Some(url) => url.clone(),
+                    None => {
+                        self.tabs[index].loading = None;
+                        continue;
+                    }
+                },
+            };
+            let fetched = match tab.fetch {
+                None if self.loader.borrow().handles(url.scheme()) => {
+                    info!("Load {}", url);
+                    let fetch = self.start_fetch(&url, load.cache_mode());
+                    self.tabs[index].fetch = Some(fetch);
+                    continue;
+                }
+                None => Ok(None),
+                Some(fetch) => match finished.iter().position(|(number, _)| *number == fetch) {
+                    Some(position) => self.fetched_document(&url, finished.swap_remove(position).1),
+                    None => continue,
+                },
+            };
+            let mut document = self.create_document(Some(url.clone()));
+            let response = fetched.as_ref().ok().and_then(Option::as_ref);
+            document.set_title(response.filter(|_| url.scheme() != "view-source").and_then(dom::response_title));
+            if index == self.active_tab {
+                document.on_window_event(&Event::Focused { focus: self.window_focused });
+            }
+            let tab = &mut self.tabs[index];
+            tab.loading = None;
+            tab.fetch = None;
+            tab.history.set_scroll(tab.document.scroll_position());
+            let previous = std::mem::replace(&mut tab.document, document);
+            tab.error = fetched.as_ref().err().map(|page| (**page).clone());
+            tab.source = match &fetched {
+                Ok(Some(response)) if url.scheme() == "view-source" => Some(SourceView::new(response)),
+                _ => None,
+            };
+            let title = String::from(tab.document.title().unwrap_or(""));
+            match load {
+                Load::Navigate(url) => {
+                    tab.history.cache_current(previous);
+                    tab.history.push(url.clone());
+                    visited.push((url.clone(), title));
+                    if let Some(fragment) = url.fragment() {
+                        tab.document.scroll_to_fragment(fragment);
+                    }
+                }
+                Load::History(history_index) => {
+                    tab.history.cache_current(previous);
+                    tab.history.go_to(history_index);
+                    let entry = tab.history.current().unwrap();
+                    tab.document.scroll_to(entry.scroll);
+                    visited.push((entry.url.clone(), title));
+                }
+                Load::Reload { .. } => {
+                    let scroll = previous.scroll_position();
+                    tab.document.scroll_to(scroll);
+                }
+            }
+            loaded = true;
+        }
+        for (url, title) in visited.iter() {
+            self.record_visit(url, title);
+        }
+        if loaded {
+            self.sync_address();
+        }
+        loaded
+    }
+
+    /// Start fetching the resource of a document off the event loop. Returns the number of the fetch,
+    ///   for `load_pending` to find its result.
+    fn start_fetch(&mut self, url: &Url, mode: CacheMode) -> u64 {
+        let mut request = Request::get(url.clone());
+        request.top_level = true;
+        let fetch = self.next_fetch;
+        self.next_fetch += 1;
+        self.fetcher
+            .start(&self.loader.borrow(), request, mode, move |result| (fetch, result));
+        fetch
+    }
+
+    /// The resource of a document, None for schemes without handler as `about:`.
+    ///   A failure gives the page shown instead.
+    fn fetched_document(
+        &self,
+        url: &Url,
+        result: Result<Response, SimpleError>,
+    ) -> Result<Option<Response>, Box<ErrorPage>> {
+        match result {
+            Ok(response) => {
+                debug!(
+                    "{} {} {} ({} bytes{})",
+                    response.url,
+                    response.status,
+                    response.reason,
+                    response.body.len(),
+                    if response.from_cache { ", cached" } else { "" }
+                );
+                fixme!("Build the document from the response");
+                Ok(Some(response))
+            }
+            Err(err) => {
+                error!("Error while loading {}: {}", url, err);
+                let http = self.http.lock().unwrap();
+                let certificate_host = url
+                    .host_str()
+                    .zip(url.port_or_known_default())
+                    .filter(|(host, port)| http.certificate_problem(host, *port).is_some())
+                    .map(|(host, _)| String::from(host));
+                Err(Box::new(ErrorPage::new(url.clone(), &err.to_string(), certificate_host)))
+            }
+        }
+    }
+
+    /// Add `url`, titled `title`, to the browsing history. Internal pages are not recorded.
+    fn record_visit(&mut self, url: &Url, title: &str) {
+        if url.scheme() == "about" {
+            return;
+        }
+        self.history.borrow_mut().record_visit(url, title, history_db::now());
+    }
+
+    /// Bookmark the page of the active tab, or remove its bookmark.
+    fn toggle_bookmark(&mut self) {
+        let url = match self.active_tab().document.url() {
+            Some(url) => url.clone(),
+            None => return,
+        };
+        let title = self.active_tab().title();
+        let added = self.bookmarks.borrow_mut().toggle(&url, &title, history_db::now());
+        self.message = Some(String::from(if added { "Bookmark added" } else { "Bookmark removed" }));
+        self.invalidate_ui();
+    }
+
+    /// True if the active tab is at `about:config`, which the browser paints itself.
+    fn showing_config(&self) -> bool {
+        let tab = self.active_tab();
+        !tab.is_loading() && tab.document.url().is_some_and(|url| url.as_str() == "about:config")
+    }
+
+    /// True if the active tab is at `about:cookies`, which the browser paints itself.
+    fn showing_cookies(&self) -> bool {
+        let tab = self.active_tab();
+        !tab.is_loading() && tab.document.url().is_some_and(|url| url.as_str() == "about:cookies")
+    }
+
+    /// True if the active tab shows the page of a failed load.
+    fn showing_error(&self) -> bool {
+        let tab = self.active_tab();
+        !tab.is_loading() && tab.error.is_some()
+    }
+
+    /// True if the active tab shows the text of a `view-source:` URL.
+    fn showing_source(&self) -> bool {
+        let tab = self.active_tab();
+        !tab.is_loading() && tab.error.is_none() && tab.source.is_some()
+    }
+
+    /// The override button of a certificate error trusts the certificate and loads the page again.
+    fn on_error_page_click(&mut self, position: &Position) {
+        let bounds = self.content_viewport().scale(1.0 / self.scale_factor);
+        let position = self.to_css(position);
+        let origin = match &self.active_tab().error {
+            Some(page) if page.is_override_at(&bounds, &position) => {
+                page.certificate_host.clone().zip(page.url.port_or_known_default())
+            }
+            _ => None,
+        };
+        if let Some((host, port)) = origin {
+            if self.http.lock().unwrap().allow_certificate(&host, port) {
+                self.reload();
+            }
+        }
+    }
+
+    /// Delete the cookie or the site clicked in `about:cookies`.
+    fn on_cookies_click(&mut self, position: &Position) {
+        let bounds = self.content_viewport().scale(1.0 / self.scale_factor);
+        let position = self.to_css(position);
+        let mut http = self.http.lock().unwrap();
+        let hit = CookiePage::new(http.cookies()).hit(&bounds, &position);
+        match hit {
+            Some(CookieHit::DeleteSite(site)) => http.cookies_mut().remove_site(&site),
+            Some(CookieHit::DeleteCookie(cookie)) => http.cookies_mut().remove(&cookie),
+            None => return,
+        }
+        drop(http);
+        self.invalidate_ui();
+    }
+
+    fn on_config_click(&mut self, position: &Position) {
+        let bounds = self.content_viewport().scale(1.0 / self.scale_factor);
+        let position = self.to_css(position);
+        let result = self
+            .config_page
+            .on_click(&mut self.settings.borrow_mut(), &bounds, &position);
+        self.on_settings_edited(result);
+    }
+
+    /// Keys of `about:config` while a value is typed. Returns false for keys it does not use.
+    fn on_config_key(&mut self, key: &Key, modifiers: &Modifiers) -> bool {
+        let result = self
+            .config_page
+            .on_key(&mut self.settings.borrow_mut(), key, modifiers);
+        let handled = *result.as_ref().unwrap_or(&true);
+        self.on_settings_edited(result.map(|_| ()));
+        handled
+    }
+
+    /// Report invalid values and apply the settings the browser window uses. Documents restyle themselves.
+    fn on_settings_edited(&mut self, result: Result<(), SimpleError>) {
+        self.message = match result {
+            Ok(()) => None,
+            Err(err) => Some(err.to_string()),
+        };
+        let default_zoom = self.settings.borrow().default_zoom;
+        self.zoom_map.borrow_mut().set_default_level(default_zoom);
+        let cache_size = self.settings.borrow().cache_size_bytes();
+        if let Some(cache) = self.http.lock().unwrap().cache_mut() {
+            cache.set_max_size(cache_size);
+        }
+        self.http.lock().unwrap().cookies_mut().policy = self.settings.borrow().cookie_policy;
+        let proxies = ProxyConfig::from_settings(&self.settings.borrow());
+        self.http.lock().unwrap().set_proxies(proxies);
+        self.invalidate_ui();
+    }
+
+    pub fn go_home(&mut self) {
+        let home_page = self.settings.borrow().home_page.clone();
+        self.navigate(home_page);
+    }
+
+    fn toggle_sidebar(&mut self) {
+        self.sidebar_open = !self.sidebar_open;
+        let viewport = self.content_viewport();
+        self.active_document().set_viewport(viewport);
+        self.invalidate_ui();
+    }
+
+    fn on_sidebar_click(&mut self, position: &Position) {
+        let rows = sidebar::bookmark_rows(self.bookmarks.borrow().root());
+        let index = self.sidebar_layout().row_at(rows.len(), &self.to_css(position));
+        if let Some(url) = index.and_then(|index| rows[index].url.clone()) {
+            self.navigate(url);
+        }
+    }
+
+    /// Zoom the active document, remember the level for its origin and apply it to its other tabs.
+    fn set_zoom(&mut self, level: f64) {
+        self.active_document().set_zoom(level);
+        let level = self.active_document().zoom();
+        let url = match self.active_document().url() {
+            Some(url) => url.clone(),
+            None => return,
+        };
+        self.zoom_map.borrow_mut().set(&url, level);
+        let origin = url.origin();
+        for tab in self.tabs.iter_mut() {
+            if tab.document.url().is_some_and(|u| u.origin() == origin) {
+                tab.document.set_zoom(level);
+            }
+        }
+    }
+
+    fn focus_address(&mut self) {
+        self.address_focused = true;
+        self.address.select_all();
+        self.invalidate_ui();
+    }
+
+    /// Give the keyboard back to the document, dropping what was typed.
+    fn blur_address(&mut self) {
+        self.address_focused = false;
+        self.set_suggestions(vec![]);
+        self.sync_address();
+    }
+
+    /// Ask in the status bar for the time range of history to clear, chosen by `on_clear_history_key`.
+    fn ask_clear_history(&mut self) {
+        self.blur_address();
+        self.clearing_history = true;
+        self.message = Some(String::from(
+            "Clear the history of: 1 the last hour, 2 the last day, 3 the last week, 4 all time (other keys cancel)",
+        ));
+    }
+
+    /// The key answering `ask_clear_history`. Returns false when no range is asked for.
+    fn on_clear_history_key(&mut self, key: &Key) -> bool {
+        if !self.clearing_history {
+            return false;
+        }
+        let range = match key {
+            // Modifiers still held from the shortcut
+            Key::Other => return true,
+            Key::Char('1') => Some(ClearRange::LastHour),
+            Key::Char('2') => Some(ClearRange::LastDay),
+            Key::Char('3') => Some(ClearRange::LastWeek),
+            Key::Char('4') => Some(ClearRange::All),
+            _ => None,
+        };
+        self.clearing_history = false;
+        match range {
+            Some(range) => self.clear_history(range),
+            None => self.message = Some(String::from("History not cleared")),
+        }
+        true
+    }
+
+    fn clear_history(&mut self, range: ClearRange) {
+        self.history.borrow_mut().clear(range.since(history_db::now()));
+        self.message = Some(format!("History cleared: {:?}", range));
+        self.set_suggestions(vec![]);
+    }
+
+    /// Replace the suggestions drop-down, repainting the document it uncovers.
+    fn set_suggestions(&mut self, suggestions: Vec<Suggestion>) {
+        if !self.suggestions.is_empty() {
+            let previous = self.suggestions_bounds();
+            self.active_document().invalidate_window_rect(&previous);
+        }
+        self.suggestions = suggestions;
+        self.selected_suggestion = None;
+        self.invalidate_ui();
+    }
+
+    /// Look up what is typed in the history. With `complete`, the best match is also completed inline,
+    ///   with the completion selected so typing on replaces it.
+    fn update_suggestions(&mut self, complete: bool) {
+        let input = String::from(self.address.text());
+        let (suggestions, completion) = {
+            let history = self.history.borrow();
+            let records = history.suggest(&input, history_db::now(), MAX_SUGGESTIONS);
+            let completion = records
+                .first()
+                .and_then(|record| history_db::inline_completion(input.trim_start(), &record.url));
+            let suggestions = records
+                .into_iter()
+                .map(|record| Suggestion {
+                    url: record.url.clone(),
+                    title: record.title.clone(),
+                })
+                .collect();
+            (suggestions, completion)
+        };
+        self.set_suggestions(suggestions);
+        let at_end = self.address.cursor() == input.chars().count() && self.address.selection().is_none();
+        if let Some(completion) = completion.filter(|c| complete && at_end && !c.is_empty()) {
+            let typed = input.chars().count();
+            self.address.set_text(&format!("{}{}", input, completion));
+            let end = self.address.cursor();
+            self.address.move_to(typed, false);
+            self.address.move_to(end, true);
+        }
+    }
+
+    /// Highlight the suggestion `delta` rows away and show its URL in the address bar.
+    fn move_suggestion(&mut self, delta: isize) {
+        let count = self.suggestions.len() as isize;
+        let index = match self.selected_suggestion {
+            Some(index) => index as isize + delta,
+            None if delta > 0 => 0,
+            None => count - 1,
+        };
+        self.selected_suggestion = if index < 0 || index >= count {
+            None
+        } else {
+            Some(index as usize)
+        };
+        if let Some(suggestion) = self.selected_suggestion.map(|index| &self.suggestions[index]) {
+            let url = String::from(suggestion.url.as_str());
+            self.address.set_text(&url);
+        }
+        self.invalidate_ui();
+    }
+
+    fn open_suggestion(&mut self, index: usize) {
+        let url = self.suggestions[index].url.clone();
+        self.navigate(url);
+    }
+
+    /// Show the URL of the active tab, unless the user is typing.
+    fn sync_address(&mut self) {
+        if !self.address_focused {
+            let url = self.active_tab().display_url();
+            self.address.set_text(url.as_ref().map_or("", |url| url.as_str()));
+        }
+        self.invalidate_ui();
+    }
+
+    /// Go where the address bar says.
+    fn commit_address(&mut self) {
+        let input = String::from(self.address.text());
+        let keyword = self.bookmarks.borrow().expand_keyword(&input);
+        match keyword.or_else(|| chrome::fixup_url(&input)) {
+            Some(url) => self.navigate(url),
+            None => {
+                warn!("Not an address: {:?}", input);
+                self.message = Some(format!("Not an address: {}", input.trim()));
+            }
+        }
+    }
+
+    /// Keys of the focused address bar. Returns false for keys it does not use.
+    fn on_address_key(&mut self, key: &Key, modifiers: &Modifiers) -> bool {
+        match key {
+            Key::Enter => match self.selected_suggestion {
+                Some(index) => self.open_suggestion(index),
+                None => self.commit_address(),
+            },
+            Key::Escape if !self.suggestions.is_empty() => self.set_suggestions(vec![]),
+            Key::Escape => self.blur_address(),
+            Key::Down if !self.suggestions.is_empty() => self.move_suggestion(1),
+            Key::Up if !self.suggestions.is_empty() => self.move_suggestion(-1),
+            _ => {
+                let text = String::from(self.address.text());
+                if !self.address.on_key(key, modifiers) {
+                    return false;
+                }
+                if self.address.text() != text {
+                    // Deleting the completion must not bring it back
+                    self.update_suggestions(false);
+                }
+            }
+        }
+        self.invalidate_ui();
+        true
+    }
+
+    /// Window shortcuts. Returns false for keys the document should get.
+    fn on_shortcut(&mut self, key: &Key, modifiers: &Modifiers) -> bool {
+        if !modifiers.primary() {
+            match key {
+                Key::F5 if modifiers.shift => self.hard_reload(),
+                Key::F5 => self.reload(),
+                Key::F6 => self.focus_address(),
+                Key::Home if modifiers.alt => self.go_home(),
+                Key::Left if modifiers.alt => self.go(-1),
+                Key::Right if modifiers.alt => self.go(1),
+                Key::Escape if self.active_tab().is_loading() => self.stop(),
+                _ => return false,
+            }
+            return true;
+        }
+        let zoom = self.active_document().zoom();
+        match key {
+            Key::Char('t') => {
+                self.open_tab(None);
+            }
+            Key::Char('n') => self.request_window(None),
+            Key::Char('w') => self.close_tab(self.active_tab),
+            Key::Char('l') => self.focus_address(),
+            Key::Char('r') if modifiers.shift => self.hard_reload(),
+            Key::Char('r') => self.reload(),
+            Key::Char('d') => self.toggle_bookmark(),
+            Key::Char('b') => self.toggle_sidebar(),
+            Key::Delete if modifiers.shift => self.ask_clear_history(),
+            Key::Tab | Key::PageDown | Key::PageUp => {
+                let count = self.tabs.len();
+                let backward = (*key == Key::Tab && modifiers.shift) || *key == Key::PageUp;
+                let step = if backward { count - 1 } else { 1 };
+                self.select_tab((self.active_tab + step) % count);
+            }
+            Key::Char(digit @ '1'..='8') => self.select_tab(*digit as usize - '1' as usize),
+            Key::Char('9') => self.select_tab(self.tabs.len() - 1),
+            Key::Plus | Key::Equals => self.set_zoom(zoom::zoom_in(zoom)),
+            Key::Minus => self.set_zoom(zoom::zoom_out(zoom)),
+            Key::Char('0') => {
+                let level = self.zoom_map.borrow().default_level();
+                self.set_zoom(level);
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    fn to_css(&self, position: &Position) -> Position {
+        Position {
+            left: position.left / self.scale_factor,
+            top: position.top / self.scale_factor,
+        }
+    }
+
+    fn on_strip_click(&mut self, position: &Position) {
+        match self.strip_layout().hit(&self.to_css(position)) {
+            Some(TabStripHit::Tab(index)) => self.select_tab(index),
+            Some(TabStripHit::Close(index)) => self.close_tab(index),
+            Some(TabStripHit::NewTab) => {
+                self.open_tab(None);
+            }
+            None => (),
+        }
+    }
+
+    fn on_toolbar_click(&mut self, position: &Position) {
+        let css = self.to_css(position);
+        let layout = self.toolbar_layout();
+        match layout.hit(&css) {
+            Some(ChromeHit::Back) => self.go(-1),
+            Some(ChromeHit::Forward) => self.go(1),
+            Some(ChromeHit::Reload) if self.active_tab().is_loading() => self.stop(),
+            Some(ChromeHit::Reload) => self.reload(),
+            Some(ChromeHit::AddressBar) if !self.address_focused => self.focus_address(),
+            Some(ChromeHit::AddressBar) => {
+                let index = layout.address_index_at(&self.address, &css);
+                self.address.move_to(index, false);
+                self.invalidate_ui();
+            }
+            None => (),
+        }
+    }
+
+    /// Show the hovered link, else the load in progress, else the last message.
+    fn update_status(&mut self) {
+        let hovered = match self.cursor_position {
+            Some(position) if self.content_viewport().contains_point(&position) => {
+                self.active_document().hovered_link()
+            }
+            _ => None,
+        };
+        let status = match (hovered, self.active_tab().display_url()) {
+            (Some(link), _) => String::from(link.url.as_str()),
+            (None, Some(url)) if self.active_tab().is_loading() => format!("Loading {}", url),
+            _ => self.message.clone().unwrap_or_default(),
+        };
+        if status != self.status {
+            self.status = status;
+            let status = self.status_bar_bounds().scale(self.scale_factor);
+            self.invalidated_ui.union_rect(&status);
+        }
+    }
+
+    fn suggestion_at(&self, position: &Position) -> Option<usize> {
+        self.toolbar_layout()
+            .suggestion_at(self.suggestions.len(), &self.to_css(position))
+    }
+
+    /// Follow the link clicked in the active document, if any.
+    fn follow_link(&mut self) {
+        if let Some(link) = self.active_document().take_followed_link() {
+            if link.new_window {
+                self.request_window(Some(link.url));
+            } else {
+                self.navigate(link.url);
+            }
+        }
+    }
+
+    fn on_resize(&mut self) {
+        let size = self.window.inner_size();
+        let size = IntSize::new(size.width, size.height);
+        if size == self.window_size {
+            debug!("Window size is the same, no need to resize");
+            return;
+        }
+        debug!("Resize window to {}x{}", size.width, size.height);
+        self.window_size = size;
+        self.gfx_buffer.resize();
+        let viewport = self.content_viewport();
+        self.active_document().set_viewport(viewport);
+        self.invalidate_ui();
+    }
+
+    pub fn redraw_requested(&mut self) {
+        self.invalidate_ui();
+        self.active_document().redraw_requested();
+    }
+
+    pub fn on_frame(&mut self, elapsed: f64) {
+        let mut painted = false;
+        let ui = std::mem::take(&mut self.invalidated_ui);
+        if !ui.is_empty() {
+            let strip = self.strip_layout();
+            let toolbar = self.toolbar_layout();
+            let titles: Vec<String> = self.tabs.iter().map(Tab::title).collect();
+            let tab = &self.tabs[self.active_tab];
+            let state = ToolbarState {
+                can_go_back: tab.can_go_back(),
+                can_go_forward: tab.can_go_forward(),
+                loading: tab.is_loading(),
+                address: &self.address,
+                address_focused: self.address_focused,
+            };
+            let status_bar = self.status_bar_bounds();
+            let sidebar = self.sidebar_layout();
+            let rows = if self.sidebar_open {
+                sidebar::bookmark_rows(self.bookmarks.borrow().root())
+            } else {
+                vec![]
+            };
+            let current = tab.document.url();
+            let content = self.content_viewport().scale(1.0 / self.scale_factor);
+            let config = self.showing_config();
+            let cookies = self
+                .showing_cookies()
+                .then(|| CookiePage::new(self.http.lock().unwrap().cookies()));
+            let error = tab.error.as_ref().filter(|_| self.showing_error());
+            let source = tab.source.as_ref().filter(|_| self.showing_source());
+            let settings = self.settings.borrow();
+            let mut surface = self.gfx_buffer.surface();
+            for rect in ui.rects() {
+                strip.paint(&mut surface, &titles, self.active_tab, self.scale_factor, &rect);
+                toolbar.paint(&mut surface, &state, self.scale_factor, &rect);
+                chrome::paint_status_bar(&mut surface, &status_bar, &self.status, self.scale_factor, &rect);
+                if self.sidebar_open {
+                    sidebar.paint(&mut surface, &rows, current, self.scale_factor, &rect);
+                }
+                if config {
+                    self.config_page.paint(&mut surface, &settings, &content, self.scale_factor, &rect);
+                }
+                if let Some(page) = &cookies {
+                    page.paint(&mut surface, &content, self.scale_factor, &rect);
+                }
+                if let Some(page) = error {
+                    page.paint(&mut surface, &content, self.scale_factor, &rect);
+                }
+                if let Some(view) = source {
+                    view.paint(&mut surface, &content, self.scale_factor, &rect);
+                }
+            }
+            painted = true;
+        }
+        if !self.showing_config() && !self.showing_cookies() && !self.showing_error() && !self.showing_source() {
+            let document = &mut self.tabs[self.active_tab].document;
+            painted |= document.on_frame(&mut self.gfx_buffer.surface(), elapsed);
+        }
+        // The drop-down is over the document: paint it again over what the document repainted
+        if painted && !self.suggestions.is_empty() {
+            let bounds = self.suggestions_bounds();
+            let toolbar = self.toolbar_layout();
+            let mut surface = self.gfx_buffer.surface();
+            toolbar.paint_suggestions(
+                &mut surface,
+                &self.suggestions,
+                self.selected_suggestion,
+                self.scale_factor,
+                &bounds,
+            );
+        }
+        if painted {
+            if let Err(err) = self.gfx_buffer.render() {
+                error!("Error while rendering on framebuffer: {:?}", err);
+            }
+        }
+        // Start the loads asked for since the last frame, and build the documents fetched
+        if self.load_pending() {
+            self.update_status();
+        }
+    }
+
+    /// True while the window needs frames: animations, loads and UI changes.
+    pub fn is_animating(&mut self) -> bool {
+        !self.invalidated_ui.is_empty()
+            || self.tabs.iter().any(Tab::is_loading)
+            || self.active_document().is_animating()
+    }
+}
+
+impl EventHandler for BrowserWindow {
+    fn on_window_event(&mut self, event: &Event) {
+        match event {
+            Event::Resized => self.on_resize(),
+            Event::CloseRequested => self.close_requested = true,
+            Event::ScaleFactorChanged { scale_factor } => {
+                self.scale_factor = *scale_factor;
+                self.on_resize();
+                let viewport = self.content_viewport();
+                for tab in self.tabs.iter_mut() {
+                    tab.document.set_scale_factor(*scale_factor);
+                }
+                self.active_document().set_viewport(viewport);
+                self.invalidate_ui();
+            }
+            Event::Focused { focus } => {
+                self.window_focused = *focus;
+                self.active_document().on_window_event(event);
+            }
+            Event::CursorMoved { position, device_id } => {
+                self.cursor_position = Some(*position);
+                if self.content_viewport().contains_point(position) {
+                    self.active_document().on_window_event(event);
+                } else {
+                    let device_id = device_id.clone();
+                    self.active_document().on_window_event(&Event::CursorLeft { device_id });
+                }
+            }
+            Event::CursorLeft { .. } => {
+                self.cursor_position = None;
+                self.active_document().on_window_event(event);
+            }
+            Event::MouseInput {
+                pressed, button, ..
+            } => match self.cursor_position {
+                Some(position) if position.top < self.strip_height() => {
+                    if *pressed && *button == MouseButton::Left {
+                        self.on_strip_click(&position);
+                    }
+                }
+                Some(position) if position.top < self.chrome_height() => {
+                    if *pressed && *button == MouseButton::Left {
+                        self.on_toolbar_click(&position);
+                    }
+                }
+                Some(position) if self.sidebar_open && self.sidebar_layout().bounds.contains_point(&self.to_css(&position)) => {
+                    if *pressed && *button == MouseButton::Left {
+                        self.on_sidebar_click(&position);
+                    }
+                }
+                Some(position) if !self.content_viewport().contains_point(&position) => (),
+                Some(position) if self.suggestion_at(&position).is_some() => {
+                    if *pressed && *button == MouseButton::Left {
+                        let index = self.suggestion_at(&position).unwrap();
+                        self.open_suggestion(index);
+                    }
+                }
+                Some(position) if self.showing_error() => {
+                    if *pressed && self.address_focused {
+                        self.blur_address();
+                    }
+                    if *pressed && *button == MouseButton::Left {
+                        self.on_error_page_click(&position);
+                    }
+                }
+                Some(position) if self.showing_config() => {
+                    if *pressed && self.address_focused {
+                        self.blur_address();
+                    }
+                    if *pressed && *button == MouseButton::Left {
+                        self.on_config_click(&position);
+                    }
+                }
+                Some(_) if self.showing_source() => {
+                    if *pressed && self.address_focused {
+                        self.blur_address();
+                    }
+                }
+                Some(position) if self.showing_cookies() => {
+                    if *pressed && self.address_focused {
+                        self.blur_address();
+                    }
+                    if *pressed && *button == MouseButton::Left {
+                        self.on_cookies_click(&position);
+                    }
+                }
+                _ => {
+                    if *pressed && self.address_focused {
+                        self.blur_address();
+                    }
+                    self.active_document().on_window_event(event);
+                    self.follow_link();
+                }
+            },
+            Event::KeyboardInput {
+                pressed, key, modifiers, ..
+            } => {
+                let handled = *pressed
+                    && (self.on_clear_history_key(key)
+                        || (self.address_focused && self.on_address_key(key, modifiers))
+                        || (!self.address_focused
+                            && self.showing_config()
+                            && self.config_page.is_editing()
+                            && self.on_config_key(key, modifiers))
+                        || self.on_shortcut(key, modifiers));
+                if !handled && !self.address_focused {
+                    self.active_document().on_window_event(event);
+                }
+            }
+            Event::ReceivedCharacter { character } => {
+                if !self.address_focused && self.showing_config() && self.config_page.is_editing() {
+                    if !character.is_control() {
+                        self.config_page.insert(&character.to_string());
+                        self.invalidate_ui();
+                    }
+                } else if !self.address_focused {
+                    self.active_document().on_window_event(event);
+                } else if !character.is_control() {
+                    self.address.insert(&character.to_string());
+                    self.update_suggestions(true);
+                }
+            }
+            Event::MouseWheel { delta, modifiers, .. } if modifiers.primary() => {
+                let y = match delta {
+                    WheelDelta::Lines { y, .. } => *y,
+                    WheelDelta::Pixels { y, .. } => *y,
+                };
+                let level = self.active_document().zoom();
+                if y > 0.0 {
+                    self.set_zoom(zoom::zoom_in(level));
+                } else if y < 0.0 {
+                    self.set_zoom(zoom::zoom_out(level));
+                }
+            }
+            Event::MouseWheel { delta, .. } if self.showing_source() => {
+                let y = match delta {
+                    WheelDelta::Lines { y, .. } => y * SOURCE_SCROLL_LINE,
+                    WheelDelta::Pixels { y, .. } => y / self.scale_factor,
+                };
+                let bounds = self.content_viewport().scale(1.0 / self.scale_factor);
+                if let Some(view) = self.tabs[self.active_tab].source.as_mut() {
+                    view.scroll_by(-y, &bounds);
+                }
+                self.invalidate_ui();
+            }
+            _ => self.active_document().on_window_event(event),
+        }
+        self.update_status();
+    }
+}
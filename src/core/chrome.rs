@@ -0,0 +1,443 @@
+#![allow(dead_code)]
+#![allow(unused_imports)]
+
+use std::path::PathBuf;
+
+use log::{debug, error, info, warn};
+use url::Url;
+
+use crate::fixme;
+
+use super::box_painter::paint_box;
+use super::color::Color;
+use super::geometry::{Position, Rect, Size, Transform};
+use super::gfxbuffer::Surface;
+use super::path::{LineCap, LineJoin, Path, StrokeStyle};
+use super::rasterizer;
+use super::style::{BorderSide, BorderStyle, Borders, BoxStyle, CornerRadii};
+use super::tab_strip::{ACTIVE_TAB_COLOR, ICON_COLOR};
+use super::text::{self, draw_text, text_width};
+use super::text_field::TextField;
+
+/// Sizes are in CSS pixels, like the tab strip.
+pub const TOOLBAR_HEIGHT: f64 = 36.0;
+pub const STATUS_BAR_HEIGHT: f64 = 20.0;
+const BUTTON_SIZE: f64 = 28.0;
+const BUTTON_GAP: f64 = 4.0;
+const ADDRESS_BAR_HEIGHT: f64 = 26.0;
+const ADDRESS_PADDING: f64 = 8.0;
+const UI_FONT_SIZE: f64 = 12.0;
+const STATUS_FONT_SIZE: f64 = 10.0;
+const LOADING_BAR_HEIGHT: f64 = 2.0;
+const SUGGESTION_HEIGHT: f64 = 24.0;
+pub const MAX_SUGGESTIONS: usize = 6;
+
+const DISABLED_ICON_COLOR: Color = Color {
+    r: 0x80,
+    g: 0x82,
+    b: 0x86,
+    a: 0xff,
+};
+const ADDRESS_COLOR: Color = Color {
+    r: 0x20,
+    g: 0x21,
+    b: 0x24,
+    a: 0xff,
+};
+const ACCENT_COLOR: Color = Color {
+    r: 0x8a,
+    g: 0xb4,
+    b: 0xf8,
+    a: 0xff,
+};
+const SELECTION_COLOR: Color = Color {
+    r: 0x39,
+    g: 0x5a,
+    b: 0x8c,
+    a: 0xff,
+};
+const STATUS_COLOR: Color = Color {
+    r: 0x29,
+    g: 0x2a,
+    b: 0x2d,
+    a: 0xff,
+};
+
+/// A page from the history offered under the address bar.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Suggestion {
+    pub url: Url,
+    pub title: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChromeHit {
+    Back,
+    Forward,
+    /// Reload, or stop while loading
+    Reload,
+    AddressBar,
+}
+
+/// What the toolbar shows.
+#[derive(Debug)]
+pub struct ToolbarState<'s> {
+    pub can_go_back: bool,
+    pub can_go_forward: bool,
+    pub loading: bool,
+    pub address: &'s TextField,
+    pub address_focused: bool,
+}
+
+/// Where the toolbar controls go, in CSS pixels of the window.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToolbarLayout {
+    pub bounds: Rect,
+    pub back: Rect,
+    pub forward: Rect,
+    pub reload: Rect,
+    pub address_bar: Rect,
+}
+
+impl ToolbarLayout {
+    /// A toolbar `width` wide with its top edge at `top`.
+    pub fn new(top: f64, width: f64) -> Self {
+        let button_top = top + (TOOLBAR_HEIGHT - BUTTON_SIZE) / 2.0;
+        let button = |i: f64| Rect::new(BUTTON_GAP + i * (BUTTON_SIZE + BUTTON_GAP), button_top, BUTTON_SIZE, BUTTON_SIZE);
+        let reload = button(2.0);
+        let address_left = reload.right() + BUTTON_GAP * 2.0;
+        Self {
+            bounds: Rect::new(0.0, top, width, TOOLBAR_HEIGHT),
+            back: button(0.0),
+            forward: button(1.0),
+            reload,
+            address_bar: Rect::new(
+                address_left,
+                top + (TOOLBAR_HEIGHT - ADDRESS_BAR_HEIGHT) / 2.0,
+                (width - address_left - BUTTON_GAP * 2.0).max(0.0),
+                ADDRESS_BAR_HEIGHT,
+            ),
+        }
+    }
+
+    pub fn hit(&self, pt: &Position) -> Option<ChromeHit> {
+        [
+            (self.back, ChromeHit::Back),
+            (self.forward, ChromeHit::Forward),
+            (self.reload, ChromeHit::Reload),
+            (self.address_bar, ChromeHit::AddressBar),
+        ]
+        .iter()
+        .find(|(rect, _)| rect.contains_point(pt))
+        .map(|(_, hit)| *hit)
+    }
+
+    /// Character of the address text under `pt`.
+    pub fn address_index_at(&self, address: &TextField, pt: &Position) -> usize {
+        let x = pt.left - self.address_bar.left() - ADDRESS_PADDING + self.address_scroll(address);
+        text::char_index_at(address.text(), UI_FONT_SIZE, x)
+    }
+
+    /// Horizontal scroll of the address text keeping the caret visible.
+    fn address_scroll(&self, address: &TextField) -> f64 {
+        let visible = self.address_bar.width() - ADDRESS_PADDING * 2.0;
+        (address.cursor() as f64 * UI_FONT_SIZE - visible).max(0.0)
+    }
+
+    /// The drop-down of `count` suggestions, under the address bar and over the document.
+    pub fn suggestions_bounds(&self, count: usize) -> Rect {
+        Rect::new(
+            self.address_bar.left(),
+            self.bounds.bottom(),
+            self.address_bar.width(),
+            count as f64 * SUGGESTION_HEIGHT,
+        )
+    }
+
+    /// Index of the suggestion under `pt`, among `count`.
+    pub fn suggestion_at(&self, count: usize, pt: &Position) -> Option<usize> {
+        let bounds = self.suggestions_bounds(count);
+        if !bounds.contains_point(pt) {
+            return None;
+        }
+        Some((((pt.top - bounds.top()) / SUGGESTION_HEIGHT) as usize).min(count - 1))
+    }
+
+    /// Paint the suggestions drop-down, highlighting the `selected` one.
+    pub fn paint_suggestions(
+        &self,
+        surface: &mut Surface,
+        suggestions: &[Suggestion],
+        selected: Option<usize>,
+        scale: f64,
+        clip: &Rect,
+    ) {
+        let bounds = self.suggestions_bounds(suggestions.len());
+        paint_box(surface, &bounds.scale(scale), &BoxStyle::with_background(ADDRESS_COLOR), clip);
+        let text_width = bounds.width() - ADDRESS_PADDING * 2.0;
+        for (i, suggestion) in suggestions.iter().enumerate() {
+            let row = Rect::new(
+                bounds.left(),
+                bounds.top() + i as f64 * SUGGESTION_HEIGHT,
+                bounds.width(),
+                SUGGESTION_HEIGHT,
+            );
+            if selected == Some(i) {
+                paint_box(surface, &row.scale(scale), &BoxStyle::with_background(SELECTION_COLOR), clip);
+            }
+            let label = if suggestion.title.is_empty() {
+                String::from(suggestion.url.as_str())
+            } else {
+                format!("{} - {}", suggestion.title, suggestion.url)
+            };
+            let label = text::elide(&label, UI_FONT_SIZE, text_width);
+            let origin = Position {
+                left: (row.left() + ADDRESS_PADDING) * scale,
+                top: (row.top() + (SUGGESTION_HEIGHT - UI_FONT_SIZE) / 2.0) * scale,
+            };
+            draw_text(surface, &label, &origin, UI_FONT_SIZE * scale, ICON_COLOR, clip);
+        }
+    }
+
+    /// Paint the toolbar, `scale` device pixels per CSS pixel.
+    pub fn paint(&self, surface: &mut Surface, state: &ToolbarState, scale: f64, clip: &Rect) {
+        paint_box(surface, &self.bounds.scale(scale), &BoxStyle::with_background(ACTIVE_TAB_COLOR), clip);
+
+        let transform = Transform::scale(scale, scale);
+        let mut icon = StrokeStyle::new(1.75);
+        icon.cap = LineCap::Round;
+        icon.join = LineJoin::Round;
+        let icon_color = |enabled: bool| if enabled { ICON_COLOR } else { DISABLED_ICON_COLOR };
+
+        let (back, forward) = (arrow(&self.back, -1.0), arrow(&self.forward, 1.0));
+        rasterizer::stroke_path(surface, &back, &transform, &icon, icon_color(state.can_go_back), clip);
+        rasterizer::stroke_path(surface, &forward, &transform, &icon, icon_color(state.can_go_forward), clip);
+        let reload = if state.loading {
+            cross(&self.reload)
+        } else {
+            reload_arrow(&self.reload)
+        };
+        rasterizer::stroke_path(surface, &reload, &transform, &icon, ICON_COLOR, clip);
+
+        self.paint_address_bar(surface, state, scale, clip);
+
+        if state.loading {
+            let bar = Rect::new(
+                self.bounds.left(),
+                self.bounds.bottom() - LOADING_BAR_HEIGHT,
+                self.bounds.width(),
+                LOADING_BAR_HEIGHT,
+            );
+            paint_box(surface, &bar.scale(scale), &BoxStyle::with_background(ACCENT_COLOR), clip);
+        }
+    }
+
+    fn paint_address_bar(&self, surface: &mut Surface, state: &ToolbarState, scale: f64, clip: &Rect) {
+        let mut style = BoxStyle::with_background(ADDRESS_COLOR);
+        style.border_radius = CornerRadii::uniform(ADDRESS_BAR_HEIGHT / 2.0 * scale);
+        if state.address_focused {
+            style.borders = Borders::all(BorderSide::new(1.5 * scale, BorderStyle::Solid, ACCENT_COLOR));
+        }
+        let bar = self.address_bar.scale(scale);
+        paint_box(surface, &bar, &style, clip);
+
+        let inner = Rect::new(
+            self.address_bar.left() + ADDRESS_PADDING,
+            self.address_bar.top(),
+            (self.address_bar.width() - ADDRESS_PADDING * 2.0).max(0.0),
+            self.address_bar.height(),
+        );
+        let text_clip = match inner.scale(scale).get_intersection_rect(clip) {
+            Some(text_clip) => text_clip,
+            None => return,
+        };
+        let origin = Position {
+            left: inner.left() - self.address_scroll(state.address),
+            top: inner.top() + (inner.height() - UI_FONT_SIZE) / 2.0,
+        };
+        if state.address_focused {
+            if let Some((begin, end)) = state.address.selection() {
+                let selected = Rect::new(
+                    origin.left + begin as f64 * UI_FONT_SIZE,
+                    origin.top - 2.0,
+                    (end - begin) as f64 * UI_FONT_SIZE,
+                    UI_FONT_SIZE + 4.0,
+                );
+                paint_box(surface, &selected.scale(scale), &BoxStyle::with_background(SELECTION_COLOR), &text_clip);
+            }
+        }
+        draw_text(
+            surface,
+            state.address.text(),
+            &Position {
+                left: origin.left * scale,
+                top: origin.top * scale,
+            },
+            UI_FONT_SIZE * scale,
+            ICON_COLOR,
+            &text_clip,
+        );
+        if state.address_focused {
+            let caret = Rect::new(
+                origin.left + state.address.cursor() as f64 * UI_FONT_SIZE,
+                origin.top - 2.0,
+                1.0,
+                UI_FONT_SIZE + 4.0,
+            );
+            paint_box(surface, &caret.scale(scale), &BoxStyle::with_background(ICON_COLOR), &text_clip);
+        }
+    }
+}
+
+/// Paint the status bar in `bounds`, in CSS pixels, showing `status`.
+pub fn paint_status_bar(surface: &mut Surface, bounds: &Rect, status: &str, scale: f64, clip: &Rect) {
+    paint_box(surface, &bounds.scale(scale), &BoxStyle::with_background(STATUS_COLOR), clip);
+    let status = text::elide(status, STATUS_FONT_SIZE, bounds.width() - ADDRESS_PADDING * 2.0);
+    let origin = Position {
+        left: (bounds.left() + ADDRESS_PADDING) * scale,
+        top: (bounds.top() + (bounds.height() - STATUS_FONT_SIZE) / 2.0) * scale,
+    };
+    draw_text(surface, &status, &origin, STATUS_FONT_SIZE * scale, ICON_COLOR, clip);
+}
+
+/// `<` or `>` with a shaft, pointing left for a negative `direction`.
+fn arrow(button: &Rect, direction: f64) -> Path {
+    let (cx, cy) = (button.left() + button.width() / 2.0, button.top() + button.height() / 2.0);
+    let mut path = Path::new();
+    path.move_to(cx - 6.0 * direction, cy)
+        .line_to(cx + 6.0 * direction, cy)
+        .move_to(cx, cy - 6.0)
+        .line_to(cx + 6.0 * direction, cy)
+        .line_to(cx, cy + 6.0);
+    path
+}
+
+fn cross(button: &Rect) -> Path {
+    let (cx, cy) = (button.left() + button.width() / 2.0, button.top() + button.height() / 2.0);
+    let mut path = Path::new();
+    path.move_to(cx - 5.0, cy - 5.0)
+        .line_to(cx + 5.0, cy + 5.0)
+        .move_to(cx + 5.0, cy - 5.0)
+        .line_to(cx - 5.0, cy + 5.0);
+    path
+}
+
+/// Three quarters of a circle ending in an arrow head.
+fn reload_arrow(button: &Rect) -> Path {
+    let (cx, cy) = (button.left() + button.width() / 2.0, button.top() + button.height() / 2.0);
+    let radius = 6.0;
+    let point = |angle: f64| (cx + radius * angle.cos(), cy + radius * angle.sin());
+    let mut path = Path::new();
+    let (x, y) = point(0.0);
+    path.move_to(x, y);
+    for step in 1..=12 {
+        let (x, y) = point(step as f64 / 12.0 * std::f64::consts::PI * 1.5);
+        path.line_to(x, y);
+    }
+    // Head at the end of the arc, which points to the right
+    let (x, y) = point(std::f64::consts::PI * 1.5);
+    path.move_to(x - 1.0, y - 3.5).line_to(x + 2.5, y).line_to(x - 1.0, y + 3.5);
+    path
+}
+
+/// Turn what was typed in the address bar into a URL.
+///   Absolute URLs are kept, paths become `file:` URLs and host names get a scheme.
+pub fn fixup_url(input: &str) -> Option<Url> {
+    let input = input.trim();
+    if input.is_empty() {
+        return None;
+    }
+    if input.starts_with('/') {
+        return Url::from_file_path(input).ok();
+    }
+    if let Some(rest) = input.strip_prefix("~/") {
+        let home = std::env::var_os("HOME")?;
+        return Url::from_file_path(PathBuf::from(home).join(rest)).ok();
+    }
+    if input.contains(char::is_whitespace) {
+        fixme!("Search the web for text that is not an address");
+        return None;
+    }
+    if let Ok(url) = Url::parse(input) {
+        // `localhost:8080` and `example.com:80` parse as a scheme followed by a path
+        let hierarchical = input[url.scheme().len()..].starts_with("://");
+        if hierarchical || ["about", "data", "blob", "file", "mailto", "view-source"].contains(&url.scheme()) {
+            return Some(url);
+        }
+    }
+    let host = input.split(['/', '?', '#']).next().unwrap_or("");
+    let host_name = host.rsplit_once(':').map_or(host, |(name, port)| {
+        if port.chars().all(|c| c.is_ascii_digit()) {
+            name
+        } else {
+            host
+        }
+    });
+    let local = host_name == "localhost" || host_name.parse::<std::net::Ipv4Addr>().is_ok() || host_name.starts_with('[');
+    if !local && !host_name.contains('.') {
+        return None;
+    }
+    let scheme = if local { "http" } else { "https" };
+    Url::parse(&format!("{}://{}", scheme, input)).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use assert2::check;
+
+    use super::*;
+
+    fn fixed(input: &str) -> Option<String> {
+        fixup_url(input).map(|url| String::from(url.as_str()))
+    }
+
+    #[test]
+    fn url_fixup() {
+        check!(fixed("https://example.com/a") == Some(String::from("https://example.com/a")));
+        check!(fixed("  example.com ") == Some(String::from("https://example.com/")));
+        check!(fixed("example.com:8080/a?b") == Some(String::from("https://example.com:8080/a?b")));
+        check!(fixed("localhost:3000") == Some(String::from("http://localhost:3000/")));
+        check!(fixed("127.0.0.1/x") == Some(String::from("http://127.0.0.1/x")));
+        check!(fixed("about:blank") == Some(String::from("about:blank")));
+        check!(fixed("/tmp/a b.png") == Some(String::from("file:///tmp/a%20b.png")));
+        check!(fixed("/tmp/page.html") == Some(String::from("file:///tmp/page.html")));
+        check!(fixed("two words") == None);
+        check!(fixed("intranet") == None);
+        check!(fixed("") == None);
+    }
+
+    #[test]
+    fn toolbar_hit_testing() {
+        let layout = ToolbarLayout::new(34.0, 800.0);
+        check!(layout.address_bar.right() < 800.0);
+        check!(layout.address_bar.left() > layout.reload.right());
+        let center = |r: &Rect| Position {
+            left: r.left() + r.width() / 2.0,
+            top: r.top() + r.height() / 2.0,
+        };
+        check!(layout.hit(&center(&layout.back)) == Some(ChromeHit::Back));
+        check!(layout.hit(&center(&layout.forward)) == Some(ChromeHit::Forward));
+        check!(layout.hit(&center(&layout.reload)) == Some(ChromeHit::Reload));
+        check!(layout.hit(&center(&layout.address_bar)) == Some(ChromeHit::AddressBar));
+        check!(layout.hit(&Position { left: 400.0, top: 10.0 }) == None);
+
+        let mut address = TextField::new();
+        address.set_text("abcdef");
+        let third = Position {
+            left: layout.address_bar.left() + ADDRESS_PADDING + UI_FONT_SIZE * 3.0,
+            top: center(&layout.address_bar).top,
+        };
+        check!(layout.address_index_at(&address, &third) == 3);
+
+        let suggestions = layout.suggestions_bounds(3);
+        check!(suggestions.top() == layout.bounds.bottom());
+        let row = |i: f64| Position {
+            left: suggestions.left() + 10.0,
+            top: suggestions.top() + (i + 0.5) * SUGGESTION_HEIGHT,
+        };
+        check!(layout.suggestion_at(3, &row(0.0)) == Some(0));
+        check!(layout.suggestion_at(3, &row(2.0)) == Some(2));
+        check!(layout.suggestion_at(3, &row(3.0)) == None);
+        check!(layout.suggestion_at(0, &row(0.0)) == None);
+    }
+}
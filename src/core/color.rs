@@ -3,7 +3,7 @@
 
 use log::{debug, error, info, warn};
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Color {
     pub r: u8,
     pub g: u8,
@@ -48,6 +48,21 @@ impl Color {
     pub fn as_u8_ref(&self) -> [u8;4] {
         [self.r, self.g, self.b, self.a]
     }
+
+    pub fn new(r: u8, g: u8, b: u8, a: u8) -> Color {
+        Color { r, g, b, a }
+    }
+
+    /// Same color with every channel scaled by `factor`, alpha untouched. Used for 3D border shades.
+    pub fn shade(&self, factor: f64) -> Color {
+        let scale = |c: u8| (c as f64 * factor).round().clamp(0.0, 255.0) as u8;
+        Color {
+            r: scale(self.r),
+            g: scale(self.g),
+            b: scale(self.b),
+            a: self.a,
+        }
+    }
 }
 
 pub struct Colors {}
@@ -0,0 +1,306 @@
+#![allow(dead_code)]
+#![allow(unused_imports)]
+
+use log::{debug, error, info, warn};
+
+use crate::fixme;
+
+use super::box_painter::paint_box;
+use super::color::Color;
+use super::dom::window_events::{Key, Modifiers};
+use super::geometry::{Position, Rect};
+use super::gfxbuffer::Surface;
+use super::settings::{setting_kind, SettingKind, Settings, SETTING_KEYS};
+use super::simple_error::SimpleError;
+use super::style::{BorderSide, BorderStyle, Borders, BoxStyle};
+use super::tab_strip::ICON_COLOR;
+use super::text::{draw_text, elide, text_width};
+use super::text_field::TextField;
+
+/// Sizes are in CSS pixels at zoom 1, like the rest of the browser UI.
+const HEADER_HEIGHT: f64 = 44.0;
+const ROW_HEIGHT: f64 = 24.0;
+const PADDING: f64 = 16.0;
+const TITLE_FONT_SIZE: f64 = 16.0;
+const FONT_SIZE: f64 = 11.0;
+const RESET_LABEL: &str = "reset";
+
+const PAGE_COLOR: Color = Color {
+    r: 0x20,
+    g: 0x21,
+    b: 0x24,
+    a: 0xff,
+};
+const ODD_ROW_COLOR: Color = Color {
+    r: 0x29,
+    g: 0x2a,
+    b: 0x2d,
+    a: 0xff,
+};
+const MODIFIED_COLOR: Color = Color {
+    r: 0x8a,
+    g: 0xb4,
+    b: 0xf8,
+    a: 0xff,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConfigHit {
+    Value(usize),
+    Reset(usize),
+}
+
+/// `about:config`: every setting with its value, painted by the browser over the document area.
+///   Booleans and choices change on click, other values are typed in.
+#[derive(Debug, Default)]
+pub struct ConfigPage {
+    /// Row being edited and what was typed
+    editing: Option<(usize, TextField)>,
+}
+
+impl ConfigPage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_editing(&self) -> bool {
+        self.editing.is_some()
+    }
+
+    fn row_bounds(bounds: &Rect, index: usize) -> Rect {
+        Rect::new(
+            bounds.left(),
+            bounds.top() + HEADER_HEIGHT + index as f64 * ROW_HEIGHT,
+            bounds.width(),
+            ROW_HEIGHT,
+        )
+    }
+
+    /// Left edge of the value column.
+    fn value_left(bounds: &Rect) -> f64 {
+        bounds.left() + PADDING + (bounds.width() * 0.4).max(180.0)
+    }
+
+    fn reset_bounds(bounds: &Rect, index: usize) -> Rect {
+        let row = Self::row_bounds(bounds, index);
+        let width = text_width(RESET_LABEL, FONT_SIZE) + PADDING;
+        Rect::new(row.right() - width - PADDING / 2.0, row.top(), width, row.height())
+    }
+
+    /// What is under `pt`, for a page in `bounds`. Reset buttons only show on modified settings.
+    pub fn hit(&self, settings: &Settings, bounds: &Rect, pt: &Position) -> Option<ConfigHit> {
+        let index = (0..SETTING_KEYS.len()).find(|i| Self::row_bounds(bounds, *i).contains_point(pt))?;
+        let (key, _) = SETTING_KEYS[index];
+        if !settings.is_default(key) && Self::reset_bounds(bounds, index).contains_point(pt) {
+            return Some(ConfigHit::Reset(index));
+        }
+        Some(ConfigHit::Value(index))
+    }
+
+    /// Toggle, cycle, reset or start typing the setting clicked. Clicking elsewhere stops typing.
+    pub fn on_click(&mut self, settings: &mut Settings, bounds: &Rect, pt: &Position) -> Result<(), SimpleError> {
+        let hit = self.hit(settings, bounds, pt);
+        if hit.is_none_or(|hit| Some(hit) != self.editing.as_ref().map(|(i, _)| ConfigHit::Value(*i))) {
+            self.editing = None;
+        }
+        match hit {
+            Some(ConfigHit::Reset(index)) => settings.reset(SETTING_KEYS[index].0),
+            Some(ConfigHit::Value(index)) if self.editing.is_none() => {
+                let (key, kind) = SETTING_KEYS[index];
+                let value = settings.get(key).unwrap_or_default();
+                match kind {
+                    SettingKind::Bool => settings.set(key, if value == "true" { "false" } else { "true" }),
+                    SettingKind::Choice(choices) => {
+                        let current = choices.iter().position(|c| *c == value).unwrap_or(0);
+                        settings.set(key, choices[(current + 1) % choices.len()])
+                    }
+                    SettingKind::Number | SettingKind::String => {
+                        let mut field = TextField::new();
+                        field.set_text(&value);
+                        field.select_all();
+                        self.editing = Some((index, field));
+                        Ok(())
+                    }
+                }
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Keys while typing a value: Enter sets it, Escape cancels. Returns false for keys not used.
+    ///   An invalid value is reported and stays in the field.
+    pub fn on_key(&mut self, settings: &mut Settings, key: &Key, modifiers: &Modifiers) -> Result<bool, SimpleError> {
+        let (index, field) = match self.editing.as_mut() {
+            Some(editing) => editing,
+            None => return Ok(false),
+        };
+        match key {
+            Key::Enter => {
+                settings.set(SETTING_KEYS[*index].0, field.text())?;
+                self.editing = None;
+            }
+            Key::Escape => self.editing = None,
+            _ => return Ok(field.on_key(key, modifiers)),
+        }
+        Ok(true)
+    }
+
+    pub fn insert(&mut self, text: &str) {
+        if let Some((_, field)) = self.editing.as_mut() {
+            field.insert(text);
+        }
+    }
+
+    /// Paint the page in `bounds`, in CSS pixels, `scale` device pixels per CSS pixel.
+    pub fn paint(&self, surface: &mut Surface, settings: &Settings, bounds: &Rect, scale: f64, clip: &Rect) {
+        let clip = match bounds.scale(scale).get_intersection_rect(clip) {
+            Some(clip) => clip,
+            None => return,
+        };
+        paint_box(surface, &bounds.scale(scale), &BoxStyle::with_background(PAGE_COLOR), &clip);
+        let title = Position {
+            left: (bounds.left() + PADDING) * scale,
+            top: (bounds.top() + (HEADER_HEIGHT - TITLE_FONT_SIZE) / 2.0) * scale,
+        };
+        draw_text(surface, "about:config", &title, TITLE_FONT_SIZE * scale, ICON_COLOR, &clip);
+        fixme!("Filter the settings by name");
+
+        let value_left = Self::value_left(bounds);
+        for (i, (key, _)) in SETTING_KEYS.iter().enumerate() {
+            let row = Self::row_bounds(bounds, i);
+            if i % 2 == 1 {
+                paint_box(surface, &row.scale(scale), &BoxStyle::with_background(ODD_ROW_COLOR), &clip);
+            }
+            let modified = !settings.is_default(key);
+            let color = if modified { MODIFIED_COLOR } else { ICON_COLOR };
+            let text_top = row.top() + (ROW_HEIGHT - FONT_SIZE) / 2.0;
+            let key_text = elide(key, FONT_SIZE, value_left - row.left() - PADDING * 2.0);
+            let origin = Position {
+                left: (row.left() + PADDING) * scale,
+                top: text_top * scale,
+            };
+            draw_text(surface, &key_text, &origin, FONT_SIZE * scale, color, &clip);
+
+            let reset = Self::reset_bounds(bounds, i);
+            let value_width = (reset.left() - value_left - PADDING).max(0.0);
+            let origin = Position {
+                left: value_left * scale,
+                top: text_top * scale,
+            };
+            match &self.editing {
+                Some((index, field)) if *index == i => {
+                    self.paint_field(surface, field, &row, value_left, value_width, scale, &clip)
+                }
+                _ => {
+                    let value = settings.get(key).unwrap_or_default();
+                    let value = elide(&value, FONT_SIZE, value_width);
+                    draw_text(surface, &value, &origin, FONT_SIZE * scale, color, &clip);
+                }
+            }
+            if modified {
+                let origin = Position {
+                    left: (reset.left() + PADDING / 2.0) * scale,
+                    top: text_top * scale,
+                };
+                draw_text(surface, RESET_LABEL, &origin, FONT_SIZE * scale, MODIFIED_COLOR, &clip);
+            }
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn paint_field(
+        &self,
+        surface: &mut Surface,
+        field: &TextField,
+        row: &Rect,
+        left: f64,
+        width: f64,
+        scale: f64,
+        clip: &Rect,
+    ) {
+        let bounds = Rect::new(left - 4.0, row.top() + 2.0, width + 8.0, row.height() - 4.0);
+        let mut style = BoxStyle::with_background(PAGE_COLOR);
+        style.borders = Borders::all(BorderSide::new(scale, BorderStyle::Solid, MODIFIED_COLOR));
+        paint_box(surface, &bounds.scale(scale), &style, clip);
+        let clip = match bounds.scale(scale).get_intersection_rect(clip) {
+            Some(clip) => clip,
+            None => return,
+        };
+        let scroll = (field.cursor() as f64 * FONT_SIZE - width).max(0.0);
+        let top = row.top() + (ROW_HEIGHT - FONT_SIZE) / 2.0;
+        let caret = Rect::new(left - scroll + field.cursor() as f64 * FONT_SIZE, top - 2.0, 1.0, FONT_SIZE + 4.0);
+        if let Some((begin, end)) = field.selection() {
+            let selected = Rect::new(
+                left - scroll + begin as f64 * FONT_SIZE,
+                top - 2.0,
+                (end - begin) as f64 * FONT_SIZE,
+                FONT_SIZE + 4.0,
+            );
+            paint_box(surface, &selected.scale(scale), &BoxStyle::with_background(ODD_ROW_COLOR), &clip);
+        }
+        let origin = Position {
+            left: (left - scroll) * scale,
+            top: top * scale,
+        };
+        draw_text(surface, field.text(), &origin, FONT_SIZE * scale, ICON_COLOR, &clip);
+        paint_box(surface, &caret.scale(scale), &BoxStyle::with_background(ICON_COLOR), &clip);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert2::check;
+
+    use super::*;
+
+    fn center(rect: &Rect) -> Position {
+        Position {
+            left: rect.left() + rect.width() / 2.0,
+            top: rect.top() + rect.height() / 2.0,
+        }
+    }
+
+    fn row_of(key: &str) -> usize {
+        SETTING_KEYS.iter().position(|(k, _)| *k == key).unwrap()
+    }
+
+    #[test]
+    fn editing_settings() {
+        let bounds = Rect::new(0.0, 70.0, 800.0, 500.0);
+        let mut settings = Settings::new();
+        let mut page = ConfigPage::new();
+        let value = |key: &str| {
+            let row = ConfigPage::row_bounds(&bounds, row_of(key));
+            Position {
+                left: ConfigPage::value_left(&bounds) + 10.0,
+                top: center(&row).top,
+            }
+        };
+
+        // Booleans toggle, choices cycle
+        page.on_click(&mut settings, &bounds, &value("javascript.enabled")).unwrap();
+        check!(!settings.javascript_enabled);
+        page.on_click(&mut settings, &bounds, &value("network.cookie.policy")).unwrap();
+        check!(settings.get("network.cookie.policy") == Some(String::from("reject-all")));
+        let reset = center(&ConfigPage::reset_bounds(&bounds, row_of("javascript.enabled")));
+        check!(page.hit(&settings, &bounds, &reset) == Some(ConfigHit::Reset(row_of("javascript.enabled"))));
+        page.on_click(&mut settings, &bounds, &reset).unwrap();
+        check!(settings.javascript_enabled);
+
+        // Other values are typed, replacing the selected value
+        let none = Modifiers::default();
+        page.on_click(&mut settings, &bounds, &value("font.size.default")).unwrap();
+        check!(page.is_editing());
+        page.insert("99");
+        check!(page.on_key(&mut settings, &Key::Enter, &none).is_err());
+        check!(page.is_editing());
+        check!(page.on_key(&mut settings, &Key::Backspace, &none).unwrap());
+        check!(page.on_key(&mut settings, &Key::Enter, &none).unwrap());
+        check!(!page.is_editing());
+        check!(settings.default_font_size == 9.0);
+
+        page.on_click(&mut settings, &bounds, &value("network.proxy")).unwrap();
+        page.on_click(&mut settings, &bounds, &Position { left: 10.0, top: 75.0 }).unwrap();
+        check!(!page.is_editing());
+    }
+}
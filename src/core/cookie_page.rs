@@ -0,0 +1,285 @@
+#![allow(dead_code)]
+#![allow(unused_imports)]
+
+use log::{debug, error, info, warn};
+
+use crate::fixme;
+
+use super::box_painter::paint_box;
+use super::color::Color;
+use super::cookies::{Cookie, CookieJar};
+use super::geometry::{Position, Rect};
+use super::gfxbuffer::Surface;
+use super::style::BoxStyle;
+use super::tab_strip::ICON_COLOR;
+use super::text::{draw_text, elide, text_width};
+
+/// Sizes are in CSS pixels at zoom 1, like the rest of the browser UI.
+const HEADER_HEIGHT: f64 = 44.0;
+const ROW_HEIGHT: f64 = 24.0;
+const PADDING: f64 = 16.0;
+const TITLE_FONT_SIZE: f64 = 16.0;
+const FONT_SIZE: f64 = 11.0;
+const DELETE_LABEL: &str = "delete";
+
+const PAGE_COLOR: Color = Color {
+    r: 0x20,
+    g: 0x21,
+    b: 0x24,
+    a: 0xff,
+};
+const SITE_ROW_COLOR: Color = Color {
+    r: 0x29,
+    g: 0x2a,
+    b: 0x2d,
+    a: 0xff,
+};
+const DETAIL_COLOR: Color = Color {
+    r: 0x9a,
+    g: 0xa0,
+    b: 0xa6,
+    a: 0xff,
+};
+const DELETE_COLOR: Color = Color {
+    r: 0xf2,
+    g: 0x8b,
+    b: 0x82,
+    a: 0xff,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+enum Row {
+    Site { site: String, count: usize },
+    Cookie(Cookie),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CookieHit {
+    DeleteSite(String),
+    DeleteCookie(Cookie),
+}
+
+/// `about:cookies`: the cookies of every site, painted by the browser over the document area,
+///   each site and cookie with a button deleting it.
+#[derive(Debug)]
+pub struct CookiePage {
+    rows: Vec<Row>,
+}
+
+impl CookiePage {
+    /// The page listing the cookies in `jar` now.
+    pub fn new(jar: &CookieJar) -> Self {
+        let mut rows = vec![];
+        for site in jar.sites() {
+            let mut cookies: Vec<&Cookie> = jar.cookies_of_site(&site).collect();
+            cookies.sort_by(|a, b| (&a.domain, &a.path, &a.name).cmp(&(&b.domain, &b.path, &b.name)));
+            rows.push(Row::Site {
+                site: site.clone(),
+                count: cookies.len(),
+            });
+            rows.extend(cookies.into_iter().map(|cookie| Row::Cookie(cookie.clone())));
+        }
+        Self { rows }
+    }
+
+    fn row_bounds(bounds: &Rect, index: usize) -> Rect {
+        Rect::new(
+            bounds.left(),
+            bounds.top() + HEADER_HEIGHT + index as f64 * ROW_HEIGHT,
+            bounds.width(),
+            ROW_HEIGHT,
+        )
+    }
+
+    fn delete_bounds(bounds: &Rect, index: usize) -> Rect {
+        let row = Self::row_bounds(bounds, index);
+        let width = text_width(DELETE_LABEL, FONT_SIZE) + PADDING;
+        Rect::new(row.right() - width - PADDING / 2.0, row.top(), width, row.height())
+    }
+
+    /// Left edge of the details column of cookie rows.
+    fn details_left(bounds: &Rect) -> f64 {
+        bounds.left() + PADDING * 2.0 + (bounds.width() * 0.35).max(160.0)
+    }
+
+    /// The delete button under `pt`, for a page in `bounds`.
+    pub fn hit(&self, bounds: &Rect, pt: &Position) -> Option<CookieHit> {
+        let index = (0..self.rows.len()).find(|i| Self::delete_bounds(bounds, *i).contains_point(pt))?;
+        Some(match &self.rows[index] {
+            Row::Site { site, .. } => CookieHit::DeleteSite(site.clone()),
+            Row::Cookie(cookie) => CookieHit::DeleteCookie(cookie.clone()),
+        })
+    }
+
+    /// Where the cookie goes, when it expires and its flags.
+    fn details(cookie: &Cookie, now: u64) -> String {
+        let mut details = format!("{}{}", if cookie.host_only { "" } else { "." }, cookie.domain);
+        details.push_str(&cookie.path);
+        match cookie.expires {
+            Some(expires) => {
+                let days = expires.saturating_sub(now) / (24 * 60 * 60);
+                details.push_str(&format!("  expires in {} days", days));
+            }
+            None => details.push_str("  session"),
+        }
+        if cookie.secure {
+            details.push_str("  Secure");
+        }
+        if cookie.http_only {
+            details.push_str("  HttpOnly");
+        }
+        details.push_str(&format!("  SameSite={}", cookie.same_site.as_str()));
+        details
+    }
+
+    /// Paint the page in `bounds`, in CSS pixels, `scale` device pixels per CSS pixel.
+    pub fn paint(&self, surface: &mut Surface, bounds: &Rect, scale: f64, clip: &Rect) {
+        let clip = match bounds.scale(scale).get_intersection_rect(clip) {
+            Some(clip) => clip,
+            None => return,
+        };
+        paint_box(
+            surface,
+            &bounds.scale(scale),
+            &BoxStyle::with_background(PAGE_COLOR),
+            &clip,
+        );
+        let title = Position {
+            left: (bounds.left() + PADDING) * scale,
+            top: (bounds.top() + (HEADER_HEIGHT - TITLE_FONT_SIZE) / 2.0) * scale,
+        };
+        draw_text(
+            surface,
+            "about:cookies",
+            &title,
+            TITLE_FONT_SIZE * scale,
+            ICON_COLOR,
+            &clip,
+        );
+        fixme!("Scroll the cookies that don't fit, filter them by site");
+        if self.rows.is_empty() {
+            let origin = Position {
+                left: (bounds.left() + PADDING) * scale,
+                top: (bounds.top() + HEADER_HEIGHT) * scale,
+            };
+            draw_text(surface, "No cookies", &origin, FONT_SIZE * scale, DETAIL_COLOR, &clip);
+            return;
+        }
+
+        let now = super::http_cache::unix_now();
+        let details_left = Self::details_left(bounds);
+        for (i, row) in self.rows.iter().enumerate() {
+            let bounds_of_row = Self::row_bounds(bounds, i);
+            let delete = Self::delete_bounds(bounds, i);
+            let text_top = (bounds_of_row.top() + (ROW_HEIGHT - FONT_SIZE) / 2.0) * scale;
+            match row {
+                Row::Site { site, count } => {
+                    paint_box(
+                        surface,
+                        &bounds_of_row.scale(scale),
+                        &BoxStyle::with_background(SITE_ROW_COLOR),
+                        &clip,
+                    );
+                    let plural = if *count == 1 { "" } else { "s" };
+                    let text = format!("{} ({} cookie{})", site, count, plural);
+                    let text = elide(&text, FONT_SIZE, delete.left() - bounds_of_row.left() - PADDING * 2.0);
+                    let origin = Position {
+                        left: (bounds_of_row.left() + PADDING) * scale,
+                        top: text_top,
+                    };
+                    draw_text(surface, &text, &origin, FONT_SIZE * scale, ICON_COLOR, &clip);
+                }
+                Row::Cookie(cookie) => {
+                    let name_left = bounds_of_row.left() + PADDING * 2.0;
+                    let text = format!("{}={}", cookie.name, cookie.value);
+                    let text = elide(&text, FONT_SIZE, details_left - name_left - PADDING);
+                    let origin = Position {
+                        left: name_left * scale,
+                        top: text_top,
+                    };
+                    draw_text(surface, &text, &origin, FONT_SIZE * scale, ICON_COLOR, &clip);
+                    let details = Self::details(cookie, now);
+                    let details = elide(&details, FONT_SIZE, (delete.left() - details_left - PADDING).max(0.0));
+                    let origin = Position {
+                        left: details_left * scale,
+                        top: text_top,
+                    };
+                    draw_text(surface, &details, &origin, FONT_SIZE * scale, DETAIL_COLOR, &clip);
+                }
+            }
+            let origin = Position {
+                left: (delete.left() + PADDING / 2.0) * scale,
+                top: text_top,
+            };
+            draw_text(surface, DELETE_LABEL, &origin, FONT_SIZE * scale, DELETE_COLOR, &clip);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert2::check;
+    use url::Url;
+
+    use super::super::http::{Headers, Request, Response};
+    use super::*;
+
+    fn center(rect: &Rect) -> Position {
+        Position {
+            left: rect.left() + rect.width() / 2.0,
+            top: rect.top() + rect.height() / 2.0,
+        }
+    }
+
+    #[test]
+    fn listing_and_deleting() {
+        let mut jar = CookieJar::new();
+        for (url, cookie) in [
+            ("https://www.example.com/", "a=1"),
+            ("https://shop.example.com/", "b=2; Max-Age=600; Secure"),
+            ("https://news.example/", "c=3"),
+        ] {
+            let request = Request::get(Url::parse(url).unwrap());
+            let mut headers = Headers::new();
+            headers.add("Set-Cookie", cookie);
+            let response = Response {
+                url: request.url.clone(),
+                status: 200,
+                reason: String::from("OK"),
+                headers,
+                body: vec![],
+                redirects: vec![],
+                from_cache: false,
+            };
+            jar.store_response(&request, &response, 0);
+        }
+        let page = CookiePage::new(&jar);
+        let sites: Vec<&Row> = page.rows.iter().filter(|row| matches!(row, Row::Site { .. })).collect();
+        check!(
+            sites
+                == vec![
+                    &Row::Site {
+                        site: String::from("example.com"),
+                        count: 2
+                    },
+                    &Row::Site {
+                        site: String::from("news.example"),
+                        count: 1
+                    }
+                ]
+        );
+        check!(
+            CookiePage::details(&jar.cookies()[1], 0) == "shop.example.com/  expires in 0 days  Secure  SameSite=Lax"
+        );
+
+        let bounds = Rect::new(0.0, 70.0, 800.0, 500.0);
+        check!(page.hit(&bounds, &center(&CookiePage::row_bounds(&bounds, 1))) == None);
+        // Cookies of a site sort by domain
+        let hit = page.hit(&bounds, &center(&CookiePage::delete_bounds(&bounds, 2)));
+        check!(matches!(&hit, Some(CookieHit::DeleteCookie(cookie)) if cookie.name == "a"));
+        check!(
+            page.hit(&bounds, &center(&CookiePage::delete_bounds(&bounds, 3)))
+                == Some(CookieHit::DeleteSite(String::from("news.example")))
+        );
+    }
+}
@@ -0,0 +1,729 @@
+#![allow(dead_code)]
+#![allow(unused_imports)]
+
+use std::path::{Path, PathBuf};
+
+use log::{debug, error, info, warn};
+use url::{Host, Url};
+
+use crate::fixme;
+
+use super::http::{Request, Response};
+use super::http_cache::parse_http_date;
+use super::settings::CookiePolicy;
+use super::simple_error::SimpleError;
+
+/// Longest lifetime of a cookie, whatever it asks (RFC 6265bis section 5.6.1)
+const MAX_AGE_LIMIT: u64 = 400 * 24 * 60 * 60;
+/// Longest name and value together
+const MAX_COOKIE_SIZE: usize = 4096;
+const MAX_COOKIES_PER_SITE: usize = 180;
+const MAX_COOKIES: usize = 3000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SameSite {
+    Strict,
+    Lax,
+    None,
+}
+
+impl SameSite {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SameSite::Strict => "Strict",
+            SameSite::Lax => "Lax",
+            SameSite::None => "None",
+        }
+    }
+}
+
+/// Site of a host, to tell first from third parties: its registrable domain, as `example.co.uk`
+///   for `www.example.co.uk`. IP addresses and public suffixes are their own site.
+pub fn site(host: &str) -> String {
+    let host = host.to_ascii_lowercase();
+    if is_ip_address(&host) {
+        return host;
+    }
+    String::from(psl::domain_str(&host).unwrap_or(&host))
+}
+
+fn is_ip_address(host: &str) -> bool {
+    host.starts_with('[') || host.parse::<std::net::IpAddr>().is_ok()
+}
+
+/// `host` is `domain` or one of its subdomains (RFC 6265bis section 5.1.3).
+fn domain_matches(host: &str, domain: &str) -> bool {
+    host == domain
+        || (host.len() > domain.len()
+            && host.ends_with(domain)
+            && host.as_bytes()[host.len() - domain.len() - 1] == b'.'
+            && !is_ip_address(host))
+}
+
+/// `path` is `cookie_path` or below it (RFC 6265bis section 5.1.4).
+fn path_matches(path: &str, cookie_path: &str) -> bool {
+    path == cookie_path
+        || (path.starts_with(cookie_path)
+            && (cookie_path.ends_with('/') || path.as_bytes().get(cookie_path.len()) == Some(&b'/')))
+}
+
+/// Directory of the URL path, for cookies without a `Path` (RFC 6265bis section 5.1.4).
+fn default_path(url: &Url) -> String {
+    let path = url.path();
+    match path.rfind('/') {
+        Some(0) | None => String::from("/"),
+        Some(slash) => String::from(&path[..slash]),
+    }
+}
+
+/// Origins where secure cookies may be set: https and the local host.
+fn is_secure_origin(url: &Url) -> bool {
+    url.scheme() == "https"
+        || match url.host() {
+            Some(Host::Domain(domain)) => domain == "localhost" || domain.ends_with(".localhost"),
+            Some(Host::Ipv4(address)) => address.is_loopback(),
+            Some(Host::Ipv6(address)) => address.is_loopback(),
+            None => false,
+        }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cookie {
+    pub name: String,
+    pub value: String,
+    /// The host that set it, or the domain of its `Domain` attribute
+    pub domain: String,
+    /// Sent to `domain` only, not to its subdomains
+    pub host_only: bool,
+    pub path: String,
+    /// Seconds since the epoch. Session cookies have none and are forgotten at exit.
+    pub expires: Option<u64>,
+    pub secure: bool,
+    pub http_only: bool,
+    pub same_site: SameSite,
+    pub created: u64,
+    pub last_access: u64,
+}
+
+impl Cookie {
+    /// The cookie of a `Set-Cookie` header received from `url` (RFC 6265bis sections 5.6 and 5.7).
+    ///   The error says why the header is ignored.
+    pub fn parse(header: &str, url: &Url, now: u64) -> Result<Self, SimpleError> {
+        let (pair, attributes) = header.split_once(';').unwrap_or((header, ""));
+        let (name, value) = match pair.split_once('=') {
+            Some((name, value)) => (name.trim(), value.trim()),
+            None => ("", pair.trim()),
+        };
+        if name.is_empty() && value.is_empty() {
+            return Err(SimpleError::new("Cookie without name nor value"));
+        }
+        if name.len() + value.len() > MAX_COOKIE_SIZE {
+            return Err(SimpleError::new("Cookie too large"));
+        }
+        if name.chars().chain(value.chars()).any(|c| c.is_control() && c != '\t') {
+            return Err(SimpleError::new("Control character in cookie"));
+        }
+        // Fields of the jar file are separated by tabs: only the value, the last one, may hold some
+        if name.contains('\t') {
+            return Err(SimpleError::new("Tab in cookie name"));
+        }
+        let host = url
+            .host_str()
+            .ok_or_else(|| SimpleError::new("Cookie from a URL without host"))?
+            .to_ascii_lowercase();
+        let mut cookie = Cookie {
+            name: String::from(name),
+            value: String::from(value),
+            domain: host.clone(),
+            host_only: true,
+            path: default_path(url),
+            expires: None,
+            secure: false,
+            http_only: false,
+            same_site: SameSite::Lax,
+            created: now,
+            last_access: now,
+        };
+        let mut max_age = None;
+        let mut expires = None;
+        let mut domain = None;
+        for attribute in attributes.split(';') {
+            let (key, value) = match attribute.split_once('=') {
+                Some((key, value)) => (key.trim(), value.trim()),
+                None => (attribute.trim(), ""),
+            };
+            match key.to_ascii_lowercase().as_str() {
+                "expires" => expires = parse_http_date(value).or(expires),
+                "max-age" => {
+                    if let Ok(delta) = value.parse::<i64>() {
+                        // Zero or negative ages expire at once
+                        max_age = Some(if delta <= 0 {
+                            0
+                        } else {
+                            now.saturating_add(delta as u64)
+                        });
+                    }
+                }
+                "domain" if !value.is_empty() => {
+                    domain = Some(value.trim_start_matches('.').to_ascii_lowercase());
+                }
+                "path" if value.starts_with('/') && !value.contains('\t') => cookie.path = String::from(value),
+                "path" => cookie.path = default_path(url),
+                "secure" => cookie.secure = true,
+                "httponly" => cookie.http_only = true,
+                "samesite" => {
+                    cookie.same_site = match value.to_ascii_lowercase().as_str() {
+                        "strict" => SameSite::Strict,
+                        "none" => SameSite::None,
+                        _ => SameSite::Lax,
+                    }
+                }
+                _ => (),
+            }
+        }
+        cookie.expires = max_age.or(expires).map(|expires| expires.min(now + MAX_AGE_LIMIT));
+
+        if let Some(domain) = domain {
+            let public_suffix = !is_ip_address(&domain) && psl::domain_str(&domain).is_none();
+            if public_suffix && domain != host {
+                return Err(SimpleError::new(
+                    format!("Cookie for the public suffix {}", domain).as_str(),
+                ));
+            }
+            if !domain_matches(&host, &domain) {
+                return Err(SimpleError::new(format!("Cookie of {} for {}", host, domain).as_str()));
+            }
+            // A public suffix that is the host itself only gets host-only cookies
+            cookie.host_only = public_suffix;
+            cookie.domain = domain;
+        }
+        if cookie.secure && !is_secure_origin(url) {
+            return Err(SimpleError::new("Secure cookie from an insecure origin"));
+        }
+        if cookie.same_site == SameSite::None && !cookie.secure {
+            return Err(SimpleError::new("SameSite=None cookie without Secure"));
+        }
+        let lowercase_name = cookie.name.to_ascii_lowercase();
+        if lowercase_name.starts_with("__secure-") && !cookie.secure {
+            return Err(SimpleError::new("__Secure- cookie without Secure"));
+        }
+        if lowercase_name.starts_with("__host-") && !(cookie.secure && cookie.host_only && cookie.path == "/") {
+            return Err(SimpleError::new(
+                "__Host- cookie with a domain, a path or without Secure",
+            ));
+        }
+        Ok(cookie)
+    }
+
+    /// True if the cookie goes with requests to `url`.
+    fn matches(&self, url: &Url) -> bool {
+        let host = url.host_str().unwrap_or("").to_ascii_lowercase();
+        let domain = if self.host_only {
+            host == self.domain
+        } else {
+            domain_matches(&host, &self.domain)
+        };
+        domain && path_matches(url.path(), &self.path) && (!self.secure || is_secure_origin(url))
+    }
+
+    fn is_expired(&self, now: u64) -> bool {
+        self.expires.is_some_and(|expires| expires <= now)
+    }
+
+    /// Same cookie, possibly with another value (RFC 6265bis section 5.7 step 22).
+    fn replaces(&self, other: &Cookie) -> bool {
+        self.name == other.name
+            && self.domain == other.domain
+            && self.host_only == other.host_only
+            && self.path == other.path
+    }
+
+    pub fn site(&self) -> String {
+        site(&self.domain)
+    }
+}
+
+/// Request from a document of another site than the one it asks: cookies follow the policy and SameSite.
+fn is_cross_site(request: &Request) -> bool {
+    let top = match &request.site_for_cookies {
+        Some(top) => top,
+        None => return false,
+    };
+    let site_of = |url: &Url| url.host_str().map(site);
+    // Schemeful same-site: http and https pages of a site are different sites
+    site_of(top) != site_of(&request.url) || is_secure_origin(top) != is_secure_origin(&request.url)
+}
+
+/// Every cookie, saved in the profile as a line per persistent cookie.
+#[derive(Debug)]
+pub struct CookieJar {
+    cookies: Vec<Cookie>,
+    pub policy: CookiePolicy,
+    path: Option<PathBuf>,
+    /// Access times of persistent cookies changed since the last save
+    accessed: bool,
+}
+
+impl Default for CookieJar {
+    fn default() -> Self {
+        Self {
+            cookies: vec![],
+            policy: CookiePolicy::RejectThirdParty,
+            path: None,
+            accessed: false,
+        }
+    }
+}
+
+impl CookieJar {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load from `path` and save there on every change. Access times are saved with the next change,
+    ///   or by `flush`. A missing file is an empty jar.
+    pub fn load(path: &Path) -> Result<Self, SimpleError> {
+        let mut jar = Self::new();
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(err) => return Err(SimpleError::new(format!("{:?}: {}", path, err).as_str())),
+        };
+        let bool_field = |field: &str| field == "TRUE";
+        for line in text.lines().filter(|line| !line.is_empty() && !line.starts_with('#')) {
+            let fields: Vec<&str> = line.splitn(11, '\t').collect();
+            let cookie = match fields[..] {
+                [domain, host_only, path, secure, http_only, same_site, expires, created, last_access, name, value] => {
+                    let number = |field: &str| field.parse::<u64>().ok();
+                    Some(Cookie {
+                        name: String::from(name),
+                        value: String::from(value),
+                        domain: String::from(domain),
+                        host_only: bool_field(host_only),
+                        path: String::from(path),
+                        expires: number(expires),
+                        secure: bool_field(secure),
+                        http_only: bool_field(http_only),
+                        same_site: match same_site {
+                            "Strict" => SameSite::Strict,
+                            "None" => SameSite::None,
+                            _ => SameSite::Lax,
+                        },
+                        created: number(created).unwrap_or(0),
+                        last_access: number(last_access).unwrap_or(0),
+                    })
+                }
+                _ => None,
+            };
+            match cookie {
+                Some(cookie) if cookie.expires.is_some() => jar.cookies.push(cookie),
+                _ => warn!("Ignoring cookie line {:?} in {:?}", line, path),
+            }
+        }
+        jar.path = Some(path.to_path_buf());
+        jar.remove_expired(super::http_cache::unix_now());
+        Ok(jar)
+    }
+
+    pub fn len(&self) -> usize {
+        self.cookies.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cookies.is_empty()
+    }
+
+    pub fn cookies(&self) -> &[Cookie] {
+        &self.cookies
+    }
+
+    /// Sites with cookies, sorted.
+    pub fn sites(&self) -> Vec<String> {
+        let mut sites: Vec<String> = self.cookies.iter().map(Cookie::site).collect();
+        sites.sort();
+        sites.dedup();
+        sites
+    }
+
+    pub fn cookies_of_site<'j>(&'j self, site: &'j str) -> impl Iterator<Item = &'j Cookie> + 'j {
+        self.cookies.iter().filter(move |cookie| cookie.site() == site)
+    }
+
+    /// Whether the policy lets cookies go with `request`, or be set by its response.
+    fn allowed(&self, request: &Request) -> bool {
+        match self.policy {
+            CookiePolicy::AcceptAll => true,
+            // Top-level navigations to another site are first party: the user goes there
+            CookiePolicy::RejectThirdParty => request.top_level || !is_cross_site(request),
+            CookiePolicy::RejectAll => false,
+        }
+    }
+
+    /// Value of the `Cookie` header of `request`, if it gets cookies.
+    pub fn cookie_header(&mut self, request: &Request, now: u64) -> Option<String> {
+        if !self.allowed(request) {
+            return None;
+        }
+        let cross_site = is_cross_site(request);
+        let safe_method = matches!(request.method.as_str(), "GET" | "HEAD" | "OPTIONS" | "TRACE");
+        let mut cookies: Vec<&mut Cookie> = self
+            .cookies
+            .iter_mut()
+            .filter(|cookie| !cookie.is_expired(now) && cookie.matches(&request.url))
+            .filter(|cookie| match cookie.same_site {
+                _ if !cross_site => true,
+                SameSite::Strict => false,
+                SameSite::Lax => request.top_level && safe_method,
+                SameSite::None => true,
+            })
+            .collect();
+        if cookies.is_empty() {
+            return None;
+        }
+        // Longer paths first, then older cookies (RFC 6265bis section 5.8.3)
+        cookies.sort_by(|a, b| b.path.len().cmp(&a.path.len()).then(a.created.cmp(&b.created)));
+        let header = cookies
+            .iter_mut()
+            .map(|cookie| {
+                cookie.last_access = now;
+                self.accessed |= cookie.expires.is_some();
+                match cookie.name.is_empty() {
+                    true => cookie.value.clone(),
+                    false => format!("{}={}", cookie.name, cookie.value),
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("; ");
+        Some(header)
+    }
+
+    /// Store the cookies set by the `Set-Cookie` headers of `response` to `request`.
+    pub fn store_response(&mut self, request: &Request, response: &Response, now: u64) {
+        let headers: Vec<&str> = response.headers.get_all("Set-Cookie").collect();
+        if headers.is_empty() {
+            return;
+        }
+        if !self.allowed(request) {
+            debug!("Blocked {} cookies from {}", headers.len(), request.url);
+            return;
+        }
+        let cross_site = is_cross_site(request);
+        let mut changed = false;
+        for header in headers {
+            match Cookie::parse(header, &request.url, now) {
+                // Cross-site requests may only set SameSite=None cookies, but top-level navigations
+                Ok(cookie) if cross_site && !request.top_level && cookie.same_site != SameSite::None => {
+                    debug!("Ignoring cross-site cookie {} from {}", cookie.name, request.url);
+                }
+                Ok(cookie) => changed |= self.insert(cookie, &request.url, now),
+                Err(err) => debug!("Ignoring cookie from {}: {}: {:?}", request.url, err, header),
+            }
+        }
+        if changed {
+            self.save();
+        }
+    }
+
+    /// Add `cookie` set by `url`, replacing the same cookie. Expired cookies remove it.
+    fn insert(&mut self, mut cookie: Cookie, url: &Url, now: u64) -> bool {
+        // Insecure origins can't overwrite secure cookies (RFC 6265bis section 5.7 step 16)
+        if !is_secure_origin(url) {
+            let host = url.host_str().unwrap_or("").to_ascii_lowercase();
+            let shadows_secure = self.cookies.iter().any(|existing| {
+                existing.secure
+                    && existing.name == cookie.name
+                    && (domain_matches(&existing.domain, &cookie.domain)
+                        || domain_matches(&cookie.domain, &existing.domain))
+                    && path_matches(&cookie.path, &existing.path)
+                    && domain_matches(&host, &existing.domain)
+            });
+            if shadows_secure {
+                debug!("Ignoring cookie {} shadowing a secure cookie", cookie.name);
+                return false;
+            }
+        }
+        let existing = self.cookies.iter().position(|existing| existing.replaces(&cookie));
+        if let Some(index) = existing {
+            let old = self.cookies.remove(index);
+            cookie.created = old.created;
+        }
+        if cookie.is_expired(now) {
+            return existing.is_some();
+        }
+        debug!("Cookie {} for {}{}", cookie.name, cookie.domain, cookie.path);
+        self.cookies.push(cookie);
+        self.enforce_limits(now);
+        true
+    }
+
+    /// Remove expired cookies, then the least recently used beyond the limits.
+    fn enforce_limits(&mut self, now: u64) {
+        self.remove_expired(now);
+        let site = self.cookies.last().map(Cookie::site);
+        if let Some(site) = site {
+            let mut of_site: Vec<(u64, usize)> = self
+                .cookies
+                .iter()
+                .enumerate()
+                .filter(|(_, cookie)| cookie.site() == site)
+                .map(|(index, cookie)| (cookie.last_access, index))
+                .collect();
+            if of_site.len() > MAX_COOKIES_PER_SITE {
+                of_site.sort();
+                let mut evicted: Vec<usize> = of_site[..of_site.len() - MAX_COOKIES_PER_SITE]
+                    .iter()
+                    .map(|(_, index)| *index)
+                    .collect();
+                evicted.sort_unstable();
+                for index in evicted.into_iter().rev() {
+                    self.cookies.remove(index);
+                }
+            }
+        }
+        while self.cookies.len() > MAX_COOKIES {
+            let oldest = (0..self.cookies.len()).min_by_key(|index| self.cookies[*index].last_access);
+            if let Some(index) = oldest {
+                self.cookies.remove(index);
+            }
+        }
+    }
+
+    fn remove_expired(&mut self, now: u64) {
+        self.cookies.retain(|cookie| !cookie.is_expired(now));
+    }
+
+    pub fn remove(&mut self, cookie: &Cookie) {
+        self.cookies.retain(|existing| !existing.replaces(cookie));
+        self.save();
+    }
+
+    pub fn remove_site(&mut self, site: &str) {
+        self.cookies.retain(|cookie| cookie.site() != site);
+        self.save();
+    }
+
+    pub fn clear(&mut self) {
+        self.cookies.clear();
+        self.save();
+    }
+
+    /// Save the access times changed since the last save, as before exiting.
+    pub fn flush(&mut self) {
+        if self.accessed {
+            self.save();
+        }
+    }
+
+    fn save(&mut self) {
+        self.accessed = false;
+        let path = match &self.path {
+            Some(path) => path,
+            None => return,
+        };
+        let mut text =
+            String::from("# domain host-only path secure http-only same-site expires created accessed name value\n");
+        let field = |value: bool| if value { "TRUE" } else { "FALSE" };
+        for cookie in self.cookies.iter() {
+            let expires = match cookie.expires {
+                Some(expires) => expires,
+                None => continue,
+            };
+            text.push_str(&format!(
+                "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\n",
+                cookie.domain,
+                field(cookie.host_only),
+                cookie.path,
+                field(cookie.secure),
+                field(cookie.http_only),
+                cookie.same_site.as_str(),
+                expires,
+                cookie.created,
+                cookie.last_access,
+                cookie.name,
+                cookie.value
+            ));
+        }
+        if let Err(err) = std::fs::write(path, text) {
+            error!("Error while saving cookies to {:?}: {}", path, err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert2::check;
+
+    use super::super::http::Headers;
+    use super::*;
+
+    fn url(text: &str) -> Url {
+        Url::parse(text).unwrap()
+    }
+
+    fn set_cookies(jar: &mut CookieJar, request: &Request, headers: &[&str], now: u64) {
+        let mut response_headers = Headers::new();
+        for header in headers {
+            response_headers.add("Set-Cookie", header);
+        }
+        let response = Response {
+            url: request.url.clone(),
+            status: 200,
+            reason: String::from("OK"),
+            headers: response_headers,
+            body: vec![],
+            redirects: vec![],
+            from_cache: false,
+        };
+        jar.store_response(request, &response, now);
+    }
+
+    #[test]
+    fn parsing() {
+        let page = url("https://www.example.co.uk/app/page");
+        let cookie = Cookie::parse(
+            "id=42; Path=/; Domain=.Example.co.uk; Max-Age=60; Secure; HttpOnly; SameSite=Strict",
+            &page,
+            100,
+        )
+        .unwrap();
+        check!(cookie.name == "id");
+        check!(cookie.domain == "example.co.uk");
+        check!(!cookie.host_only);
+        check!(cookie.expires == Some(160));
+        check!(cookie.secure && cookie.http_only);
+        check!(cookie.same_site == SameSite::Strict);
+        check!(cookie.site() == "example.co.uk");
+
+        let cookie = Cookie::parse(
+            "flag; Expires=Wed, 21-Oct-2015 07:28:00 GMT; Path=relative",
+            &page,
+            1445000000,
+        )
+        .unwrap();
+        check!(cookie.name == "");
+        check!(cookie.value == "flag");
+        check!(cookie.path == "/app");
+        check!(cookie.host_only);
+        check!(cookie.expires == Some(1445412480));
+        check!(cookie.same_site == SameSite::Lax);
+        // Max-Age wins over Expires, lifetimes are capped
+        let cookie = Cookie::parse(
+            "a=b; Max-Age=999999999; Expires=Wed, 21 Oct 2015 07:28:00 GMT",
+            &page,
+            0,
+        )
+        .unwrap();
+        check!(cookie.expires == Some(MAX_AGE_LIMIT));
+
+        check!(Cookie::parse("a=b; Domain=co.uk", &page, 0).is_err());
+        check!(Cookie::parse("a=b; Domain=other.com", &page, 0).is_err());
+        check!(Cookie::parse("a=b; Secure", &url("http://example.com/"), 0).is_err());
+        check!(Cookie::parse("a=b; Secure", &url("http://localhost:8080/"), 0).is_ok());
+        check!(Cookie::parse("a=b; SameSite=None", &page, 0).is_err());
+        check!(Cookie::parse("__Host-a=b; Secure; Path=/; Domain=example.co.uk", &page, 0).is_err());
+        check!(Cookie::parse("__Host-a=b; Secure; Path=/", &page, 0).is_ok());
+        check!(Cookie::parse("=", &page, 0).is_err());
+        check!(Cookie::parse("a\tb=c", &page, 0).is_err());
+        // A public suffix may set host-only cookies on itself
+        let intranet = Cookie::parse("a=b; Domain=intranet", &url("http://intranet/"), 0).unwrap();
+        check!(intranet.host_only);
+        let address = Cookie::parse("a=b; Domain=10.0.0.1", &url("http://10.0.0.1/"), 0).unwrap();
+        check!(address.site() == "10.0.0.1");
+    }
+
+    #[test]
+    fn sending_and_policies() {
+        let mut jar = CookieJar::new();
+        let login = Request::get(url("https://tools.corp.example/login"));
+        set_cookies(
+            &mut jar,
+            &login,
+            &[
+                "session=s1; Path=/; Secure",
+                "lang=fr; Domain=corp.example",
+                "strict=1; SameSite=Strict",
+                "gone=1; Max-Age=0",
+            ],
+            0,
+        );
+        check!(jar.len() == 3);
+        check!(
+            jar.cookie_header(&Request::get(url("https://tools.corp.example/report")), 1)
+                .as_deref()
+                == Some("session=s1; lang=fr; strict=1")
+        );
+        check!(
+            jar.cookie_header(&Request::get(url("http://wiki.corp.example/")), 1)
+                .as_deref()
+                == Some("lang=fr")
+        );
+        check!(jar.cookie_header(&Request::get(url("https://example.com/")), 1) == None);
+
+        // Replacing keeps the creation time, expiring removes
+        set_cookies(&mut jar, &login, &["lang=en; Domain=corp.example"], 5);
+        check!(
+            jar.cookies()
+                .iter()
+                .find(|c| c.name == "lang")
+                .map(|c| (c.value.as_str(), c.created))
+                == Some(("en", 0))
+        );
+        set_cookies(&mut jar, &login, &["lang=en; Domain=corp.example; Max-Age=-1"], 5);
+        check!(jar.len() == 2);
+
+        // Requests made by a page of another site
+        let mut image = Request::get(url("https://tools.corp.example/pixel.png"));
+        image.site_for_cookies = Some(url("https://news.example/"));
+        jar.policy = CookiePolicy::AcceptAll;
+        check!(jar.cookie_header(&image, 1) == None);
+        let mut navigation = image.clone();
+        navigation.top_level = true;
+        check!(jar.cookie_header(&navigation, 1).as_deref() == Some("session=s1"));
+        set_cookies(&mut jar, &image, &["tracker=1; SameSite=None; Secure", "lax=1"], 2);
+        check!(jar.cookie_header(&image, 3).as_deref() == Some("tracker=1"));
+        check!(!jar.cookies().iter().any(|c| c.name == "lax"));
+
+        jar.policy = CookiePolicy::RejectThirdParty;
+        check!(jar.cookie_header(&image, 1) == None);
+        check!(jar.cookie_header(&navigation, 1).is_some());
+        jar.policy = CookiePolicy::RejectAll;
+        check!(jar.cookie_header(&Request::get(url("https://tools.corp.example/")), 1) == None);
+
+        check!(jar.sites() == vec![String::from("corp.example")]);
+        jar.remove_site("corp.example");
+        check!(jar.is_empty());
+    }
+
+    #[test]
+    fn persistence() {
+        let path = std::env::temp_dir().join(format!("rowser-cookies-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let now = super::super::http_cache::unix_now();
+        let mut jar = CookieJar::load(&path).unwrap();
+        let request = Request::get(url("https://example.com/"));
+        set_cookies(
+            &mut jar,
+            &request,
+            &[
+                "kept=1; Max-Age=3600; HttpOnly; SameSite=Strict",
+                "session=1",
+                "tabs=a\tb; Max-Age=3600; Path=/a\tb",
+                "tab\tname=1; Max-Age=3600",
+            ],
+            now,
+        );
+        check!(jar.len() == 3);
+        let loaded = CookieJar::load(&path).unwrap();
+        check!(loaded.cookies().len() == 2);
+        check!(loaded.cookies()[0] == jar.cookies()[0]);
+        check!(loaded.cookies()[1].value == "a\tb");
+        check!(loaded.cookies()[1] == jar.cookies()[2]);
+
+        // Sending cookies does not write the jar, flushing it does
+        check!(jar.cookie_header(&request, now + 10).is_some());
+        check!(CookieJar::load(&path).unwrap().cookies()[0].last_access == now);
+        jar.flush();
+        check!(CookieJar::load(&path).unwrap().cookies()[0].last_access == now + 10);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -3,11 +3,27 @@
 
 pub(crate) mod window_events;
 
-use super::gfxbuffer::GfxBuffer;
-use super::{geometry::*, gfxbuffer};
+use super::bitmap::{Bitmap, DecodedImage, ScaleFilter};
+use super::box_painter::paint_box_clips;
+use super::gfxbuffer::{self, Surface};
+use super::http::Response;
+use super::image_animation::ImageAnimation;
+use super::image_cache::ImageCache;
+use super::loader::Loader;
+use super::simple_error::SimpleError;
+use super::srcset::SrcSet;
+use super::zoom;
+use super::style::BoxStyle;
+use super::geometry::*;
+use super::rasterizer;
+use super::media::MediaFeatures;
+use super::region::Region;
+use super::settings::Settings;
 use core::fmt;
 use std::cell::RefCell;
 use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::rc::Rc;
 use std::{error::Error, fmt::Display};
 
 use super::color::{Color, Colors};
@@ -24,52 +40,128 @@ use super::dom::window_events::*;
 enum DomElementType {
     Root,
     Span,
+    Img,
 }
 
 #[derive(Debug)]
 struct DomElementChild {}
 
+/// Where an element leads when clicked.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Link {
+    pub url: url::Url,
+    /// `target=_blank`: open in a new window
+    pub new_window: bool,
+}
+
+/// An image an element waits for.
+#[derive(Debug, Clone, PartialEq)]
+struct PendingImage {
+    url: url::Url,
+    /// Once loaded, the element is sized to the intrinsic size divided by this density. None keeps its size.
+    density: Option<f64>,
+}
+
 #[derive(Debug)]
 struct DomElement {
     bounds: Rect,
     node_type: DomElementType,
     initialized: bool,
     paint_order: u32,
+    image: Option<Rc<DecodedImage>>,
+    animation: Option<ImageAnimation>,
+    /// Loading, to replace `image`
+    pending_image: Option<PendingImage>,
+    style: BoxStyle,
+    index_in_tree: usize,
+    /// Candidates of `<img srcset>`, selected again when the device pixel ratio changes
+    srcset: Option<SrcSet>,
+    link: Option<Link>,
+    /// The `id` attribute, target of URL fragments
+    id: Option<String>,
+    /// Index in the tree of the parent element, None for the root
+    parent: Option<usize>,
 }
 
+/// Lines scrolled by a wheel notch
+const SCROLL_LINE_PX: f64 = 40.0;
+
+/// A document: its element tree and everything needed to paint it into a part of the window.
 #[derive(Debug)]
-pub struct DomRoot<'a> {
+pub struct DomRoot {
     tree: Tree<DomElement>,
-    window: Option<&'a winit::window::Window>,
-    gfx_buffer: GfxBuffer<'a>,
-    invalidated_rects: Vec<Rect>,
-    last_window_size: Size,
-    redraw_requested_invoked: bool,
+    /// Area of the window showing the document, in device pixels
+    viewport: Rect,
+    /// Damage in device pixels, relative to the viewport
+    invalidated_rects: Region,
+    image_cache: ImageCache,
+    window_focused: bool,
+    cursor_position: Option<Position>,
+    /// Element under the cursor when the primary button was pressed
+    pressed_element: Option<usize>,
+    /// Device pixels per window pixel, from the platform.
+    scale_factor: f64,
+    /// Page zoom: scales CSS pixels on top of `scale_factor`.
+    zoom: f64,
+    /// Document position at the top left of the viewport, in CSS pixels
+    scroll: Position,
+    url: Option<url::Url>,
+    /// The `<title>` of the document
+    title: Option<String>,
+    /// Link clicked, until the browser window takes it
+    followed_link: Option<Link>,
+    /// Shared by all the documents: when its revision changes, the document restyles
+    settings: Rc<RefCell<Settings>>,
+    settings_revision: u64,
 }
 
-impl<'a> DomRoot<'a> {
-    pub fn new(window: &'a winit::window::Window) -> Self {
-        let wsize = window.inner_size();
+impl DomRoot {
+    /// `viewport` is in device pixels.
+    pub fn new(
+        viewport: Rect,
+        scale_factor: f64,
+        url: Option<url::Url>,
+        zoom: f64,
+        settings: Rc<RefCell<Settings>>,
+        loader: Rc<RefCell<Loader>>,
+    ) -> Self {
+        let device_pixel_ratio = scale_factor * zoom;
         let mut tree = Tree::new();
         if let Err(err) = tree.add_node(TreeNodeType::Root, || DomElement {
-            bounds: Rect::new(0.0, 0.0, wsize.width as f64, wsize.height as f64),
+            bounds: Rect::new(0.0, 0.0, viewport.width(), viewport.height()).scale(1.0 / device_pixel_ratio),
             node_type: DomElementType::Root,
             initialized: false,
             paint_order: 0,
+            image: None,
+            animation: None,
+            pending_image: None,
+            style: BoxStyle::with_background(Colors::BLACK),
+            index_in_tree: 0,
+            srcset: None,
+            link: None,
+            id: None,
+            parent: None,
         }) {
             panic!("Error while get root of tree {}", err);
         }
 
+        let settings_revision = settings.borrow().revision();
         let mut root = DomRoot {
             tree,
-            last_window_size: Size {
-                width: wsize.width as f64,
-                height: wsize.height as f64,
-            },
-            window: Some(window),
-            gfx_buffer: GfxBuffer::new(window.clone()),
-            invalidated_rects: Vec::new(),
-            redraw_requested_invoked:false
+            viewport,
+            invalidated_rects: Region::new(),
+            image_cache: ImageCache::new(loader, url.clone()),
+            window_focused: true,
+            cursor_position: None,
+            pressed_element: None,
+            scale_factor,
+            zoom,
+            scroll: Position { left: 0.0, top: 0.0 },
+            url,
+            title: None,
+            followed_link: None,
+            settings_revision,
+            settings,
         };
 
         root.tree.get_node_mut(0).unwrap().init();
@@ -79,119 +171,524 @@ impl<'a> DomRoot<'a> {
         root
     }
 
-    fn on_window_resize(&mut self) {
-        let wsize = self.window.as_ref().unwrap().inner_size();
-        debug!("Resize window to {}x{}", wsize.width, wsize.height);
-        if self.last_window_size == (wsize.width, wsize.height) {
-            debug!("Window size is the same, no need to resize");
-            return; 
+    pub fn url(&self) -> Option<&url::Url> {
+        self.url.as_ref()
+    }
+
+    /// Change the URL without loading: fragment navigation and pushed history states.
+    pub fn set_url(&mut self, url: url::Url) {
+        self.url = Some(url);
+    }
+
+    /// The `<title>` of the document, without extra whitespace. None if it has none.
+    pub fn title(&self) -> Option<&str> {
+        self.title.as_deref()
+    }
+
+    pub fn set_title(&mut self, title: Option<String>) {
+        self.title = title;
+    }
+
+    pub fn scroll_position(&self) -> Position {
+        self.scroll
+    }
+
+    /// Scroll to the element the fragment points to. An empty fragment or `top` scrolls to the top.
+    pub fn scroll_to_fragment(&mut self, fragment: &str) {
+        let fragment = percent_decode(fragment);
+        let top = Position { left: 0.0, top: 0.0 };
+        if fragment.is_empty() || fragment.eq_ignore_ascii_case("top") {
+            self.scroll_to(top);
+            return;
         }
-        
+        let mut target: Option<Rect> = None;
+        if let Err(err) = self.tree.trasverse_sorted_children(
+            0,
+            |a: &DomElement, b: &DomElement| a.paint_order.cmp(&b.paint_order),
+            &mut |node, _, _| {
+                if target.is_none() && node.id.as_deref() == Some(fragment.as_str()) {
+                    target = Some(node.bounds);
+                }
+            },
+        ) {
+            panic!("Error while tree.foreach_children {}", err);
+        }
+        match target {
+            Some(bounds) => self.scroll_to(bounds.position()),
+            None => debug!("No element with id {:?}", fragment),
+        }
+    }
+
+    fn on_window_focus(&mut self, focus: bool) {
+        debug!("Window focus {}", focus);
+        self.window_focused = focus;
+    }
+
+    /// `position` is in device pixels of the window, as reported by the platform.
+    fn on_cursor_moved(&mut self, position: Option<Position>) {
+        let scale = self.device_pixel_ratio();
+        self.cursor_position = position.map(|p| Position {
+            left: (p.left - self.viewport.left()) / scale + self.scroll.left,
+            top: (p.top - self.viewport.top()) / scale + self.scroll.top,
+        });
+    }
+
+    /// Device pixels per CSS pixel. Element geometry is in CSS pixels, the frame in device pixels.
+    ///   Same as the `devicePixelRatio` of the DOM, so it includes page zoom.
+    pub fn device_pixel_ratio(&self) -> f64 {
+        self.scale_factor * self.zoom
+    }
+
+    pub fn zoom(&self) -> f64 {
+        self.zoom
+    }
+
+    /// Zoom the page. Remembering the level is up to the caller.
+    pub fn set_zoom(&mut self, level: f64) {
+        let level = level.max(zoom::ZOOM_LEVELS[0]).min(zoom::ZOOM_LEVELS[zoom::ZOOM_LEVELS.len() - 1]);
+        if level == self.zoom {
+            return;
+        }
+        debug!("Zoom {} -> {}", self.zoom, level);
+        self.zoom = level;
+        self.on_device_pixel_ratio_changed();
+    }
+
+    fn on_mouse_wheel(&mut self, delta: &WheelDelta) {
+        let (x, y) = match delta {
+            WheelDelta::Lines { x, y } => (x * SCROLL_LINE_PX, y * SCROLL_LINE_PX),
+            WheelDelta::Pixels { x, y } => (x / self.device_pixel_ratio(), y / self.device_pixel_ratio()),
+        };
+        self.scroll_to(Position {
+            left: self.scroll.left - x,
+            top: self.scroll.top - y,
+        });
+    }
+
+    /// Scroll so that `position` of the document is at the top left of the viewport, within the document extent.
+    pub fn scroll_to(&mut self, position: Position) {
+        let root = self.tree.get_node_mut(0).unwrap().bounds;
+        let extent = self.document_extent();
+        let position = Position {
+            left: position.left.min(extent.width - root.width()).max(0.0),
+            top: position.top.min(extent.height - root.height()).max(0.0),
+        };
+        if position == self.scroll {
+            return;
+        }
+        self.scroll = position;
+        self.redraw_requested();
+    }
+
+    /// Size of the scrollable area: from the document origin to the farthest painted pixel.
+    fn document_extent(&mut self) -> Size {
+        let mut extent = Size::new(0.0, 0.0);
+        let mut transforms: HashMap<usize, Transform3D> = HashMap::new();
+        if let Err(err) = self.tree.trasverse_sorted_children(
+            0,
+            |a: &DomElement, b: &DomElement| a.paint_order.cmp(&b.paint_order),
+            &mut |node, _, _| {
+                if let DomElementType::Root = node.node_type {
+                    return;
+                }
+                let transform = node.transform(&transforms);
+                let ink = node.ink_bounds(transform.as_ref());
+                if let Some(transform) = transform {
+                    transforms.insert(node.index_in_tree, transform);
+                }
+                extent.width = extent.width.max(ink.right());
+                extent.height = extent.height.max(ink.bottom());
+            },
+        ) {
+            panic!("Error while tree.foreach_children {}", err);
+        }
+        extent
+    }
+
+    /// Values media queries are evaluated against.
+    pub fn media_features(&mut self) -> MediaFeatures {
+        MediaFeatures {
+            viewport: self.tree.get_node_mut(0).unwrap().bounds.size,
+            resolution: self.device_pixel_ratio(),
+            em: self.settings.borrow().default_font_size,
+        }
+    }
+
+    pub fn set_scale_factor(&mut self, scale_factor: f64) {
+        debug!("Scale factor changed {} -> {}", self.scale_factor, scale_factor);
+        if scale_factor == self.scale_factor {
+            return;
+        }
+        self.scale_factor = scale_factor;
+        self.on_device_pixel_ratio_changed();
+    }
+
+    fn on_device_pixel_ratio_changed(&mut self) {
+        self.cursor_position = None;
+        // The viewport size in CSS pixels changes too: relayout and rasterize everything again
+        self.layout_viewport();
+        self.select_srcset_images();
+        let scroll = self.scroll;
+        self.scroll_to(scroll);
+    }
+
+    fn on_settings_changed(&mut self) {
+        debug!("Settings changed, restyle {:?}", self.url);
+        fixme!("Apply the font settings once text is laid out, and javascript.enabled once scripts run");
+        // `em` in media queries is the default font size
+        self.select_srcset_images();
+        self.redraw_requested();
+    }
+
+    /// Load the best `srcset` candidate of every image for the current device pixel ratio.
+    fn select_srcset_images(&mut self) {
+        let features = self.media_features();
+        let image_cache = &mut self.image_cache;
+        let mut changed: Vec<Rect> = vec![];
+        let mut transforms: HashMap<usize, Transform3D> = HashMap::new();
+        if let Err(err) = self.tree.trasverse_sorted_children(
+            0,
+            |a: &DomElement, b: &DomElement| a.paint_order.cmp(&b.paint_order),
+            &mut |node, _, _| {
+                let transform = node.transform(&transforms);
+                if let Some(transform) = transform {
+                    transforms.insert(node.index_in_tree, transform);
+                }
+                let (candidate, density) = match node.srcset.as_ref().and_then(|s| s.select(&features)) {
+                    Some((candidate, density)) => (candidate.url.clone(), density),
+                    None => return,
+                };
+                // An element still waiting for its size takes it from the new candidate
+                let density = node.pending_image.as_ref().and_then(|pending| pending.density).map(|_| density);
+                match image_cache.get_or_load(&candidate) {
+                    Some(image) if node.image.as_ref().is_none_or(|current| !Rc::ptr_eq(current, &image)) => {
+                        debug!("Switch image to {}", candidate);
+                        changed.push(node.ink_bounds(transform.as_ref()));
+                        node.pending_image = Some(PendingImage { url: candidate, density });
+                        node.set_image(image);
+                        changed.push(node.ink_bounds(node.transform(&transforms).as_ref()));
+                    }
+                    Some(_) => node.pending_image = None,
+                    None => node.pending_image = Some(PendingImage { url: candidate, density }),
+                }
+            },
+        ) {
+            panic!("Error while tree.foreach_children {}", err);
+        }
+        for rect in changed {
+            self.invalidate_rect(&rect);
+        }
+    }
+
+    fn on_mouse_input(&mut self, pressed: bool, button: MouseButton) {
+        if button != MouseButton::Left {
+            return;
+        }
+        let position = match self.cursor_position {
+            Some(position) => position,
+            None => return,
+        };
+        let target = self.hit_test(&position);
+        if pressed {
+            self.pressed_element = target;
+        } else {
+            if let Some(target) = target.filter(|t| Some(*t) == self.pressed_element) {
+                debug!("Click on element {} at {:?}", target, position);
+                fixme!("Dispatch click events");
+                self.followed_link = self.tree.get_node(target, |node, _| Ok(node.link.clone())).unwrap();
+            }
+            self.pressed_element = None;
+        }
+    }
+
+    /// Topmost element under `position`, mapping the point back through the transforms of each element
+    ///   and its ancestors.
+    pub fn hit_test(&mut self, position: &Position) -> Option<usize> {
+        let mut target = None;
+        // Transforms of the elements and their ancestors, by index in the tree
+        let mut transforms: HashMap<usize, Transform3D> = HashMap::new();
+        if let Err(err) = self.tree.trasverse_sorted_children(
+            0,
+            |a: &DomElement, b: &DomElement| a.paint_order.cmp(&b.paint_order),
+            &mut |node, _, _| {
+                let transform = node.transform(&transforms);
+                if node.hit(position, transform.as_ref()) {
+                    target = Some(node.index_in_tree);
+                }
+                if let Some(transform) = transform {
+                    transforms.insert(node.index_in_tree, transform);
+                }
+            },
+        ) {
+            panic!("Error while tree.foreach_children {}", err);
+        }
+        target
+    }
+
+    /// Link under the cursor, for the status bar.
+    pub fn hovered_link(&mut self) -> Option<Link> {
+        let position = self.cursor_position?;
+        let target = self.hit_test(&position)?;
+        self.tree.get_node(target, |node, _| Ok(node.link.clone())).unwrap()
+    }
+
+    /// The link clicked since the last call.
+    pub fn take_followed_link(&mut self) -> Option<Link> {
+        self.followed_link.take()
+    }
+
+    fn set_link(&mut self, index_in_tree: usize, link: Option<Link>) {
+        self.tree.get_node_mut(index_in_tree).unwrap().link = link;
+    }
+
+    fn set_element_id(&mut self, index_in_tree: usize, id: Option<&str>) {
+        self.tree.get_node_mut(index_in_tree).unwrap().id = id.map(String::from);
+    }
+
+    /// Move or resize the area of the window showing the document, in device pixels.
+    pub fn set_viewport(&mut self, viewport: Rect) {
+        if viewport == self.viewport {
+            return;
+        }
+        debug!("Viewport {:?} -> {:?}", self.viewport, viewport);
+        self.viewport = viewport;
+        self.layout_viewport();
+    }
+
+    fn layout_viewport(&mut self) {
+        let size = self.viewport.size;
+        let device_pixel_ratio = self.device_pixel_ratio();
         if let Err(err) = self.tree.get_node(0, |node, _| {
-            node.bounds = Rect::new(0.0, 0.0, wsize.width as f64, wsize.height as f64);
+            node.bounds = Rect::new(0.0, 0.0, size.width, size.height).scale(1.0 / device_pixel_ratio);
             debug!("Resize root to {:?}", node.bounds);
             Ok(())
         }) {
             panic!("Error while get root of tree {}", err);
         }
-        self.gfx_buffer.resize();
-
-        self.last_window_size = Size {
-            width: wsize.width as f64,
-            height: wsize.height as f64,
-        };
         //Smooth paint on resize
-        self.invalidate_rect(&Rect::new(0.0, 0.0, wsize.width as f64, wsize.height as f64));
+        self.redraw_requested();
     }
 
+    /// `rect` is in CSS pixels of the document. The invalidated region is kept in device pixels.
     fn invalidate_rect(&mut self, rect: &Rect) {
         // limit rect to actual size.
         debug!("Invalidate rect {:?}", rect);
-        let new_rect = self
+        let root_bounds = self
             .tree
-            .get_node(0, |node, _| {
-                Ok(Rect::new(
-                    rect.left().max(0.0).min(node.bounds.right()),
-                    rect.top().max(0.0).min(node.bounds.bottom()),
-                    rect.width().max(0.0).min(node.bounds.right() - rect.left()),
-                    rect.height()
-                        .max(0.0)
-                        .min(node.bounds.bottom() - rect.top()),
-                ))
-            })
+            .get_node(0, |node, _| Ok(node.bounds))
             .unwrap();
-        debug!("Invalidate rebounded rect {:?}", new_rect);
-        if new_rect.size == self.last_window_size {
-            self.invalidated_rects.clear();
-        }
-        if !self.invalidated_rects.contains(&new_rect) {
-            self.invalidated_rects.push(new_rect);
-            self.invalidated_rects = Rect::update_region(&mut self.invalidated_rects);
+        let visible = rect.sub_pos(&self.scroll);
+        if let Some(new_rect) = visible.get_intersection_rect(&root_bounds) {
+            // Damage whole device pixels, so partially covered ones are repainted too
+            let new_rect = new_rect.scale(self.device_pixel_ratio()).round_out().to_rect();
+            debug!("Invalidate rebounded rect {:?}", new_rect);
+            self.invalidated_rects.union_rect(&new_rect);
         }
     }
+    /// `rect` is in device pixels of the window, e.g. where browser UI covered the document.
+    pub fn invalidate_window_rect(&mut self, rect: &Rect) {
+        let css = rect
+            .sub_pos(&self.viewport.position())
+            .scale(1.0 / self.device_pixel_ratio())
+            .add_pos(&self.scroll);
+        self.invalidate_rect(&css);
+    }
+
+    /// Repaint the whole viewport.
     pub fn redraw_requested(&mut self) {
-        //A full windows repain is invoked... can we trust it?
         let rect = self
             .tree
             .get_node(0, |node, _| Ok(node.bounds.clone()))
-            .unwrap();        
-        self.invalidate_rect(&rect);
+            .unwrap();
+        self.invalidate_rect(&rect.add_pos(&self.scroll));
     }
 
-    fn paint_rect(&mut self, rect: &Rect) {
-        debug!("Begin paint of {:?}", rect);
-        let position = self.tree.get_node_mut(0).unwrap().bounds.position();
+    /// `rects` are in device pixels of the viewport. Each element is painted once for all of them.
+    fn paint_rects(&mut self, surface: &mut Surface, rects: &[Rect]) {
+        debug!("Begin paint of {:?}", rects);
+        let scale = self.device_pixel_ratio();
+        let origin = self.viewport.position();
+        // Where the document origin lands in the window
+        let offset = Position {
+            left: origin.left - self.scroll.left * scale,
+            top: origin.top - self.scroll.top * scale,
+        };
+        let clips: Vec<Rect> = rects.iter().map(|rect| rect.add_pos(&origin)).collect();
+        // Device pixel transforms of the elements and their ancestors, by index in the tree
+        let mut transforms: HashMap<usize, Transform3D> = HashMap::new();
 
         //fixme!("Should check all nodes and arrange them using potizioning and z-index");
         if let Err(err) = self.tree.trasverse_sorted_children(
             0,
             |a: &DomElement, b: &DomElement| a.paint_order.cmp(&b.paint_order),
             &mut |node, _, _| {
-                debug!("Paint {:?} on {:?}", rect, node.bounds);
-                let rect = node.bounds.rebound(&rect.add_pos(&position));
+                debug!("Paint {:?} on {:?}", clips, node.bounds);
+                let style = node.style.scaled(scale);
+                let border_box = node.bounds.scale(scale).add_pos(&offset);
+                let ancestors = node.parent.and_then(|parent| transforms.get(&parent));
+                let transform = compose(style.transform_matrix(&border_box), ancestors);
+                let ink = match (node.node_type, &transform) {
+                    (DomElementType::Root, _) => None,
+                    (_, None) => Some(ink_bounds(&style, &border_box)),
+                    // Behind the viewer: nothing gets painted
+                    (_, Some(transform)) => Some(
+                        transform
+                            .map_rect(&ink_bounds(&style, &border_box))
+                            .unwrap_or_else(|| Rect::new(0.0, 0.0, 0.0, 0.0)),
+                    ),
+                };
+                let rects: Vec<Rect> = clips
+                    .iter()
+                    .map(|clip| ink.map_or(*clip, |ink| ink.rebound(clip)))
+                    .filter(|rect| !rect.is_empty())
+                    .collect();
 
-                if !rect.is_empty() {
-                    debug!("Paint rect after rebound {:?}", rect);
-                    node.paint(&mut self.gfx_buffer, &rect);
+                if !rects.is_empty() {
+                    debug!("Paint rects after rebound {:?}", rects);
+                    node.paint(surface, &rects, &style, &border_box, transform.as_ref());
                 } else {
-                    debug!("Skip rect after rebound {:?}", rect);
+                    debug!("Skip rects after rebound");
+                }
+                if let Some(transform) = transform {
+                    transforms.insert(node.index_in_tree, transform);
                 }
-                //self.paint_rect(index_in_tree, &rect);
             },
         ) {
             panic!("Error while tree.foreach_children {}", err);
         }
+    }
+
+    /// Paint the damaged part of the viewport on `surface`, the whole window. Returns true if something was painted.
+    pub fn paint(&mut self, surface: &mut Surface) -> bool {
+        let region = std::mem::take(&mut self.invalidated_rects);
+        let rects = region.rects();
+        let someinvalidated = rects.len();
+        if someinvalidated > 0 {
+            self.paint_rects(surface, &rects);
+        }
 
-        let result = self.gfx_buffer.render();
-        if result.is_err() {
-            error!("Error while rendering on framebuffer: {:?}", result.err());
+        if someinvalidated > 0 {
+            debug!("Painted {} rects", someinvalidated);
         }
+        someinvalidated > 0
     }
-    fn paint(&mut self) {
-        let mut someinvalidated = 0;
 
-        let rects = std::mem::replace(&mut self.invalidated_rects, vec![]);
+    /// Advance animations, then paint. Returns true if something was painted.
+    pub fn on_frame(&mut self, surface: &mut Surface, elapsed: f64) -> bool {
+        //main Frame is here
+        let revision = self.settings.borrow().revision();
+        if revision != self.settings_revision {
+            self.settings_revision = revision;
+            self.on_settings_changed();
+        }
+        self.receive_images();
+        self.animate_images(elapsed * 1000.0);
 
-        for rect in rects {
-            // Recoursivly paint rect on all children
-            self.paint_rect(&rect);
-            someinvalidated += 1;
+        if !self.invalidated_rects.is_empty() {
+            return self.paint(surface);
         }
+        false
+    }
 
-        if someinvalidated > 0 {
-            debug!(
-                "Painted {} rects. Remains: {}",
-                someinvalidated,
-                self.invalidated_rects.len()
-            );
+    /// True while some image loads or animation needs frames, so the event loop should not sleep.
+    pub fn is_animating(&mut self) -> bool {
+        // A frame to restyle, or to show the images loaded
+        if self.settings.borrow().revision() != self.settings_revision || self.image_cache.is_loading() {
+            return true;
+        }
+        if !self.window_focused {
+            return false;
+        }
+        let scroll = self.scroll;
+        let viewport = self.tree.get_node_mut(0).unwrap().bounds.add_pos(&scroll);
+        let mut animating = false;
+        let mut transforms: HashMap<usize, Transform3D> = HashMap::new();
+        if let Err(err) = self.tree.trasverse_sorted_children(
+            0,
+            |a: &DomElement, b: &DomElement| a.paint_order.cmp(&b.paint_order),
+            &mut |node, _, _| {
+                let transform = node.transform(&transforms);
+                if let Some(animation) = &node.animation {
+                    animating |= !animation.is_finished()
+                        && node.ink_bounds(transform.as_ref()).intersect_rect(&viewport);
+                }
+                if let Some(transform) = transform {
+                    transforms.insert(node.index_in_tree, transform);
+                }
+            },
+        ) {
+            panic!("Error while tree.foreach_children {}", err);
         }
+        animating
     }
 
-    pub fn on_frame(&mut self, _: f64) {
-        //main Frame is here
+    /// Show the images loaded since the last frame in the elements waiting for them.
+    fn receive_images(&mut self) {
+        let loaded = self.image_cache.take_loaded();
+        if loaded.is_empty() {
+            return;
+        }
+        let mut changed: Vec<Rect> = vec![];
+        let mut transforms: HashMap<usize, Transform3D> = HashMap::new();
+        if let Err(err) = self.tree.trasverse_sorted_children(
+            0,
+            |a: &DomElement, b: &DomElement| a.paint_order.cmp(&b.paint_order),
+            &mut |node, _, _| {
+                let image = node
+                    .pending_image
+                    .as_ref()
+                    .and_then(|pending| loaded.iter().find(|(url, _)| *url == pending.url));
+                if let Some((url, image)) = image {
+                    debug!("Show image {}", url);
+                    changed.push(node.ink_bounds(node.transform(&transforms).as_ref()));
+                    node.set_image(image.clone());
+                    changed.push(node.ink_bounds(node.transform(&transforms).as_ref()));
+                }
+                // Its size may have changed the transform origin
+                if let Some(transform) = node.transform(&transforms) {
+                    transforms.insert(node.index_in_tree, transform);
+                }
+            },
+        ) {
+            panic!("Error while tree.foreach_children {}", err);
+        }
+        for rect in changed {
+            self.invalidate_rect(&rect);
+        }
+    }
 
-        if self.invalidated_rects.len() > 0 {
-            self.paint();
+    /// Advance animated images. Only visible images play, and nothing plays while the window is unfocused.
+    fn animate_images(&mut self, elapsed_ms: f64) {
+        if !self.window_focused {
+            return;
+        }
+        let scroll = self.scroll;
+        let viewport = self.tree.get_node_mut(0).unwrap().bounds.add_pos(&scroll);
+        let mut changed: Vec<Rect> = vec![];
+        let mut transforms: HashMap<usize, Transform3D> = HashMap::new();
+        if let Err(err) = self.tree.trasverse_sorted_children(
+            0,
+            |a: &DomElement, b: &DomElement| a.paint_order.cmp(&b.paint_order),
+            &mut |node, _, _| {
+                let transform = node.transform(&transforms);
+                if let Some(transform) = transform {
+                    transforms.insert(node.index_in_tree, transform);
+                }
+                let ink = node.ink_bounds(transform.as_ref());
+                if !ink.intersect_rect(&viewport) {
+                    return;
+                }
+                if let (Some(image), Some(animation)) = (&node.image, &mut node.animation) {
+                    if animation.advance(image, elapsed_ms) {
+                        changed.push(ink);
+                    }
+                }
+            },
+        ) {
+            panic!("Error while tree.foreach_children {}", err);
+        }
+        for rect in changed {
+            self.invalidate_rect(&rect);
         }
     }
 
@@ -212,27 +709,526 @@ impl<'a> DomRoot<'a> {
                 node_type,
                 initialized: false,
                 paint_order: 0,
+                image: None,
+                animation: None,
+                pending_image: None,
+                style: match node_type {
+                    DomElementType::Span => BoxStyle::with_background(Colors::WHITE),
+                    _ => BoxStyle::default(),
+                },
+                index_in_tree: 0,
+                srcset: None,
+                link: None,
+                id: None,
+                parent: Some(parent_index_in_tree),
             })
             .unwrap();
-        self.tree.get_node_mut(index_in_tree).unwrap().paint_order = self.tree.len() as u32;
+        let paint_order = self.tree.len() as u32;
+        let node = self.tree.get_node_mut(index_in_tree).unwrap();
+        node.paint_order = paint_order;
+        node.index_in_tree = index_in_tree;
         index_in_tree
     }
+
+    /// Create an `<img>` at `position`. Without an explicit `size` the intrinsic size of the image is used,
+    ///   the element being empty until the image is loaded.
+    fn create_img_element_at(
+        &mut self,
+        parent_index_in_tree: usize,
+        position: Position,
+        src: &url::Url,
+        size: Option<Size>,
+    ) -> usize {
+        self.create_img_element(parent_index_in_tree, position, src, size, 1.0)
+    }
+
+    /// Create an `<img>` of the image at `src`, of the intrinsic size divided by `density` without `size`.
+    fn create_img_element(
+        &mut self,
+        parent_index_in_tree: usize,
+        position: Position,
+        src: &url::Url,
+        size: Option<Size>,
+        density: f64,
+    ) -> usize {
+        let index_in_tree = self.create_dom_element_at(
+            DomElementType::Img,
+            parent_index_in_tree,
+            Rect::new(position.left, position.top, 0.0, 0.0),
+        );
+        let image = self.image_cache.get_or_load(src);
+        let node = self.tree.get_node_mut(index_in_tree).unwrap();
+        if let Some(size) = size {
+            node.bounds = Rect::new(position.left, position.top, size.width, size.height);
+        }
+        node.pending_image = Some(PendingImage {
+            url: src.clone(),
+            density: size.is_none().then_some(density),
+        });
+        if let Some(image) = image {
+            node.set_image(image);
+        }
+        index_in_tree
+    }
+
+    /// Create an `<img srcset sizes>`, loading the candidate that best fits the device pixel ratio.
+    ///   Without an explicit `size`, the intrinsic size is divided by the candidate density.
+    fn create_img_element_from_srcset(
+        &mut self,
+        parent_index_in_tree: usize,
+        position: Position,
+        base: &url::Url,
+        srcset: &str,
+        sizes: Option<&str>,
+        size: Option<Size>,
+    ) -> Result<usize, SimpleError> {
+        let srcset = SrcSet::parse(base, srcset, sizes);
+        let (candidate, density) = srcset
+            .select(&self.media_features())
+            .map(|(candidate, density)| (candidate.url.clone(), density))
+            .ok_or_else(|| SimpleError::new("No valid srcset candidate"))?;
+        let index_in_tree = self.create_img_element(parent_index_in_tree, position, &candidate, size, density);
+        self.tree.get_node_mut(index_in_tree).unwrap().srcset = Some(srcset);
+        Ok(index_in_tree)
+    }
 }
 
 impl DomElement {
-    fn paint(&self, gfx: &mut GfxBuffer, rect: &Rect) {
-        match self.node_type {
-            DomElementType::Root => {
-                gfx.clear(rect, Colors::BLACK);
+    /// Show `image` in place of the pending one, playing it from its first frame if animated. An element
+    ///   waiting for its size takes the intrinsic size divided by the density.
+    fn set_image(&mut self, image: Rc<DecodedImage>) {
+        if let Some(density) = self.pending_image.take().and_then(|pending| pending.density) {
+            let size = image.intrinsic_size();
+            self.bounds = Rect::new(
+                self.bounds.left(),
+                self.bounds.top(),
+                size.width / density,
+                size.height / density,
+            );
+        }
+        self.animation = image.is_animated().then(ImageAnimation::new);
+        self.image = Some(image);
+    }
+
+    /// `rects`, the scaled `style` and `border_box` are in device pixels. `transform` composes the transforms
+    ///   of the element and its ancestors.
+    fn paint(
+        &self,
+        surface: &mut Surface,
+        rects: &[Rect],
+        style: &BoxStyle,
+        border_box: &Rect,
+        transform: Option<&Transform3D>,
+    ) {
+        if let DomElementType::Root = self.node_type {
+            for rect in rects {
+                surface.fill(&rect.round_out(), &Colors::BLACK.as_u8_ref());
+            }
+            return;
+        }
+        match transform {
+            None => self.paint_content(surface, style, border_box, rects),
+            Some(transform) => self.paint_transformed(surface, style, border_box, transform, rects),
+        }
+    }
+
+    /// Paint the element box with `style` and its border box placed at `border_box`, in each of `clips`.
+    fn paint_content(&self, surface: &mut Surface, style: &BoxStyle, border_box: &Rect, clips: &[Rect]) {
+        paint_box_clips(surface, border_box, style, clips);
+        if let Some(image) = &self.image {
+            let frame = self.animation.as_ref().map_or(0, |animation| animation.frame);
+            for clip in clips {
+                gfxbuffer::blit(
+                    surface,
+                    &image.frames[frame].bitmap,
+                    border_box,
+                    clip,
+                    ScaleFilter::Bilinear,
+                );
             }
-            DomElementType::Span => {
-                fixme!("Span Paint todo");
-                gfx.clear(rect, Colors::WHITE);
+        }
+    }
+
+    /// Paint untransformed into a layer once, then map the layer onto `surface` in each of `clips`.
+    fn paint_transformed(
+        &self,
+        surface: &mut Surface,
+        style: &BoxStyle,
+        border_box: &Rect,
+        transform: &Transform3D,
+        clips: &[Rect],
+    ) {
+        // The layer is painted again on every frame, it isn't cached between frames
+        let ink = ink_bounds(style, border_box);
+        let origin = Position {
+            left: ink.left().floor(),
+            top: ink.top().floor(),
+        };
+        let width = (ink.right() - origin.left).ceil().max(0.0) as u32;
+        let height = (ink.bottom() - origin.top).ceil().max(0.0) as u32;
+        let mut layer = match Bitmap::transparent(width, height) {
+            Ok(layer) => layer,
+            Err(err) => {
+                warn!("Transformed element not painted: {}", err);
+                return;
             }
+        };
+        {
+            let mut layer_surface = Surface::new(&mut layer.pixels, width as usize, height as usize);
+            let layer_box = border_box.sub_pos(&origin);
+            self.paint_content(&mut layer_surface, style, &layer_box, &[Rect::from_u32(0, 0, width, height)]);
         }
+        for clip in clips {
+            rasterizer::draw_transformed(surface, &layer, &origin, transform, clip);
+        }
+    }
+
+    /// True if `position` falls in the border box, once mapped back through `transform`, composing those
+    ///   of the element and its ancestors.
+    fn hit(&self, position: &Position, transform: Option<&Transform3D>) -> bool {
+        match self.node_type {
+            DomElementType::Root => false,
+            _ => {
+                let local = match transform {
+                    None => Some(*position),
+                    Some(transform) => transform.unproject_point(position),
+                };
+                local.is_some_and(|p| {
+                    p.left >= self.bounds.left()
+                        && p.left < self.bounds.right()
+                        && p.top >= self.bounds.top()
+                        && p.top < self.bounds.bottom()
+                })
+            }
+        }
+    }
+
+    /// The element transform then those of its ancestors, found in `transforms` by index in the tree,
+    ///   in CSS pixels.
+    fn transform(&self, transforms: &HashMap<usize, Transform3D>) -> Option<Transform3D> {
+        let ancestors = self.parent.and_then(|parent| transforms.get(&parent));
+        compose(self.style.transform_matrix(&self.bounds), ancestors)
     }
+
+    /// Area painted on screen, after `transform`, in CSS pixels.
+    fn ink_bounds(&self, transform: Option<&Transform3D>) -> Rect {
+        let ink = ink_bounds(&self.style, &self.bounds);
+        match transform {
+            None => ink,
+            // Behind the viewer: nothing gets painted
+            Some(transform) => transform
+                .map_rect(&ink)
+                .unwrap_or_else(|| Rect::new(0.0, 0.0, 0.0, 0.0)),
+        }
+    }
+
     fn init(&mut self) {
         debug!("Init {:?}", self);
         self.initialized = true;
     }
 }
+
+/// `transform` of an element, then those of its `ancestors`.
+fn compose(transform: Option<Transform3D>, ancestors: Option<&Transform3D>) -> Option<Transform3D> {
+    match (transform, ancestors) {
+        (Some(transform), Some(ancestors)) => Some(transform.then(ancestors)),
+        (transform, None) => transform,
+        (None, ancestors) => ancestors.copied(),
+    }
+}
+
+/// `border_box` grown by whatever paints outside of it: outer shadows and outline.
+fn ink_bounds(style: &BoxStyle, border_box: &Rect) -> Rect {
+    let mut overflow: f64 = 0.0;
+    for shadow in style.box_shadows.iter().filter(|s| !s.inset) {
+        overflow = overflow.max(
+            shadow.offset.left.abs().max(shadow.offset.top.abs())
+                + shadow.spread
+                + (shadow.blur * 1.5).ceil(),
+        );
+    }
+    if let Some(outline) = &style.outline {
+        overflow = overflow.max(outline.offset + outline.side.used_width());
+    }
+    if overflow <= 0.0 {
+        return *border_box;
+    }
+    Rect::new(
+        border_box.left() - overflow,
+        border_box.top() - overflow,
+        border_box.width() + overflow * 2.0,
+        border_box.height() + overflow * 2.0,
+    )
+}
+
+/// Text of the `<title>` element of `html`, whitespace collapsed.
+pub fn html_title(html: &str) -> Option<String> {
+    // Lowercase ASCII keeps byte offsets
+    let lower = html.to_ascii_lowercase();
+    let open = lower.find("<title")?;
+    let start = open + lower[open..].find('>')? + 1;
+    let end = start + lower[start..].find("</title").unwrap_or(lower.len() - start);
+    fixme!("Decode character references of titles");
+    let title = html[start..end].split_whitespace().collect::<Vec<&str>>().join(" ");
+    Some(title).filter(|title| !title.is_empty())
+}
+
+/// The `<title>` of `response` if it is an HTML document.
+pub fn response_title(response: &Response) -> Option<String> {
+    match response.mime_type().as_deref() {
+        Some("text/html") | Some("application/xhtml+xml") => html_title(&String::from_utf8_lossy(&response.body)),
+        _ => None,
+    }
+}
+
+/// `%XX` escapes of a URL fragment decoded, invalid UTF-8 replaced.
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .filter(|_| bytes[i] == b'%')
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
+
+    use assert2::check;
+    use base64::engine::general_purpose::STANDARD;
+    use base64::Engine;
+    use image::codecs::gif::GifEncoder;
+    use image::{Delay, DynamicImage, Frame, ImageFormat, Rgba, RgbaImage};
+
+    use super::super::bitmap::decode_image;
+    use super::super::http::HttpClient;
+    use super::super::style::{LengthPercentage, TransformFunction};
+    use super::*;
+
+    const RED: [u8; 4] = [255, 0, 0, 255];
+    const BLUE: [u8; 4] = [0, 0, 255, 255];
+
+    fn document(width: u32, height: u32) -> DomRoot {
+        let loader = Rc::new(RefCell::new(Loader::new(Arc::new(Mutex::new(HttpClient::new())))));
+        DomRoot::new(
+            Rect::from_u32(0, 0, width, height),
+            1.0,
+            Some(url::Url::parse("https://example.com/").unwrap()),
+            1.0,
+            Rc::new(RefCell::new(Settings::new())),
+            loader,
+        )
+    }
+
+    fn png_data(width: u32, height: u32, color: [u8; 4]) -> Vec<u8> {
+        let mut data = Vec::new();
+        DynamicImage::ImageRgba8(RgbaImage::from_pixel(width, height, Rgba(color)))
+            .write_to(&mut Cursor::new(&mut data), ImageFormat::Png)
+            .unwrap();
+        data
+    }
+
+    fn png(width: u32, height: u32, color: [u8; 4]) -> DecodedImage {
+        decode_image(&png_data(width, height, color)).unwrap()
+    }
+
+    /// Two frames of `size` x `size` pixels, red then blue, played once.
+    fn gif(size: u32) -> DecodedImage {
+        let mut data = Vec::new();
+        {
+            let mut encoder = GifEncoder::new(&mut data);
+            let frames = [RED, BLUE].map(|color| {
+                let img = RgbaImage::from_pixel(size, size, Rgba(color));
+                Frame::from_parts(img, 0, 0, Delay::from_numer_denom_ms(100, 1))
+            });
+            encoder.encode_frames(frames).unwrap();
+        }
+        decode_image(&data).unwrap()
+    }
+
+    /// Paint everything into a new buffer of the document size.
+    fn render(document: &mut DomRoot) -> Vec<u8> {
+        let (width, height) = (document.viewport.width() as usize, document.viewport.height() as usize);
+        let mut buffer = vec![0; width * height * 4];
+        document.redraw_requested();
+        document.paint(&mut Surface::new(&mut buffer, width, height));
+        buffer
+    }
+
+    /// Run frames until the images being loaded are shown, then paint everything as `render`.
+    fn render_loaded(document: &mut DomRoot) -> Vec<u8> {
+        let (width, height) = (document.viewport.width() as usize, document.viewport.height() as usize);
+        let mut buffer = vec![0; width * height * 4];
+        let start = Instant::now();
+        while document.image_cache.is_loading() && start.elapsed() < Duration::from_secs(10) {
+            document.on_frame(&mut Surface::new(&mut buffer, width, height), 0.0);
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        render(document)
+    }
+
+    fn pixel(buffer: &[u8], width: usize, x: usize, y: usize) -> [u8; 4] {
+        let i = (y * width + x) * 4;
+        [buffer[i], buffer[i + 1], buffer[i + 2], buffer[i + 3]]
+    }
+
+    #[test]
+    fn images() {
+        let mut document = document(100, 100);
+        let src = url::Url::parse("https://example.com/red.png").unwrap();
+        document.image_cache.insert(&src, png(4, 2, RED));
+        let position = Position { left: 10.0, top: 20.0 };
+        let img = document.create_img_element_at(0, position, &src, None);
+        check!(document.tree.get_node_mut(img).unwrap().bounds == Rect::new(10.0, 20.0, 4.0, 2.0));
+
+        let buffer = render(&mut document);
+        check!(pixel(&buffer, 100, 10, 20) == RED);
+        check!(pixel(&buffer, 100, 13, 21) == RED);
+        check!(pixel(&buffer, 100, 14, 21) != RED);
+        check!(pixel(&buffer, 100, 13, 22) != RED);
+    }
+
+    #[test]
+    fn loaded_images() {
+        let mut document = document(100, 100);
+        let data = STANDARD.encode(png_data(4, 2, RED));
+        let src = url::Url::parse(&format!("data:image/png;base64,{}", data)).unwrap();
+        let position = Position { left: 10.0, top: 20.0 };
+        let img = document.create_img_element_at(0, position, &src, None);
+        // Empty until loaded off the event loop, which keeps sending frames meanwhile
+        check!(document.tree.get_node_mut(img).unwrap().bounds == Rect::new(10.0, 20.0, 0.0, 0.0));
+        check!(document.is_animating());
+
+        let buffer = render_loaded(&mut document);
+        check!(document.tree.get_node_mut(img).unwrap().bounds == Rect::new(10.0, 20.0, 4.0, 2.0));
+        check!(pixel(&buffer, 100, 13, 21) == RED);
+        check!(!document.is_animating());
+
+        // Images that fail to load leave their element empty
+        let missing = url::Url::parse("unknown://example.com/missing.png").unwrap();
+        let img = document.create_img_element_at(0, position, &missing, None);
+        render_loaded(&mut document);
+        let node = document.tree.get_node_mut(img).unwrap();
+        check!(node.image.is_none());
+        check!(node.bounds == Rect::new(10.0, 20.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn animated_images() {
+        let mut document = document(20, 20);
+        let src = url::Url::parse("https://example.com/anim.gif").unwrap();
+        document.image_cache.insert(&src, gif(2));
+        let position = Position { left: 1.0, top: 1.0 };
+        document.create_img_element_at(0, position, &src, None);
+        check!(document.is_animating());
+
+        let mut buffer = render(&mut document);
+        check!(pixel(&buffer, 20, 1, 1) == RED);
+        let mut surface = Surface::new(&mut buffer, 20, 20);
+        check!(!document.on_frame(&mut surface, 0.05));
+        check!(document.on_frame(&mut surface, 0.06));
+        check!(pixel(&buffer, 20, 1, 1) == BLUE);
+        // Played once, it stays on the last frame
+        let mut surface = Surface::new(&mut buffer, 20, 20);
+        check!(!document.on_frame(&mut surface, 0.2));
+        check!(!document.is_animating());
+        check!(pixel(&buffer, 20, 1, 1) == BLUE);
+    }
+
+    #[test]
+    fn transformed_animated_images() {
+        let mut document = document(20, 20);
+        let src = url::Url::parse("https://example.com/anim.gif").unwrap();
+        document.image_cache.insert(&src, gif(4));
+        // Right of the viewport, brought into it twice as large: painted at 2,2 to 10,10
+        let parent = document.create_dom_element_at(DomElementType::Span, 0, Rect::new(42.0, 2.0, 4.0, 4.0));
+        let style = &mut document.tree.get_node_mut(parent).unwrap().style;
+        style.transform = vec![
+            TransformFunction::Translate(LengthPercentage::Px(-40.0), LengthPercentage::Px(0.0)),
+            TransformFunction::Scale(2.0, 2.0),
+        ];
+        style.transform_origin = (LengthPercentage::Px(0.0), LengthPercentage::Px(0.0));
+        let position = Position { left: 42.0, top: 2.0 };
+        document.create_img_element_at(parent, position, &src, None);
+        check!(document.is_animating());
+
+        let mut buffer = render(&mut document);
+        check!(pixel(&buffer, 20, 9, 9) == RED);
+        let mut surface = Surface::new(&mut buffer, 20, 20);
+        check!(document.on_frame(&mut surface, 0.11));
+        // The whole painted area is repainted, not only the untransformed bounds
+        check!(pixel(&buffer, 20, 2, 2) == BLUE);
+        check!(pixel(&buffer, 20, 9, 9) == BLUE);
+    }
+
+    #[test]
+    fn srcset_images() {
+        let mut document = document(40, 40);
+        let base = url::Url::parse("https://example.com/").unwrap();
+        document.image_cache.insert(&base.join("low.png").unwrap(), png(4, 4, RED));
+        document.image_cache.insert(&base.join("high.png").unwrap(), png(8, 8, BLUE));
+        let position = Position { left: 2.0, top: 2.0 };
+        let img = document
+            .create_img_element_from_srcset(0, position, &base, "low.png 1x, high.png 2x", None, None)
+            .unwrap();
+        check!(document.tree.get_node_mut(img).unwrap().bounds == Rect::new(2.0, 2.0, 4.0, 4.0));
+        check!(pixel(&render(&mut document), 40, 3, 3) == RED);
+
+        // The same CSS size at twice the device pixels, from the 2x candidate
+        document.set_scale_factor(2.0);
+        check!(document.tree.get_node_mut(img).unwrap().bounds == Rect::new(2.0, 2.0, 4.0, 4.0));
+        let buffer = render(&mut document);
+        check!(pixel(&buffer, 40, 4, 4) == BLUE);
+        check!(pixel(&buffer, 40, 11, 11) == BLUE);
+        check!(pixel(&buffer, 40, 12, 12) != BLUE);
+    }
+
+    #[test]
+    fn transformed_ancestors() {
+        let mut document = document(100, 100);
+        let parent = document.create_dom_element_at(DomElementType::Span, 0, Rect::new(20.0, 20.0, 40.0, 40.0));
+        document.tree.get_node_mut(parent).unwrap().style.transform = vec![TransformFunction::Rotate(90.0)];
+        let child = document.create_dom_element_at(DomElementType::Span, parent, Rect::new(20.0, 20.0, 10.0, 10.0));
+        document.tree.get_node_mut(child).unwrap().style = BoxStyle::with_background(Color::from_u8_arr(&BLUE));
+
+        // Rotating the parent around its center moves the child from its top left corner to the top right
+        check!(document.hit_test(&Position { left: 55.0, top: 25.0 }) == Some(child));
+        check!(document.hit_test(&Position { left: 25.0, top: 25.0 }) == Some(parent));
+        check!(document.hit_test(&Position { left: 65.0, top: 25.0 }) == None);
+        let buffer = render(&mut document);
+        check!(pixel(&buffer, 100, 55, 25) == BLUE);
+        check!(pixel(&buffer, 100, 25, 25) == [0xff, 0xff, 0xff, 0xff]);
+    }
+
+    #[test]
+    fn titles() {
+        check!(html_title("<html><head><TITLE id=t>\n  Hello\n  world </title>") == Some(String::from("Hello world")));
+        check!(html_title("<title>Unclosed") == Some(String::from("Unclosed")));
+        check!(html_title("<title> </title>") == None);
+        check!(html_title("<p>No title</p>") == None);
+
+        let url = url::Url::parse("https://example.com/").unwrap();
+        let html = b"<title>Page</title>".to_vec();
+        let response = Response::new(url.clone(), 200, "text/html; charset=utf-8", html.clone());
+        check!(response_title(&response) == Some(String::from("Page")));
+        check!(response_title(&Response::new(url, 200, "text/plain", html)) == None);
+    }
+}
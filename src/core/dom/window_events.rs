@@ -4,7 +4,7 @@ use crate::core::geometry::*;
 
 use super::*;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct DeviceId {
     id: String,
 }
@@ -17,6 +17,64 @@ impl DeviceId {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MouseButton {
+    Left,
+    Right,
+    Middle,
+    Other(u16),
+}
+
+/// Keys the browser reacts to. Letters and digits are `Char`, lowercase.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Key {
+    Char(char),
+    Plus,
+    Minus,
+    Equals,
+    Enter,
+    Escape,
+    Backspace,
+    Delete,
+    Tab,
+    Left,
+    Right,
+    Up,
+    Down,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+    F5,
+    F6,
+    Other,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Modifiers {
+    pub ctrl: bool,
+    pub shift: bool,
+    pub alt: bool,
+    pub logo: bool,
+}
+
+impl Modifiers {
+    /// Modifier of the shortcuts: Cmd on macOS, Ctrl elsewhere.
+    pub fn primary(&self) -> bool {
+        if cfg!(target_os = "macos") {
+            self.logo
+        } else {
+            self.ctrl
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WheelDelta {
+    Lines { x: f64, y: f64 },
+    Pixels { x: f64, y: f64 },
+}
+
 #[derive(Debug)]
 pub enum Event {
     Resized,
@@ -33,12 +91,37 @@ pub enum Event {
     CursorLeft {
         device_id: DeviceId,
     },
+    MouseInput {
+        device_id: DeviceId,
+        pressed: bool,
+        button: MouseButton,
+    },
     CursorEntered {
         device_id: DeviceId,
     },
     Focused {
         focus: bool,
     },
+    KeyboardInput {
+        device_id: DeviceId,
+        pressed: bool,
+        key: Key,
+        modifiers: Modifiers,
+    },
+    /// Text typed, after keyboard layout and dead keys. Comes along `KeyboardInput`.
+    ReceivedCharacter {
+        character: char,
+    },
+    /// Positive `y` scrolls up, away from the user.
+    MouseWheel {
+        device_id: DeviceId,
+        delta: WheelDelta,
+        modifiers: Modifiers,
+    },
+    /// Device pixels per CSS pixel changed, e.g. moving to another monitor.
+    ScaleFactorChanged {
+        scale_factor: f64,
+    },
     Moved {
         position: Position,
     },
@@ -59,11 +142,29 @@ pub trait EventHandler {
     fn on_window_resize(&mut self) {}
 }
 
-impl<'a> EventHandler for DomRoot<'a> {
+/// Events of the document area. Window wide events and shortcuts are handled by the browser window first.
+impl EventHandler for DomRoot {
     fn on_window_event(&mut self, event: &Event) {
         match event {
-            Event::Resized => self.on_window_resize(),
+            Event::Resized => (),
             Event::CloseRequested => (),
+            Event::Focused { focus } => self.on_window_focus(*focus),
+            Event::ScaleFactorChanged { scale_factor } => self.set_scale_factor(*scale_factor),
+            #[allow(unused)]
+            Event::KeyboardInput {
+                device_id,
+                pressed,
+                key,
+                modifiers,
+            } => (),
+            Event::ReceivedCharacter { .. } => (),
+            #[allow(unused)]
+            Event::MouseWheel {
+                device_id,
+                delta,
+                modifiers,
+            } => self.on_mouse_wheel(delta),
+            // Raw device motion: wheel zoom and scrolling come from `MouseWheel`
             #[allow(unused)]
             Event::AxisMotion {
                 device_id,
@@ -74,7 +175,15 @@ impl<'a> EventHandler for DomRoot<'a> {
             Event::CursorMoved {
                 device_id,
                 position,
-            } => (),
+            } => self.on_cursor_moved(Some(*position)),
+            #[allow(unused)]
+            Event::CursorLeft { device_id } => self.on_cursor_moved(None),
+            #[allow(unused)]
+            Event::MouseInput {
+                device_id,
+                pressed,
+                button,
+            } => self.on_mouse_input(*pressed, *button),
             _ => {
                 fixme!(format!("{:?}", event))
             }
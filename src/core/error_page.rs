@@ -0,0 +1,223 @@
+#![allow(dead_code)]
+#![allow(unused_imports)]
+
+use log::{debug, error, info, warn};
+use url::Url;
+
+use crate::fixme;
+
+use super::box_painter::paint_box;
+use super::color::Color;
+use super::geometry::{Position, Rect};
+use super::gfxbuffer::Surface;
+use super::style::{BoxStyle, CornerRadii};
+use super::tab_strip::ICON_COLOR;
+use super::text::{draw_text, elide, text_width};
+
+/// Sizes are in CSS pixels at zoom 1, like the rest of the browser UI.
+const MAX_WIDTH: f64 = 640.0;
+const PADDING: f64 = 32.0;
+const TITLE_FONT_SIZE: f64 = 18.0;
+const FONT_SIZE: f64 = 11.0;
+const LINE_HEIGHT: f64 = 18.0;
+const BUTTON_HEIGHT: f64 = 28.0;
+const OVERRIDE_LABEL: &str = "Accept the risk and continue";
+
+const PAGE_COLOR: Color = Color {
+    r: 0x20,
+    g: 0x21,
+    b: 0x24,
+    a: 0xff,
+};
+const WARNING_COLOR: Color = Color {
+    r: 0xf2,
+    g: 0x8b,
+    b: 0x82,
+    a: 0xff,
+};
+const BUTTON_COLOR: Color = Color {
+    r: 0x3c,
+    g: 0x40,
+    b: 0x43,
+    a: 0xff,
+};
+
+/// Shown by the browser instead of a document that could not be fetched.
+///   Refused certificates get a button to trust the certificate anyway.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ErrorPage {
+    pub url: Url,
+    pub message: String,
+    /// Host whose refused certificate the page offers to trust
+    pub certificate_host: Option<String>,
+}
+
+impl ErrorPage {
+    pub fn new(url: Url, message: &str, certificate_host: Option<String>) -> Self {
+        Self {
+            url,
+            message: String::from(message),
+            certificate_host,
+        }
+    }
+
+    pub fn title(&self) -> &str {
+        match self.certificate_host {
+            Some(_) => "Warning: certificate not trusted",
+            None => "This page could not be loaded",
+        }
+    }
+
+    /// Lines of text under the title.
+    fn lines(&self, width: f64) -> Vec<String> {
+        let mut lines = wrap(&self.message, FONT_SIZE, width);
+        if let Some(host) = &self.certificate_host {
+            lines.push(String::new());
+            let warning = format!(
+                "Someone could be impersonating {} to steal what you send it. \
+                  Continue only if you know why its certificate is not trusted.",
+                host
+            );
+            lines.extend(wrap(&warning, FONT_SIZE, width));
+        }
+        lines
+    }
+
+    /// Text column of a page in `bounds`.
+    fn column(bounds: &Rect) -> Rect {
+        let width = (bounds.width() - PADDING * 2.0).clamp(0.0, MAX_WIDTH);
+        Rect::new(
+            bounds.left() + (bounds.width() - width) / 2.0,
+            bounds.top() + PADDING * 2.0,
+            width,
+            (bounds.height() - PADDING * 2.0).max(0.0),
+        )
+    }
+
+    /// The override button, for certificate errors.
+    fn override_bounds(&self, bounds: &Rect) -> Option<Rect> {
+        self.certificate_host.as_ref()?;
+        let column = Self::column(bounds);
+        let lines = self.lines(column.width()).len() as f64;
+        let top = column.top() + TITLE_FONT_SIZE + LINE_HEIGHT * (lines + 2.0);
+        let width = text_width(OVERRIDE_LABEL, FONT_SIZE) + PADDING;
+        Some(Rect::new(column.left(), top, width, BUTTON_HEIGHT))
+    }
+
+    /// True if `pt` is on the button trusting the certificate, for a page in `bounds`.
+    pub fn is_override_at(&self, bounds: &Rect, pt: &Position) -> bool {
+        self.override_bounds(bounds)
+            .is_some_and(|button| button.contains_point(pt))
+    }
+
+    /// Paint the page in `bounds`, in CSS pixels, `scale` device pixels per CSS pixel.
+    pub fn paint(&self, surface: &mut Surface, bounds: &Rect, scale: f64, clip: &Rect) {
+        let clip = match bounds.scale(scale).get_intersection_rect(clip) {
+            Some(clip) => clip,
+            None => return,
+        };
+        paint_box(
+            surface,
+            &bounds.scale(scale),
+            &BoxStyle::with_background(PAGE_COLOR),
+            &clip,
+        );
+        let column = Self::column(bounds);
+        let title_color = if self.certificate_host.is_some() {
+            WARNING_COLOR
+        } else {
+            ICON_COLOR
+        };
+        let title = elide(self.title(), TITLE_FONT_SIZE, column.width());
+        let origin = Position {
+            left: column.left() * scale,
+            top: column.top() * scale,
+        };
+        draw_text(surface, &title, &origin, TITLE_FONT_SIZE * scale, title_color, &clip);
+
+        let mut top = column.top() + TITLE_FONT_SIZE + LINE_HEIGHT;
+        let url = elide(self.url.as_str(), FONT_SIZE, column.width());
+        for line in std::iter::once(url).chain(self.lines(column.width())) {
+            let origin = Position {
+                left: column.left() * scale,
+                top: top * scale,
+            };
+            draw_text(surface, &line, &origin, FONT_SIZE * scale, ICON_COLOR, &clip);
+            top += LINE_HEIGHT;
+        }
+
+        if let Some(button) = self.override_bounds(bounds) {
+            let mut style = BoxStyle::with_background(BUTTON_COLOR);
+            style.border_radius = CornerRadii::uniform(4.0 * scale);
+            paint_box(surface, &button.scale(scale), &style, &clip);
+            let origin = Position {
+                left: (button.left() + PADDING / 2.0) * scale,
+                top: (button.top() + (BUTTON_HEIGHT - FONT_SIZE) / 2.0) * scale,
+            };
+            draw_text(
+                surface,
+                OVERRIDE_LABEL,
+                &origin,
+                FONT_SIZE * scale,
+                WARNING_COLOR,
+                &clip,
+            );
+        }
+    }
+}
+
+/// Split `text` in lines of at most `width`, between words when possible.
+fn wrap(text: &str, size: f64, width: f64) -> Vec<String> {
+    let max_chars = ((width / size) as usize).max(1);
+    let mut lines = vec![];
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        let mut word = word;
+        if !line.is_empty() && line.chars().count() + 1 + word.chars().count() > max_chars {
+            lines.push(std::mem::take(&mut line));
+        }
+        // Words longer than a line are cut
+        while word.chars().count() > max_chars {
+            let (start, rest) = word.split_at(word.char_indices().nth(max_chars).unwrap().0);
+            if !line.is_empty() {
+                lines.push(std::mem::take(&mut line));
+            }
+            lines.push(String::from(start));
+            word = rest;
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(word);
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use assert2::check;
+
+    use super::*;
+
+    #[test]
+    fn wrapping_and_override_button() {
+        check!(wrap("one two three", 10.0, 75.0) == vec!["one two", "three"]);
+        check!(wrap("abcdefghij", 10.0, 40.0) == vec!["abcd", "efgh", "ij"]);
+
+        let bounds = Rect::new(0.0, 70.0, 800.0, 500.0);
+        let url = Url::parse("https://self-signed.example/").unwrap();
+        let page = ErrorPage::new(url.clone(), "Connection refused", None);
+        check!(page.override_bounds(&bounds) == None);
+        let page = ErrorPage::new(url, "Not trusted", Some(String::from("self-signed.example")));
+        let button = page.override_bounds(&bounds).unwrap();
+        let center = Position {
+            left: button.left() + button.width() / 2.0,
+            top: button.top() + button.height() / 2.0,
+        };
+        check!(page.is_override_at(&bounds, &center));
+        check!(!page.is_override_at(&bounds, &Position { left: 5.0, top: 75.0 }));
+    }
+}
@@ -0,0 +1,106 @@
+#![allow(dead_code)]
+#![allow(unused_imports)]
+
+use std::sync::mpsc::{self, Receiver, Sender};
+
+use log::{debug, error, info, warn};
+
+use super::http::{Request, Response};
+use super::http_cache::CacheMode;
+use super::loader::Loader;
+use super::simple_error::SimpleError;
+
+/// Loads URLs on threads of their own, for the event loop to take the results without waiting for them.
+#[derive(Debug)]
+pub struct Fetcher<T> {
+    sender: Sender<T>,
+    receiver: Receiver<T>,
+    /// Loads started whose result was not taken yet
+    pending: usize,
+}
+
+impl<T: Send + 'static> Default for Fetcher<T> {
+    fn default() -> Self {
+        let (sender, receiver) = mpsc::channel();
+        Self {
+            sender,
+            receiver,
+            pending: 0,
+        }
+    }
+}
+
+impl<T: Send + 'static> Fetcher<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load `request` with a clone of `loader` on a new thread, where `finish` turns the response into
+    ///   the result taken by `take_finished`.
+    pub fn start<F>(&mut self, loader: &Loader, request: Request, mode: CacheMode, finish: F)
+    where
+        F: FnOnce(Result<Response, SimpleError>) -> T + Send + 'static,
+    {
+        debug!("Fetch {} in the background", request.url);
+        let loader = loader.clone();
+        let sender = self.sender.clone();
+        std::thread::spawn(move || {
+            let result = finish(loader.load(request, mode));
+            // Fails only once the fetcher is dropped, when nobody wants the result
+            let _ = sender.send(result);
+        });
+        self.pending += 1;
+    }
+
+    /// Results of the loads finished since the last call, without waiting for the others.
+    pub fn take_finished(&mut self) -> Vec<T> {
+        let finished: Vec<T> = self.receiver.try_iter().collect();
+        self.pending -= finished.len();
+        finished
+    }
+
+    /// True while some load was started and its result not taken.
+    pub fn is_busy(&self) -> bool {
+        self.pending > 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
+
+    use assert2::check;
+    use url::Url;
+
+    use super::super::http::HttpClient;
+    use super::*;
+
+    #[test]
+    fn background_loads() {
+        let loader = Loader::new(Arc::new(Mutex::new(HttpClient::new())));
+        let mut fetcher = Fetcher::new();
+        check!(!fetcher.is_busy());
+        for url in ["data:,first", "data:,second", "nothing:here"] {
+            let request = Request::get(Url::parse(url).unwrap());
+            fetcher.start(&loader, request, CacheMode::Default, move |result| {
+                (url, result.map(|response| response.body))
+            });
+        }
+        check!(fetcher.is_busy());
+
+        let mut finished = vec![];
+        let start = Instant::now();
+        while fetcher.is_busy() && start.elapsed() < Duration::from_secs(10) {
+            finished.append(&mut fetcher.take_finished());
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        finished.sort_by_key(|(url, _)| *url);
+        check!(finished.len() == 3);
+        check!(finished[0].0 == "data:,first");
+        check!(finished[0].1.as_deref().ok() == Some(&b"first"[..]));
+        check!(finished[1].1.as_deref().ok() == Some(&b"second"[..]));
+        check!(finished[2].1.is_err());
+        check!(fetcher.take_finished().is_empty());
+    }
+}
@@ -3,6 +3,8 @@
 
 use log::{debug, error, info, warn};
 
+use super::region::Region;
+
 #[derive(Debug, Clone, PartialEq, Copy)]
 pub struct Position {
     pub left: f64,
@@ -85,8 +87,8 @@ impl Rect {
                 top: self.top().max(tocheck.top()),
             },
             size: Size {
-                width: self.width().min(tocheck.right() - self.left()).max(0.0),
-                height: self.height().min(tocheck.height() - self.top()).max(0.0),
+                width: (self.right().min(tocheck.right()) - self.left().max(tocheck.left())).max(0.0),
+                height: (self.bottom().min(tocheck.bottom()) - self.top().max(tocheck.top())).max(0.0),
             },
         }
     }
@@ -238,72 +240,53 @@ impl Rect {
         region
     }
 
+    /// Multiply every coordinate, as from CSS pixels to device pixels.
+    pub fn scale(&self, factor: f64) -> Rect {
+        Rect::new(
+            self.left() * factor,
+            self.top() * factor,
+            self.width() * factor,
+            self.height() * factor,
+        )
+    }
+
+    /// Smallest device pixel rectangle covering this one: for invalidation and clipping.
+    pub fn round_out(&self) -> IntRect {
+        IntRect::from_edges(
+            self.left().floor() as i32,
+            self.top().floor() as i32,
+            self.right().ceil() as i32,
+            self.bottom().ceil() as i32,
+        )
+    }
+
+    /// Each edge rounded to the nearest pixel boundary: for painting edges.
+    ///   Two rects sharing an edge still share it once snapped, so no seam or overlap appears.
+    pub fn snap(&self) -> IntRect {
+        IntRect::from_edges(
+            self.left().round() as i32,
+            self.top().round() as i32,
+            self.right().round() as i32,
+            self.bottom().round() as i32,
+        )
+    }
+
+    /// Smallest rectangle containing both.
     pub fn union(&self, r2: &Self) -> Self {
+        let left = self.left().min(r2.left());
+        let top = self.top().min(r2.top());
         Self {
-            position: Position {
-                left: self.left().min(r2.left()),
-                top: self.top().min(r2.top()),
-            },
+            position: Position { left, top },
             size: Size {
-                width: if self.width() > r2.width() {
-                    self.width()
-                } else {
-                    r2.width() + (r2.left() - self.left())
-                },
-                height: if self.height() > r2.height() {
-                    self.height()
-                } else {
-                    r2.height() + (r2.top() - self.top())
-                },
+                width: self.right().max(r2.right()) - left,
+                height: self.bottom().max(r2.bottom()) - top,
             },
         }
     }
 
-    /// Returns a list of non overlapping rectangles.
-    ///   For performance reasons, the input vector will be modified and become invalid.
-    pub fn update_region(region: &mut Vec<Rect>) -> Vec<Rect> {
-        //search for intersaction between rectangles
-        let mut new_region: Vec<Rect> = vec![];
-        let mut i = 0;
-        while i < region.len() {
-            //println!("{} < {}",i,region.len());
-            if new_region.is_empty() {
-                new_region.push(region[i]);
-            } else {
-                /*
-                ####
-                ####
-                ##òòòò
-                ##òòòò
-                  òòòò
-                  òòòò
-                */
-                let mut modified = false;
-                for j in 0..new_region.len() {
-                    if let Some(intersection) = region[i].get_intersection_rect(&new_region[j]) {
-                        region.append(&mut region[i].subtract_rect(&new_region[j]));
-
-                        //println!("Intersect: {:?} with {:?}", new_region[j],region[i]);
-                        //println!("Intersection: {:?}", intersection);
-                        //new_region[j] = region[i];
-                        //println!("Region: {:?}", region);
-                        //println!("New Region: {:?}", new_region);
-                        //if region.len() > 4 {
-                        //    panic!();
-                        //}
-                        // Original region is now modified.
-                        //   Subtracted portion is added to the end.
-                        //   Only the intersection is added to the new region.
-                        modified = true;
-                    }
-                }
-                if !modified {
-                    new_region.push(region[i]);
-                }
-            }
-            i += 1;
-        }
-        new_region
+    /// Returns a list of non overlapping rectangles covering the same area.
+    pub fn update_region(region: &[Rect]) -> Vec<Rect> {
+        Region::from_rects(region).rects()
     }
 }
 
@@ -366,6 +349,391 @@ impl PartialEq<Size> for Size {
     }
 }*/
 
+/// Size in device pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IntSize {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl IntSize {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self { width, height }
+    }
+    pub fn to_size(self) -> Size {
+        Size::new(self.width as f64, self.height as f64)
+    }
+}
+
+/// Rectangle in device pixels, covering the pixels `left..right` and `top..bottom`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IntRect {
+    pub left: i32,
+    pub top: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl IntRect {
+    pub fn new(left: i32, top: i32, width: u32, height: u32) -> Self {
+        Self {
+            left,
+            top,
+            width,
+            height,
+        }
+    }
+    /// Edges out of order give an empty rectangle.
+    pub fn from_edges(left: i32, top: i32, right: i32, bottom: i32) -> Self {
+        Self {
+            left,
+            top,
+            width: (right - left).max(0) as u32,
+            height: (bottom - top).max(0) as u32,
+        }
+    }
+    pub fn from_size(size: IntSize) -> Self {
+        Self::new(0, 0, size.width, size.height)
+    }
+    pub fn right(&self) -> i32 {
+        self.left + self.width as i32
+    }
+    pub fn bottom(&self) -> i32 {
+        self.top + self.height as i32
+    }
+    pub fn size(&self) -> IntSize {
+        IntSize::new(self.width, self.height)
+    }
+    pub fn is_empty(&self) -> bool {
+        self.width == 0 || self.height == 0
+    }
+    pub fn intersection(&self, other: &IntRect) -> Option<IntRect> {
+        let rect = Self::from_edges(
+            self.left.max(other.left),
+            self.top.max(other.top),
+            self.right().min(other.right()),
+            self.bottom().min(other.bottom()),
+        );
+        if rect.is_empty() {
+            None
+        } else {
+            Some(rect)
+        }
+    }
+    pub fn contains_point(&self, x: i32, y: i32) -> bool {
+        x >= self.left && x < self.right() && y >= self.top && y < self.bottom()
+    }
+    pub fn to_rect(self) -> Rect {
+        Rect::new(
+            self.left as f64,
+            self.top as f64,
+            self.width as f64,
+            self.height as f64,
+        )
+    }
+}
+
+/// 2D affine transform: `x' = a*x + c*y + e`, `y' = b*x + d*y + f`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transform {
+    pub a: f64,
+    pub b: f64,
+    pub c: f64,
+    pub d: f64,
+    pub e: f64,
+    pub f: f64,
+}
+
+impl Transform {
+    pub const IDENTITY: Transform = Transform {
+        a: 1.0,
+        b: 0.0,
+        c: 0.0,
+        d: 1.0,
+        e: 0.0,
+        f: 0.0,
+    };
+
+    pub fn new(a: f64, b: f64, c: f64, d: f64, e: f64, f: f64) -> Self {
+        Self { a, b, c, d, e, f }
+    }
+    pub fn translate(tx: f64, ty: f64) -> Self {
+        Self::new(1.0, 0.0, 0.0, 1.0, tx, ty)
+    }
+    pub fn scale(sx: f64, sy: f64) -> Self {
+        Self::new(sx, 0.0, 0.0, sy, 0.0, 0.0)
+    }
+    /// Clockwise on screen, since y grows downwards.
+    pub fn rotate(radians: f64) -> Self {
+        let (sin, cos) = radians.sin_cos();
+        Self::new(cos, sin, -sin, cos, 0.0, 0.0)
+    }
+
+    /// Apply `self` first, then `next`.
+    pub fn then(&self, next: &Transform) -> Self {
+        Self {
+            a: next.a * self.a + next.c * self.b,
+            b: next.b * self.a + next.d * self.b,
+            c: next.a * self.c + next.c * self.d,
+            d: next.b * self.c + next.d * self.d,
+            e: next.a * self.e + next.c * self.f + next.e,
+            f: next.b * self.e + next.d * self.f + next.f,
+        }
+    }
+
+    pub fn map_f64(&self, x: f64, y: f64) -> (f64, f64) {
+        (
+            self.a * x + self.c * y + self.e,
+            self.b * x + self.d * y + self.f,
+        )
+    }
+    pub fn map_point(&self, pt: &Position) -> Position {
+        let (left, top) = self.map_f64(pt.left, pt.top);
+        Position { left, top }
+    }
+    pub fn is_identity(&self) -> bool {
+        *self == Self::IDENTITY
+    }
+
+    pub fn skew(x_radians: f64, y_radians: f64) -> Self {
+        Self::new(1.0, y_radians.tan(), x_radians.tan(), 1.0, 0.0, 0.0)
+    }
+
+    pub fn determinant(&self) -> f64 {
+        self.a * self.d - self.b * self.c
+    }
+
+    pub fn inverse(&self) -> Option<Transform> {
+        let det = self.determinant();
+        if det.abs() < 1e-12 {
+            return None;
+        }
+        Some(Self {
+            a: self.d / det,
+            b: -self.b / det,
+            c: -self.c / det,
+            d: self.a / det,
+            e: (self.c * self.f - self.d * self.e) / det,
+            f: (self.b * self.e - self.a * self.f) / det,
+        })
+    }
+
+    /// Bounding box of the mapped rectangle.
+    pub fn map_rect(&self, rect: &Rect) -> Rect {
+        bounding_rect(&rect_corners(rect).map(|p| self.map_point(&p)))
+    }
+}
+
+fn rect_corners(rect: &Rect) -> [Position; 4] {
+    [
+        Position {
+            left: rect.left(),
+            top: rect.top(),
+        },
+        Position {
+            left: rect.right(),
+            top: rect.top(),
+        },
+        Position {
+            left: rect.right(),
+            top: rect.bottom(),
+        },
+        Position {
+            left: rect.left(),
+            top: rect.bottom(),
+        },
+    ]
+}
+
+fn bounding_rect(points: &[Position]) -> Rect {
+    let left = points.iter().map(|p| p.left).fold(f64::MAX, f64::min);
+    let top = points.iter().map(|p| p.top).fold(f64::MAX, f64::min);
+    let right = points.iter().map(|p| p.left).fold(f64::MIN, f64::max);
+    let bottom = points.iter().map(|p| p.top).fold(f64::MIN, f64::max);
+    Rect::new(left, top, right - left, bottom - top)
+}
+
+/// 4x4 matrix transform, `m[row][col]`, applied to column vectors `(x, y, z, 1)`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transform3D {
+    pub m: [[f64; 4]; 4],
+}
+
+impl Transform3D {
+    pub const IDENTITY: Transform3D = Transform3D {
+        m: [
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ],
+    };
+
+    pub fn translate(tx: f64, ty: f64, tz: f64) -> Self {
+        let mut t = Self::IDENTITY;
+        t.m[0][3] = tx;
+        t.m[1][3] = ty;
+        t.m[2][3] = tz;
+        t
+    }
+    pub fn scale(sx: f64, sy: f64, sz: f64) -> Self {
+        let mut t = Self::IDENTITY;
+        t.m[0][0] = sx;
+        t.m[1][1] = sy;
+        t.m[2][2] = sz;
+        t
+    }
+    /// Rotation around the axis (x, y, z), as CSS `rotate3d`.
+    pub fn rotate(x: f64, y: f64, z: f64, radians: f64) -> Self {
+        let length = (x * x + y * y + z * z).sqrt();
+        if length < 1e-12 {
+            return Self::IDENTITY;
+        }
+        let (x, y, z) = (x / length, y / length, z / length);
+        let (sin, cos) = radians.sin_cos();
+        let k = 1.0 - cos;
+        Self {
+            m: [
+                [cos + x * x * k, x * y * k - z * sin, x * z * k + y * sin, 0.0],
+                [y * x * k + z * sin, cos + y * y * k, y * z * k - x * sin, 0.0],
+                [z * x * k - y * sin, z * y * k + x * sin, cos + z * z * k, 0.0],
+                [0.0, 0.0, 0.0, 1.0],
+            ],
+        }
+    }
+    /// CSS `perspective(distance)`.
+    pub fn perspective(distance: f64) -> Self {
+        let mut t = Self::IDENTITY;
+        if distance > 0.0 {
+            t.m[3][2] = -1.0 / distance;
+        }
+        t
+    }
+
+    /// Apply `self` first, then `next`.
+    pub fn then(&self, next: &Transform3D) -> Self {
+        let mut m = [[0.0; 4]; 4];
+        for (row, out) in m.iter_mut().enumerate() {
+            for (col, cell) in out.iter_mut().enumerate() {
+                *cell = (0..4).map(|k| next.m[row][k] * self.m[k][col]).sum();
+            }
+        }
+        Self { m }
+    }
+
+    /// Gauss-Jordan elimination with partial pivoting.
+    pub fn inverse(&self) -> Option<Transform3D> {
+        let mut a = self.m;
+        let mut inv = Self::IDENTITY.m;
+        for col in 0..4 {
+            let pivot = (col..4)
+                .max_by(|x, y| a[*x][col].abs().total_cmp(&a[*y][col].abs()))
+                .unwrap();
+            // NaN sorts above every number: matrices with one have no inverse either
+            if !a[pivot][col].is_finite() || a[pivot][col].abs() < 1e-12 {
+                return None;
+            }
+            a.swap(col, pivot);
+            inv.swap(col, pivot);
+            let p = a[col][col];
+            for k in 0..4 {
+                a[col][k] /= p;
+                inv[col][k] /= p;
+            }
+            for row in 0..4 {
+                if row != col {
+                    let factor = a[row][col];
+                    for k in 0..4 {
+                        a[row][k] -= factor * a[col][k];
+                        inv[row][k] -= factor * inv[col][k];
+                    }
+                }
+            }
+        }
+        Some(Self { m: inv })
+    }
+
+    /// Map a point of the z=0 plane. `None` when it ends up behind the viewer.
+    pub fn map_point(&self, pt: &Position) -> Option<Position> {
+        let m = &self.m;
+        let w = m[3][0] * pt.left + m[3][1] * pt.top + m[3][3];
+        if w <= 1e-12 {
+            return None;
+        }
+        Some(Position {
+            left: (m[0][0] * pt.left + m[0][1] * pt.top + m[0][3]) / w,
+            top: (m[1][0] * pt.left + m[1][1] * pt.top + m[1][3]) / w,
+        })
+    }
+
+    /// Inverse of `map_point`: the point of the z=0 plane that this transform maps onto `pt`.
+    pub fn unproject_point(&self, pt: &Position) -> Option<Position> {
+        let n = self.inverse()?.m;
+        if n[2][2].abs() < 1e-12 {
+            return None;
+        }
+        // Pick the depth along the viewing ray that lands on the plane z=0
+        let z = -(n[2][0] * pt.left + n[2][1] * pt.top + n[2][3]) / n[2][2];
+        let w = n[3][0] * pt.left + n[3][1] * pt.top + n[3][2] * z + n[3][3];
+        if w.abs() < 1e-12 {
+            return None;
+        }
+        Some(Position {
+            left: (n[0][0] * pt.left + n[0][1] * pt.top + n[0][2] * z + n[0][3]) / w,
+            top: (n[1][0] * pt.left + n[1][1] * pt.top + n[1][2] * z + n[1][3]) / w,
+        })
+    }
+
+    /// The four corners of `rect` once mapped, or `None` if some corner goes behind the viewer.
+    pub fn map_quad(&self, rect: &Rect) -> Option<[Position; 4]> {
+        let corners = rect_corners(rect);
+        Some([
+            self.map_point(&corners[0])?,
+            self.map_point(&corners[1])?,
+            self.map_point(&corners[2])?,
+            self.map_point(&corners[3])?,
+        ])
+    }
+
+    /// Bounding box of the mapped rectangle.
+    pub fn map_rect(&self, rect: &Rect) -> Option<Rect> {
+        Some(bounding_rect(&self.map_quad(rect)?))
+    }
+
+    /// The 2D affine part, if this transform has nothing else.
+    pub fn to_2d(self) -> Option<Transform> {
+        let m = &self.m;
+        let is_2d = m[0][2] == 0.0
+            && m[1][2] == 0.0
+            && m[2][0] == 0.0
+            && m[2][1] == 0.0
+            && m[2][2] == 1.0
+            && m[2][3] == 0.0
+            && m[3][0] == 0.0
+            && m[3][1] == 0.0
+            && m[3][2] == 0.0
+            && m[3][3] == 1.0;
+        if is_2d {
+            Some(Transform::new(m[0][0], m[1][0], m[0][1], m[1][1], m[0][3], m[1][3]))
+        } else {
+            None
+        }
+    }
+}
+
+impl From<Transform> for Transform3D {
+    fn from(t: Transform) -> Self {
+        Self {
+            m: [
+                [t.a, t.c, 0.0, t.e],
+                [t.b, t.d, 0.0, t.f],
+                [0.0, 0.0, 1.0, 0.0],
+                [0.0, 0.0, 0.0, 1.0],
+            ],
+        }
+    }
+}
+
 pub fn print_region_ascii(region: &Vec<Rect>) {
     //Find bounds on the region
     let mut bounds = Rect::new(0.0, 0.0, 0.0, 0.0);
@@ -441,12 +809,83 @@ mod tests {
         println!("Was {:?}", &region);
         print_region_ascii(&region);
 
-        let new_region = Rect::update_region(&mut region);
+        let new_region = Rect::update_region(&region);
 
         println!("Updated {:?}", &new_region);
 
         print_region_ascii(&new_region);
 
-        check!(new_region.len() == 5);
+        check!(new_region.len() == 3);
+        for (i, a) in new_region.iter().enumerate() {
+            for b in new_region.iter().skip(i + 1) {
+                check!(a.get_intersection_rect(b).is_none());
+            }
+        }
+        let area: f64 = new_region.iter().map(|r| r.width() * r.height()).sum();
+        check!(area == 30.0);
+    }
+    #[test]
+    fn union_and_rebound() {
+        let r1 = Rect::from_u32(10, 10, 20, 5);
+        let r2 = Rect::from_u32(0, 12, 5, 30);
+        check!(r1.union(&r2) == (0, 10, 30, 32));
+        check!(r2.union(&r1) == (0, 10, 30, 32));
+        check!(r1.rebound(&Rect::from_u32(15, 0, 100, 12)) == (15, 10, 15, 2));
+        check!(r1.rebound(&r2).is_empty());
+    }
+    #[test]
+    fn pixel_snapping() {
+        let r = Rect::new(10.4, 5.6, 20.2, 0.2);
+        check!(r.round_out() == IntRect::new(10, 5, 21, 1));
+        check!(r.snap() == IntRect::new(10, 6, 21, 0));
+        check!(r.snap().is_empty());
+        // Adjacent subpixel rects stay adjacent once snapped
+        let a = Rect::new(0.0, 0.0, 10.5, 4.0).snap();
+        let b = Rect::new(10.5, 0.0, 10.0, 4.0).snap();
+        check!(a.right() == b.left);
+        check!(a.intersection(&b).is_none());
+        check!(IntRect::new(-2, -2, 5, 5).intersection(&IntRect::from_size(IntSize::new(4, 4))) == Some(IntRect::new(0, 0, 3, 3)));
+    }
+    #[test]
+    fn transform_compose() {
+        let t = Transform::scale(2.0, 3.0).then(&Transform::translate(10.0, 20.0));
+        check!(t.map_f64(1.0, 1.0) == (12.0, 23.0));
+        let r = Transform::rotate(std::f64::consts::FRAC_PI_2);
+        let (x, y) = r.map_f64(1.0, 0.0);
+        check!(x.abs() < 1e-12);
+        check!((y - 1.0).abs() < 1e-12);
+    }
+    #[test]
+    fn transform_inverse() {
+        let t = Transform::rotate(0.7)
+            .then(&Transform::scale(2.0, 0.5))
+            .then(&Transform::translate(3.0, -4.0));
+        let back = t.then(&t.inverse().unwrap());
+        let (x, y) = back.map_f64(5.0, 7.0);
+        check!((x - 5.0).abs() < 1e-9);
+        check!((y - 7.0).abs() < 1e-9);
+        check!(Transform::scale(0.0, 1.0).inverse().is_none());
+        let bounds = Transform::rotate(std::f64::consts::FRAC_PI_2).map_rect(&Rect::from_u32(0, 0, 4, 2));
+        check!((bounds.width() - 2.0).abs() < 1e-9);
+        check!((bounds.height() - 4.0).abs() < 1e-9);
+    }
+    #[test]
+    fn transform_3d() {
+        let t = Transform3D::rotate(0.0, 1.0, 0.0, 0.5)
+            .then(&Transform3D::translate(0.0, 0.0, -10.0))
+            .then(&Transform3D::perspective(100.0))
+            .then(&Transform3D::translate(50.0, 50.0, 0.0));
+        let pt = Position { left: 7.0, top: -3.0 };
+        let mapped = t.map_point(&pt).unwrap();
+        let back = t.unproject_point(&mapped).unwrap();
+        check!((back.left - pt.left).abs() < 1e-9);
+        check!((back.top - pt.top).abs() < 1e-9);
+        check!(t.to_2d().is_none());
+
+        let flat = Transform3D::from(Transform::scale(2.0, 3.0).then(&Transform::translate(1.0, 1.0)));
+        check!(flat.to_2d() == Some(Transform::new(2.0, 0.0, 0.0, 3.0, 1.0, 1.0)));
+        let inverse = flat.inverse().unwrap().then(&flat);
+        check!(inverse.map_point(&pt).unwrap().left - pt.left < 1e-9);
+        check!(Transform3D::scale(f64::NAN, 1.0, 1.0).inverse().is_none());
     }
 }
@@ -12,20 +12,24 @@ use super::simple_error::SimpleError;
 use std::rc::Rc;
 //use std::cell::RefCell;
 
-use super::geometry::{Rect, Size};
+use super::geometry::{IntRect, IntSize, Rect, Size};
 
 #[derive(Debug)]
-pub struct GfxBuffer<'a> {
+pub struct GfxBuffer {
     //surface_texture: &'a SurfaceTexture<'a, winit::window::Window>,
     pixels: Pixels,
-    window: &'a winit::window::Window,
-    window_size: Size,
+    window: Rc<winit::window::Window>,
+    window_size: IntSize,
     //buffer: RgbImage,
 }
+use super::bitmap::{Bitmap, ScaleFilter};
 use super::color::Color;
+use super::geometry::Transform;
+use super::path::{FillRule, Path, StrokeStyle};
+use super::rasterizer;
 
-impl<'a> GfxBuffer<'a> {
-    pub fn new(window: &'a winit::window::Window) -> Self {
+impl GfxBuffer {
+    pub fn new(window: Rc<winit::window::Window>) -> Self {
         let window_size = window.inner_size();
 
         assert!(
@@ -36,21 +40,18 @@ impl<'a> GfxBuffer<'a> {
         );
 
         let surface_texture =
-            SurfaceTexture::new(window_size.width, window_size.height, window);
+            SurfaceTexture::new(window_size.width, window_size.height, &*window);
         let pixels = Pixels::new(window_size.width, window_size.height, surface_texture).unwrap();
 
         Self {
-            window: window,
+            window,
             //surface_texture: &surface_texture,
             /*buffer: ImageBuffer::new(
                 window_size.width,
                 window_size.height,
             ),*/
             pixels: pixels,
-            window_size: Size {
-                width: window_size.width as f64,
-                height: window_size.height as f64,
-            },
+            window_size: IntSize::new(window_size.width, window_size.height),
         }
     }
     pub fn resize(&mut self) {
@@ -63,10 +64,7 @@ impl<'a> GfxBuffer<'a> {
             u16::MAX
         );
 
-        self.window_size = Size {
-            width: window_size.width as f64,
-            height: window_size.height as f64,
-        };
+        self.window_size = IntSize::new(window_size.width, window_size.height);
 
         self.pixels
             .resize_surface(window_size.width, window_size.height);
@@ -84,23 +82,36 @@ impl<'a> GfxBuffer<'a> {
         }
         Ok(ret.unwrap())
     }
-    pub fn clear(&mut self, rect: &Rect, color: Color) {
+    #[allow(dead_code)]
+    pub fn clear(&mut self, rect: &IntRect, color: Color) {
         fixme!("Pixels get_frame should be called one per frame and draw all elements");
-        let frame = self.pixels.get_frame();
-        debug!("Clear rect {:?} ons wsize: {:?}", rect,&self.window_size);
-        let window_size = &self.window_size;
-        for (i, pixel) in frame.chunks_exact_mut(4).enumerate() {
-            let x = i % window_size.width as usize;
-            let y = i / window_size.width as usize;
-            if rect.contains_point_f64(x as f64, y as f64) {
-                pixel.copy_from_slice(&color.as_u8_ref());
-            }
-        }
+        debug!("Clear rect {:?} ons wsize: {:?}", rect, &self.window_size);
+        self.surface().fill(rect, &color.as_u8_ref());
+    }
+    /// Pixel access to the frame, for painting routines not tied to a window.
+    pub fn surface(&mut self) -> Surface<'_> {
+        let width = self.window_size.width as usize;
+        let height = self.window_size.height as usize;
+        Surface::new(self.pixels.get_frame(), width, height)
+    }
+
+    /// Paint `bitmap` scaled into `dest`, touching only pixels inside `clip`.
+    #[allow(dead_code)]
+    pub fn blit(&mut self, bitmap: &Bitmap, dest: &Rect, clip: &Rect, filter: ScaleFilter) {
+        blit(&mut self.surface(), bitmap, dest, clip, filter);
+    }
+    #[allow(dead_code)]
+    pub fn fill_path(&mut self, path: &Path, transform: &Transform, rule: FillRule, color: Color, clip: &Rect) {
+        rasterizer::fill_path(&mut self.surface(), path, transform, rule, color, clip);
+    }
+    #[allow(dead_code)]
+    pub fn stroke_path(&mut self, path: &Path, transform: &Transform, style: &StrokeStyle, color: Color, clip: &Rect) {
+        rasterizer::stroke_path(&mut self.surface(), path, transform, style, color, clip);
     }
     #[allow(dead_code)]
     pub fn draw(&mut self) -> Result<(), SimpleError> {
         let frame = self.pixels.get_frame();
-        let window_size = &self.window_size;
+        let window_size = self.window_size.to_size();
 
         for (i, pixel) in frame.chunks_exact_mut(4).enumerate() {
             let x = i % window_size.width as usize;
@@ -120,3 +131,150 @@ impl<'a> GfxBuffer<'a> {
         self.render()
     }
 }
+
+/// Paint `bitmap` scaled into `dest`, touching only pixels inside `clip`.
+pub fn blit(surface: &mut Surface, bitmap: &Bitmap, dest: &Rect, clip: &Rect, filter: ScaleFilter) {
+    // Image edges land on pixel boundaries, so neighbouring images never leave a seam
+    let dest = dest.snap().to_rect();
+    if dest.is_empty() || bitmap.width == 0 || bitmap.height == 0 {
+        return;
+    }
+    let (x_begin, x_end, y_begin, y_end) = match surface.pixel_span(&dest, clip) {
+        Some(span) => span,
+        None => return,
+    };
+    let scale_x = bitmap.width as f64 / dest.width();
+    let scale_y = bitmap.height as f64 / dest.height();
+    for y in y_begin..y_end {
+        let v = (y as f64 + 0.5 - dest.top()) * scale_y;
+        for x in x_begin..x_end {
+            let u = (x as f64 + 0.5 - dest.left()) * scale_x;
+            surface.blend(x, y, &bitmap.sample(u, v, filter), 1.0);
+        }
+    }
+}
+
+/// Source-over composite of `src`, scaled by `coverage` (0..1), onto the RGBA pixel `dst`.
+pub fn blend_pixel(dst: &mut [u8], src: &[u8; 4], coverage: f64) {
+    let sa = src[3] as f64 / 255.0 * coverage;
+    if sa <= 0.0 {
+        return;
+    }
+    if sa >= 1.0 {
+        dst.copy_from_slice(src);
+        return;
+    }
+    let da = dst[3] as f64 / 255.0;
+    let out_a = sa + da * (1.0 - sa);
+    for c in 0..3 {
+        let value = (src[c] as f64 * sa + dst[c] as f64 * da * (1.0 - sa)) / out_a;
+        dst[c] = value.round() as u8;
+    }
+    dst[3] = (out_a * 255.0).round() as u8;
+}
+
+/// A mutable view over RGBA pixels, row by row.
+pub struct Surface<'f> {
+    data: &'f mut [u8],
+    pub width: usize,
+    pub height: usize,
+}
+
+impl<'f> Surface<'f> {
+    pub fn new(data: &'f mut [u8], width: usize, height: usize) -> Self {
+        assert!(
+            data.len() >= width * height * 4,
+            "Surface {}x{} on {} bytes",
+            width,
+            height,
+            data.len()
+        );
+        Self {
+            data,
+            width,
+            height,
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn pixel(&self, x: usize, y: usize) -> [u8; 4] {
+        let i = (y * self.width + x) * 4;
+        [
+            self.data[i],
+            self.data[i + 1],
+            self.data[i + 2],
+            self.data[i + 3],
+        ]
+    }
+
+    pub fn blend(&mut self, x: usize, y: usize, color: &[u8; 4], coverage: f64) {
+        let i = (y * self.width + x) * 4;
+        blend_pixel(&mut self.data[i..i + 4], color, coverage);
+    }
+
+    pub fn bounds(&self) -> IntRect {
+        IntRect::new(0, 0, self.width as u32, self.height as u32)
+    }
+
+    /// Set every pixel of `rect` to `color`, without blending.
+    pub fn fill(&mut self, rect: &IntRect, color: &[u8; 4]) {
+        let area = match rect.intersection(&self.bounds()) {
+            Some(area) => area,
+            None => return,
+        };
+        for y in area.top as usize..area.bottom() as usize {
+            let row = y * self.width * 4;
+            let begin = row + area.left as usize * 4;
+            let end = row + area.right() as usize * 4;
+            for pixel in self.data[begin..end].chunks_exact_mut(4) {
+                pixel.copy_from_slice(color);
+            }
+        }
+    }
+
+    /// Pixels touched by `rect` inside `clip` and the surface, as `(x_begin, x_end, y_begin, y_end)`.
+    ///   Both are rounded out, so partially covered pixels are included.
+    pub fn pixel_span(&self, rect: &Rect, clip: &Rect) -> Option<(usize, usize, usize, usize)> {
+        let area = rect
+            .round_out()
+            .intersection(&clip.round_out())?
+            .intersection(&self.bounds())?;
+        Some((
+            area.left as usize,
+            area.right() as usize,
+            area.top as usize,
+            area.bottom() as usize,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert2::check;
+
+    use super::*;
+
+    #[test]
+    fn fill_only_touches_rect() {
+        let mut data = vec![0; 6 * 4 * 4];
+        let mut surface = Surface::new(&mut data, 6, 4);
+        surface.fill(&IntRect::new(4, 2, 10, 10), &[1, 2, 3, 4]);
+        check!(surface.pixel(4, 2) == [1, 2, 3, 4]);
+        check!(surface.pixel(5, 3) == [1, 2, 3, 4]);
+        check!(surface.pixel(3, 3) == [0, 0, 0, 0]);
+        check!(surface.pixel(5, 1) == [0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn blit_snaps_to_pixels() {
+        let bitmap = Bitmap::new(1, 1, vec![255, 255, 255, 255]).unwrap();
+        let mut data = vec![0; 8 * 4];
+        let mut surface = Surface::new(&mut data, 8, 1);
+        let clip = Rect::from_u32(0, 0, 8, 1);
+        blit(&mut surface, &bitmap, &Rect::new(0.5, 0.0, 2.0, 1.0), &clip, ScaleFilter::Nearest);
+        blit(&mut surface, &bitmap, &Rect::new(2.5, 0.0, 2.0, 1.0), &clip, ScaleFilter::Nearest);
+        // Rounded to 1..3 and 3..5: no seam between them, and no half blended edge
+        let covered: Vec<u8> = (0..8).map(|x| surface.pixel(x, 0)[3]).collect();
+        check!(covered == vec![0, 255, 255, 255, 255, 0, 0, 0]);
+    }
+}
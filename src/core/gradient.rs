@@ -0,0 +1,319 @@
+#![allow(dead_code)]
+#![allow(unused_imports)]
+
+use std::f64::consts::SQRT_2;
+
+use log::{debug, error, info, warn};
+
+use super::color::Color;
+use super::geometry::{Position, Size};
+
+/// 4x4 Bayer matrix, used to dither the 8 bit output of gradients so they don't band.
+const BAYER_4X4: [[f64; 4]; 4] = [
+    [0.0, 8.0, 2.0, 10.0],
+    [12.0, 4.0, 14.0, 6.0],
+    [3.0, 11.0, 1.0, 9.0],
+    [15.0, 7.0, 13.0, 5.0],
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ColorStop {
+    pub color: Color,
+    /// Fraction of the gradient line. Missing positions are spread evenly between their neighbours.
+    pub position: Option<f64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RadialShape {
+    Circle,
+    Ellipse,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RadialExtent {
+    ClosestSide,
+    ClosestCorner,
+    FarthestSide,
+    FarthestCorner,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GradientKind {
+    /// CSS angle: 0deg points up, 90deg points right.
+    Linear { angle: f64 },
+    /// `center` is a fraction of the gradient box.
+    Radial {
+        shape: RadialShape,
+        extent: RadialExtent,
+        center: Position,
+    },
+    Conic { from_angle: f64, center: Position },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Gradient {
+    pub kind: GradientKind,
+    pub stops: Vec<ColorStop>,
+    pub repeating: bool,
+}
+
+impl ColorStop {
+    pub fn new(color: Color, position: Option<f64>) -> Self {
+        Self { color, position }
+    }
+}
+
+impl Gradient {
+    pub fn linear(angle: f64, stops: Vec<ColorStop>) -> Self {
+        Self {
+            kind: GradientKind::Linear { angle },
+            stops,
+            repeating: false,
+        }
+    }
+
+    /// Stop positions with CSS fixup: first defaults to 0, last to 1, never going backwards.
+    pub fn resolved_stops(&self) -> Vec<(f64, Color)> {
+        let count = self.stops.len();
+        let mut positions: Vec<Option<f64>> = self.stops.iter().map(|s| s.position).collect();
+        if count == 0 {
+            return vec![];
+        }
+        if positions[0].is_none() {
+            positions[0] = Some(0.0);
+        }
+        if positions[count - 1].is_none() {
+            positions[count - 1] = Some(positions[0].unwrap().max(1.0));
+        }
+        let mut highest = f64::MIN;
+        for position in positions.iter_mut().flatten() {
+            highest = highest.max(*position);
+            *position = highest;
+        }
+        let mut i = 0;
+        while i < count {
+            if positions[i].is_some() {
+                i += 1;
+                continue;
+            }
+            let before = positions[i - 1].unwrap();
+            let mut j = i;
+            while positions[j].is_none() {
+                j += 1;
+            }
+            let after = positions[j].unwrap();
+            let steps = (j - i + 1) as f64;
+            for (n, k) in (i..j).enumerate() {
+                positions[k] = Some(before + (after - before) * (n + 1) as f64 / steps);
+            }
+            i = j;
+        }
+        positions
+            .into_iter()
+            .zip(self.stops.iter())
+            .map(|(p, s)| (p.unwrap(), s.color))
+            .collect()
+    }
+
+    /// Position on the gradient line of the point (x, y), relative to a gradient box of `size`.
+    pub fn parameter(&self, x: f64, y: f64, size: &Size) -> f64 {
+        let (w, h) = (size.width, size.height);
+        match self.kind {
+            GradientKind::Linear { angle } => {
+                let radians = angle.to_radians();
+                let (dx, dy) = (radians.sin(), -radians.cos());
+                let length = (w * dx).abs() + (h * dy).abs();
+                if length <= 0.0 {
+                    return 0.0;
+                }
+                ((x - w / 2.0) * dx + (y - h / 2.0) * dy) / length + 0.5
+            }
+            GradientKind::Radial {
+                shape,
+                extent,
+                center,
+            } => {
+                let cx = center.left * w;
+                let cy = center.top * h;
+                let (rx, ry) = radial_radii(shape, extent, cx, cy, w, h);
+                if rx <= 0.0 || ry <= 0.0 {
+                    return 0.0;
+                }
+                let dx = (x - cx) / rx;
+                let dy = (y - cy) / ry;
+                (dx * dx + dy * dy).sqrt()
+            }
+            GradientKind::Conic { from_angle, center } => {
+                let dx = x - center.left * w;
+                let dy = y - center.top * h;
+                let angle = dx.atan2(-dy).to_degrees() - from_angle;
+                angle.rem_euclid(360.0) / 360.0
+            }
+        }
+    }
+
+    /// Premultiplied RGBA (0..255) at position `t` of the gradient line.
+    pub fn color_at(&self, stops: &[(f64, Color)], t: f64) -> [f64; 4] {
+        if stops.is_empty() {
+            return [0.0; 4];
+        }
+        let first = stops[0].0;
+        let last = stops[stops.len() - 1].0;
+        let t = if self.repeating && last > first {
+            first + (t - first).rem_euclid(last - first)
+        } else {
+            t
+        };
+        let premultiplied = |c: &Color| {
+            let a = c.a as f64 / 255.0;
+            [c.r as f64 * a, c.g as f64 * a, c.b as f64 * a, c.a as f64]
+        };
+        if t <= first {
+            return premultiplied(&stops[0].1);
+        }
+        for pair in stops.windows(2) {
+            let (p0, c0) = pair[0];
+            let (p1, c1) = pair[1];
+            if t <= p1 {
+                let a = premultiplied(&c0);
+                let b = premultiplied(&c1);
+                let f = if p1 > p0 { (t - p0) / (p1 - p0) } else { 1.0 };
+                return [
+                    a[0] + (b[0] - a[0]) * f,
+                    a[1] + (b[1] - a[1]) * f,
+                    a[2] + (b[2] - a[2]) * f,
+                    a[3] + (b[3] - a[3]) * f,
+                ];
+            }
+        }
+        premultiplied(&stops[stops.len() - 1].1)
+    }
+}
+
+fn radial_radii(shape: RadialShape, extent: RadialExtent, cx: f64, cy: f64, w: f64, h: f64) -> (f64, f64) {
+    let (near_x, far_x) = (cx.abs().min((w - cx).abs()), cx.abs().max((w - cx).abs()));
+    let (near_y, far_y) = (cy.abs().min((h - cy).abs()), cy.abs().max((h - cy).abs()));
+    match (shape, extent) {
+        (RadialShape::Circle, RadialExtent::ClosestSide) => (near_x.min(near_y), near_x.min(near_y)),
+        (RadialShape::Circle, RadialExtent::FarthestSide) => (far_x.max(far_y), far_x.max(far_y)),
+        (RadialShape::Circle, RadialExtent::ClosestCorner) => {
+            let r = (near_x * near_x + near_y * near_y).sqrt();
+            (r, r)
+        }
+        (RadialShape::Circle, RadialExtent::FarthestCorner) => {
+            let r = (far_x * far_x + far_y * far_y).sqrt();
+            (r, r)
+        }
+        (RadialShape::Ellipse, RadialExtent::ClosestSide) => (near_x, near_y),
+        (RadialShape::Ellipse, RadialExtent::FarthestSide) => (far_x, far_y),
+        // Same aspect ratio as the matching side ellipse, passing through the corner
+        (RadialShape::Ellipse, RadialExtent::ClosestCorner) => (near_x * SQRT_2, near_y * SQRT_2),
+        (RadialShape::Ellipse, RadialExtent::FarthestCorner) => (far_x * SQRT_2, far_y * SQRT_2),
+    }
+}
+
+/// Quantize a premultiplied color to straight 8 bit RGBA, dithered by the pixel position.
+pub fn dither(color: &[f64; 4], x: usize, y: usize) -> [u8; 4] {
+    let noise = (BAYER_4X4[y % 4][x % 4] + 0.5) / 16.0 - 0.5;
+    let alpha = color[3];
+    if alpha <= 0.0 {
+        return [0, 0, 0, 0];
+    }
+    let a = (alpha + noise).round().clamp(0.0, 255.0);
+    let channel = |c: f64| (c * 255.0 / alpha + noise).round().clamp(0.0, 255.0) as u8;
+    [channel(color[0]), channel(color[1]), channel(color[2]), a as u8]
+}
+
+#[cfg(test)]
+mod tests {
+    use assert2::check;
+
+    use super::super::color::Colors;
+    use super::*;
+
+    fn stops(positions: &[Option<f64>]) -> Vec<ColorStop> {
+        positions
+            .iter()
+            .map(|p| ColorStop::new(Colors::BLACK, *p))
+            .collect()
+    }
+
+    #[test]
+    fn stop_fixup() {
+        let gradient = Gradient::linear(180.0, stops(&[None, None, Some(0.8), Some(0.4), None]));
+        let resolved: Vec<f64> = gradient.resolved_stops().iter().map(|s| s.0).collect();
+        check!(resolved == vec![0.0, 0.4, 0.8, 0.8, 1.0]);
+    }
+
+    #[test]
+    fn linear_parameter() {
+        let size = Size::new(100.0, 50.0);
+        let to_bottom = Gradient::linear(180.0, vec![]);
+        check!(to_bottom.parameter(10.0, 0.0, &size).abs() < 1e-9);
+        check!((to_bottom.parameter(10.0, 50.0, &size) - 1.0).abs() < 1e-9);
+        let to_right = Gradient::linear(90.0, vec![]);
+        check!((to_right.parameter(25.0, 10.0, &size) - 0.25).abs() < 1e-9);
+        // Diagonal: the corners are exactly at the ends of the gradient line
+        let diagonal = Gradient::linear(135.0, vec![]);
+        check!(diagonal.parameter(0.0, 0.0, &Size::new(10.0, 10.0)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn radial_and_conic_parameter() {
+        let size = Size::new(100.0, 100.0);
+        let center = Position { left: 0.5, top: 0.5 };
+        let radial = Gradient {
+            kind: GradientKind::Radial {
+                shape: RadialShape::Circle,
+                extent: RadialExtent::ClosestSide,
+                center,
+            },
+            stops: vec![],
+            repeating: false,
+        };
+        check!(radial.parameter(50.0, 50.0, &size) == 0.0);
+        check!(radial.parameter(100.0, 50.0, &size) == 1.0);
+        let conic = Gradient {
+            kind: GradientKind::Conic {
+                from_angle: 0.0,
+                center,
+            },
+            stops: vec![],
+            repeating: false,
+        };
+        check!((conic.parameter(100.0, 50.0, &size) - 0.25).abs() < 1e-9);
+        check!((conic.parameter(50.0, 100.0, &size) - 0.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn repeating_color() {
+        let mut gradient = Gradient::linear(
+            180.0,
+            vec![
+                ColorStop::new(Colors::BLACK, Some(0.0)),
+                ColorStop::new(Colors::WHITE, Some(0.25)),
+            ],
+        );
+        gradient.repeating = true;
+        let resolved = gradient.resolved_stops();
+        check!(gradient.color_at(&resolved, 0.125)[0] == 127.5);
+        check!(gradient.color_at(&resolved, 0.375)[0] == 127.5);
+        gradient.repeating = false;
+        check!(gradient.color_at(&resolved, 0.375)[0] == 255.0);
+    }
+
+    #[test]
+    fn dither_stays_close() {
+        let color = [100.4, 100.4, 100.4, 255.0];
+        for y in 0..4 {
+            for x in 0..4 {
+                let c = dither(&color, x, y);
+                check!(c[0] == 100 || c[0] == 101);
+                check!(c[3] == 255);
+            }
+        }
+        let values: Vec<u8> = (0..4).map(|x| dither(&color, x, 0)[0]).collect();
+        check!(values.contains(&100));
+        check!(values.contains(&101));
+    }
+}
@@ -0,0 +1,455 @@
+#![allow(dead_code)]
+#![allow(unused_imports)]
+
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use log::{debug, error, info, warn};
+use url::Url;
+
+use super::simple_error::SimpleError;
+
+/// Visits kept per page to compute its frecency, and lines of the log per page once compacted.
+const RECENT_VISITS: usize = 10;
+
+const DAY: u64 = 24 * 60 * 60;
+
+/// Seconds since the Unix epoch.
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
+
+/// A visited page.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistoryRecord {
+    pub url: Url,
+    pub title: String,
+    pub visit_count: u32,
+    pub last_visit: u64,
+    /// Most recent visits, oldest first
+    recent_visits: Vec<u64>,
+}
+
+impl HistoryRecord {
+    /// Visit count weighted by how recent the visits are, like Firefox does.
+    pub fn frecency(&self, now: u64) -> f64 {
+        if self.recent_visits.is_empty() {
+            return 0.0;
+        }
+        let weight = |time: &u64| {
+            let age = now.saturating_sub(*time);
+            if age <= 4 * DAY {
+                100.0
+            } else if age <= 14 * DAY {
+                70.0
+            } else if age <= 31 * DAY {
+                50.0
+            } else if age <= 90 * DAY {
+                30.0
+            } else {
+                10.0
+            }
+        };
+        let sampled: f64 = self.recent_visits.iter().map(weight).sum();
+        self.visit_count as f64 * sampled / self.recent_visits.len() as f64
+    }
+}
+
+/// Time ranges history can be cleared for.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ClearRange {
+    LastHour,
+    LastDay,
+    LastWeek,
+    All,
+}
+
+impl ClearRange {
+    /// First second of the range, ending `now`.
+    pub fn since(&self, now: u64) -> u64 {
+        match self {
+            ClearRange::LastHour => now.saturating_sub(60 * 60),
+            ClearRange::LastDay => now.saturating_sub(DAY),
+            ClearRange::LastWeek => now.saturating_sub(7 * DAY),
+            ClearRange::All => 0,
+        }
+    }
+}
+
+/// Lines of the history log: `count` visits of `url`, the last one at `time`.
+#[derive(Debug, Clone, PartialEq)]
+struct Visit {
+    time: u64,
+    url: String,
+    count: u32,
+}
+
+/// Browsing history, saved as a log of `time<TAB>url<TAB>title` visit lines.
+///   Visits are appended as they happen; loading compacts the log and clearing rewrites it. Compacted
+///   lines end with `<TAB>count` when they stand for older visits too.
+#[derive(Debug, Default)]
+pub struct HistoryDb {
+    records: HashMap<String, HistoryRecord>,
+    /// The lines of the log, to rewrite it without a time range
+    visits: Vec<Visit>,
+    path: Option<PathBuf>,
+}
+
+impl HistoryDb {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load from `path` and append there on every visit. A missing file is an empty history.
+    ///   The visits of a page beyond its `RECENT_VISITS` last ones are folded into the log lines kept.
+    pub fn load(path: &Path) -> Result<Self, SimpleError> {
+        let mut db = Self::new();
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(err) => return Err(SimpleError::new(format!("{:?}: {}", path, err).as_str())),
+        };
+        let mut titles: HashMap<String, String> = HashMap::new();
+        let mut visits = vec![];
+        for line in text.lines().filter(|l| !l.trim().is_empty()) {
+            let mut fields = line.splitn(4, '\t');
+            let time = fields.next().and_then(|t| t.parse::<u64>().ok());
+            let url = fields.next().and_then(|u| Url::parse(u).ok());
+            let title = fields.next().unwrap_or("");
+            let count = fields.next().map_or(Some(1), |c| c.parse::<u32>().ok());
+            match (time, url, count) {
+                (Some(time), Some(url), Some(count)) => {
+                    if !title.is_empty() {
+                        titles.insert(String::from(url.as_str()), String::from(title));
+                    }
+                    visits.push(Visit {
+                        time,
+                        url: String::from(url.as_str()),
+                        count,
+                    });
+                }
+                _ => warn!("Ignoring history entry {:?} in {:?}", line, path),
+            }
+        }
+        let lines = visits.len();
+        for visit in compact(visits) {
+            if let Ok(url) = Url::parse(&visit.url) {
+                let title = titles.get(&visit.url).map_or("", String::as_str);
+                db.add_visit(&url, title, visit.time, visit.count);
+            }
+        }
+        db.path = Some(path.to_path_buf());
+        if db.visits.len() < lines {
+            debug!("History log compacted from {} to {} lines", lines, db.visits.len());
+            if let Err(err) = db.save() {
+                error!("Error while saving history: {}", err);
+            }
+        }
+        Ok(db)
+    }
+
+    /// Add `count` visits of `url`, the last one at `time`.
+    fn add_visit(&mut self, url: &Url, title: &str, time: u64, count: u32) {
+        let record = self
+            .records
+            .entry(String::from(url.as_str()))
+            .or_insert_with(|| HistoryRecord {
+                url: url.clone(),
+                title: String::new(),
+                visit_count: 0,
+                last_visit: 0,
+                recent_visits: vec![],
+            });
+        record.visit_count += count;
+        record.last_visit = record.last_visit.max(time);
+        record.recent_visits.push(time);
+        if record.recent_visits.len() > RECENT_VISITS {
+            record.recent_visits.remove(0);
+        }
+        if !title.is_empty() {
+            record.title = String::from(title);
+        }
+        self.visits.push(Visit {
+            time,
+            url: String::from(url.as_str()),
+            count,
+        });
+    }
+
+    /// Record a visit of `url` at `time` and save it.
+    pub fn record_visit(&mut self, url: &Url, title: &str, time: u64) {
+        // Tabs and line breaks would break the log
+        let title: String = title.chars().map(|c| if c.is_control() { ' ' } else { c }).collect();
+        self.add_visit(url, &title, time, 1);
+        if let Err(err) = self.append(&format!("{}\t{}\t{}\n", time, url, title)) {
+            error!("Error while saving history: {}", err);
+        }
+    }
+
+    pub fn get(&self, url: &Url) -> Option<&HistoryRecord> {
+        self.records.get(url.as_str())
+    }
+
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    /// Pages for the address bar: the ones starting with `input` first, then the ones containing it
+    ///   in their URL or title, each group by decreasing frecency.
+    pub fn suggest(&self, input: &str, now: u64, limit: usize) -> Vec<&HistoryRecord> {
+        let input = input.trim().to_lowercase();
+        if input.is_empty() {
+            return vec![];
+        }
+        let mut matches: Vec<(u8, f64, &HistoryRecord)> = self
+            .records
+            .values()
+            .filter_map(|record| {
+                let url = record.url.as_str().to_lowercase();
+                let tier = if strip_url(&url).starts_with(&input) || url.starts_with(&input) {
+                    0
+                } else if url.contains(&input) || record.title.to_lowercase().contains(&input) {
+                    1
+                } else {
+                    return None;
+                };
+                Some((tier, record.frecency(now), record))
+            })
+            .collect();
+        matches.sort_by(|a, b| {
+            a.0.cmp(&b.0)
+                .then(b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal))
+                .then(a.2.url.as_str().cmp(b.2.url.as_str()))
+        });
+        matches.into_iter().take(limit).map(|(_, _, record)| record).collect()
+    }
+
+    /// Forget the visits from `since` on, and the pages left without visits.
+    pub fn clear(&mut self, since: u64) {
+        let visits = std::mem::take(&mut self.visits);
+        let kept: Vec<Visit> = visits.into_iter().filter(|visit| visit.time < since).collect();
+        let titles: HashMap<String, String> = self
+            .records
+            .drain()
+            .map(|(url, record)| (url, record.title))
+            .collect();
+        for visit in kept.iter() {
+            if let Ok(url) = Url::parse(&visit.url) {
+                let title = titles.get(&visit.url).map_or("", String::as_str);
+                self.add_visit(&url, title, visit.time, visit.count);
+            }
+        }
+        info!("History cleared since {}, {} pages left", since, self.records.len());
+        if let Err(err) = self.save() {
+            error!("Error while saving history: {}", err);
+        }
+    }
+
+    fn append(&self, line: &str) -> Result<(), SimpleError> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .and_then(|mut file| file.write_all(line.as_bytes()))
+            .map_err(|err| SimpleError::new(format!("{:?}: {}", path, err).as_str()))
+    }
+
+    fn save(&self) -> Result<(), SimpleError> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        let text: String = self
+            .visits
+            .iter()
+            .map(|visit| {
+                let title = self.records.get(&visit.url).map_or("", |r| r.title.as_str());
+                match visit.count {
+                    1 => format!("{}\t{}\t{}\n", visit.time, visit.url, title),
+                    count => format!("{}\t{}\t{}\t{}\n", visit.time, visit.url, title, count),
+                }
+            })
+            .collect();
+        std::fs::write(path, text).map_err(|err| SimpleError::new(format!("{:?}: {}", path, err).as_str()))
+    }
+}
+
+/// `visits` by time, with the visits of each page beyond its `RECENT_VISITS` last ones counted in the
+///   oldest one kept.
+fn compact(mut visits: Vec<Visit>) -> Vec<Visit> {
+    visits.sort_by_key(|visit| visit.time);
+    let mut kept: Vec<Visit> = vec![];
+    // Per URL, the index in `kept` of its oldest visit so far and how many were kept
+    let mut oldest: HashMap<String, (usize, usize)> = HashMap::new();
+    for visit in visits.into_iter().rev() {
+        match oldest.get_mut(&visit.url) {
+            Some((index, lines)) if *lines >= RECENT_VISITS => kept[*index].count += visit.count,
+            Some((index, lines)) => {
+                *index = kept.len();
+                *lines += 1;
+                kept.push(visit);
+            }
+            None => {
+                oldest.insert(visit.url.clone(), (kept.len(), 1));
+                kept.push(visit);
+            }
+        }
+    }
+    kept.reverse();
+    kept
+}
+
+/// `url` without scheme and `www.`, as typed in the address bar.
+pub fn strip_url(url: &str) -> &str {
+    let url = url.split_once("://").map_or(url, |(_, rest)| rest);
+    url.strip_prefix("www.").unwrap_or(url)
+}
+
+/// What to append to `input` to complete it inline to `url`, when `url` starts with it.
+///   The scheme and `www.` may be left out of `input`.
+pub fn inline_completion(input: &str, url: &Url) -> Option<String> {
+    if input.is_empty() {
+        return None;
+    }
+    let input = input.to_lowercase();
+    [url.as_str(), strip_url(url.as_str())].iter().find_map(|candidate| {
+        if !candidate.to_lowercase().starts_with(&input) {
+            return None;
+        }
+        let rest: String = candidate.chars().skip(input.chars().count()).collect();
+        Some(rest)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use assert2::check;
+
+    use super::*;
+
+    fn url(text: &str) -> Url {
+        Url::parse(text).unwrap()
+    }
+
+    fn urls(records: Vec<&HistoryRecord>) -> Vec<&str> {
+        records.into_iter().map(|record| record.url.as_str()).collect()
+    }
+
+    #[test]
+    fn frecency_prefers_recent_and_frequent() {
+        let now = 1000 * DAY;
+        let mut db = HistoryDb::new();
+        db.record_visit(&url("https://old.com/"), "", now - 200 * DAY);
+        db.record_visit(&url("https://old.com/"), "", now - 199 * DAY);
+        db.record_visit(&url("https://recent.com/"), "", now - DAY);
+        db.record_visit(&url("https://often.com/"), "", now - DAY);
+        db.record_visit(&url("https://often.com/"), "", now - 2 * DAY);
+        let frecency = |u: &str| db.get(&url(u)).unwrap().frecency(now);
+        check!(frecency("https://old.com/") == 20.0);
+        check!(frecency("https://recent.com/") == 100.0);
+        check!(frecency("https://often.com/") == 200.0);
+        check!(db.get(&url("https://often.com/")).unwrap().visit_count == 2);
+    }
+
+    #[test]
+    fn suggestions() {
+        let now = 1000 * DAY;
+        let mut db = HistoryDb::new();
+        db.record_visit(&url("https://www.example.com/"), "Example Domain", now);
+        db.record_visit(&url("https://example.org/docs"), "", now);
+        db.record_visit(&url("https://example.org/docs"), "", now);
+        db.record_visit(&url("https://news.site/example"), "", now);
+        db.record_visit(&url("https://other.net/"), "An example title", now);
+        check!(
+            urls(db.suggest("exa", now, 10))
+                == vec![
+                    "https://example.org/docs",
+                    "https://www.example.com/",
+                    "https://news.site/example",
+                    "https://other.net/",
+                ]
+        );
+        check!(urls(db.suggest("EXAMPLE.C", now, 10)) == vec!["https://www.example.com/"]);
+        check!(db.suggest("exa", now, 1).len() == 1);
+        check!(db.suggest("  ", now, 10).is_empty());
+
+        let example = url("https://www.example.com/Docs");
+        check!(inline_completion("exa", &example) == Some(String::from("mple.com/Docs")));
+        check!(inline_completion("EXAMPLE.COM/d", &example) == Some(String::from("ocs")));
+        check!(inline_completion("https://www.e", &example) == Some(String::from("xample.com/Docs")));
+        check!(inline_completion("example.com/Docs", &example) == Some(String::new()));
+        check!(inline_completion("docs", &example) == None);
+        check!(inline_completion("", &example) == None);
+    }
+
+    #[test]
+    fn persistence_and_clearing() {
+        let path = std::env::temp_dir().join(format!("rowser-history-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let now = 1000 * DAY;
+        {
+            let mut db = HistoryDb::load(&path).unwrap();
+            db.record_visit(&url("https://a.com/"), "A\ttitle", now - 2 * DAY);
+            db.record_visit(&url("https://a.com/"), "", now - 10);
+            db.record_visit(&url("https://b.com/"), "B", now - 10);
+        }
+        let mut db = HistoryDb::load(&path).unwrap();
+        check!(db.len() == 2);
+        check!(db.get(&url("https://a.com/")).unwrap().title == "A title");
+        check!(db.get(&url("https://a.com/")).unwrap().visit_count == 2);
+
+        db.clear(ClearRange::LastHour.since(now));
+        check!(db.get(&url("https://b.com/")).is_none());
+        check!(db.get(&url("https://a.com/")).unwrap().visit_count == 1);
+        let reloaded = HistoryDb::load(&path).unwrap();
+        check!(reloaded.len() == 1);
+        check!(reloaded.get(&url("https://a.com/")).unwrap().title == "A title");
+
+        db.clear(ClearRange::All.since(now));
+        check!(db.is_empty());
+        check!(HistoryDb::load(&path).unwrap().is_empty());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn compaction() {
+        let path = std::env::temp_dir().join(format!("rowser-history-compaction-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let now = 1000 * DAY;
+        {
+            let mut db = HistoryDb::load(&path).unwrap();
+            for visit in 0..25 {
+                db.record_visit(&url("https://often.com/"), "Often", now - 100 + visit);
+            }
+            db.record_visit(&url("https://once.com/"), "", now - 50);
+        }
+        let lines = |path: &Path| std::fs::read_to_string(path).unwrap().lines().count();
+        check!(lines(&path) == 26);
+        let db = HistoryDb::load(&path).unwrap();
+        // The older visits are folded into the oldest line kept, the counts stay
+        check!(lines(&path) == RECENT_VISITS + 1);
+        let often = db.get(&url("https://often.com/")).unwrap();
+        check!(often.visit_count == 25);
+        check!(often.title == "Often");
+        check!(often.last_visit == now - 100 + 24);
+        check!(often.frecency(now) == 2500.0);
+        check!(db.get(&url("https://once.com/")).unwrap().visit_count == 1);
+
+        let reloaded = HistoryDb::load(&path).unwrap();
+        check!(lines(&path) == RECENT_VISITS + 1);
+        check!(reloaded.get(&url("https://often.com/")) == db.get(&url("https://often.com/")));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
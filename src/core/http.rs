@@ -0,0 +1,1400 @@
+#![allow(dead_code)]
+#![allow(unused_imports)]
+
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use flate2::read::{DeflateDecoder, GzDecoder, ZlibDecoder};
+use log::{debug, error, info, warn};
+use url::Url;
+
+use crate::fixme;
+
+use super::cookies::CookieJar;
+use super::http_cache::{self, CacheControl, CacheMode, HttpCache};
+use super::proxy::{self, Proxy, ProxyConfig, ProxyKind};
+use super::simple_error::SimpleError;
+use super::tls::{CertificateProblem, TlsConnector};
+
+const USER_AGENT: &str = concat!("rowser/", env!("CARGO_PKG_VERSION"));
+/// Longest status line or header line accepted
+const MAX_LINE_LENGTH: usize = 64 * 1024;
+const MAX_HEADERS: usize = 256;
+
+/// What an HTTP connection runs over: a TCP stream, or a TLS session on top of one.
+pub trait Stream: Read + Write + Send + std::fmt::Debug {}
+
+impl Stream for TcpStream {}
+
+/// Header fields in order. Names compare without case.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Headers {
+    fields: Vec<(String, String)>,
+}
+
+impl Headers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn get_all<'h>(&'h self, name: &'h str) -> impl Iterator<Item = &'h str> + 'h {
+        self.fields
+            .iter()
+            .filter(move |(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn add(&mut self, name: &str, value: &str) {
+        self.fields.push((String::from(name), String::from(value)));
+    }
+
+    /// Replace every field named `name`.
+    pub fn set(&mut self, name: &str, value: &str) {
+        self.remove(name);
+        self.add(name, value);
+    }
+
+    pub fn remove(&mut self, name: &str) {
+        self.fields.retain(|(n, _)| !n.eq_ignore_ascii_case(name));
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.fields.iter().map(|(name, value)| (name.as_str(), value.as_str()))
+    }
+
+    /// True if the comma separated values of `name` include `token`, as `Connection: close`.
+    pub fn has_token(&self, name: &str, token: &str) -> bool {
+        self.get_all(name)
+            .flat_map(|value| value.split(','))
+            .any(|t| t.trim().eq_ignore_ascii_case(token))
+    }
+}
+
+/// What a conditional request checks the cached copy of a resource against.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Validators {
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+}
+
+impl Validators {
+    pub fn is_empty(&self) -> bool {
+        self.etag.is_none() && self.last_modified.is_none()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Request {
+    pub method: String,
+    pub url: Url,
+    pub headers: Headers,
+    pub body: Vec<u8>,
+    /// URL of the top-level document making the request, for SameSite and third-party cookies.
+    ///   None for navigations started by the user.
+    pub site_for_cookies: Option<Url>,
+    /// Navigation of the top-level document
+    pub top_level: bool,
+}
+
+impl Request {
+    pub fn new(method: &str, url: Url) -> Self {
+        Self {
+            method: String::from(method),
+            url,
+            headers: Headers::new(),
+            body: vec![],
+            site_for_cookies: None,
+            top_level: false,
+        }
+    }
+
+    pub fn get(url: Url) -> Self {
+        Self::new("GET", url)
+    }
+
+    pub fn head(url: Url) -> Self {
+        Self::new("HEAD", url)
+    }
+
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.set(name, value);
+        self
+    }
+
+    /// Ask for the body only if it changed since `validators` were received: unchanged resources
+    ///   answer `304 Not Modified`.
+    pub fn conditional(mut self, validators: &Validators) -> Self {
+        if let Some(etag) = &validators.etag {
+            self.headers.set("If-None-Match", etag);
+        }
+        if let Some(last_modified) = &validators.last_modified {
+            self.headers.set("If-Modified-Since", last_modified);
+        }
+        self
+    }
+
+    /// Safe to send again when a kept-alive connection turns out to be closed.
+    fn is_idempotent(&self) -> bool {
+        matches!(self.method.as_str(), "GET" | "HEAD" | "OPTIONS" | "PUT" | "DELETE")
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Response {
+    /// Where the response came from, after redirects
+    pub url: Url,
+    pub status: u16,
+    pub reason: String,
+    pub headers: Headers,
+    /// Without transfer and content encodings
+    pub body: Vec<u8>,
+    /// URLs redirected from, in order
+    pub redirects: Vec<Url>,
+    /// Served by the HTTP cache, without asking the server
+    pub from_cache: bool,
+}
+
+impl Response {
+    /// A response made by the browser rather than received, as the one of a `data:` URL.
+    pub fn new(url: Url, status: u16, content_type: &str, body: Vec<u8>) -> Self {
+        let mut headers = Headers::new();
+        headers.set("Content-Type", content_type);
+        headers.set("Content-Length", &body.len().to_string());
+        Self {
+            url,
+            status,
+            reason: String::from(reason_phrase(status)),
+            headers,
+            body,
+            redirects: vec![],
+            from_cache: false,
+        }
+    }
+
+    /// Type and subtype of the `Content-Type`, lowercase and without parameters.
+    pub fn mime_type(&self) -> Option<String> {
+        let content_type = self.headers.get("Content-Type")?;
+        let essence = content_type.split(';').next().unwrap_or("").trim();
+        Some(essence.to_ascii_lowercase()).filter(|essence| essence.contains('/'))
+    }
+
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+
+    pub fn is_not_modified(&self) -> bool {
+        self.status == 304
+    }
+
+    pub fn validators(&self) -> Validators {
+        Validators {
+            etag: self.headers.get("ETag").map(String::from),
+            last_modified: self.headers.get("Last-Modified").map(String::from),
+        }
+    }
+}
+
+/// An open connection, with what was read ahead of the current response.
+#[derive(Debug)]
+struct Connection {
+    reader: BufReader<Box<dyn Stream>>,
+    /// HTTP proxy the requests are sent to, instead of the origin
+    forward: Option<Proxy>,
+}
+
+/// How the end of a response body is found.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum BodyLength {
+    Empty,
+    Chunked,
+    Length(usize),
+    /// Until the server closes the connection, which can't be reused
+    Close,
+}
+
+/// An HTTP/1.1 client keeping connections alive per origin.
+#[derive(Debug)]
+pub struct HttpClient {
+    /// Idle connections by origin
+    idle: HashMap<String, Vec<Connection>>,
+    pub max_idle_per_origin: usize,
+    pub max_redirects: usize,
+    /// Largest response body accepted, in bytes
+    pub max_body_size: usize,
+    pub connect_timeout: Duration,
+    /// Longest wait for data from the server
+    pub read_timeout: Duration,
+    pub user_agent: String,
+    tls: TlsConnector,
+    cache: Option<HttpCache>,
+    cookies: CookieJar,
+    proxies: ProxyConfig,
+    /// Serve only from the cache, never connecting
+    pub offline: bool,
+}
+
+impl Default for HttpClient {
+    fn default() -> Self {
+        Self {
+            idle: HashMap::new(),
+            max_idle_per_origin: 6,
+            max_redirects: 20,
+            max_body_size: 256 * 1024 * 1024,
+            connect_timeout: Duration::from_secs(15),
+            read_timeout: Duration::from_secs(30),
+            user_agent: String::from(USER_AGENT),
+            tls: TlsConnector::new(),
+            cache: None,
+            cookies: CookieJar::new(),
+            proxies: ProxyConfig::default(),
+            offline: false,
+        }
+    }
+}
+
+impl HttpClient {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A client trusting only `certificate`, in DER, as the one of a local test server.
+    pub fn with_root_certificate(certificate: &[u8]) -> Self {
+        let mut tls = TlsConnector::without_system_roots();
+        tls.add_root_certificate(certificate);
+        Self { tls, ..Self::default() }
+    }
+
+    /// Why the certificate of `host` on `port` was refused by the last connection to it, if it was.
+    pub fn certificate_problem(&self, host: &str, port: u16) -> Option<CertificateProblem> {
+        self.tls.certificate_problem(host, port)
+    }
+
+    /// Trust the certificate last refused for `host` on `port` for the rest of the session.
+    ///   Returns false if there is none.
+    pub fn allow_certificate(&mut self, host: &str, port: u16) -> bool {
+        self.tls.allow_certificate(host, port)
+    }
+
+    pub fn set_cache(&mut self, cache: Option<HttpCache>) {
+        self.cache = cache;
+    }
+
+    pub fn cache_mut(&mut self) -> Option<&mut HttpCache> {
+        self.cache.as_mut()
+    }
+
+    pub fn set_cookies(&mut self, cookies: CookieJar) {
+        self.cookies = cookies;
+    }
+
+    /// Connect through the proxies of `proxies` from now on, closing the idle connections.
+    pub fn set_proxies(&mut self, proxies: ProxyConfig) {
+        if proxies != self.proxies {
+            self.idle.clear();
+            self.proxies = proxies;
+        }
+    }
+
+    pub fn cookies(&self) -> &CookieJar {
+        &self.cookies
+    }
+
+    pub fn cookies_mut(&mut self) -> &mut CookieJar {
+        &mut self.cookies
+    }
+
+    /// Connections kept alive for later requests.
+    pub fn idle_connections(&self) -> usize {
+        self.idle.values().map(Vec::len).sum()
+    }
+
+    pub fn get(&mut self, url: &Url) -> Result<Response, SimpleError> {
+        self.fetch(Request::get(url.clone()))
+    }
+
+    /// Send `request`, following redirects, using the cache the default way.
+    pub fn fetch(&mut self, request: Request) -> Result<Response, SimpleError> {
+        self.fetch_with(request, CacheMode::Default)
+    }
+
+    /// Send `request`, following redirects, using the cache as `mode` says.
+    pub fn fetch_with(&mut self, mut request: Request, mode: CacheMode) -> Result<Response, SimpleError> {
+        let mut redirects = vec![];
+        // Redirects back to a URL already seen are fine, as after a POST or a login: `max_redirects` stops loops
+        loop {
+            // Each hop gets the cookies of its own URL
+            match self.cookies.cookie_header(&request, http_cache::unix_now()) {
+                Some(cookies) => request.headers.set("Cookie", &cookies),
+                None => request.headers.remove("Cookie"),
+            }
+            let mut response = self.send_cached(&request, mode)?;
+            if !response.from_cache {
+                self.cookies.store_response(&request, &response, http_cache::unix_now());
+            }
+            let location = match response.headers.get("Location") {
+                Some(location) if matches!(response.status, 301 | 302 | 303 | 307 | 308) => location,
+                _ => {
+                    response.redirects = redirects;
+                    return Ok(response);
+                }
+            };
+            let target = request.url.join(location).map_err(|err| {
+                SimpleError::new(format!("Invalid redirect from {} to {:?}: {}", request.url, location, err).as_str())
+            })?;
+            if redirects.len() >= self.max_redirects {
+                return Err(SimpleError::new(
+                    format!("Too many redirects from {}", redirects.first().unwrap_or(&request.url)).as_str(),
+                ));
+            }
+            debug!("Redirect {} {} -> {}", response.status, request.url, target);
+            // 303, and 301 and 302 for historical reasons, turn the request into a GET
+            let to_get = response.status == 303 && request.method != "HEAD"
+                || matches!(response.status, 301 | 302) && request.method == "POST";
+            if to_get {
+                request.method = String::from("GET");
+                request.body.clear();
+                request.headers.remove("Content-Type");
+            }
+            if target.origin() != request.url.origin() {
+                request.headers.remove("Authorization");
+            }
+            redirects.push(std::mem::replace(&mut request.url, target));
+        }
+    }
+
+    /// One request and its response, from the cache when it may (RFC 9111).
+    fn send_cached(&mut self, request: &Request, mode: CacheMode) -> Result<Response, SimpleError> {
+        let request_control = CacheControl::parse(&request.headers);
+        let mode = match mode {
+            _ if self.offline => CacheMode::OnlyIfCached,
+            _ if request_control.no_store => CacheMode::NoStore,
+            CacheMode::Default if request_control.no_cache || request_control.max_age == Some(0) => CacheMode::NoCache,
+            mode => mode,
+        };
+        // Requests with their own validators are answered by the server, for the caller to handle a 304
+        let conditional =
+            request.headers.get("If-None-Match").is_some() || request.headers.get("If-Modified-Since").is_some();
+        if self.cache.is_none() || mode == CacheMode::NoStore || conditional {
+            return self.send(request);
+        }
+        if request.method != "GET" && request.method != "HEAD" {
+            let response = self.send(request)?;
+            // Unsafe methods make the stored response outdated (RFC 9111 section 4.4)
+            if (200..400).contains(&response.status) {
+                if let Some(cache) = self.cache.as_mut() {
+                    cache.remove(&request.url);
+                }
+            }
+            return Ok(response);
+        }
+        let request_time = http_cache::unix_now();
+        let cached = match (self.cache.as_mut(), mode) {
+            (Some(cache), mode) if mode != CacheMode::Reload && request.method == "GET" => {
+                cache.lookup(request, request_time)
+            }
+            _ => None,
+        };
+        let revalidate = match (mode, cached) {
+            (CacheMode::OnlyIfCached, Some(cached)) if cached.fresh || !cached.must_revalidate => {
+                return Ok(cached.response)
+            }
+            (CacheMode::OnlyIfCached, _) => {
+                return Err(SimpleError::new(
+                    format!("{} is not in the cache, and the browser is offline", request.url).as_str(),
+                ))
+            }
+            (CacheMode::ForceCache, Some(cached)) => return Ok(cached.response),
+            (CacheMode::Default, Some(cached)) if cached.fresh => {
+                debug!("Cache hit {}", request.url);
+                return Ok(cached.response);
+            }
+            (_, cached) => cached
+                .map(|cached| cached.response.validators())
+                .filter(|validators| !validators.is_empty()),
+        };
+        let response = match &revalidate {
+            Some(validators) => {
+                debug!("Revalidating {}", request.url);
+                self.send(&request.clone().conditional(validators))?
+            }
+            None => self.send(request)?,
+        };
+        let response_time = http_cache::unix_now();
+        let cache = match self.cache.as_mut() {
+            Some(cache) if request.method == "GET" => cache,
+            _ => return Ok(response),
+        };
+        if response.is_not_modified() && revalidate.is_some() {
+            if let Some(freshened) = cache.freshen(request, &response, request_time, response_time) {
+                return Ok(freshened);
+            }
+        }
+        cache.store(request, &response, request_time, response_time);
+        Ok(response)
+    }
+
+    /// One request and its response. A kept-alive connection the server closed before answering is
+    ///   retried once.
+    fn send(&mut self, request: &Request) -> Result<Response, SimpleError> {
+        check_head(request, &self.user_agent)?;
+        let mut key = origin_key(&request.url)?;
+        if let Some(proxy) = self.proxies.proxy_for(&request.url) {
+            key = format!("{} via {}", key, proxy);
+        }
+        if let Some(mut connection) = self.idle.get_mut(&key).and_then(Vec::pop) {
+            debug!("Reuse connection to {} for {}", key, request.url);
+            match self.write_request(&mut connection, request) {
+                Ok(()) => return self.exchange(connection, request, &key),
+                // A timeout may come after the server acted on the request
+                Err(err) if request.is_idempotent() && closed_early(&err) => {
+                    debug!("Kept-alive connection to {} closed, retrying: {}", key, err);
+                }
+                Err(err) => return Err(io_error(&request.url, err)),
+            }
+        }
+        let mut connection = self.connect(&request.url)?;
+        self.write_request(&mut connection, request)
+            .map_err(|err| io_error(&request.url, err))?;
+        self.exchange(connection, request, &key)
+    }
+
+    /// Write `request` and wait for the first byte of its response.
+    fn write_request(&self, connection: &mut Connection, request: &Request) -> std::io::Result<()> {
+        let head = self.request_head(request, connection.forward.as_ref());
+        let stream = connection.reader.get_mut();
+        stream.write_all(head.as_bytes())?;
+        stream.write_all(&request.body)?;
+        stream.flush()?;
+        if connection.reader.fill_buf()?.is_empty() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::UnexpectedEof,
+                "connection closed by the server",
+            ));
+        }
+        Ok(())
+    }
+
+    /// The response to the request written on `connection`.
+    fn exchange(&mut self, mut connection: Connection, request: &Request, key: &str) -> Result<Response, SimpleError> {
+        let error = |err: std::io::Error| io_error(&request.url, err);
+        let (version, status, reason, mut headers) = loop {
+            let head = read_head(&mut connection.reader).map_err(error)?;
+            // Interim responses as `100 Continue` come before the final one
+            if !(100..200).contains(&head.1) || head.1 == 101 {
+                break head;
+            }
+        };
+        let length = body_length(request, status, &headers)?;
+        let body = match length {
+            BodyLength::Empty => vec![],
+            BodyLength::Length(length) if length > self.max_body_size => {
+                return Err(error(invalid_data(format!(
+                    "body larger than {} bytes",
+                    self.max_body_size
+                ))))
+            }
+            BodyLength::Length(length) => {
+                // Grown as data arrives rather than by the length announced
+                let mut body = vec![];
+                let read = connection.reader.by_ref().take(length as u64).read_to_end(&mut body);
+                if read.map_err(error)? < length {
+                    return Err(error(std::io::Error::new(
+                        std::io::ErrorKind::UnexpectedEof,
+                        "body cut short",
+                    )));
+                }
+                body
+            }
+            BodyLength::Chunked => read_chunked(&mut connection.reader, self.max_body_size).map_err(error)?,
+            BodyLength::Close => {
+                let mut body = vec![];
+                match read_limited(connection.reader.by_ref(), &mut body, self.max_body_size) {
+                    // TLS servers often close without a close_notify alert
+                    Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => (),
+                    result => {
+                        result.map_err(error)?;
+                    }
+                }
+                body
+            }
+        };
+        let keep_alive = length != BodyLength::Close
+            && !headers.has_token("Connection", "close")
+            && (version != "HTTP/1.0" || headers.has_token("Connection", "keep-alive"));
+        if keep_alive {
+            let idle = self.idle.entry(String::from(key)).or_default();
+            if idle.len() < self.max_idle_per_origin {
+                idle.push(connection);
+            }
+        }
+        let body = decode_content(&mut headers, body, self.max_body_size)?;
+        debug!(
+            "{} {} -> {} {} ({} bytes)",
+            request.method,
+            request.url,
+            status,
+            reason,
+            body.len()
+        );
+        Ok(Response {
+            url: request.url.clone(),
+            status,
+            reason,
+            headers,
+            body,
+            redirects: vec![],
+            from_cache: false,
+        })
+    }
+
+    /// Requests forwarded by a proxy name their whole URL (RFC 9112 section 3.2.2).
+    fn request_head(&self, request: &Request, forward: Option<&Proxy>) -> String {
+        let url = &request.url;
+        let target = match (forward, url.query()) {
+            (Some(_), _) => String::from(&url[..url::Position::AfterQuery]),
+            (None, Some(query)) => format!("{}?{}", url.path(), query),
+            (None, None) => String::from(url.path()),
+        };
+        let mut head = format!("{} {} HTTP/1.1\r\n", request.method, target);
+        let host = url.host_str().unwrap_or("");
+        match url.port() {
+            Some(port) => head.push_str(&format!("Host: {}:{}\r\n", host, port)),
+            None => head.push_str(&format!("Host: {}\r\n", host)),
+        }
+        let mut defaults = Headers::new();
+        defaults.add("User-Agent", &self.user_agent);
+        defaults.add("Accept", "*/*");
+        defaults.add("Accept-Encoding", "gzip, deflate");
+        defaults.add("Connection", "keep-alive");
+        if let Some(authorization) = forward.and_then(Proxy::authorization) {
+            defaults.add("Proxy-Authorization", &authorization);
+        }
+        if !request.body.is_empty() || matches!(request.method.as_str(), "POST" | "PUT") {
+            defaults.add("Content-Length", &request.body.len().to_string());
+        }
+        for (name, value) in defaults.iter().filter(|(name, _)| request.headers.get(name).is_none()) {
+            head.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        for (name, value) in request.headers.iter() {
+            head.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        head.push_str("\r\n");
+        head
+    }
+
+    /// A connection to the origin of `url`, tunneled through its proxy if it has one.
+    fn connect(&mut self, url: &Url) -> Result<Connection, SimpleError> {
+        let host = url
+            .host_str()
+            .ok_or_else(|| SimpleError::new(format!("No host in {}", url).as_str()))?;
+        let port = url
+            .port_or_known_default()
+            .ok_or_else(|| SimpleError::new(format!("No port for {}", url).as_str()))?;
+        let proxy = self.proxies.proxy_for(url).cloned();
+        let stream = match &proxy {
+            None => self.open_tcp(host, port)?,
+            Some(proxy) => {
+                debug!("Connect to {} through {}", host, proxy);
+                let mut stream = self.open_tcp(&proxy.host, proxy.port)?;
+                match proxy.kind {
+                    _ if proxy.forwards(url) => (),
+                    ProxyKind::Http => proxy::http_connect(&mut stream, proxy, host, port, &self.user_agent)?,
+                    ProxyKind::Socks5 | ProxyKind::Socks5h => proxy::socks5_connect(&mut stream, proxy, host, port)?,
+                }
+                stream
+            }
+        };
+        let stream: Box<dyn Stream> = match url.scheme() {
+            "http" => Box::new(stream),
+            "https" => self.tls.connect(host, port, stream)?,
+            scheme => {
+                return Err(SimpleError::new(
+                    format!("Unsupported scheme {} for {}", scheme, url).as_str(),
+                ))
+            }
+        };
+        Ok(Connection {
+            reader: BufReader::new(stream),
+            forward: proxy.filter(|proxy| proxy.forwards(url)),
+        })
+    }
+
+    /// A TCP connection to the first address of `host` accepting it.
+    fn open_tcp(&self, host: &str, port: u16) -> Result<TcpStream, SimpleError> {
+        let error = |err: std::io::Error| SimpleError::new(format!("{}:{}: {}", host, port, err).as_str());
+        let addresses: Vec<_> = (host.trim_start_matches('[').trim_end_matches(']'), port)
+            .to_socket_addrs()
+            .map_err(error)?
+            .collect();
+        let mut last_error = SimpleError::new(format!("{} has no address", host).as_str());
+        for address in addresses {
+            debug!("Connect to {} ({})", host, address);
+            match TcpStream::connect_timeout(&address, self.connect_timeout) {
+                Ok(stream) => {
+                    stream.set_read_timeout(Some(self.read_timeout)).map_err(error)?;
+                    stream.set_write_timeout(Some(self.read_timeout)).map_err(error)?;
+                    stream.set_nodelay(true).map_err(error)?;
+                    return Ok(stream);
+                }
+                Err(err) => last_error = error(err),
+            }
+        }
+        Err(last_error)
+    }
+}
+
+fn reason_phrase(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        204 => "No Content",
+        301 => "Moved Permanently",
+        302 => "Found",
+        304 => "Not Modified",
+        400 => "Bad Request",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        500 => "Internal Server Error",
+        _ => "",
+    }
+}
+
+/// Connections are shared by scheme, host and port.
+fn origin_key(url: &Url) -> Result<String, SimpleError> {
+    match url.origin() {
+        url::Origin::Tuple(..) => Ok(url.origin().ascii_serialization()),
+        url::Origin::Opaque(_) => Err(SimpleError::new(format!("Not an HTTP URL: {}", url).as_str())),
+    }
+}
+
+/// Refuse methods and header fields that would end the request head early, as values with a CR or LF.
+fn check_head(request: &Request, user_agent: &str) -> Result<(), SimpleError> {
+    let is_token = |s: &str| {
+        !s.is_empty()
+            && s.bytes()
+                .all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b))
+    };
+    if !is_token(&request.method) {
+        return Err(SimpleError::new(
+            format!("Invalid method {:?} for {}", request.method, request.url).as_str(),
+        ));
+    }
+    let fields = request.headers.iter().chain([("User-Agent", user_agent)]);
+    for (name, value) in fields {
+        if !is_token(name) || value.bytes().any(|b| matches!(b, b'\r' | b'\n' | 0)) {
+            return Err(SimpleError::new(
+                format!("Invalid header field {:?} for {}", name, request.url).as_str(),
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn io_error(url: &Url, err: std::io::Error) -> SimpleError {
+    let reason = match err.kind() {
+        std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut => String::from("timed out"),
+        _ => err.to_string(),
+    };
+    SimpleError::new(format!("{}: {}", url, reason).as_str())
+}
+
+/// True if `err` means the connection was closed, rather than slow or answering garbage.
+fn closed_early(err: &std::io::Error) -> bool {
+    matches!(
+        err.kind(),
+        std::io::ErrorKind::UnexpectedEof
+            | std::io::ErrorKind::ConnectionReset
+            | std::io::ErrorKind::ConnectionAborted
+            | std::io::ErrorKind::BrokenPipe
+    )
+}
+
+fn invalid_data(message: String) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message)
+}
+
+/// A line without its CRLF, refusing lines that don't end before `MAX_LINE_LENGTH`.
+fn read_line(reader: &mut impl BufRead) -> std::io::Result<String> {
+    let mut line = vec![];
+    let read = reader.take(MAX_LINE_LENGTH as u64).read_until(b'\n', &mut line)?;
+    if read == 0 {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::UnexpectedEof,
+            "connection closed by the server",
+        ));
+    }
+    if line.last() != Some(&b'\n') {
+        return Err(invalid_data(String::from("line too long or cut short")));
+    }
+    while matches!(line.last(), Some(b'\n') | Some(b'\r')) {
+        line.pop();
+    }
+    Ok(String::from_utf8_lossy(&line).into_owned())
+}
+
+/// Status line and header fields.
+pub fn read_head(reader: &mut impl BufRead) -> std::io::Result<(String, u16, String, Headers)> {
+    let status_line = read_line(reader)?;
+    let mut parts = status_line.splitn(3, ' ');
+    let version = parts.next().unwrap_or("");
+    let status = parts.next().and_then(|s| s.parse::<u16>().ok());
+    let status = match status {
+        Some(status) if version.starts_with("HTTP/1.") => status,
+        _ => return Err(invalid_data(format!("invalid status line {:?}", status_line))),
+    };
+    let reason = String::from(parts.next().unwrap_or(""));
+    let mut headers = Headers::new();
+    loop {
+        let line = read_line(reader)?;
+        if line.is_empty() {
+            break;
+        }
+        match line.split_once(':') {
+            Some((name, value)) => headers.add(name.trim(), value.trim()),
+            None => warn!("Ignoring header line {:?}", line),
+        }
+        if headers.fields.len() > MAX_HEADERS {
+            return Err(invalid_data(String::from("too many header fields")));
+        }
+    }
+    Ok((String::from(version), status, reason, headers))
+}
+
+fn body_length(request: &Request, status: u16, headers: &Headers) -> Result<BodyLength, SimpleError> {
+    if request.method == "HEAD" || status == 204 || status == 304 || (100..200).contains(&status) {
+        return Ok(BodyLength::Empty);
+    }
+    if headers.has_token("Transfer-Encoding", "chunked") {
+        return Ok(BodyLength::Chunked);
+    }
+    if let Some(length) = headers.get("Content-Length") {
+        return length
+            .parse()
+            .map(BodyLength::Length)
+            .map_err(|_| SimpleError::new(format!("{}: invalid Content-Length {:?}", request.url, length).as_str()));
+    }
+    Ok(BodyLength::Close)
+}
+
+/// Body sent in chunks, each preceded by its hexadecimal size, up to an empty chunk and trailers.
+///   Bodies longer than `limit` are refused.
+fn read_chunked(reader: &mut impl BufRead, limit: usize) -> std::io::Result<Vec<u8>> {
+    let mut body = vec![];
+    loop {
+        let line = read_line(reader)?;
+        // Chunk extensions follow a `;`
+        let size = line.split(';').next().unwrap_or("").trim();
+        let size =
+            usize::from_str_radix(size, 16).map_err(|_| invalid_data(format!("invalid chunk size {:?}", line)))?;
+        if size == 0 {
+            break;
+        }
+        let start = body.len();
+        start
+            .checked_add(size)
+            .filter(|end| *end <= limit)
+            .ok_or_else(|| invalid_data(format!("body larger than {} bytes", limit)))?;
+        // Grown as data arrives rather than by the size announced
+        if reader.by_ref().take(size as u64).read_to_end(&mut body)? < size {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::UnexpectedEof,
+                "chunk cut short",
+            ));
+        }
+        if !read_line(reader)?.is_empty() {
+            return Err(invalid_data(String::from("chunk longer than its size")));
+        }
+    }
+    // Trailer fields, ignored
+    while !read_line(reader)?.is_empty() {}
+    Ok(body)
+}
+
+/// Read `reader` to its end onto `body`, refusing to take it past `limit` bytes.
+fn read_limited(reader: impl Read, body: &mut Vec<u8>, limit: usize) -> std::io::Result<usize> {
+    let room = limit.saturating_sub(body.len()) as u64;
+    let read = reader.take(room + 1).read_to_end(body)?;
+    if body.len() > limit {
+        return Err(invalid_data(format!("body larger than {} bytes", limit)));
+    }
+    Ok(read)
+}
+
+/// Undo the `Content-Encoding` of `body`, last applied first, refusing to decode more than `limit` bytes.
+fn decode_content(headers: &mut Headers, body: Vec<u8>, limit: usize) -> Result<Vec<u8>, SimpleError> {
+    let encodings: Vec<String> = headers
+        .get_all("Content-Encoding")
+        .flat_map(|value| value.split(','))
+        .map(|encoding| encoding.trim().to_ascii_lowercase())
+        .filter(|encoding| !encoding.is_empty() && encoding != "identity")
+        .collect();
+    if encodings.is_empty() || body.is_empty() {
+        return Ok(body);
+    }
+    let mut body = body;
+    for encoding in encodings.iter().rev() {
+        let mut decoded = vec![];
+        let result = match encoding.as_str() {
+            "gzip" | "x-gzip" => read_limited(GzDecoder::new(&body[..]), &mut decoded, limit),
+            // Meant to be zlib wrapped, but some servers send raw deflate data
+            "deflate" => read_limited(ZlibDecoder::new(&body[..]), &mut decoded, limit).or_else(|err| {
+                if err.kind() == std::io::ErrorKind::InvalidData && decoded.len() > limit {
+                    return Err(err);
+                }
+                decoded.clear();
+                read_limited(DeflateDecoder::new(&body[..]), &mut decoded, limit)
+            }),
+            _ => {
+                return Err(SimpleError::new(
+                    format!("Unsupported content encoding {}", encoding).as_str(),
+                ))
+            }
+        };
+        result.map_err(|err| SimpleError::new(format!("Invalid {} data: {}", encoding, err).as_str()))?;
+        body = decoded;
+    }
+    headers.remove("Content-Encoding");
+    headers.remove("Content-Length");
+    Ok(body)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::net::TcpListener;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+
+    use assert2::check;
+    use flate2::write::{GzEncoder, ZlibEncoder};
+    use flate2::Compression;
+    use rustls::pki_types::{PrivateKeyDer, PrivatePkcs8KeyDer};
+    use rustls::{ServerConfig, ServerConnection, StreamOwned};
+
+    use super::*;
+
+    pub type Handler = Arc<dyn Fn(&str) -> Vec<u8> + Send + Sync>;
+
+    /// A local server answering every request with `handler(request head)`.
+    ///   It drops the connection after answering paths starting with `/drop` or `/close`.
+    pub struct TestServer {
+        pub url: Url,
+        pub connections: Arc<AtomicUsize>,
+        pub requests: Arc<Mutex<Vec<String>>>,
+        /// Self-signed certificate of `https://localhost`, in DER
+        pub certificate: Option<Vec<u8>>,
+        tls: Option<Arc<ServerConfig>>,
+    }
+
+    impl TestServer {
+        pub fn start(handler: Handler) -> Self {
+            Self::start_with(handler, None)
+        }
+
+        /// Serve `https://localhost` with a new self-signed certificate.
+        ///   Connections without SNI for localhost or without ALPN for HTTP/1.1 are dropped.
+        pub fn start_tls(handler: Handler) -> Self {
+            let rcgen::CertifiedKey { cert, key_pair } =
+                rcgen::generate_simple_self_signed(vec![String::from("localhost")]).unwrap();
+            let key = PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(key_pair.serialize_der()));
+            let mut config = ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+                .with_safe_default_protocol_versions()
+                .unwrap()
+                .with_no_client_auth()
+                .with_single_cert(vec![cert.der().clone()], key)
+                .unwrap();
+            config.alpn_protocols = vec![b"http/1.1".to_vec()];
+            let mut server = Self::start_with(handler, Some(Arc::new(config)));
+            server.url.set_host(Some("localhost")).unwrap();
+            server.url.set_scheme("https").unwrap();
+            server.certificate = Some(cert.der().to_vec());
+            server
+        }
+
+        /// Another server on a new port, with the same certificate.
+        pub fn start_twin(&self, handler: Handler) -> Self {
+            let mut server = Self::start_with(handler, self.tls.clone());
+            server.url.set_host(self.url.host_str()).unwrap();
+            server.url.set_scheme(self.url.scheme()).unwrap();
+            server.certificate = self.certificate.clone();
+            server
+        }
+
+        fn start_with(handler: Handler, tls: Option<Arc<ServerConfig>>) -> Self {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let url = Url::parse(&format!("http://{}/", listener.local_addr().unwrap())).unwrap();
+            let connections = Arc::new(AtomicUsize::new(0));
+            let requests = Arc::new(Mutex::new(vec![]));
+            let (count, log, config) = (connections.clone(), requests.clone(), tls.clone());
+            thread::spawn(move || {
+                for stream in listener.incoming().flatten() {
+                    count.fetch_add(1, Ordering::SeqCst);
+                    let (handler, log, tls) = (handler.clone(), log.clone(), config.clone());
+                    thread::spawn(move || match tls {
+                        None => serve_connection(stream, handler, log),
+                        Some(config) => {
+                            let mut stream = stream;
+                            let mut connection = ServerConnection::new(config).unwrap();
+                            while connection.is_handshaking() {
+                                if connection.complete_io(&mut stream).is_err() {
+                                    return;
+                                }
+                            }
+                            if connection.server_name() == Some("localhost")
+                                && connection.alpn_protocol() == Some(b"http/1.1")
+                            {
+                                serve_connection(StreamOwned::new(connection, stream), handler, log);
+                            }
+                        }
+                    });
+                }
+            });
+            Self {
+                url,
+                connections,
+                requests,
+                certificate: None,
+                tls,
+            }
+        }
+
+        pub fn connection_count(&self) -> usize {
+            self.connections.load(Ordering::SeqCst)
+        }
+
+        pub fn last_request(&self) -> String {
+            self.requests.lock().unwrap().last().cloned().unwrap_or_default()
+        }
+    }
+
+    fn serve_connection(stream: impl Read + Write, handler: Handler, log: Arc<Mutex<Vec<String>>>) {
+        let mut reader = BufReader::new(stream);
+        loop {
+            let mut head = String::new();
+            loop {
+                let mut line = String::new();
+                match reader.read_line(&mut line) {
+                    Ok(0) | Err(_) => return,
+                    Ok(_) => (),
+                }
+                head.push_str(&line);
+                if line == "\r\n" {
+                    break;
+                }
+            }
+            let length = head
+                .lines()
+                .find_map(|l| {
+                    l.to_ascii_lowercase()
+                        .strip_prefix("content-length:")
+                        .map(|v| v.trim().to_string())
+                })
+                .and_then(|v| v.parse::<usize>().ok())
+                .unwrap_or(0);
+            let mut body = vec![0; length];
+            if reader.read_exact(&mut body).is_err() {
+                return;
+            }
+            log.lock().unwrap().push(head.clone());
+            let mut response = handler(&head);
+            // Responses to HEAD have no body
+            if head.starts_with("HEAD ") {
+                let end = response
+                    .windows(4)
+                    .position(|w| w == b"\r\n\r\n")
+                    .map_or(response.len(), |i| i + 4);
+                response.truncate(end);
+            }
+            if reader
+                .get_mut()
+                .write_all(&response)
+                .and_then(|_| reader.get_mut().flush())
+                .is_err()
+            {
+                return;
+            }
+            let close = head
+                .split(' ')
+                .nth(1)
+                .is_some_and(|path| path.starts_with("/drop") || path.starts_with("/close"))
+                || String::from_utf8_lossy(&response)
+                    .to_ascii_lowercase()
+                    .contains("connection: close");
+            if close {
+                return;
+            }
+        }
+    }
+
+    pub fn response(status: &str, headers: &[&str], body: &[u8]) -> Vec<u8> {
+        let mut response = format!("HTTP/1.1 {}\r\n", status);
+        for header in headers {
+            response.push_str(header);
+            response.push_str("\r\n");
+        }
+        response.push_str("\r\n");
+        let mut response = response.into_bytes();
+        response.extend_from_slice(body);
+        response
+    }
+
+    fn path(head: &str) -> &str {
+        head.split(' ').nth(1).unwrap_or("")
+    }
+
+    fn ok(body: &str) -> Vec<u8> {
+        response("200 OK", &[&format!("Content-Length: {}", body.len())], body.as_bytes())
+    }
+
+    #[test]
+    fn keep_alive_and_body_lengths() {
+        let server = TestServer::start(Arc::new(|head: &str| match path(head) {
+            "/chunked" => response(
+                "200 OK",
+                &["Transfer-Encoding: chunked"],
+                b"5;ext=1\r\nHello\r\n8\r\n, world!\r\n0\r\nTrailer: x\r\n\r\n",
+            ),
+            "/huge-chunk" => response(
+                "200 OK",
+                &["Transfer-Encoding: chunked"],
+                b"ffffffffffffffff\r\nHello\r\n0\r\n\r\n",
+            ),
+            "/huge-length" => response("200 OK", &["Content-Length: 99999999999999"], b"short"),
+            "/close" => b"HTTP/1.0 200 OK\r\n\r\nuntil the end".to_vec(),
+            "/drop" => ok("dropped"),
+            _ => ok("plain"),
+        }));
+        let mut client = HttpClient::new();
+        let url = |path: &str| server.url.join(path).unwrap();
+
+        check!(client.get(&url("/a")).unwrap().body == b"plain");
+        check!(client.get(&url("/chunked")).unwrap().body == b"Hello, world!");
+        check!(client.get(&url("/b")).unwrap().body == b"plain");
+        check!(server.connection_count() == 1);
+        check!(client.idle_connections() == 1);
+        check!(server.last_request().contains("Host: 127.0.0.1:"));
+
+        // Close delimited bodies end the connection
+        check!(client.get(&url("/close")).unwrap().body == b"until the end");
+        check!(client.idle_connections() == 0);
+        check!(server.connection_count() == 1);
+
+        // The server closing a kept-alive connection is retried on a new one
+        check!(client.get(&url("/drop")).unwrap().body == b"dropped");
+        check!(client.get(&url("/c")).unwrap().body == b"plain");
+        check!(server.connection_count() == 3);
+
+        let head = client.fetch(Request::head(url("/a"))).unwrap();
+        check!(head.status == 200);
+        check!(head.body.is_empty());
+
+        let error = client.get(&url("/huge-chunk")).unwrap_err();
+        check!(error.to_string().contains("body larger than"));
+        let error = client.get(&url("/huge-length")).unwrap_err();
+        check!(error.to_string().contains("body larger than"));
+        client.max_body_size = 5;
+        let error = client.get(&url("/close")).unwrap_err();
+        check!(error.to_string().contains("body larger than"));
+    }
+
+    #[test]
+    fn header_injection() {
+        let server = TestServer::start(Arc::new(|_: &str| ok("plain")));
+        let mut client = HttpClient::new();
+        let request = Request::get(server.url.clone()).with_header("X-Test", "1\r\nX-Injected: 1");
+        check!(client.fetch(request).is_err());
+        let request = Request::get(server.url.clone()).with_header("X-Test\r\nX-Injected", "1");
+        check!(client.fetch(request).is_err());
+        let request = Request::get(server.url.clone()).with_header("X-Test", "nul\0");
+        check!(client.fetch(request).is_err());
+        let mut request = Request::get(server.url.clone());
+        request.method = String::from("GET / HTTP/1.1\r\nX-Injected: 1\r\n\r\nGET");
+        check!(client.fetch(request).is_err());
+        check!(server.requests.lock().unwrap().is_empty());
+        let request = Request::get(server.url.clone()).with_header("X-Test", "a value: with \"quotes\"");
+        check!(client.fetch(request).unwrap().body == b"plain");
+    }
+
+    #[test]
+    fn redirects() {
+        let server = TestServer::start(Arc::new(|head: &str| match path(head) {
+            "/start" => response("302 Found", &["Location: /middle", "Content-Length: 0"], b""),
+            "/middle" => response(
+                "301 Moved Permanently",
+                &["Location: final?x=1", "Content-Length: 0"],
+                b"",
+            ),
+            "/loop" => response(
+                "307 Temporary Redirect",
+                &["Location: /loop2", "Content-Length: 0"],
+                b"",
+            ),
+            "/loop2" => response("308 Permanent Redirect", &["Location: /loop", "Content-Length: 0"], b""),
+            "/see-other" => response("303 See Other", &["Location: /final", "Content-Length: 0"], b""),
+            _ if head.starts_with("POST /form") => {
+                response("303 See Other", &["Location: /form", "Content-Length: 0"], b"")
+            }
+            _ if head.starts_with("GET /form") => ok("submitted"),
+            _ if head.starts_with("GET /final") => ok("arrived"),
+            _ => response("405 Method Not Allowed", &["Content-Length: 0"], b""),
+        }));
+        let mut client = HttpClient::new();
+        let response = client.get(&server.url.join("/start").unwrap()).unwrap();
+        check!(response.body == b"arrived");
+        check!(response.url.path() == "/final");
+        check!(response.url.query() == Some("x=1"));
+        check!(response.redirects.len() == 2);
+
+        let error = client.get(&server.url.join("/loop").unwrap()).unwrap_err();
+        check!(error.to_string().contains("Too many redirects"));
+
+        // Post/Redirect/Get to the URL posted to
+        let mut post = Request::new("POST", server.url.join("/form").unwrap());
+        post.body = b"a=1".to_vec();
+        let response = client.fetch(post).unwrap();
+        check!(response.body == b"submitted");
+        check!(response.redirects.len() == 1);
+
+        let mut post = Request::new("POST", server.url.join("/see-other").unwrap());
+        post.body = b"a=1".to_vec();
+        check!(client.fetch(post).unwrap().body == b"arrived");
+
+        client.max_redirects = 1;
+        check!(client.get(&server.url.join("/start").unwrap()).is_err());
+    }
+
+    #[test]
+    fn content_encodings() {
+        let server = TestServer::start(Arc::new(|head: &str| {
+            let mut body = vec![];
+            let encoding = match path(head) {
+                "/bomb" => {
+                    let mut encoder = GzEncoder::new(&mut body, Compression::default());
+                    encoder.write_all(&[0; 100_000]).unwrap();
+                    encoder.finish().unwrap();
+                    "gzip"
+                }
+                "/gzip" => {
+                    let mut encoder = GzEncoder::new(&mut body, Compression::default());
+                    encoder.write_all(b"gzipped text").unwrap();
+                    encoder.finish().unwrap();
+                    "gzip"
+                }
+                _ => {
+                    let mut encoder = ZlibEncoder::new(&mut body, Compression::default());
+                    encoder.write_all(b"deflated text").unwrap();
+                    encoder.finish().unwrap();
+                    "deflate"
+                }
+            };
+            response(
+                "200 OK",
+                &[
+                    &format!("Content-Encoding: {}", encoding),
+                    &format!("Content-Length: {}", body.len()),
+                ],
+                &body,
+            )
+        }));
+        let mut client = HttpClient::new();
+        let gzip = client.get(&server.url.join("/gzip").unwrap()).unwrap();
+        check!(gzip.body == b"gzipped text");
+        check!(gzip.headers.get("Content-Encoding") == None);
+        check!(server.last_request().contains("Accept-Encoding: gzip, deflate"));
+        check!(client.get(&server.url.join("/deflate").unwrap()).unwrap().body == b"deflated text");
+
+        client.max_body_size = 10_000;
+        let error = client.get(&server.url.join("/bomb").unwrap()).unwrap_err();
+        check!(error.to_string().contains("body larger than 10000 bytes"));
+    }
+
+    #[test]
+    fn conditional_requests() {
+        let server = TestServer::start(Arc::new(|head: &str| {
+            if head.contains("If-None-Match: \"v1\"") {
+                response("304 Not Modified", &["ETag: \"v1\""], b"")
+            } else {
+                response(
+                    "200 OK",
+                    &[
+                        "ETag: \"v1\"",
+                        "Last-Modified: Tue, 01 Jun 2021 10:00:00 GMT",
+                        "Content-Length: 4",
+                    ],
+                    b"data",
+                )
+            }
+        }));
+        let mut client = HttpClient::new();
+        let first = client.get(&server.url).unwrap();
+        let validators = first.validators();
+        check!(validators.etag.as_deref() == Some("\"v1\""));
+        let second = client
+            .fetch(Request::get(server.url.clone()).conditional(&validators))
+            .unwrap();
+        check!(second.is_not_modified());
+        check!(second.body.is_empty());
+        check!(server
+            .last_request()
+            .contains("If-Modified-Since: Tue, 01 Jun 2021 10:00:00 GMT"));
+        check!(server.connection_count() == 1);
+    }
+
+    #[test]
+    fn timeouts() {
+        let server = TestServer::start(Arc::new(|head: &str| match path(head) {
+            "/slow" => {
+                thread::sleep(Duration::from_millis(500));
+                ok("late")
+            }
+            _ => ok("fast"),
+        }));
+        let mut client = HttpClient::new();
+        client.read_timeout = Duration::from_millis(100);
+        check!(client.get(&server.url).unwrap().body == b"fast");
+        // Not retried on a new connection: the server may have acted on the request
+        let error = client.get(&server.url.join("/slow").unwrap()).unwrap_err();
+        check!(error.to_string().contains("timed out"));
+        check!(client.idle_connections() == 0);
+        check!(server.requests.lock().unwrap().len() == 2);
+        check!(server.connection_count() == 1);
+    }
+
+    #[test]
+    fn tls() {
+        let server = TestServer::start_tls(Arc::new(|head: &str| match path(head) {
+            "/close" => b"HTTP/1.0 200 OK\r\n\r\nuntil the end".to_vec(),
+            _ => ok("secret"),
+        }));
+        let certificate = server.certificate.clone().unwrap();
+        let mut client = HttpClient::with_root_certificate(&certificate);
+        check!(client.get(&server.url).unwrap().body == b"secret");
+        check!(client.get(&server.url).unwrap().body == b"secret");
+        check!(server.connection_count() == 1);
+        check!(client.get(&server.url.join("/close").unwrap()).unwrap().body == b"until the end");
+
+        // Unknown certificates are refused, until the user trusts them
+        let mut client = HttpClient::new();
+        let error = client.get(&server.url).unwrap_err();
+        check!(error.to_string().contains("not trusted"));
+        let port = server.url.port().unwrap();
+        let problem = client.certificate_problem("localhost", port).unwrap();
+        check!(problem.reason == "it is issued by an unknown authority");
+        check!(!client.allow_certificate("localhost", port + 1));
+        check!(client.allow_certificate("localhost", port));
+        check!(!client.allow_certificate("localhost", port));
+        check!(client.get(&server.url).unwrap().body == b"secret");
+        check!(client.certificate_problem("localhost", port) == None);
+
+        // Exceptions are for one port: another server of the host may be someone else
+        let other = server.start_twin(Arc::new(|_: &str| ok("other")));
+        check!(client.get(&other.url).is_err());
+        check!(client
+            .certificate_problem("localhost", other.url.port().unwrap())
+            .is_some());
+        check!(client.get(&server.url).unwrap().body == b"secret");
+
+        // The name must match: the certificate is only for localhost
+        let mut by_address = server.url.clone();
+        by_address.set_host(Some("127.0.0.1")).unwrap();
+        let mut client = HttpClient::with_root_certificate(&certificate);
+        check!(client.get(&by_address).is_err());
+        let problem = client.certificate_problem("127.0.0.1", port).unwrap();
+        check!(problem.reason == "it is not valid for 127.0.0.1");
+    }
+
+    #[test]
+    fn cache_modes() {
+        let server = TestServer::start(Arc::new(|head: &str| match path(head) {
+            "/fresh" => response("200 OK", &["Cache-Control: max-age=600", "Content-Length: 5"], b"fresh"),
+            _ if head.contains("If-None-Match: \"v1\"") => response("304 Not Modified", &["ETag: \"v1\""], b""),
+            _ => response(
+                "200 OK",
+                &["ETag: \"v1\"", "Cache-Control: no-cache", "Content-Length: 4"],
+                b"data",
+            ),
+        }));
+        let mut client = HttpClient::new();
+        client.set_cache(Some(HttpCache::new()));
+        let requests = || server.requests.lock().unwrap().len();
+        let fresh = server.url.join("/fresh").unwrap();
+        let validated = server.url.join("/validated").unwrap();
+
+        check!(!client.get(&fresh).unwrap().from_cache);
+        let cached = client.get(&fresh).unwrap();
+        check!(cached.from_cache);
+        check!(cached.body == b"fresh");
+        check!(requests() == 1);
+
+        // Plain reloads revalidate, hard reloads fetch again
+        let reloaded = client
+            .fetch_with(Request::get(fresh.clone()), CacheMode::Reload)
+            .unwrap();
+        check!(!reloaded.from_cache);
+        check!(!server.last_request().contains("If-None-Match"));
+        check!(requests() == 2);
+
+        check!(client.get(&validated).unwrap().body == b"data");
+        let revalidated = client
+            .fetch_with(Request::get(validated.clone()), CacheMode::NoCache)
+            .unwrap();
+        check!(server.last_request().contains("If-None-Match: \"v1\""));
+        check!(revalidated.status == 200);
+        check!(revalidated.body == b"data");
+        check!(requests() == 4);
+        check!(
+            client
+                .fetch_with(Request::get(validated.clone()), CacheMode::ForceCache)
+                .unwrap()
+                .from_cache
+        );
+        check!(requests() == 4);
+
+        client.offline = true;
+        check!(client.get(&validated).unwrap().body == b"data");
+        check!(client.get(&server.url.join("/unknown").unwrap()).is_err());
+        check!(requests() == 4);
+    }
+
+    #[test]
+    fn cookies() {
+        let server = TestServer::start(Arc::new(|head: &str| match path(head) {
+            "/login" => response(
+                "302 Found",
+                &[
+                    "Set-Cookie: session=abc; Path=/; HttpOnly",
+                    "Location: /home",
+                    "Content-Length: 0",
+                ],
+                b"",
+            ),
+            "/logout" => response("200 OK", &["Set-Cookie: session=; Max-Age=0", "Content-Length: 0"], b""),
+            _ => ok("page"),
+        }));
+        let mut client = HttpClient::new();
+        let url = |path: &str| server.url.join(path).unwrap();
+        check!(client.get(&url("/login")).unwrap().body == b"page");
+        check!(server.last_request().contains("Cookie: session=abc\r\n"));
+        check!(client.cookies().len() == 1);
+
+        // Images of another site's page are third-party
+        let mut image = Request::get(url("/image.png"));
+        image.site_for_cookies = Some(Url::parse("http://other.example/").unwrap());
+        client.fetch(image).unwrap();
+        check!(!server.last_request().contains("Cookie:"));
+
+        client.get(&url("/logout")).unwrap();
+        client.get(&url("/home")).unwrap();
+        check!(!server.last_request().contains("Cookie:"));
+        check!(client.cookies().is_empty());
+    }
+}
@@ -0,0 +1,801 @@
+#![allow(dead_code)]
+#![allow(unused_imports)]
+
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use log::{debug, error, info, warn};
+use url::Url;
+
+use super::http::{Headers, Request, Response};
+use super::simple_error::SimpleError;
+
+/// Bytes the cache may use until told otherwise
+const DEFAULT_CACHE_SIZE: u64 = 256 * 1024 * 1024;
+/// Longest heuristic freshness, given to responses with only a `Last-Modified` date
+const MAX_HEURISTIC_FRESHNESS: u64 = 7 * 24 * 60 * 60;
+/// Final statuses cacheable without explicit freshness (RFC 9110 section 15.1)
+const HEURISTICALLY_CACHEABLE: [u16; 11] = [200, 203, 204, 300, 301, 308, 404, 405, 410, 414, 501];
+/// Connection headers, never stored
+const HOP_BY_HOP: [&str; 5] = [
+    "Connection",
+    "Keep-Alive",
+    "Proxy-Connection",
+    "Transfer-Encoding",
+    "Upgrade",
+];
+
+/// How a request uses the cache, as the `cache` mode of fetch.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CacheMode {
+    /// Fresh responses come from the cache, stale ones are revalidated
+    Default,
+    /// The cache is neither read nor written
+    NoStore,
+    /// Always from the network, and stored: a hard reload
+    Reload,
+    /// Revalidated even when fresh: a plain reload
+    NoCache,
+    /// Cached responses even when stale, the network only without one: history navigation
+    ForceCache,
+    /// Only from the cache, failing without a cached response: offline mode
+    OnlyIfCached,
+}
+
+/// The directives of `Cache-Control` this cache follows.
+///   A browser cache is private: `private` responses are stored and `s-maxage` is ignored.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CacheControl {
+    pub max_age: Option<u64>,
+    pub no_store: bool,
+    /// Stored, but revalidated before every use
+    pub no_cache: bool,
+    pub private: bool,
+    pub must_revalidate: bool,
+}
+
+impl CacheControl {
+    pub fn parse(headers: &Headers) -> Self {
+        let mut directives = Self::default();
+        for directive in headers.get_all("Cache-Control").flat_map(|value| value.split(',')) {
+            let (name, argument) = match directive.split_once('=') {
+                Some((name, argument)) => (name.trim(), Some(argument.trim().trim_matches('"'))),
+                None => (directive.trim(), None),
+            };
+            match name.to_ascii_lowercase().as_str() {
+                // Invalid ages are stale (RFC 9111 section 4.2.1)
+                "max-age" => directives.max_age = Some(argument.and_then(|age| age.parse().ok()).unwrap_or(0)),
+                "no-store" => directives.no_store = true,
+                // `no-cache="field"` only concerns some fields: revalidating everything is simpler and safe
+                "no-cache" => directives.no_cache = true,
+                "private" => directives.private = true,
+                "must-revalidate" | "proxy-revalidate" => directives.must_revalidate = true,
+                _ => (),
+            }
+        }
+        if headers.get("Cache-Control").is_none() && headers.has_token("Pragma", "no-cache") {
+            directives.no_cache = true;
+        }
+        directives
+    }
+}
+
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
+
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+const WEEKDAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+
+/// Days since 1970-01-01 of a date of the proleptic Gregorian calendar.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month = month as i64;
+    let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_index + 2) / 5 + 1) as u32;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    } as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+/// Seconds since the epoch of an HTTP date: IMF-fixdate, or the obsolete RFC 850 and asctime formats.
+pub fn parse_http_date(text: &str) -> Option<u64> {
+    let tokens: Vec<&str> = text
+        .split([' ', ',', '-'])
+        .filter(|token| !token.is_empty())
+        .collect();
+    let month = tokens
+        .iter()
+        .find_map(|token| MONTHS.iter().position(|m| m.eq_ignore_ascii_case(token)))? as u32
+        + 1;
+    let mut time = tokens.iter().find(|token| token.contains(':'))?.split(':');
+    let mut numbers = tokens
+        .iter()
+        .filter(|token| !token.is_empty() && token.chars().all(|c| c.is_ascii_digit()));
+    let day: u32 = numbers.next()?.parse().ok()?;
+    let year = numbers.next()?;
+    let year: i64 = match (year.len(), year.parse::<i64>().ok()?) {
+        // Two digit years of RFC 850 dates
+        (2, year) if year < 70 => 2000 + year,
+        (2, year) => 1900 + year,
+        (_, year) => year,
+    };
+    let hour: u64 = time.next()?.parse().ok()?;
+    let minute: u64 = time.next()?.parse().ok()?;
+    let second: u64 = time.next()?.parse().ok()?;
+    if !(1..=31).contains(&day) || hour > 23 || minute > 59 || second > 60 {
+        return None;
+    }
+    let days = days_from_civil(year, month, day);
+    if days < 0 {
+        return None;
+    }
+    Some(days as u64 * 86400 + hour * 3600 + minute * 60 + second)
+}
+
+/// IMF-fixdate, as `Sun, 06 Nov 1994 08:49:37 GMT`.
+pub fn format_http_date(time: u64) -> String {
+    let days = (time / 86400) as i64;
+    let (year, month, day) = civil_from_days(days);
+    let seconds = time % 86400;
+    format!(
+        "{}, {:02} {} {} {:02}:{:02}:{:02} GMT",
+        WEEKDAYS[(days % 7) as usize],
+        day,
+        MONTHS[month as usize - 1],
+        year,
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+/// Cache key: the URL without its fragment.
+fn cache_key(url: &Url) -> String {
+    let mut url = url.clone();
+    url.set_fragment(None);
+    String::from(url.as_str())
+}
+
+/// Name of the file of an entry: FNV-1a of its key, stable between runs.
+fn file_name(key: &str) -> String {
+    let hash = key.bytes().fold(0xcbf29ce484222325u64, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    });
+    format!("{:016x}", hash)
+}
+
+/// A stored response, without its body when the body is on disk.
+#[derive(Debug, Clone, PartialEq)]
+struct Entry {
+    url: Url,
+    status: u16,
+    reason: String,
+    headers: Headers,
+    /// Values of the request headers named by `Vary`, which later requests must match
+    vary: Vec<(String, String)>,
+    request_time: u64,
+    response_time: u64,
+    /// Bytes used, on disk or in memory
+    size: u64,
+    /// Higher is more recent, for LRU eviction
+    last_used: u64,
+    body: Option<Vec<u8>>,
+}
+
+impl Entry {
+    fn date(&self) -> u64 {
+        self.headers
+            .get("Date")
+            .and_then(parse_http_date)
+            .unwrap_or(self.response_time)
+    }
+
+    /// How long the response stays fresh after it was generated (RFC 9111 section 4.2.1).
+    fn freshness_lifetime(&self) -> u64 {
+        if let Some(max_age) = CacheControl::parse(&self.headers).max_age {
+            return max_age;
+        }
+        if let Some(expires) = self.headers.get("Expires") {
+            // Invalid dates, as `0`, are in the past
+            return parse_http_date(expires).map_or(0, |expires| expires.saturating_sub(self.date()));
+        }
+        let last_modified = self.headers.get("Last-Modified").and_then(parse_http_date);
+        match last_modified {
+            Some(last_modified) if HEURISTICALLY_CACHEABLE.contains(&self.status) => {
+                (self.date().saturating_sub(last_modified) / 10).min(MAX_HEURISTIC_FRESHNESS)
+            }
+            _ => 0,
+        }
+    }
+
+    /// Age of the response at `now` (RFC 9111 section 4.2.3).
+    fn current_age(&self, now: u64) -> u64 {
+        let apparent_age = self.response_time.saturating_sub(self.date());
+        let age_value: u64 = self.headers.get("Age").and_then(|age| age.parse().ok()).unwrap_or(0);
+        let response_delay = self.response_time.saturating_sub(self.request_time);
+        let corrected_initial_age = apparent_age.max(age_value + response_delay);
+        corrected_initial_age + now.saturating_sub(self.response_time)
+    }
+
+    fn head(&self) -> String {
+        let mut head = format!(
+            "url {}\nstatus {} {}\nrequest-time {}\nresponse-time {}\n",
+            self.url, self.status, self.reason, self.request_time, self.response_time
+        );
+        for (name, value) in self.vary.iter() {
+            head.push_str(&format!("vary {}: {}\n", name, value));
+        }
+        for (name, value) in self.headers.iter() {
+            head.push_str(&format!("header {}: {}\n", name, value));
+        }
+        head.push('\n');
+        head
+    }
+
+    /// Read the head written by `head`, leaving `reader` at the body.
+    fn read_head(reader: &mut impl BufRead) -> Result<Self, SimpleError> {
+        let invalid = |line: &str| SimpleError::new(format!("Invalid cache entry line {:?}", line).as_str());
+        let mut url = None;
+        let mut entry = Entry {
+            url: Url::parse("about:blank").unwrap(),
+            status: 0,
+            reason: String::new(),
+            headers: Headers::new(),
+            vary: vec![],
+            request_time: 0,
+            response_time: 0,
+            size: 0,
+            last_used: 0,
+            body: None,
+        };
+        loop {
+            let mut line = String::new();
+            let read = reader
+                .read_line(&mut line)
+                .map_err(|err| SimpleError::new(err.to_string().as_str()))?;
+            let line = line.trim_end_matches('\n');
+            if read == 0 || line.is_empty() {
+                break;
+            }
+            let (field, value) = line.split_once(' ').ok_or_else(|| invalid(line))?;
+            let header = || value.split_once(": ").ok_or_else(|| invalid(line));
+            match field {
+                "url" => url = Some(Url::parse(value).map_err(|_| invalid(line))?),
+                "status" => {
+                    let (status, reason) = value.split_once(' ').unwrap_or((value, ""));
+                    entry.status = status.parse().map_err(|_| invalid(line))?;
+                    entry.reason = String::from(reason);
+                }
+                "request-time" => entry.request_time = value.parse().map_err(|_| invalid(line))?,
+                "response-time" => entry.response_time = value.parse().map_err(|_| invalid(line))?,
+                "vary" => {
+                    let (name, value) = header()?;
+                    entry.vary.push((String::from(name), String::from(value)));
+                }
+                "header" => {
+                    let (name, value) = header()?;
+                    entry.headers.add(name, value);
+                }
+                _ => return Err(invalid(line)),
+            }
+        }
+        entry.url = url.ok_or_else(|| SimpleError::new("Cache entry without url"))?;
+        Ok(entry)
+    }
+}
+
+/// A response found in the cache for a request.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CachedResponse {
+    pub response: Response,
+    /// Usable without asking the server
+    pub fresh: bool,
+    /// Must not be used once stale, even offline
+    pub must_revalidate: bool,
+}
+
+/// Responses to GET requests kept for reuse, bounded in size with least recently used eviction.
+///   With a directory each entry is a file, so the cache outlives the session.
+#[derive(Debug)]
+pub struct HttpCache {
+    dir: Option<PathBuf>,
+    entries: HashMap<String, Entry>,
+    max_size: u64,
+    size: u64,
+    /// Use counter, ordering the entries for eviction
+    uses: u64,
+}
+
+impl Default for HttpCache {
+    fn default() -> Self {
+        Self {
+            dir: None,
+            entries: HashMap::new(),
+            max_size: DEFAULT_CACHE_SIZE,
+            size: 0,
+            uses: 0,
+        }
+    }
+}
+
+impl HttpCache {
+    /// A cache in memory, forgotten at exit.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The cache in `dir`, created if missing. Unreadable entries are removed.
+    pub fn open(dir: &Path) -> Result<Self, SimpleError> {
+        let error = |err: std::io::Error| SimpleError::new(format!("{:?}: {}", dir, err).as_str());
+        std::fs::create_dir_all(dir).map_err(error)?;
+        let mut files = vec![];
+        for file in std::fs::read_dir(dir).map_err(error)?.flatten() {
+            let metadata = match file.metadata() {
+                Ok(metadata) if metadata.is_file() => metadata,
+                _ => continue,
+            };
+            files.push((metadata.modified().unwrap_or(UNIX_EPOCH), metadata.len(), file.path()));
+        }
+        // Files are touched on use: the oldest were used least recently
+        files.sort();
+        let mut cache = Self {
+            dir: Some(dir.to_path_buf()),
+            ..Self::default()
+        };
+        for (_, size, path) in files {
+            let loaded = std::fs::File::open(&path)
+                .map_err(|err| SimpleError::new(err.to_string().as_str()))
+                .and_then(|file| Entry::read_head(&mut BufReader::new(file)))
+                .and_then(|entry| {
+                    let key = cache_key(&entry.url);
+                    match path.file_name() == Some(file_name(&key).as_ref()) {
+                        true => Ok((key, entry)),
+                        false => Err(SimpleError::new("Entry in the file of another URL")),
+                    }
+                });
+            match loaded {
+                Ok((key, mut entry)) => {
+                    cache.uses += 1;
+                    entry.last_used = cache.uses;
+                    entry.size = size;
+                    cache.size += size;
+                    cache.entries.insert(key, entry);
+                }
+                Err(err) => {
+                    warn!("Removing cache entry {:?}: {}", path, err);
+                    let _ = std::fs::remove_file(&path);
+                }
+            }
+        }
+        info!("HTTP cache in {:?}: {} entries, {} bytes", dir, cache.len(), cache.size);
+        Ok(cache)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Bytes used by the entries.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Bound the size, evicting entries to fit.
+    pub fn set_max_size(&mut self, max_size: u64) {
+        self.max_size = max_size;
+        self.evict(0);
+    }
+
+    /// The response stored for `request`, if its `Vary` headers match.
+    pub fn lookup(&mut self, request: &Request, now: u64) -> Option<CachedResponse> {
+        let key = cache_key(&request.url);
+        let entry = self.entries.get(&key)?;
+        let matches = entry
+            .vary
+            .iter()
+            .all(|(name, value)| request.headers.get(name).unwrap_or("") == value);
+        if !matches {
+            debug!("Cache entry of {} varies", request.url);
+            return None;
+        }
+        let body = match (&entry.body, &self.dir) {
+            (Some(body), _) => body.clone(),
+            (None, Some(dir)) => match read_body(&dir.join(file_name(&key))) {
+                Ok(body) => body,
+                Err(err) => {
+                    warn!("Cache entry of {} unreadable: {}", request.url, err);
+                    self.remove(&request.url);
+                    return None;
+                }
+            },
+            (None, None) => return None,
+        };
+        let control = CacheControl::parse(&entry.headers);
+        let age = entry.current_age(now);
+        let fresh = !control.no_cache && age < entry.freshness_lifetime();
+        let mut headers = entry.headers.clone();
+        headers.set("Age", &age.to_string());
+        let response = Response {
+            url: request.url.clone(),
+            status: entry.status,
+            reason: entry.reason.clone(),
+            headers,
+            body,
+            redirects: vec![],
+            from_cache: true,
+        };
+        self.touch(&key);
+        Some(CachedResponse {
+            response,
+            fresh,
+            must_revalidate: control.must_revalidate,
+        })
+    }
+
+    /// Store the response to `request` if it may be. Returns true if stored.
+    pub fn store(&mut self, request: &Request, response: &Response, request_time: u64, response_time: u64) -> bool {
+        let request_control = CacheControl::parse(&request.headers);
+        let control = CacheControl::parse(&response.headers);
+        let explicit = control.max_age.is_some() || response.headers.get("Expires").is_some();
+        let vary: Vec<&str> = response
+            .headers
+            .get_all("Vary")
+            .flat_map(|value| value.split(','))
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .collect();
+        let storable = request.method == "GET"
+            && response.status >= 200
+            && response.status != 206
+            && !request_control.no_store
+            && !control.no_store
+            && !vary.contains(&"*")
+            && (explicit || control.no_cache || HEURISTICALLY_CACHEABLE.contains(&response.status));
+        if !storable {
+            debug!("Not caching {} {}", request.url, response.status);
+            self.remove(&request.url);
+            return false;
+        }
+        let mut headers = response.headers.clone();
+        for name in HOP_BY_HOP.iter() {
+            headers.remove(name);
+        }
+        let entry = Entry {
+            url: request.url.clone(),
+            status: response.status,
+            reason: response.reason.clone(),
+            headers,
+            vary: vary
+                .iter()
+                .map(|name| {
+                    (
+                        String::from(*name),
+                        String::from(request.headers.get(name).unwrap_or("")),
+                    )
+                })
+                .collect(),
+            request_time,
+            response_time,
+            size: 0,
+            last_used: 0,
+            body: None,
+        };
+        self.insert(entry, &response.body)
+    }
+
+    /// Update the entry of `request` with a `304 Not Modified` answer to its revalidation,
+    ///   and return the response it now holds.
+    pub fn freshen(
+        &mut self,
+        request: &Request,
+        not_modified: &Response,
+        request_time: u64,
+        response_time: u64,
+    ) -> Option<Response> {
+        let cached = self.lookup(request, response_time)?;
+        let key = cache_key(&request.url);
+        let mut entry = self.entries.get(&key)?.clone();
+        // The stored body and its description stay, the rest is updated (RFC 9111 section 3.2)
+        for (name, value) in not_modified.headers.iter() {
+            let kept = [
+                "Content-Length",
+                "Content-Encoding",
+                "Content-Type",
+                "Content-Range",
+                "Age",
+            ]
+            .iter()
+            .chain(HOP_BY_HOP.iter())
+            .any(|kept| kept.eq_ignore_ascii_case(name));
+            if !kept {
+                entry.headers.set(name, value);
+            }
+        }
+        entry.request_time = request_time;
+        entry.response_time = response_time;
+        self.insert(entry, &cached.response.body);
+        self.lookup(request, response_time).map(|cached| cached.response)
+    }
+
+    pub fn remove(&mut self, url: &Url) {
+        let key = cache_key(url);
+        if let Some(entry) = self.entries.remove(&key) {
+            self.size -= entry.size;
+            if let Some(dir) = &self.dir {
+                let _ = std::fs::remove_file(dir.join(file_name(&key)));
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        let urls: Vec<Url> = self.entries.values().map(|entry| entry.url.clone()).collect();
+        for url in urls.iter() {
+            self.remove(url);
+        }
+    }
+
+    fn insert(&mut self, mut entry: Entry, body: &[u8]) -> bool {
+        let key = cache_key(&entry.url);
+        self.remove(&entry.url);
+        let head = entry.head();
+        entry.size = (head.len() + body.len()) as u64;
+        if entry.size > self.max_size {
+            return false;
+        }
+        self.evict(entry.size);
+        match &self.dir {
+            Some(dir) => {
+                let path = dir.join(file_name(&key));
+                let written = std::fs::File::create(&path).and_then(|mut file| {
+                    file.write_all(head.as_bytes())?;
+                    file.write_all(body)
+                });
+                if let Err(err) = written {
+                    error!("Error while caching {}: {:?}: {}", entry.url, path, err);
+                    let _ = std::fs::remove_file(&path);
+                    return false;
+                }
+            }
+            None => entry.body = Some(body.to_vec()),
+        }
+        debug!("Cached {} ({} bytes)", entry.url, entry.size);
+        self.uses += 1;
+        entry.last_used = self.uses;
+        self.size += entry.size;
+        self.entries.insert(key, entry);
+        true
+    }
+
+    /// Remove the least recently used entries until `needed` more bytes fit.
+    fn evict(&mut self, needed: u64) {
+        while self.size + needed > self.max_size {
+            let oldest = self
+                .entries
+                .values()
+                .min_by_key(|entry| entry.last_used)
+                .map(|entry| entry.url.clone());
+            match oldest {
+                Some(url) => {
+                    debug!("Evicting {} from the cache", url);
+                    self.remove(&url);
+                }
+                None => break,
+            }
+        }
+    }
+
+    fn touch(&mut self, key: &str) {
+        self.uses += 1;
+        if let Some(entry) = self.entries.get_mut(key) {
+            entry.last_used = self.uses;
+        }
+        // The modification time keeps the order of use between runs
+        if let Some(dir) = &self.dir {
+            let touched = std::fs::File::options()
+                .append(true)
+                .open(dir.join(file_name(key)))
+                .and_then(|file| file.set_modified(SystemTime::now()));
+            if let Err(err) = touched {
+                debug!("Cannot touch cache entry {}: {}", key, err);
+            }
+        }
+    }
+}
+
+fn read_body(path: &Path) -> Result<Vec<u8>, SimpleError> {
+    let file = std::fs::File::open(path).map_err(|err| SimpleError::new(format!("{:?}: {}", path, err).as_str()))?;
+    let mut reader = BufReader::new(file);
+    Entry::read_head(&mut reader)?;
+    let mut body = vec![];
+    reader
+        .read_to_end(&mut body)
+        .map_err(|err| SimpleError::new(format!("{:?}: {}", path, err).as_str()))?;
+    Ok(body)
+}
+
+#[cfg(test)]
+mod tests {
+    use assert2::check;
+
+    use super::*;
+
+    fn response(url: &Url, headers: &[(&str, &str)], body: &str) -> Response {
+        let mut response = Response {
+            url: url.clone(),
+            status: 200,
+            reason: String::from("OK"),
+            headers: Headers::new(),
+            body: body.as_bytes().to_vec(),
+            redirects: vec![],
+            from_cache: false,
+        };
+        for (name, value) in headers {
+            response.headers.add(name, value);
+        }
+        response
+    }
+
+    #[test]
+    fn http_dates() {
+        let date = 784111777;
+        check!(parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT") == Some(date));
+        check!(parse_http_date("Sunday, 06-Nov-94 08:49:37 GMT") == Some(date));
+        check!(parse_http_date("Sun Nov  6 08:49:37 1994") == Some(date));
+        check!(parse_http_date("0") == None);
+        check!(format_http_date(date) == "Sun, 06 Nov 1994 08:49:37 GMT");
+        check!(parse_http_date(&format_http_date(1_700_000_000)) == Some(1_700_000_000));
+    }
+
+    #[test]
+    fn freshness() {
+        let url = Url::parse("https://example.com/page#top").unwrap();
+        let request = Request::get(url.clone());
+        let mut cache = HttpCache::new();
+        let date = format_http_date(1000);
+
+        check!(cache.store(
+            &request,
+            &response(&url, &[("Cache-Control", "max-age=60"), ("Date", &date)], "a"),
+            1000,
+            1000
+        ));
+        let cached = cache
+            .lookup(&Request::get(Url::parse("https://example.com/page").unwrap()), 1030)
+            .unwrap();
+        check!(cached.fresh);
+        check!(cached.response.body == b"a");
+        check!(cached.response.headers.get("Age") == Some("30"));
+        check!(!cache.lookup(&request, 1061).unwrap().fresh);
+
+        // Ages add the time spent reaching the cache
+        let aged = response(
+            &url,
+            &[("Cache-Control", "max-age=60"), ("Date", &date), ("Age", "50")],
+            "a",
+        );
+        cache.store(&request, &aged, 1000, 1005);
+        check!(cache.lookup(&request, 1009).unwrap().fresh);
+        check!(!cache.lookup(&request, 1010).unwrap().fresh);
+
+        let expires = format_http_date(1100);
+        cache.store(
+            &request,
+            &response(&url, &[("Expires", &expires), ("Date", &date)], "b"),
+            1000,
+            1000,
+        );
+        check!(cache.lookup(&request, 1099).unwrap().fresh);
+        cache.store(&request, &response(&url, &[("Expires", "0")], "b"), 1000, 1000);
+        check!(!cache.lookup(&request, 1000).unwrap().fresh);
+
+        // 10% of the time since the last modification
+        let modified = response(&url, &[("Last-Modified", &format_http_date(0)), ("Date", &date)], "c");
+        cache.store(&request, &modified, 1000, 1000);
+        check!(cache.lookup(&request, 1099).unwrap().fresh);
+        check!(!cache.lookup(&request, 1100).unwrap().fresh);
+
+        let no_cache = response(&url, &[("Cache-Control", "no-cache, max-age=60")], "d");
+        cache.store(&request, &no_cache, 1000, 1000);
+        check!(!cache.lookup(&request, 1000).unwrap().fresh);
+
+        let private = response(&url, &[("Cache-Control", "private, max-age=60")], "e");
+        check!(cache.store(&request, &private, 1000, 1000));
+        check!(!cache.store(
+            &request,
+            &response(&url, &[("Cache-Control", "no-store")], "f"),
+            1000,
+            1000
+        ));
+        check!(cache.lookup(&request, 1000) == None);
+        let no_store = request.clone().with_header("Cache-Control", "no-store");
+        check!(!cache.store(&no_store, &private, 1000, 1000));
+    }
+
+    #[test]
+    fn vary_and_revalidation() {
+        let url = Url::parse("https://example.com/").unwrap();
+        let french = Request::get(url.clone()).with_header("Accept-Language", "fr");
+        let mut cache = HttpCache::new();
+        let varying = response(
+            &url,
+            &[
+                ("Vary", "Accept-Language"),
+                ("ETag", "\"1\""),
+                ("Cache-Control", "max-age=0"),
+            ],
+            "bonjour",
+        );
+        check!(cache.store(&french, &varying, 0, 0));
+        check!(cache.lookup(&Request::get(url.clone()), 0) == None);
+        let cached = cache.lookup(&french, 0).unwrap();
+        check!(!cached.fresh);
+        check!(cached.response.validators().etag.as_deref() == Some("\"1\""));
+
+        let mut not_modified = response(&url, &[("Cache-Control", "max-age=100"), ("Content-Length", "0")], "");
+        not_modified.status = 304;
+        let freshened = cache.freshen(&french, &not_modified, 10, 10).unwrap();
+        check!(freshened.body == b"bonjour");
+        check!(freshened.status == 200);
+        check!(freshened.headers.get("Content-Length") == None);
+        check!(cache.lookup(&french, 50).unwrap().fresh);
+
+        check!(!cache.store(&french, &response(&url, &[("Vary", "*")], ""), 0, 0));
+    }
+
+    #[test]
+    fn disk_and_eviction() {
+        let dir = std::env::temp_dir().join(format!("rowser-http-cache-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let url = |n: usize| Url::parse(&format!("https://example.com/{}", n)).unwrap();
+        let mut cache = HttpCache::open(&dir).unwrap();
+        for n in 0..3 {
+            let stored = response(&url(n), &[("Cache-Control", "max-age=60")], &"x".repeat(100));
+            check!(cache.store(&Request::get(url(n)), &stored, 0, 0));
+        }
+        let entry_size = cache.size() / 3;
+        check!(cache.lookup(&Request::get(url(0)), 0).is_some());
+        // Room for two: the least recently used goes
+        cache.set_max_size(entry_size * 2);
+        check!(cache.len() == 2);
+        check!(cache.lookup(&Request::get(url(1)), 0) == None);
+
+        let mut reopened = HttpCache::open(&dir).unwrap();
+        check!(reopened.len() == 2);
+        check!(reopened.size() == cache.size());
+        let cached = reopened.lookup(&Request::get(url(2)), 30).unwrap();
+        check!(cached.fresh);
+        check!(cached.response.body == "x".repeat(100).as_bytes());
+
+        reopened.clear();
+        check!(std::fs::read_dir(&dir).unwrap().count() == 0);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
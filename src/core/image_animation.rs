@@ -0,0 +1,139 @@
+#![allow(dead_code)]
+#![allow(unused_imports)]
+
+use log::{debug, error, info, warn};
+
+use super::bitmap::{DecodedImage, LoopCount};
+
+/// Frames with a delay at or below this are shown for `DEFAULT_FRAME_DELAY_MS`, as other browsers do.
+const MIN_FRAME_DELAY_MS: u32 = 10;
+const DEFAULT_FRAME_DELAY_MS: f64 = 100.0;
+/// A long pause (window blocked, machine suspended) must not fast forward the whole animation.
+const MAX_STEP_MS: f64 = 1000.0;
+
+/// Playback state of an animated image, advanced by `DomRoot::on_frame`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImageAnimation {
+    pub frame: usize,
+    frame_elapsed_ms: f64,
+    plays_completed: u32,
+    finished: bool,
+}
+
+impl ImageAnimation {
+    pub fn new() -> Self {
+        Self {
+            frame: 0,
+            frame_elapsed_ms: 0.0,
+            plays_completed: 0,
+            finished: false,
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    fn frame_delay_ms(image: &DecodedImage, frame: usize) -> f64 {
+        let delay = image.frames[frame].delay_ms;
+        if delay <= MIN_FRAME_DELAY_MS {
+            DEFAULT_FRAME_DELAY_MS
+        } else {
+            delay as f64
+        }
+    }
+
+    /// Move forward by `elapsed_ms`. Returns true when the visible frame changed.
+    pub fn advance(&mut self, image: &DecodedImage, elapsed_ms: f64) -> bool {
+        if self.finished || !image.is_animated() {
+            return false;
+        }
+        let previous = self.frame;
+        self.frame_elapsed_ms += elapsed_ms.min(MAX_STEP_MS);
+
+        loop {
+            let delay = Self::frame_delay_ms(image, self.frame);
+            if self.frame_elapsed_ms < delay {
+                break;
+            }
+            if self.frame + 1 < image.frames.len() {
+                self.frame_elapsed_ms -= delay;
+                self.frame += 1;
+                continue;
+            }
+            self.plays_completed += 1;
+            if let LoopCount::Finite(plays) = image.loop_count {
+                if self.plays_completed >= plays {
+                    // Stay on the last frame forever
+                    self.finished = true;
+                    self.frame_elapsed_ms = 0.0;
+                    break;
+                }
+            }
+            self.frame_elapsed_ms -= delay;
+            self.frame = 0;
+        }
+
+        previous != self.frame
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert2::check;
+    use image::ImageFormat;
+
+    use super::super::bitmap::{Bitmap, ImageFrame};
+    use super::*;
+
+    fn animated(delays: &[u32], loop_count: LoopCount) -> DecodedImage {
+        DecodedImage {
+            format: ImageFormat::Gif,
+            width: 1,
+            height: 1,
+            frames: delays
+                .iter()
+                .map(|delay_ms| ImageFrame {
+                    bitmap: Bitmap::new(1, 1, vec![0, 0, 0, 255]).unwrap(),
+                    delay_ms: *delay_ms,
+                })
+                .collect(),
+            loop_count,
+        }
+    }
+
+    #[test]
+    fn advance_by_frame_delay() {
+        let image = animated(&[100, 200], LoopCount::Infinite);
+        let mut animation = ImageAnimation::new();
+        check!(!animation.advance(&image, 50.0));
+        check!(animation.advance(&image, 60.0));
+        check!(animation.frame == 1);
+        check!(!animation.advance(&image, 150.0));
+        check!(animation.advance(&image, 50.0));
+        check!(animation.frame == 0);
+    }
+
+    #[test]
+    fn stop_after_loop_count() {
+        let image = animated(&[100, 100], LoopCount::Finite(2));
+        let mut animation = ImageAnimation::new();
+        for _ in 0..3 {
+            animation.advance(&image, 100.0);
+        }
+        check!(animation.frame == 1);
+        check!(!animation.is_finished());
+        animation.advance(&image, 100.0);
+        check!(animation.is_finished());
+        check!(animation.frame == 1);
+        check!(!animation.advance(&image, 1000.0));
+    }
+
+    #[test]
+    fn tiny_delays_use_default() {
+        let image = animated(&[0, 0], LoopCount::Infinite);
+        let mut animation = ImageAnimation::new();
+        check!(!animation.advance(&image, 50.0));
+        check!(animation.advance(&image, 50.0));
+    }
+}
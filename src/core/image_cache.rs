@@ -0,0 +1,153 @@
+#![allow(dead_code)]
+#![allow(unused_imports)]
+
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+use log::{debug, error, info, warn};
+use url::Url;
+
+use crate::fixme;
+
+use super::bitmap::{decode_image, DecodedImage};
+use super::fetcher::Fetcher;
+use super::http::{Request, Response};
+use super::http_cache::CacheMode;
+use super::loader::Loader;
+use super::simple_error::SimpleError;
+
+/// Decoded images shared by every element pointing to the same URL, loaded and decoded off the event loop.
+#[derive(Debug)]
+pub struct ImageCache {
+    images: HashMap<String, Rc<DecodedImage>>,
+    loader: Rc<RefCell<Loader>>,
+    /// URL of the document showing the images, for their cookies
+    site_for_cookies: Option<Url>,
+    /// URLs of the images `fetcher` is loading
+    loading: HashSet<String>,
+    fetcher: Fetcher<(Url, Result<DecodedImage, SimpleError>)>,
+}
+
+impl ImageCache {
+    /// Images of the document at `site_for_cookies`, loaded with `loader`.
+    pub fn new(loader: Rc<RefCell<Loader>>, site_for_cookies: Option<Url>) -> Self {
+        Self {
+            images: HashMap::new(),
+            loader,
+            site_for_cookies,
+            loading: HashSet::new(),
+            fetcher: Fetcher::new(),
+        }
+    }
+
+    pub fn get(&self, url: &Url) -> Option<Rc<DecodedImage>> {
+        self.images.get(url.as_str()).cloned()
+    }
+
+    pub fn insert(&mut self, url: &Url, image: DecodedImage) -> Rc<DecodedImage> {
+        let image = Rc::new(image);
+        self.images.insert(String::from(url.as_str()), image.clone());
+        image
+    }
+
+    /// The image at `url` if it is loaded. If not, it starts loading, and `take_loaded` gives it once decoded.
+    pub fn get_or_load(&mut self, url: &Url) -> Option<Rc<DecodedImage>> {
+        if let Some(image) = self.get(url) {
+            debug!("Image cache hit {}", url);
+            return Some(image);
+        }
+        if self.loading.insert(String::from(url.as_str())) {
+            let mut request = Request::get(url.clone());
+            request.site_for_cookies = self.site_for_cookies.clone();
+            let image_url = url.clone();
+            self.fetcher
+                .start(&self.loader.borrow(), request, CacheMode::Default, move |result| {
+                    let image = result.and_then(|response| decode_response(&image_url, response));
+                    (image_url, image)
+                });
+        }
+        None
+    }
+
+    /// The images loaded since the last call, now in the cache. Those that failed to load are logged, and
+    ///   loaded again if asked again.
+    pub fn take_loaded(&mut self) -> Vec<(Url, Rc<DecodedImage>)> {
+        let mut loaded = vec![];
+        for (url, result) in self.fetcher.take_finished() {
+            self.loading.remove(url.as_str());
+            match result {
+                Ok(image) => {
+                    let image = self.insert(&url, image);
+                    loaded.push((url, image));
+                }
+                Err(err) => warn!("Error while loading {}: {}", url, err),
+            }
+        }
+        loaded
+    }
+
+    /// True while some image is loading.
+    pub fn is_loading(&self) -> bool {
+        self.fetcher.is_busy()
+    }
+
+    pub fn len(&self) -> usize {
+        self.images.len()
+    }
+}
+
+/// The image of `response`, decoded on the thread that loaded it.
+fn decode_response(url: &Url, response: Response) -> Result<DecodedImage, SimpleError> {
+    if !response.is_success() {
+        return Err(SimpleError::new(
+            format!("{}: {} {}", url, response.status, response.reason).as_str(),
+        ));
+    }
+    decode_image(&response.body).map_err(|err| SimpleError::new(format!("{}: {}", url, err).as_str()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
+
+    use assert2::check;
+
+    use super::super::http::HttpClient;
+    use super::*;
+
+    #[test]
+    fn cache_by_url() {
+        let mut data = Vec::new();
+        image::DynamicImage::ImageRgba8(image::RgbaImage::new(3, 1))
+            .write_to(&mut std::io::Cursor::new(&mut data), image::ImageFormat::Png)
+            .unwrap();
+        let path = std::env::temp_dir().join("rowser_image_cache_test.png");
+        std::fs::write(&path, &data).unwrap();
+        let url = Url::from_file_path(&path).unwrap();
+
+        let loader = Rc::new(RefCell::new(Loader::new(Arc::new(Mutex::new(HttpClient::new())))));
+        let mut cache = ImageCache::new(loader, None);
+        check!(cache.get_or_load(&url).is_none());
+        // Asked again while loading, it is loaded once
+        check!(cache.get_or_load(&url).is_none());
+        check!(cache.is_loading());
+        let mut loaded = vec![];
+        let start = Instant::now();
+        while cache.is_loading() && start.elapsed() < Duration::from_secs(10) {
+            loaded.append(&mut cache.take_loaded());
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        check!(loaded.len() == 1);
+        check!(loaded[0].0 == url);
+        let first = loaded[0].1.clone();
+        // Once cached the file is no longer needed
+        std::fs::remove_file(&path).unwrap();
+        let second = cache.get_or_load(&url).unwrap();
+        check!(Rc::ptr_eq(&first, &second));
+        check!(!cache.is_loading());
+        check!(cache.len() == 1);
+        check!(first.intrinsic_size() == (3, 1));
+    }
+}
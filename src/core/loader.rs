@@ -0,0 +1,432 @@
+#![allow(dead_code)]
+#![allow(unused_imports)]
+
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::hash::{BuildHasher, Hasher};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use base64::engine::general_purpose::{GeneralPurpose, GeneralPurposeConfig};
+use base64::engine::DecodePaddingMode;
+use base64::Engine;
+use log::{debug, error, info, warn};
+use percent_encoding::percent_decode_str;
+use url::Url;
+
+use crate::fixme;
+
+use super::bookmarks;
+use super::http::{HttpClient, Request, Response};
+use super::http_cache::CacheMode;
+use super::simple_error::SimpleError;
+
+/// Type of `data:` URLs without one (RFC 2397).
+const DEFAULT_DATA_TYPE: &str = "text/plain;charset=US-ASCII";
+
+/// Base64 of `data:` URLs, padded or not (forgiving-base64 of the Infra standard).
+const FORGIVING_BASE64: GeneralPurpose = GeneralPurpose::new(
+    &base64::alphabet::STANDARD,
+    GeneralPurposeConfig::new()
+        .with_decode_padding_mode(DecodePaddingMode::Indifferent)
+        .with_decode_allow_trailing_bits(true),
+);
+
+/// Loads the URLs of a scheme for a `Loader`, on the thread loading them.
+pub trait SchemeHandler: Debug + Send + Sync {
+    /// The response to `request`. `loader` loads the other URLs it needs, `mode` says how to use the HTTP cache.
+    fn load(&self, loader: &Loader, request: Request, mode: CacheMode) -> Result<Response, SimpleError>;
+}
+
+/// Data in memory, loaded by the `blob:` URLs made for it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Blob {
+    pub data: Vec<u8>,
+    pub content_type: String,
+}
+
+impl Blob {
+    pub fn new(data: Vec<u8>, content_type: &str) -> Self {
+        Self {
+            data,
+            content_type: String::from(content_type),
+        }
+    }
+}
+
+/// Loads documents and their resources with the handler registered for their scheme.
+///   Clones share the HTTP client and the blobs, to load on other threads.
+#[derive(Debug, Clone)]
+pub struct Loader {
+    handlers: HashMap<String, Arc<dyn SchemeHandler>>,
+    http: Arc<Mutex<HttpClient>>,
+    /// Blobs by `blob:` URL
+    blobs: Arc<Mutex<HashMap<String, Blob>>>,
+}
+
+impl Loader {
+    /// A loader of `http`, `https`, `file`, `data`, `blob` and `view-source` URLs, the first two with `http`.
+    pub fn new(http: Arc<Mutex<HttpClient>>) -> Self {
+        let mut loader = Self {
+            handlers: HashMap::new(),
+            http,
+            blobs: Arc::new(Mutex::new(HashMap::new())),
+        };
+        loader.register("http", Arc::new(HttpHandler));
+        loader.register("https", Arc::new(HttpHandler));
+        loader.register("file", Arc::new(FileHandler));
+        loader.register("data", Arc::new(DataHandler));
+        loader.register("blob", Arc::new(BlobHandler));
+        loader.register("view-source", Arc::new(ViewSourceHandler));
+        loader
+    }
+
+    pub fn http(&self) -> &Arc<Mutex<HttpClient>> {
+        &self.http
+    }
+
+    /// Load the URLs of `scheme` with `handler`, instead of the handler it had if any.
+    pub fn register(&mut self, scheme: &str, handler: Arc<dyn SchemeHandler>) {
+        self.handlers.insert(scheme.to_ascii_lowercase(), handler);
+    }
+
+    /// Stop loading the URLs of `scheme`. Returns false if it had no handler.
+    pub fn unregister(&mut self, scheme: &str) -> bool {
+        self.handlers.remove(&scheme.to_ascii_lowercase()).is_some()
+    }
+
+    pub fn handles(&self, scheme: &str) -> bool {
+        self.handlers.contains_key(scheme)
+    }
+
+    /// The response to `request`, by the handler of its scheme.
+    pub fn load(&self, request: Request, mode: CacheMode) -> Result<Response, SimpleError> {
+        let handler = self.handlers.get(request.url.scheme()).cloned().ok_or_else(|| {
+            SimpleError::new(format!("Unsupported scheme '{}' for {}", request.url.scheme(), request.url).as_str())
+        })?;
+        handler.load(self, request, mode)
+    }
+
+    pub fn get(&self, url: &Url) -> Result<Response, SimpleError> {
+        self.load(Request::get(url.clone()), CacheMode::Default)
+    }
+
+    /// A new `blob:` URL loading `blob`, for documents of the origin of `origin`, until revoked.
+    ///   Navigations started by the user load it too.
+    pub fn create_object_url(&mut self, origin: &Url, blob: Blob) -> Url {
+        let url = Url::parse(&format!(
+            "blob:{}/{}",
+            origin.origin().ascii_serialization(),
+            random_uuid()
+        ))
+        .unwrap();
+        self.blobs.lock().unwrap().insert(String::from(url.as_str()), blob);
+        url
+    }
+
+    /// Forget the blob of `url`. Returns false if it had none.
+    pub fn revoke_object_url(&mut self, url: &Url) -> bool {
+        self.blobs.lock().unwrap().remove(url.as_str()).is_some()
+    }
+}
+
+/// A version 4 UUID, as `blob:` URLs have.
+fn random_uuid() -> String {
+    let random = |seed: u64| {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u64(seed);
+        hasher.finish().to_be_bytes()
+    };
+    let mut bytes = [random(0), random(1)].concat();
+    bytes[6] = bytes[6] & 0x0f | 0x40;
+    bytes[8] = bytes[8] & 0x3f | 0x80;
+    let hex: String = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
+
+#[derive(Debug)]
+struct HttpHandler;
+
+impl SchemeHandler for HttpHandler {
+    fn load(&self, loader: &Loader, request: Request, mode: CacheMode) -> Result<Response, SimpleError> {
+        loader.http.lock().unwrap().fetch_with(request, mode)
+    }
+}
+
+#[derive(Debug)]
+struct FileHandler;
+
+impl SchemeHandler for FileHandler {
+    fn load(&self, _: &Loader, request: Request, _: CacheMode) -> Result<Response, SimpleError> {
+        let url = request.url;
+        let path = url
+            .to_file_path()
+            .map_err(|_| SimpleError::new(format!("Invalid file url {}", url).as_str()))?;
+        if path.is_dir() {
+            let body = directory_listing(&path)?;
+            return Ok(Response::new(url, 200, "text/html", body));
+        }
+        let body = std::fs::read(&path).map_err(|err| SimpleError::new(format!("{:?}: {}", path, err).as_str()))?;
+        let extension = path.extension().and_then(|extension| extension.to_str()).unwrap_or("");
+        let content_type = match extension.to_ascii_lowercase().as_str() {
+            "html" | "htm" => "text/html",
+            "xhtml" => "application/xhtml+xml",
+            "xml" => "application/xml",
+            "svg" => "image/svg+xml",
+            "css" => "text/css",
+            "js" | "mjs" => "text/javascript",
+            "json" => "application/json",
+            "png" => "image/png",
+            "jpg" | "jpeg" => "image/jpeg",
+            "gif" => "image/gif",
+            "webp" => "image/webp",
+            "txt" => "text/plain",
+            _ => "application/octet-stream",
+        };
+        Ok(Response::new(url, 200, content_type, body))
+    }
+}
+
+/// An HTML page linking to the entries of the directory `path`, sorted by name.
+fn directory_listing(path: &Path) -> Result<Vec<u8>, SimpleError> {
+    let error = |err: std::io::Error| SimpleError::new(format!("{:?}: {}", path, err).as_str());
+    let mut entries = std::fs::read_dir(path)
+        .map_err(error)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(error)?;
+    entries.sort();
+    let title = bookmarks::escape(&format!("Index of {}", path.display()));
+    let mut html = format!("<!DOCTYPE html>\n<title>{}</title>\n<h1>{}</h1>\n<ul>\n", title, title);
+    for entry in entries {
+        let name = entry
+            .file_name()
+            .map_or_else(String::new, |name| name.to_string_lossy().into_owned());
+        let (url, name) = if entry.is_dir() {
+            (Url::from_directory_path(&entry), format!("{}/", name))
+        } else {
+            (Url::from_file_path(&entry), name)
+        };
+        if let Ok(url) = url {
+            let (url, name) = (bookmarks::escape(url.as_str()), bookmarks::escape(&name));
+            html.push_str(&format!("<li><a href=\"{}\">{}</a>\n", url, name));
+        }
+    }
+    html.push_str("</ul>\n");
+    Ok(html.into_bytes())
+}
+
+#[derive(Debug)]
+struct DataHandler;
+
+impl SchemeHandler for DataHandler {
+    fn load(&self, _: &Loader, request: Request, _: CacheMode) -> Result<Response, SimpleError> {
+        let (content_type, body) = parse_data_url(&request.url)?;
+        Ok(Response::new(request.url, 200, &content_type, body))
+    }
+}
+
+/// Type and content of a `data:` URL, `data:[<type>][;base64],<data>` (data: URL processor of the Fetch standard).
+fn parse_data_url(url: &Url) -> Result<(String, Vec<u8>), SimpleError> {
+    // Without the fragment
+    let input = &url[url::Position::BeforePath..url::Position::AfterQuery];
+    let (content_type, data) = input
+        .split_once(',')
+        .ok_or_else(|| SimpleError::new(format!("No comma in the data URL {}", url).as_str()))?;
+    let mut body: Vec<u8> = percent_decode_str(data).collect();
+    let content_type = content_type.trim_matches(|c: char| c.is_ascii_whitespace());
+    let content_type = match content_type.rsplit_once(';') {
+        Some((content_type, parameter)) if parameter.trim().eq_ignore_ascii_case("base64") => {
+            body.retain(|byte| !byte.is_ascii_whitespace());
+            body = FORGIVING_BASE64
+                .decode(&body)
+                .map_err(|err| SimpleError::new(format!("Invalid base64 in the data URL {}: {}", url, err).as_str()))?;
+            content_type
+        }
+        _ => content_type,
+    };
+    let content_type = match content_type {
+        _ if content_type.starts_with(';') => format!("text/plain{}", content_type),
+        _ if content_type.contains('/') => String::from(content_type),
+        _ => String::from(DEFAULT_DATA_TYPE),
+    };
+    Ok((content_type, body))
+}
+
+#[derive(Debug)]
+struct BlobHandler;
+
+impl SchemeHandler for BlobHandler {
+    fn load(&self, loader: &Loader, request: Request, _: CacheMode) -> Result<Response, SimpleError> {
+        let mut url = request.url.clone();
+        url.set_fragment(None);
+        if request.method != "GET" {
+            return Err(SimpleError::new(format!("Cannot {} {}", request.method, url).as_str()));
+        }
+        if let Some(site) = request
+            .site_for_cookies
+            .as_ref()
+            .filter(|site| site.origin() != url.origin())
+        {
+            return Err(SimpleError::new(
+                format!("{} is not loaded by documents of {}", url, site).as_str(),
+            ));
+        }
+        let blobs = loader.blobs.lock().unwrap();
+        let blob = blobs
+            .get(url.as_str())
+            .ok_or_else(|| SimpleError::new(format!("No blob at {}, or it was revoked", url).as_str()))?;
+        Ok(Response::new(request.url, 200, &blob.content_type, blob.data.clone()))
+    }
+}
+
+/// `view-source:<url>` loads `url` and answers it as is, for the browser to show its text.
+#[derive(Debug)]
+struct ViewSourceHandler;
+
+impl SchemeHandler for ViewSourceHandler {
+    fn load(&self, loader: &Loader, mut request: Request, mode: CacheMode) -> Result<Response, SimpleError> {
+        let inner = Url::parse(&request.url.as_str()["view-source:".len()..])
+            .map_err(|err| SimpleError::new(format!("Invalid URL in {}: {}", request.url, err).as_str()))?;
+        if inner.scheme() == "view-source" {
+            return Err(SimpleError::new(
+                format!("Nested view-source in {}", request.url).as_str(),
+            ));
+        }
+        request.url = inner;
+        let mut response = loader.load(request, mode)?;
+        response.url = Url::parse(&format!("view-source:{}", response.url)).unwrap();
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert2::check;
+
+    use super::super::http::tests::{response, TestServer};
+    use super::*;
+
+    fn loader() -> Loader {
+        Loader::new(Arc::new(Mutex::new(HttpClient::new())))
+    }
+
+    fn data(url: &str) -> (String, Vec<u8>) {
+        parse_data_url(&Url::parse(url).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn data_urls() {
+        check!(data("data:,Hello%2C%20World%21") == (String::from(DEFAULT_DATA_TYPE), b"Hello, World!".to_vec()));
+        check!(
+            data("data:text/html;base64,PGI+aGk8L2I+#fragment") == (String::from("text/html"), b"<b>hi</b>".to_vec())
+        );
+        check!(data("data:;charset=utf-8,%C3%A9").0 == "text/plain;charset=utf-8");
+        check!(data("data:image/png;base64,iVBO Rw0K").1 == b"\x89PNG\r\n".to_vec());
+        check!(data("data:text/plain;base64,YQ").1 == b"a".to_vec());
+        check!(data("data:text/plain;charset=utf-8;base64,4pyTIMOgIGxhIG1vZGU=").1 == "✓ à la mode".as_bytes());
+        check!(parse_data_url(&Url::parse("data:text/plain").unwrap()).is_err());
+        check!(parse_data_url(&Url::parse("data:;base64,!!").unwrap()).is_err());
+
+        let response = loader()
+            .get(&Url::parse("data:text/css,p{color:red}").unwrap())
+            .unwrap();
+        check!(response.status == 200);
+        check!(response.mime_type().as_deref() == Some("text/css"));
+        check!(response.body == b"p{color:red}");
+    }
+
+    #[test]
+    fn files_and_directories() {
+        let dir = std::env::temp_dir().join(format!("rowser-loader-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("b & c")).unwrap();
+        std::fs::write(dir.join("a.html"), "<p>a</p>").unwrap();
+
+        let response = loader().get(&Url::from_file_path(dir.join("a.html")).unwrap()).unwrap();
+        check!(response.mime_type().as_deref() == Some("text/html"));
+        check!(response.body == b"<p>a</p>");
+        let response = loader().get(&Url::from_directory_path(&dir).unwrap()).unwrap();
+        check!(response.mime_type().as_deref() == Some("text/html"));
+        let listing = String::from_utf8(response.body).unwrap();
+        let file = listing
+            .find(Url::from_file_path(dir.join("a.html")).unwrap().as_str())
+            .unwrap();
+        let subdirectory = listing.find(">b &amp; c/</a>").unwrap();
+        check!(file < subdirectory);
+        check!(loader()
+            .get(&Url::from_file_path(dir.join("missing")).unwrap())
+            .is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn blobs_and_custom_schemes() {
+        let mut loader = loader();
+        let origin = Url::parse("https://app.example/page").unwrap();
+        let url = loader.create_object_url(&origin, Blob::new(b"{}".to_vec(), "application/json"));
+        check!(url.as_str().starts_with("blob:https://app.example/"));
+        check!(url.as_str().len() == "blob:https://app.example/".len() + 36);
+        let response = loader.get(&url.join("#part").unwrap()).unwrap();
+        check!(response.body == b"{}");
+        check!(response.headers.get("Content-Type") == Some("application/json"));
+        // Loads of documents of the origin only
+        let mut request = Request::get(url.clone());
+        request.site_for_cookies = Some(origin.join("/other").unwrap());
+        check!(loader.load(request.clone(), CacheMode::Default).is_ok());
+        request.site_for_cookies = Some(Url::parse("https://evil.example/").unwrap());
+        check!(loader.load(request, CacheMode::Default).is_err());
+        check!(loader.revoke_object_url(&url));
+        check!(loader.get(&url).is_err());
+
+        #[derive(Debug)]
+        struct Echo;
+        impl SchemeHandler for Echo {
+            fn load(&self, _: &Loader, request: Request, _: CacheMode) -> Result<Response, SimpleError> {
+                let body = request.url.path().as_bytes().to_vec();
+                Ok(Response::new(request.url, 200, "text/plain", body))
+            }
+        }
+        let custom = Url::parse("echo:hello").unwrap();
+        check!(loader.get(&custom).is_err());
+        loader.register("echo", Arc::new(Echo));
+        check!(loader.get(&custom).unwrap().body == b"hello");
+        check!(
+            loader
+                .get(&Url::parse("view-source:echo:source").unwrap())
+                .unwrap()
+                .body
+                == b"source"
+        );
+        check!(loader.unregister("echo"));
+        check!(!loader.handles("echo"));
+    }
+
+    #[test]
+    fn view_source() {
+        let server = TestServer::start(Arc::new(|_: &str| {
+            response(
+                "200 OK",
+                &["Content-Type: text/html; charset=utf-8", "Content-Length: 13"],
+                b"<p>source</p>",
+            )
+        }));
+        let loader = loader();
+        let url = Url::parse(&format!("view-source:{}page?q=1", server.url)).unwrap();
+        let response = loader.get(&url).unwrap();
+        check!(response.url == url);
+        check!(response.mime_type().as_deref() == Some("text/html"));
+        check!(response.body == b"<p>source</p>");
+        check!(loader
+            .get(&Url::parse("view-source:view-source:data:,x").unwrap())
+            .is_err());
+    }
+}
@@ -0,0 +1,248 @@
+#![allow(dead_code)]
+#![allow(unused_imports)]
+
+use log::{debug, error, info, warn};
+
+use super::geometry::Size;
+use super::simple_error::SimpleError;
+
+/// What media queries are evaluated against.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MediaFeatures {
+    /// Viewport size in CSS pixels.
+    pub viewport: Size,
+    /// Device pixels per CSS pixel, as `devicePixelRatio`.
+    pub resolution: f64,
+    /// Pixels per `em`: the default font size of the user, not the one of any element.
+    pub em: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Range {
+    Min,
+    Max,
+    Exact,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Length {
+    Px(f64),
+    /// `em` and `rem`
+    Em(f64),
+}
+
+impl Length {
+    fn resolve(&self, features: &MediaFeatures) -> f64 {
+        match self {
+            Length::Px(px) => *px,
+            Length::Em(em) => em * features.em,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum MediaFeature {
+    Width(Range, Length),
+    Height(Range, Length),
+    /// In dppx
+    Resolution(Range, f64),
+}
+
+impl MediaFeature {
+    fn parse(text: &str) -> Result<MediaFeature, SimpleError> {
+        let inner = text
+            .strip_prefix('(')
+            .and_then(|t| t.strip_suffix(')'))
+            .ok_or_else(|| SimpleError::new(format!("Expected a media feature, found {}", text).as_str()))?;
+        let (name, value) = inner
+            .split_once(':')
+            .ok_or_else(|| SimpleError::new(format!("Media feature without value {}", text).as_str()))?;
+        let name = name.trim();
+        let value = value.trim();
+        let (range, name) = if let Some(name) = name.strip_prefix("min-") {
+            (Range::Min, name)
+        } else if let Some(name) = name.strip_prefix("max-") {
+            (Range::Max, name)
+        } else if let Some(name) = name.strip_prefix("-webkit-min-") {
+            (Range::Min, name)
+        } else if let Some(name) = name.strip_prefix("-webkit-max-") {
+            (Range::Max, name)
+        } else {
+            (Range::Exact, name.strip_prefix("-webkit-").unwrap_or(name))
+        };
+        match name {
+            "width" => Ok(MediaFeature::Width(range, parse_length(value)?)),
+            "height" => Ok(MediaFeature::Height(range, parse_length(value)?)),
+            "resolution" => Ok(MediaFeature::Resolution(range, parse_resolution(value)?)),
+            "device-pixel-ratio" => Ok(MediaFeature::Resolution(range, parse_number(value)?)),
+            _ => Err(SimpleError::new(format!("Unsupported media feature {}", name).as_str())),
+        }
+    }
+
+    fn matches(&self, features: &MediaFeatures) -> bool {
+        let compare = |range: &Range, actual: f64, expected: f64| match range {
+            Range::Min => actual >= expected,
+            Range::Max => actual <= expected,
+            Range::Exact => (actual - expected).abs() < 1e-9,
+        };
+        match self {
+            MediaFeature::Width(range, value) => compare(range, features.viewport.width, value.resolve(features)),
+            MediaFeature::Height(range, value) => compare(range, features.viewport.height, value.resolve(features)),
+            MediaFeature::Resolution(range, value) => compare(range, features.resolution, *value),
+        }
+    }
+}
+
+fn parse_number(value: &str) -> Result<f64, SimpleError> {
+    value
+        .parse::<f64>()
+        .map_err(|_| SimpleError::new(format!("Invalid number {}", value).as_str()))
+}
+
+fn parse_length(value: &str) -> Result<Length, SimpleError> {
+    if let Some(number) = value.strip_suffix("px") {
+        Ok(Length::Px(parse_number(number)?))
+    } else if let Some(number) = value.strip_suffix("em") {
+        Ok(Length::Em(parse_number(number.strip_suffix('r').unwrap_or(number))?))
+    } else if value == "0" {
+        Ok(Length::Px(0.0))
+    } else {
+        Err(SimpleError::new(format!("Invalid length {}", value).as_str()))
+    }
+}
+
+/// Resolution in dppx.
+fn parse_resolution(value: &str) -> Result<f64, SimpleError> {
+    if let Some(number) = value.strip_suffix("dppx") {
+        parse_number(number)
+    } else if let Some(number) = value.strip_suffix("dpcm") {
+        Ok(parse_number(number)? * 2.54 / 96.0)
+    } else if let Some(number) = value.strip_suffix("dpi") {
+        Ok(parse_number(number)? / 96.0)
+    } else if let Some(number) = value.strip_suffix('x') {
+        parse_number(number)
+    } else {
+        Err(SimpleError::new(format!("Invalid resolution {}", value).as_str()))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct MediaQuery {
+    negated: bool,
+    /// False for media types other than `all` and `screen`.
+    media_type_matches: bool,
+    features: Vec<MediaFeature>,
+}
+
+impl MediaQuery {
+    fn parse(text: &str) -> Result<MediaQuery, SimpleError> {
+        let mut negated = false;
+        let mut media_type_matches = true;
+        let mut features = vec![];
+        for (i, part) in text.split(" and ").map(str::trim).enumerate() {
+            if part.starts_with('(') {
+                features.push(MediaFeature::parse(part)?);
+                continue;
+            }
+            if i > 0 {
+                return Err(SimpleError::new(format!("Unexpected {} in media query", part).as_str()));
+            }
+            let mut words = part.split_whitespace();
+            let mut media_type = words.next().unwrap_or("all");
+            if media_type == "not" || media_type == "only" {
+                negated = media_type == "not";
+                media_type = words.next().unwrap_or("all");
+            }
+            media_type_matches = matches!(media_type, "all" | "screen");
+        }
+        Ok(MediaQuery {
+            negated,
+            media_type_matches,
+            features,
+        })
+    }
+
+    fn matches(&self, features: &MediaFeatures) -> bool {
+        let matched = self.media_type_matches && self.features.iter().all(|f| f.matches(features));
+        matched != self.negated
+    }
+}
+
+/// Comma separated media queries, as in `@media` or the `media` and `sizes` attributes.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct MediaQueryList {
+    /// `None` for queries that failed to parse: they never match.
+    queries: Vec<Option<MediaQuery>>,
+}
+
+impl MediaQueryList {
+    pub fn parse(text: &str) -> MediaQueryList {
+        let text = text.trim().to_ascii_lowercase();
+        if text.is_empty() {
+            return MediaQueryList::default();
+        }
+        let queries = text
+            .split(',')
+            .map(|query| match MediaQuery::parse(query.trim()) {
+                Ok(query) => Some(query),
+                Err(err) => {
+                    warn!("Ignoring media query {:?}: {}", query, err);
+                    None
+                }
+            })
+            .collect();
+        MediaQueryList { queries }
+    }
+
+    /// An empty list matches everything.
+    pub fn matches(&self, features: &MediaFeatures) -> bool {
+        self.queries.is_empty()
+            || self
+                .queries
+                .iter()
+                .any(|query| query.as_ref().is_some_and(|q| q.matches(features)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert2::check;
+
+    use super::*;
+
+    fn features(width: f64, resolution: f64) -> MediaFeatures {
+        MediaFeatures {
+            viewport: Size::new(width, 600.0),
+            resolution,
+            em: 16.0,
+        }
+    }
+
+    #[test]
+    fn resolution_queries() {
+        let hidpi = MediaQueryList::parse("(min-resolution: 2dppx), (-webkit-min-device-pixel-ratio: 2)");
+        check!(hidpi.matches(&features(800.0, 2.0)));
+        check!(!hidpi.matches(&features(800.0, 1.0)));
+        check!(MediaQueryList::parse("(max-resolution: 144dpi)").matches(&features(800.0, 1.5)));
+        check!(MediaQueryList::parse("(resolution: 1x)").matches(&features(800.0, 1.0)));
+    }
+
+    #[test]
+    fn types_and_widths() {
+        let query = MediaQueryList::parse("screen and (min-width: 40em) and (max-width: 1000px)");
+        check!(query.matches(&features(800.0, 1.0)));
+        check!(!query.matches(&features(600.0, 1.0)));
+        // A larger default font size makes `em` queries larger
+        let large_font = MediaFeatures {
+            em: 24.0,
+            ..features(800.0, 1.0)
+        };
+        check!(!query.matches(&large_font));
+        check!(!MediaQueryList::parse("print").matches(&features(800.0, 1.0)));
+        check!(MediaQueryList::parse("not print").matches(&features(800.0, 1.0)));
+        check!(MediaQueryList::parse("").matches(&features(800.0, 1.0)));
+        // Unknown features make only their own query fail
+        check!(!MediaQueryList::parse("(hover: hover)").matches(&features(800.0, 1.0)));
+        check!(MediaQueryList::parse("(hover: hover), all").matches(&features(800.0, 1.0)));
+    }
+}
@@ -0,0 +1,511 @@
+#![allow(dead_code)]
+#![allow(unused_imports)]
+
+use std::f64::consts::PI;
+
+use log::{debug, error, info, warn};
+
+use super::geometry::{Position, Rect, Transform};
+
+/// Maximum distance, in pixels, between a curve and the lines replacing it.
+pub const FLATTEN_TOLERANCE: f64 = 0.1;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PathCommand {
+    MoveTo(Position),
+    LineTo(Position),
+    QuadTo(Position, Position),
+    CubicTo(Position, Position, Position),
+    Close,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FillRule {
+    NonZero,
+    EvenOdd,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LineJoin {
+    Miter,
+    Round,
+    Bevel,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LineCap {
+    Butt,
+    Round,
+    Square,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StrokeStyle {
+    pub width: f64,
+    pub join: LineJoin,
+    pub cap: LineCap,
+    pub miter_limit: f64,
+    /// Alternating dash and gap lengths. An odd count is repeated twice, like SVG does.
+    pub dashes: Vec<f64>,
+    pub dash_offset: f64,
+}
+
+impl StrokeStyle {
+    pub fn new(width: f64) -> Self {
+        Self {
+            width,
+            join: LineJoin::Miter,
+            cap: LineCap::Butt,
+            miter_limit: 4.0,
+            dashes: vec![],
+            dash_offset: 0.0,
+        }
+    }
+}
+
+/// A flattened sub-path.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Polyline {
+    pub points: Vec<Position>,
+    pub closed: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Path {
+    pub commands: Vec<PathCommand>,
+}
+
+fn pos(left: f64, top: f64) -> Position {
+    Position { left, top }
+}
+
+fn lerp(a: &Position, b: &Position, t: f64) -> Position {
+    pos(a.left + (b.left - a.left) * t, a.top + (b.top - a.top) * t)
+}
+
+fn distance(a: &Position, b: &Position) -> f64 {
+    ((b.left - a.left).powi(2) + (b.top - a.top).powi(2)).sqrt()
+}
+
+impl Path {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn move_to(&mut self, x: f64, y: f64) -> &mut Self {
+        self.commands.push(PathCommand::MoveTo(pos(x, y)));
+        self
+    }
+    pub fn line_to(&mut self, x: f64, y: f64) -> &mut Self {
+        self.commands.push(PathCommand::LineTo(pos(x, y)));
+        self
+    }
+    pub fn quad_to(&mut self, cx: f64, cy: f64, x: f64, y: f64) -> &mut Self {
+        self.commands
+            .push(PathCommand::QuadTo(pos(cx, cy), pos(x, y)));
+        self
+    }
+    pub fn cubic_to(&mut self, c1x: f64, c1y: f64, c2x: f64, c2y: f64, x: f64, y: f64) -> &mut Self {
+        self.commands
+            .push(PathCommand::CubicTo(pos(c1x, c1y), pos(c2x, c2y), pos(x, y)));
+        self
+    }
+    pub fn close(&mut self) -> &mut Self {
+        self.commands.push(PathCommand::Close);
+        self
+    }
+
+    pub fn rect(rect: &Rect) -> Self {
+        let mut path = Self::new();
+        path.move_to(rect.left(), rect.top())
+            .line_to(rect.right(), rect.top())
+            .line_to(rect.right(), rect.bottom())
+            .line_to(rect.left(), rect.bottom())
+            .close();
+        path
+    }
+
+    /// Circle made of four cubic arcs.
+    pub fn circle(cx: f64, cy: f64, radius: f64) -> Self {
+        // Control point distance giving the best cubic approximation of a quarter circle
+        let k = radius * 0.552_284_749_831;
+        let mut path = Self::new();
+        path.move_to(cx + radius, cy)
+            .cubic_to(cx + radius, cy + k, cx + k, cy + radius, cx, cy + radius)
+            .cubic_to(cx - k, cy + radius, cx - radius, cy + k, cx - radius, cy)
+            .cubic_to(cx - radius, cy - k, cx - k, cy - radius, cx, cy - radius)
+            .cubic_to(cx + k, cy - radius, cx + radius, cy - k, cx + radius, cy)
+            .close();
+        path
+    }
+
+    pub fn transform(&self, transform: &Transform) -> Path {
+        let map = |p: &Position| transform.map_point(p);
+        Path {
+            commands: self
+                .commands
+                .iter()
+                .map(|command| match command {
+                    PathCommand::MoveTo(p) => PathCommand::MoveTo(map(p)),
+                    PathCommand::LineTo(p) => PathCommand::LineTo(map(p)),
+                    PathCommand::QuadTo(c, p) => PathCommand::QuadTo(map(c), map(p)),
+                    PathCommand::CubicTo(c1, c2, p) => PathCommand::CubicTo(map(c1), map(c2), map(p)),
+                    PathCommand::Close => PathCommand::Close,
+                })
+                .collect(),
+        }
+    }
+
+    /// Replace curves by line segments no farther than `tolerance` from them.
+    pub fn flatten(&self, tolerance: f64) -> Vec<Polyline> {
+        let mut result: Vec<Polyline> = vec![];
+        let mut current = Polyline {
+            points: vec![],
+            closed: false,
+        };
+        let mut last = pos(0.0, 0.0);
+        let flush = |current: &mut Polyline, result: &mut Vec<Polyline>| {
+            if current.points.len() > 1 || (current.points.len() == 1 && !current.closed) {
+                result.push(current.clone());
+            }
+            current.points.clear();
+            current.closed = false;
+        };
+        for command in &self.commands {
+            match command {
+                PathCommand::MoveTo(p) => {
+                    flush(&mut current, &mut result);
+                    current.points.push(*p);
+                    last = *p;
+                }
+                PathCommand::LineTo(p) => {
+                    if current.points.is_empty() {
+                        current.points.push(last);
+                    }
+                    current.points.push(*p);
+                    last = *p;
+                }
+                PathCommand::QuadTo(c, p) => {
+                    if current.points.is_empty() {
+                        current.points.push(last);
+                    }
+                    let dd = pos(last.left - 2.0 * c.left + p.left, last.top - 2.0 * c.top + p.top);
+                    let dd = (dd.left * dd.left + dd.top * dd.top).sqrt();
+                    let steps = (dd / (4.0 * tolerance)).sqrt().ceil().clamp(1.0, 256.0) as usize;
+                    for i in 1..=steps {
+                        let t = i as f64 / steps as f64;
+                        current.points.push(lerp(&lerp(&last, c, t), &lerp(c, p, t), t));
+                    }
+                    last = *p;
+                }
+                PathCommand::CubicTo(c1, c2, p) => {
+                    if current.points.is_empty() {
+                        current.points.push(last);
+                    }
+                    let second_difference = |a: &Position, b: &Position, c: &Position| {
+                        let x = a.left - 2.0 * b.left + c.left;
+                        let y = a.top - 2.0 * b.top + c.top;
+                        (x * x + y * y).sqrt()
+                    };
+                    let dd = second_difference(&last, c1, c2).max(second_difference(c1, c2, p));
+                    let steps = (dd * 3.0 / (4.0 * tolerance)).sqrt().ceil().clamp(1.0, 256.0) as usize;
+                    for i in 1..=steps {
+                        let t = i as f64 / steps as f64;
+                        let a = lerp(&last, c1, t);
+                        let b = lerp(c1, c2, t);
+                        let c = lerp(c2, p, t);
+                        current.points.push(lerp(&lerp(&a, &b, t), &lerp(&b, &c, t), t));
+                    }
+                    last = *p;
+                }
+                PathCommand::Close => {
+                    if let Some(first) = current.points.first().cloned() {
+                        current.closed = true;
+                        flush(&mut current, &mut result);
+                        // A new sub-path starts where the closed one started
+                        last = first;
+                    }
+                }
+            }
+        }
+        flush(&mut current, &mut result);
+        result
+    }
+
+    /// Outline of the stroke of this path, as a path to be filled with the non-zero rule.
+    pub fn stroke(&self, style: &StrokeStyle) -> Path {
+        let half = style.width / 2.0;
+        let mut outline = Path::new();
+        if half <= 0.0 {
+            return outline;
+        }
+        for polyline in self.flatten(FLATTEN_TOLERANCE) {
+            let mut points = polyline.points.clone();
+            points.dedup_by(|a, b| distance(a, b) < 1e-9);
+            if polyline.closed && points.len() > 1 && distance(&points[0], &points[points.len() - 1]) > 1e-9 {
+                points.push(points[0]);
+            }
+            let pieces = if style.dashes.iter().any(|d| *d > 0.0) {
+                dash(&points, &style.dashes, style.dash_offset)
+            } else {
+                vec![Polyline {
+                    points,
+                    closed: polyline.closed,
+                }]
+            };
+            for piece in pieces {
+                stroke_polyline(&mut outline, &piece, style, half);
+            }
+        }
+        outline
+    }
+}
+
+/// Split `points` in the "on" intervals of the dash pattern.
+fn dash(points: &[Position], dashes: &[f64], offset: f64) -> Vec<Polyline> {
+    let mut pattern: Vec<f64> = dashes.iter().map(|d| d.max(0.0)).collect();
+    if pattern.len() % 2 == 1 {
+        pattern.extend_from_slice(&dashes.iter().map(|d| d.max(0.0)).collect::<Vec<_>>());
+    }
+    let total: f64 = pattern.iter().sum();
+    let mut result = vec![];
+    if total <= 0.0 || points.len() < 2 {
+        return result;
+    }
+    // Find where the pattern starts
+    let mut index = 0;
+    let mut remaining = pattern[0];
+    let mut skip = offset.rem_euclid(total);
+    while skip > 0.0 {
+        if skip >= remaining {
+            skip -= remaining;
+            index = (index + 1) % pattern.len();
+            remaining = pattern[index];
+        } else {
+            remaining -= skip;
+            skip = 0.0;
+        }
+    }
+
+    let mut current: Vec<Position> = if index % 2 == 0 { vec![points[0]] } else { vec![] };
+    for segment in points.windows(2) {
+        let (a, b) = (&segment[0], &segment[1]);
+        let length = distance(a, b);
+        let mut done = 0.0;
+        while length - done > remaining {
+            done += remaining;
+            let p = lerp(a, b, done / length);
+            if index % 2 == 0 {
+                if current.last() != Some(&p) {
+                    current.push(p);
+                }
+                result.push(Polyline {
+                    points: std::mem::take(&mut current),
+                    closed: false,
+                });
+            } else {
+                current = vec![p];
+            }
+            index = (index + 1) % pattern.len();
+            remaining = pattern[index];
+        }
+        remaining -= length - done;
+        if index % 2 == 0 {
+            current.push(*b);
+        }
+    }
+    if index % 2 == 0 && current.len() > 1 {
+        result.push(Polyline {
+            points: current,
+            closed: false,
+        });
+    }
+    result
+}
+
+/// Add `polygon` to `outline`, always clockwise so overlapping pieces add up under the non-zero rule.
+fn add_polygon(outline: &mut Path, polygon: &[Position]) {
+    if polygon.len() < 3 {
+        return;
+    }
+    let mut area = 0.0;
+    for i in 0..polygon.len() {
+        let a = &polygon[i];
+        let b = &polygon[(i + 1) % polygon.len()];
+        area += a.left * b.top - b.left * a.top;
+    }
+    if area.abs() < 1e-12 {
+        return;
+    }
+    let mut points: Vec<Position> = polygon.to_vec();
+    if area < 0.0 {
+        points.reverse();
+    }
+    outline.move_to(points[0].left, points[0].top);
+    for p in &points[1..] {
+        outline.line_to(p.left, p.top);
+    }
+    outline.close();
+}
+
+fn circle_polygon(center: &Position, radius: f64) -> Vec<Position> {
+    let steps = (radius * 4.0).ceil().clamp(8.0, 128.0) as usize;
+    (0..steps)
+        .map(|i| {
+            let angle = 2.0 * PI * i as f64 / steps as f64;
+            pos(center.left + radius * angle.cos(), center.top + radius * angle.sin())
+        })
+        .collect()
+}
+
+fn stroke_polyline(outline: &mut Path, polyline: &Polyline, style: &StrokeStyle, half: f64) {
+    let points = &polyline.points;
+    if points.len() == 1 || (points.len() == 2 && distance(&points[0], &points[1]) < 1e-9) {
+        // Zero length sub-path: only round and square caps show up
+        let p = &points[0];
+        match style.cap {
+            LineCap::Round => add_polygon(outline, &circle_polygon(p, half)),
+            LineCap::Square => add_polygon(
+                outline,
+                &[
+                    pos(p.left - half, p.top - half),
+                    pos(p.left + half, p.top - half),
+                    pos(p.left + half, p.top + half),
+                    pos(p.left - half, p.top + half),
+                ],
+            ),
+            LineCap::Butt => (),
+        }
+        return;
+    }
+
+    let directions: Vec<Position> = points
+        .windows(2)
+        .map(|s| {
+            let length = distance(&s[0], &s[1]).max(1e-12);
+            pos((s[1].left - s[0].left) / length, (s[1].top - s[0].top) / length)
+        })
+        .collect();
+    let normal = |d: &Position| pos(-d.top * half, d.left * half);
+
+    for (i, segment) in points.windows(2).enumerate() {
+        let n = normal(&directions[i]);
+        let (mut a, mut b) = (segment[0], segment[1]);
+        if style.cap == LineCap::Square && !polyline.closed {
+            let d = &directions[i];
+            if i == 0 {
+                a = pos(a.left - d.left * half, a.top - d.top * half);
+            }
+            if i == directions.len() - 1 {
+                b = pos(b.left + d.left * half, b.top + d.top * half);
+            }
+        }
+        add_polygon(
+            outline,
+            &[
+                pos(a.left + n.left, a.top + n.top),
+                pos(b.left + n.left, b.top + n.top),
+                pos(b.left - n.left, b.top - n.top),
+                pos(a.left - n.left, a.top - n.top),
+            ],
+        );
+    }
+
+    // Joins between consecutive segments, including the closing one
+    let mut joins: Vec<(usize, usize)> = (1..directions.len()).map(|i| (i - 1, i)).collect();
+    if polyline.closed && directions.len() > 1 {
+        joins.push((directions.len() - 1, 0));
+    }
+    for (before, after) in joins {
+        let vertex = &points[after];
+        let (d0, d1) = (&directions[before], &directions[after]);
+        let cross = d0.left * d1.top - d0.top * d1.left;
+        let dot = d0.left * d1.left + d0.top * d1.top;
+        if cross.abs() < 1e-12 && dot > 0.0 {
+            continue;
+        }
+        // The outer side of the turn is opposite to the direction of the turn
+        let side = if cross > 0.0 { -1.0 } else { 1.0 };
+        let n0 = normal(d0);
+        let n1 = normal(d1);
+        let p0 = pos(vertex.left + n0.left * side, vertex.top + n0.top * side);
+        let p1 = pos(vertex.left + n1.left * side, vertex.top + n1.top * side);
+        match style.join {
+            LineJoin::Round => add_polygon(outline, &circle_polygon(vertex, half)),
+            LineJoin::Bevel => add_polygon(outline, &[*vertex, p0, p1]),
+            LineJoin::Miter => {
+                // Miter length over stroke width is 1 / sin(theta / 2) = sqrt(2 / (1 + cos(theta)))
+                let ratio = (2.0 / (1.0 + dot).max(1e-12)).sqrt();
+                if ratio <= style.miter_limit {
+                    let scale = side / (1.0 + dot);
+                    let tip = pos(
+                        vertex.left + (n0.left + n1.left) * scale,
+                        vertex.top + (n0.top + n1.top) * scale,
+                    );
+                    add_polygon(outline, &[*vertex, p0, tip, p1]);
+                } else {
+                    add_polygon(outline, &[*vertex, p0, p1]);
+                }
+            }
+        }
+    }
+
+    if !polyline.closed && style.cap == LineCap::Round {
+        add_polygon(outline, &circle_polygon(&points[0], half));
+        add_polygon(outline, &circle_polygon(&points[points.len() - 1], half));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert2::check;
+
+    use super::*;
+
+    #[test]
+    fn flatten_curves() {
+        let polylines = Path::circle(0.0, 0.0, 50.0).flatten(FLATTEN_TOLERANCE);
+        check!(polylines.len() == 1);
+        check!(polylines[0].closed);
+        for p in &polylines[0].points {
+            let r = (p.left * p.left + p.top * p.top).sqrt();
+            check!((r - 50.0).abs() < 0.1);
+        }
+        let mut quad = Path::new();
+        quad.move_to(0.0, 0.0).quad_to(50.0, 100.0, 100.0, 0.0);
+        let points = &quad.flatten(FLATTEN_TOLERANCE)[0].points;
+        check!(points.len() > 3);
+        check!(points[points.len() - 1] == pos(100.0, 0.0));
+    }
+
+    #[test]
+    fn dash_pattern() {
+        let points = vec![pos(0.0, 0.0), pos(10.0, 0.0), pos(10.0, 10.0)];
+        let pieces = dash(&points, &[4.0, 2.0], 0.0);
+        check!(pieces.len() == 4);
+        check!(pieces[0].points == vec![pos(0.0, 0.0), pos(4.0, 0.0)]);
+        // The third dash goes around the corner
+        check!(pieces[1].points == vec![pos(6.0, 0.0), pos(10.0, 0.0)]);
+        check!(pieces[2].points == vec![pos(10.0, 2.0), pos(10.0, 6.0)]);
+        let shifted = dash(&points, &[4.0, 2.0], 5.0);
+        check!(shifted[0].points == vec![pos(1.0, 0.0), pos(5.0, 0.0)]);
+    }
+
+    #[test]
+    fn stroke_outline_is_clockwise() {
+        let mut path = Path::new();
+        path.move_to(0.0, 0.0).line_to(10.0, 0.0).line_to(10.0, 10.0);
+        let mut style = StrokeStyle::new(2.0);
+        style.join = LineJoin::Miter;
+        let outline = path.stroke(&style);
+        for polygon in outline.flatten(FLATTEN_TOLERANCE) {
+            let mut area = 0.0;
+            let points = &polygon.points;
+            for i in 0..points.len() {
+                let (a, b) = (&points[i], &points[(i + 1) % points.len()]);
+                area += a.left * b.top - b.left * a.top;
+            }
+            check!(area > 0.0);
+        }
+    }
+}
@@ -8,39 +8,50 @@
     target_os = "openbsd"
 ))]
 
-pub struct MainWindow {}
+pub struct MainWindow {
+    url: Option<Url>,
+    shared: SharedState,
+}
 
 use winit::{
-    event::{Event, WindowEvent},
+    event::{
+        ElementState, Event, KeyboardInput, ModifiersState, MouseButton, MouseScrollDelta,
+        VirtualKeyCode, WindowEvent,
+    },
     event_loop::{ControlFlow, EventLoop},
     platform::run_return::EventLoopExtRunReturn,
-    window::WindowBuilder,
 };
 
-use crate::core::dom::window_events::EventHandler;
 
 use super::dom::*;
 
 use super::geometry::*;
+use super::window_manager::{SharedState, WindowManager};
+
+use url::Url;
 
 #[allow(unused_imports)]
 use log::{debug, error, info, warn};
 
-use std::time::Instant;
+use std::time::{Duration, Instant};
+
+/// Frame interval requested while animations are running.
+const ANIMATION_FRAME_INTERVAL: Duration = Duration::from_millis(16);
 
 impl MainWindow {
+    /// `shared` is what the windows share, loaded from the profile.
+    pub fn new(url: Option<Url>, shared: SharedState) -> Self {
+        Self { url, shared }
+    }
+
     pub fn run(&self) -> Result<(), Box<dyn std::error::Error>> {
         let mut event_loop = EventLoop::new();
-        let window = WindowBuilder::new()
-            .with_title("Rowser")
-            .build(&event_loop)
-            .unwrap();
 
         let mut last_frame_time = Instant::now();
         //let mut gfx_root = dom::create_dom_element(None, dom::DomElementType::Root(&window));
 
-        let win_id = window.id();
-        let mut gfx_root = DomRoot::new(&window);
+        let mut windows = WindowManager::new(self.shared.clone());
+        windows.open_window(&event_loop, self.url.clone())?;
 
         /*let mut tree: Tree<DomElement> = Tree::new();
         tree.add_node(mtree::TreeNodeType::Root, || {
@@ -52,17 +63,15 @@ impl MainWindow {
         //let mut gfx_buffer = GfBuffer::new(window.clone());
         info!("Begin loop {:?}", event_loop);
 
-        event_loop.run_return(|event, _, control_flow| {
+        event_loop.run_return(|event, target, control_flow| {
             *control_flow = ControlFlow::Wait;
 
             match event {
                 Event::WindowEvent {
                     event: WindowEvent::Resized(_),
-                    window_id: _,
+                    window_id,
                 } => {
-                    gfx_root
-                        //.unwrap()
-                        .on_window_event(&window_events::Event::Resized);
+                    windows.on_window_event(window_id, &window_events::Event::Resized);
                 }
                 #[allow(deprecated)]
                 Event::WindowEvent {
@@ -72,11 +81,9 @@ impl MainWindow {
                             position,
                             modifiers: _,
                         },
-                    window_id: _,
+                    window_id,
                 } => {
-                    gfx_root
-                        //.unwrap()
-                        .on_window_event(&window_events::Event::CursorMoved {
+                    windows.on_window_event(window_id, &window_events::Event::CursorMoved {
                             device_id: window_events::DeviceId::new(
                                 format!("{:?}", device_id).as_str(),
                             ), // format!("{}",device_id),
@@ -93,11 +100,9 @@ impl MainWindow {
                             axis,
                             value,
                         },
-                    window_id: _,
+                    window_id,
                 } => {
-                    gfx_root
-                        //.as_mut()
-                        .on_window_event(&window_events::Event::AxisMotion {
+                    windows.on_window_event(window_id, &window_events::Event::AxisMotion {
                             device_id: window_events::DeviceId::new(
                                 format!("{:?}", device_id).as_str(),
                             ), // format!("{}",device_id),
@@ -107,11 +112,9 @@ impl MainWindow {
                 }
                 Event::WindowEvent {
                     event: WindowEvent::CursorEntered { device_id },
-                    window_id: _,
+                    window_id,
                 } => {
-                    gfx_root
-                        //.as_mut()
-                        .on_window_event(&window_events::Event::CursorEntered {
+                    windows.on_window_event(window_id, &window_events::Event::CursorEntered {
                             device_id: window_events::DeviceId::new(
                                 format!("{:?}", device_id).as_str(),
                             ), // format!("{}",device_id),
@@ -119,50 +122,202 @@ impl MainWindow {
                 }
                 Event::WindowEvent {
                     event: WindowEvent::CursorLeft { device_id },
-                    window_id: _,
+                    window_id,
                 } => {
-                    gfx_root
-                        //.as_mut()
-                        .on_window_event(&window_events::Event::CursorLeft {
+                    windows.on_window_event(window_id, &window_events::Event::CursorLeft {
                             device_id: window_events::DeviceId::new(
                                 format!("{:?}", device_id).as_str(),
                             ), // format!("{}",device_id),
                         });
                 }
+                #[allow(deprecated)]
+                Event::WindowEvent {
+                    event:
+                        WindowEvent::MouseInput {
+                            device_id,
+                            state,
+                            button,
+                            modifiers: _,
+                        },
+                    window_id,
+                } => {
+                    windows.on_window_event(window_id, &window_events::Event::MouseInput {
+                        device_id: window_events::DeviceId::new(
+                            format!("{:?}", device_id).as_str(),
+                        ),
+                        pressed: state == ElementState::Pressed,
+                        button: match button {
+                            MouseButton::Left => window_events::MouseButton::Left,
+                            MouseButton::Right => window_events::MouseButton::Right,
+                            MouseButton::Middle => window_events::MouseButton::Middle,
+                            MouseButton::Other(n) => window_events::MouseButton::Other(n),
+                        },
+                    });
+                }
+                Event::WindowEvent {
+                    event: WindowEvent::ModifiersChanged(state),
+                    window_id,
+                } => {
+                    windows.set_modifiers(window_id, map_modifiers(state));
+                }
+                Event::WindowEvent {
+                    event:
+                        WindowEvent::KeyboardInput {
+                            device_id,
+                            input:
+                                KeyboardInput {
+                                    state,
+                                    virtual_keycode: Some(keycode),
+                                    ..
+                                },
+                            is_synthetic: false,
+                        },
+                    window_id,
+                } => {
+                    windows.on_window_event(window_id, &window_events::Event::KeyboardInput {
+                        device_id: window_events::DeviceId::new(
+                            format!("{:?}", device_id).as_str(),
+                        ),
+                        pressed: state == ElementState::Pressed,
+                        key: map_key(keycode),
+                        modifiers: windows.modifiers(window_id),
+                    });
+                }
+                Event::WindowEvent {
+                    event: WindowEvent::ReceivedCharacter(character),
+                    window_id,
+                } => {
+                    windows.on_window_event(window_id, &window_events::Event::ReceivedCharacter { character });
+                }
+                #[allow(deprecated)]
+                Event::WindowEvent {
+                    event:
+                        WindowEvent::MouseWheel {
+                            device_id,
+                            delta,
+                            phase: _,
+                            modifiers: _,
+                        },
+                    window_id,
+                } => {
+                    windows.on_window_event(window_id, &window_events::Event::MouseWheel {
+                        device_id: window_events::DeviceId::new(
+                            format!("{:?}", device_id).as_str(),
+                        ),
+                        delta: match delta {
+                            MouseScrollDelta::LineDelta(x, y) => window_events::WheelDelta::Lines {
+                                x: x as f64,
+                                y: y as f64,
+                            },
+                            MouseScrollDelta::PixelDelta(position) => {
+                                window_events::WheelDelta::Pixels {
+                                    x: position.x,
+                                    y: position.y,
+                                }
+                            }
+                        },
+                        modifiers: windows.modifiers(window_id),
+                    });
+                }
+                Event::WindowEvent {
+                    event: WindowEvent::ScaleFactorChanged { scale_factor, .. },
+                    window_id,
+                } => {
+                    windows.on_window_event(window_id, &window_events::Event::ScaleFactorChanged {
+                        scale_factor,
+                    });
+                }
                 Event::WindowEvent {
                     event: WindowEvent::Focused(focus),
-                    window_id: _,
+                    window_id,
                 } => {
-                    gfx_root
-                        //.as_mut()
-                        .on_window_event(&window_events::Event::Focused { focus });
+                    windows.on_window_event(window_id, &window_events::Event::Focused { focus });
                 }
                 Event::MainEventsCleared => {
-                    gfx_root.on_frame(last_frame_time.elapsed().as_secs_f64());
+                    windows.on_frame(target, last_frame_time.elapsed().as_secs_f64());
                     last_frame_time = Instant::now();
+                    if windows.is_empty() {
+                        debug!("Exit from loop, last window closed");
+                        *control_flow = ControlFlow::Exit;
+                        return;
+                    }
+                    if windows.is_animating() {
+                        *control_flow =
+                            ControlFlow::WaitUntil(last_frame_time + ANIMATION_FRAME_INTERVAL);
+                    }
                 }
-                Event::RedrawRequested(_) => {
-                    debug!("Full RedrawRequested");
-                    gfx_root.redraw_requested();
+                Event::RedrawRequested(window_id) => {
+                    debug!("Full RedrawRequested {:?}", window_id);
+                    windows.redraw_requested(window_id);
                 }
                 Event::WindowEvent {
                     event: WindowEvent::CloseRequested,
                     window_id,
-                } if window_id == win_id => {
-                    gfx_root
-                        //.as_mut()
-                        .on_window_event(&window_events::Event::CloseRequested);
-                    debug!("Exit from loop REQUESTED");
-                    //debug!("Window refcount: {}", Rc::strong_count(&window));
-                    *control_flow = ControlFlow::Exit;
-                }
-                Event::WindowEvent { event, window_id } if window_id == win_id => {
-                    debug!("Unhandled WindowEvent {:?}", event);
-                    //gfx_root.on_window_event(&event);
+                } => {
+                    // The window is dropped after the next frame, the loop ends with the last one
+                    windows.on_window_event(window_id, &window_events::Event::CloseRequested);
+                    debug!("Close REQUESTED for {:?}", window_id);
+                }
+                Event::WindowEvent { event, window_id } => {
+                    debug!("Unhandled WindowEvent {:?} for {:?}", event, window_id);
+                    //windows.on_window_event(window_id, &event);
                 }
                 _ => (),
             }
         });
+        self.shared.flush();
         Ok(())
     }
 }
+
+fn map_modifiers(state: ModifiersState) -> window_events::Modifiers {
+    window_events::Modifiers {
+        ctrl: state.ctrl(),
+        shift: state.shift(),
+        alt: state.alt(),
+        logo: state.logo(),
+    }
+}
+
+fn map_key(keycode: VirtualKeyCode) -> window_events::Key {
+    use window_events::Key;
+    use VirtualKeyCode::*;
+    const LETTERS: [VirtualKeyCode; 26] = [
+        A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z,
+    ];
+    const DIGITS: [VirtualKeyCode; 10] = [Key0, Key1, Key2, Key3, Key4, Key5, Key6, Key7, Key8, Key9];
+    const NUMPAD_DIGITS: [VirtualKeyCode; 10] = [
+        Numpad0, Numpad1, Numpad2, Numpad3, Numpad4, Numpad5, Numpad6, Numpad7, Numpad8, Numpad9,
+    ];
+    if let Some(i) = LETTERS.iter().position(|k| *k == keycode) {
+        return Key::Char((b'a' + i as u8) as char);
+    }
+    if let Some(i) = DIGITS
+        .iter()
+        .position(|k| *k == keycode)
+        .or_else(|| NUMPAD_DIGITS.iter().position(|k| *k == keycode))
+    {
+        return Key::Char((b'0' + i as u8) as char);
+    }
+    match keycode {
+        Plus | NumpadAdd => Key::Plus,
+        Minus | NumpadSubtract => Key::Minus,
+        Equals | NumpadEquals => Key::Equals,
+        Return | NumpadEnter => Key::Enter,
+        Escape => Key::Escape,
+        Back => Key::Backspace,
+        Delete => Key::Delete,
+        Tab => Key::Tab,
+        VirtualKeyCode::Left => Key::Left,
+        VirtualKeyCode::Right => Key::Right,
+        Up => Key::Up,
+        Down => Key::Down,
+        Home => Key::Home,
+        End => Key::End,
+        PageUp => Key::PageUp,
+        PageDown => Key::PageDown,
+        F5 => Key::F5,
+        F6 => Key::F6,
+        _ => Key::Other,
+    }
+}
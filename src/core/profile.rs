@@ -0,0 +1,43 @@
+#![allow(dead_code)]
+#![allow(unused_imports)]
+
+use std::path::{Path, PathBuf};
+
+use log::{debug, error, info, warn};
+
+use super::simple_error::SimpleError;
+
+/// Directory holding everything the browser remembers between runs.
+#[derive(Debug, Clone)]
+pub struct Profile {
+    dir: PathBuf,
+}
+
+impl Profile {
+    pub fn new(dir: &Path) -> Self {
+        Self {
+            dir: dir.to_path_buf(),
+        }
+    }
+
+    /// `$XDG_DATA_HOME/rowser`, falling back to `~/.local/share/rowser`.
+    pub fn default_dir() -> Option<PathBuf> {
+        if let Some(data_home) = std::env::var_os("XDG_DATA_HOME").filter(|d| !d.is_empty()) {
+            return Some(PathBuf::from(data_home).join("rowser"));
+        }
+        std::env::var_os("HOME")
+            .filter(|h| !h.is_empty())
+            .map(|home| PathBuf::from(home).join(".local/share/rowser"))
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Path of a file of the profile. The directory is created on demand.
+    pub fn file(&self, name: &str) -> Result<PathBuf, SimpleError> {
+        std::fs::create_dir_all(&self.dir)
+            .map_err(|err| SimpleError::new(format!("{:?}: {}", self.dir, err).as_str()))?;
+        Ok(self.dir.join(name))
+    }
+}
@@ -0,0 +1,400 @@
+#![allow(dead_code)]
+#![allow(unused_imports)]
+
+use log::{debug, error, info, warn};
+
+use crate::fixme;
+
+use super::bitmap::{Bitmap, ScaleFilter};
+use super::color::Color;
+use super::geometry::{Position, Rect, Transform, Transform3D};
+use super::gfxbuffer::Surface;
+use super::path::{FillRule, Path, StrokeStyle, FLATTEN_TOLERANCE};
+
+/// Sub-scanlines per pixel row. Horizontal coverage is computed exactly.
+const SUBSAMPLES: usize = 16;
+
+#[derive(Debug, Clone, Copy)]
+struct Edge {
+    x0: f64,
+    y0: f64,
+    x1: f64,
+    y1: f64,
+    /// +1 going down, -1 going up
+    winding: i32,
+}
+
+impl Edge {
+    fn new(x0: f64, y0: f64, x1: f64, y1: f64) -> Option<Self> {
+        if y0 == y1 {
+            return None;
+        }
+        if y0 < y1 {
+            Some(Self {
+                x0,
+                y0,
+                x1,
+                y1,
+                winding: 1,
+            })
+        } else {
+            Some(Self {
+                x0: x1,
+                y0: y1,
+                x1: x0,
+                y1: y0,
+                winding: -1,
+            })
+        }
+    }
+    fn x_at(&self, y: f64) -> f64 {
+        self.x0 + (self.x1 - self.x0) * (y - self.y0) / (self.y1 - self.y0)
+    }
+}
+
+/// Add a horizontal span [xa, xb) of weight `weight` to the coverage row starting at pixel `origin`.
+fn add_span(row: &mut [f64], origin: usize, xa: f64, xb: f64, weight: f64) {
+    let begin = origin as f64;
+    let end = (origin + row.len()) as f64;
+    let xa = xa.max(begin).min(end);
+    let xb = xb.max(begin).min(end);
+    if xb <= xa {
+        return;
+    }
+    let ia = xa.floor() as usize;
+    let ib = xb.floor() as usize;
+    if ia == ib {
+        row[ia - origin] += (xb - xa) * weight;
+        return;
+    }
+    row[ia - origin] += (ia as f64 + 1.0 - xa) * weight;
+    for cell in row.iter_mut().take(ib - origin).skip(ia + 1 - origin) {
+        *cell += weight;
+    }
+    if ib < origin + row.len() {
+        row[ib - origin] += (xb - ib as f64) * weight;
+    }
+}
+
+/// Anti-aliased coverage of `path` (mapped by `transform`), row by row inside `clip`.
+///   `emit(x, y, coverage)` is called for every touched pixel.
+pub fn rasterize(
+    path: &Path,
+    transform: &Transform,
+    rule: FillRule,
+    clip: &Rect,
+    mut emit: impl FnMut(usize, usize, f64),
+) {
+    let mut edges: Vec<Edge> = vec![];
+    let mut bounds: Option<Rect> = None;
+    for polyline in path.transform(transform).flatten(FLATTEN_TOLERANCE) {
+        let points = &polyline.points;
+        // Fills always close their sub-paths
+        for i in 0..points.len() {
+            let a = &points[i];
+            let b = &points[(i + 1) % points.len()];
+            if let Some(edge) = Edge::new(a.left, a.top, b.left, b.top) {
+                edges.push(edge);
+            }
+            let point = Rect::new(a.left, a.top, 0.0, 0.0);
+            bounds = Some(bounds.map_or(point, |r| {
+                let left = r.left().min(a.left);
+                let top = r.top().min(a.top);
+                Rect::new(left, top, r.right().max(a.left) - left, r.bottom().max(a.top) - top)
+            }));
+        }
+    }
+    let bounds = match bounds {
+        Some(bounds) => bounds,
+        None => return,
+    };
+    // Grow by one pixel so zero sized bounds still intersect
+    let bounds = Rect::new(
+        bounds.left().floor(),
+        bounds.top().floor(),
+        bounds.width().ceil() + 1.0,
+        bounds.height().ceil() + 1.0,
+    );
+    let area = match bounds.get_intersection_rect(clip) {
+        Some(area) => area,
+        None => return,
+    };
+    let x_begin = area.left().max(0.0).floor() as usize;
+    let x_end = area.right().max(0.0).ceil() as usize;
+    let y_begin = area.top().max(0.0).floor() as usize;
+    let y_end = area.bottom().max(0.0).ceil() as usize;
+    if x_begin >= x_end {
+        return;
+    }
+
+    edges.sort_by(|a, b| a.y0.total_cmp(&b.y0));
+    let mut next_edge = 0;
+    let mut active: Vec<Edge> = vec![];
+    let mut row = vec![0.0; x_end - x_begin];
+    let mut crossings: Vec<(f64, i32)> = vec![];
+    let weight = 1.0 / SUBSAMPLES as f64;
+
+    for y in y_begin..y_end {
+        let row_bottom = (y + 1) as f64;
+        while next_edge < edges.len() && edges[next_edge].y0 < row_bottom {
+            active.push(edges[next_edge]);
+            next_edge += 1;
+        }
+        active.retain(|e| e.y1 > y as f64);
+        if active.is_empty() {
+            continue;
+        }
+        row.iter_mut().for_each(|c| *c = 0.0);
+
+        for sub in 0..SUBSAMPLES {
+            let sy = y as f64 + (sub as f64 + 0.5) * weight;
+            crossings.clear();
+            for edge in &active {
+                if sy >= edge.y0 && sy < edge.y1 {
+                    crossings.push((edge.x_at(sy), edge.winding));
+                }
+            }
+            crossings.sort_by(|a, b| a.0.total_cmp(&b.0));
+            let mut winding = 0;
+            for i in 0..crossings.len() {
+                winding += crossings[i].1;
+                let inside = match rule {
+                    FillRule::NonZero => winding != 0,
+                    FillRule::EvenOdd => winding % 2 != 0,
+                };
+                if inside && i + 1 < crossings.len() {
+                    add_span(&mut row, x_begin, crossings[i].0, crossings[i + 1].0, weight);
+                }
+            }
+        }
+
+        for (i, coverage) in row.iter().enumerate() {
+            if *coverage > 0.0 {
+                emit(x_begin + i, y, coverage.min(1.0));
+            }
+        }
+    }
+}
+
+/// Fill `path` with `color`, compositing over what is already painted.
+pub fn fill_path(
+    surface: &mut Surface,
+    path: &Path,
+    transform: &Transform,
+    rule: FillRule,
+    color: Color,
+    clip: &Rect,
+) {
+    let surface_rect = Rect::from_u32(0, 0, surface.width as u32, surface.height as u32);
+    let clip = match clip.get_intersection_rect(&surface_rect) {
+        Some(clip) => clip,
+        None => return,
+    };
+    let rgba = color.as_u8_ref();
+    rasterize(path, transform, rule, &clip, |x, y, coverage| {
+        surface.blend(x, y, &rgba, coverage)
+    });
+}
+
+/// Stroke `path` with `color`. The stroke width is in path units, so it is scaled by `transform` too.
+pub fn stroke_path(
+    surface: &mut Surface,
+    path: &Path,
+    transform: &Transform,
+    style: &StrokeStyle,
+    color: Color,
+    clip: &Rect,
+) {
+    fill_path(
+        surface,
+        &path.stroke(style),
+        transform,
+        FillRule::NonZero,
+        color,
+        clip,
+    );
+}
+
+/// Paint `bitmap`, whose top left corner is at `origin` before transforming, mapped by `transform`.
+///   Edges are anti-aliased from the coverage of the mapped quad, the inside is sampled through the inverse mapping.
+pub fn draw_transformed(
+    surface: &mut Surface,
+    bitmap: &Bitmap,
+    origin: &Position,
+    transform: &Transform3D,
+    clip: &Rect,
+) {
+    if bitmap.width == 0 || bitmap.height == 0 {
+        return;
+    }
+    let source = Rect::new(origin.left, origin.top, bitmap.width as f64, bitmap.height as f64);
+    let quad = match transform.map_quad(&source) {
+        Some(quad) => quad,
+        None => {
+            fixme!("Clip transformed quads crossing the viewer plane");
+            return;
+        }
+    };
+    let inverse = match transform.inverse() {
+        Some(inverse) => inverse,
+        // Flattened to a line or a point: nothing to paint
+        None => return,
+    };
+    let surface_rect = Rect::from_u32(0, 0, surface.width as u32, surface.height as u32);
+    let clip = match clip.get_intersection_rect(&surface_rect) {
+        Some(clip) => clip,
+        None => return,
+    };
+    let mut path = Path::new();
+    path.move_to(quad[0].left, quad[0].top)
+        .line_to(quad[1].left, quad[1].top)
+        .line_to(quad[2].left, quad[2].top)
+        .line_to(quad[3].left, quad[3].top)
+        .close();
+    let is_affine = transform.to_2d().is_some();
+    rasterize(&path, &Transform::IDENTITY, FillRule::NonZero, &clip, |x, y, coverage| {
+        let center = Position {
+            left: x as f64 + 0.5,
+            top: y as f64 + 0.5,
+        };
+        let local = if is_affine {
+            inverse.map_point(&center)
+        } else {
+            transform.unproject_point(&center)
+        };
+        if let Some(local) = local {
+            let color = bitmap.sample(local.left - origin.left, local.top - origin.top, ScaleFilter::Bilinear);
+            surface.blend(x, y, &color, coverage);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use assert2::check;
+
+    use super::super::path::{LineCap, LineJoin};
+    use super::*;
+
+    fn coverage_map(path: &Path, transform: &Transform, rule: FillRule, size: usize) -> Vec<f64> {
+        let mut map = vec![0.0; size * size];
+        let clip = Rect::from_u32(0, 0, size as u32, size as u32);
+        rasterize(path, transform, rule, &clip, |x, y, c| map[y * size + x] = c);
+        map
+    }
+
+    fn total(map: &[f64]) -> f64 {
+        map.iter().sum()
+    }
+
+    #[test]
+    fn fill_rect_with_half_pixels() {
+        let path = Path::rect(&Rect::new(1.5, 1.0, 3.0, 2.0));
+        let map = coverage_map(&path, &Transform::IDENTITY, FillRule::NonZero, 8);
+        check!((map[8 + 1] - 0.5).abs() < 1e-9);
+        check!((map[8 + 2] - 1.0).abs() < 1e-9);
+        check!((map[8 + 4] - 0.5).abs() < 1e-9);
+        check!(map[0] == 0.0);
+        check!((total(&map) - 6.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn triangle_area() {
+        let mut path = Path::new();
+        path.move_to(0.0, 0.0).line_to(10.0, 0.0).line_to(0.0, 10.0).close();
+        let map = coverage_map(&path, &Transform::IDENTITY, FillRule::NonZero, 12);
+        check!((total(&map) - 50.0).abs() < 0.1);
+
+        // Edges of NaN, as from a degenerate transform, are ordered too
+        let mut path = Path::new();
+        path.move_to(0.0, 0.0).line_to(f64::NAN, 5.0).line_to(0.0, 10.0).close();
+        coverage_map(&path, &Transform::IDENTITY, FillRule::NonZero, 12);
+    }
+
+    #[test]
+    fn fill_rules() {
+        let mut path = Path::rect(&Rect::from_u32(0, 0, 10, 10));
+        path.commands
+            .extend(Path::rect(&Rect::from_u32(3, 3, 4, 4)).commands);
+        let non_zero = coverage_map(&path, &Transform::IDENTITY, FillRule::NonZero, 10);
+        let even_odd = coverage_map(&path, &Transform::IDENTITY, FillRule::EvenOdd, 10);
+        check!(non_zero[5 * 10 + 5] == 1.0);
+        check!(even_odd[5 * 10 + 5] == 0.0);
+        check!((total(&even_odd) - 84.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn circle_and_transform() {
+        // Flattening keeps the polygon inside the circle, within FLATTEN_TOLERANCE
+        let circle = Path::circle(10.0, 10.0, 8.0);
+        let map = coverage_map(&circle, &Transform::IDENTITY, FillRule::NonZero, 40);
+        let area = std::f64::consts::PI * 64.0;
+        check!(total(&map) < area);
+        check!(total(&map) > area * 0.98);
+        let doubled = coverage_map(&circle, &Transform::scale(2.0, 2.0), FillRule::NonZero, 40);
+        check!(total(&doubled) < area * 4.0);
+        check!(total(&doubled) > area * 4.0 * 0.98);
+    }
+
+    #[test]
+    fn stroke_line() {
+        let mut path = Path::new();
+        path.move_to(2.0, 5.0).line_to(12.0, 5.0);
+        let style = StrokeStyle::new(2.0);
+        let map = coverage_map(&path.stroke(&style), &Transform::IDENTITY, FillRule::NonZero, 16);
+        check!((total(&map) - 20.0).abs() < 1e-6);
+        check!(map[4 * 16 + 5] == 1.0);
+        check!(map[6 * 16 + 5] == 0.0);
+
+        let mut square = style.clone();
+        square.cap = LineCap::Square;
+        let map = coverage_map(&path.stroke(&square), &Transform::IDENTITY, FillRule::NonZero, 16);
+        check!((total(&map) - 24.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn stroke_joins_do_not_cancel() {
+        let mut path = Path::new();
+        path.move_to(2.0, 2.0).line_to(12.0, 2.0).line_to(12.0, 12.0);
+        for join in [LineJoin::Miter, LineJoin::Round, LineJoin::Bevel] {
+            let mut style = StrokeStyle::new(2.0);
+            style.join = join;
+            let map = coverage_map(&path.stroke(&style), &Transform::IDENTITY, FillRule::NonZero, 16);
+            // The inner corner is covered by both segments
+            check!(map[2 * 16 + 11] == 1.0);
+            check!(map.iter().all(|c| *c <= 1.0));
+        }
+        let mut miter = StrokeStyle::new(2.0);
+        miter.join = LineJoin::Miter;
+        let map = coverage_map(&path.stroke(&miter), &Transform::IDENTITY, FillRule::NonZero, 16);
+        // Miter fills the outer corner square
+        check!(map[16 + 12] == 1.0);
+    }
+
+    #[test]
+    fn dashed_stroke() {
+        let mut path = Path::new();
+        path.move_to(0.0, 5.0).line_to(16.0, 5.0);
+        let mut style = StrokeStyle::new(2.0);
+        style.dashes = vec![4.0, 4.0];
+        let map = coverage_map(&path.stroke(&style), &Transform::IDENTITY, FillRule::NonZero, 16);
+        check!((total(&map) - 16.0).abs() < 1e-6);
+        check!(map[5 * 16 + 1] == 1.0);
+        check!(map[5 * 16 + 5] == 0.0);
+    }
+
+    #[test]
+    fn transformed_bitmap() {
+        let bitmap = Bitmap::new(2, 2, [255, 0, 0, 255].repeat(4)).unwrap();
+        let mut data = vec![0; 8 * 8 * 4];
+        let mut surface = Surface::new(&mut data, 8, 8);
+        let transform: Transform3D = Transform::scale(2.0, 2.0).into();
+        let clip = Rect::from_u32(0, 0, 8, 8);
+        draw_transformed(&mut surface, &bitmap, &Position { left: 1.0, top: 1.0 }, &transform, &clip);
+        // The 2x2 bitmap at (1, 1) covers (2, 2)-(6, 6) once scaled
+        check!(surface.pixel(2, 2) == [255, 0, 0, 255]);
+        check!(surface.pixel(5, 5) == [255, 0, 0, 255]);
+        check!(surface.pixel(6, 6) == [0, 0, 0, 0]);
+        check!(surface.pixel(1, 3) == [0, 0, 0, 0]);
+    }
+}
@@ -0,0 +1,370 @@
+#![allow(dead_code)]
+#![allow(unused_imports)]
+
+use log::{debug, error, info, warn};
+
+use super::geometry::{Position, Rect};
+
+/// Horizontal strip of a region: `spans` are sorted, disjoint and not touching `[left, right)` pairs.
+#[derive(Debug, Clone, PartialEq)]
+struct Band {
+    top: f64,
+    bottom: f64,
+    spans: Vec<(f64, f64)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum RegionOp {
+    Union,
+    Intersect,
+    Subtract,
+}
+
+impl RegionOp {
+    fn apply(&self, a: bool, b: bool) -> bool {
+        match self {
+            RegionOp::Union => a || b,
+            RegionOp::Intersect => a && b,
+            RegionOp::Subtract => a && !b,
+        }
+    }
+}
+
+/// An area made of y-x banded rectangles, as X11 and pixman regions.
+///   Bands are sorted top to bottom and never overlap; two touching bands never have the same spans,
+///   so every area has exactly one representation.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Region {
+    bands: Vec<Band>,
+}
+
+impl Region {
+    pub fn new() -> Self {
+        Self { bands: vec![] }
+    }
+
+    pub fn from_rect(rect: &Rect) -> Self {
+        if rect.width() <= 0.0 || rect.height() <= 0.0 {
+            return Self::new();
+        }
+        Self {
+            bands: vec![Band {
+                top: rect.top(),
+                bottom: rect.bottom(),
+                spans: vec![(rect.left(), rect.right())],
+            }],
+        }
+    }
+
+    pub fn from_rects(rects: &[Rect]) -> Self {
+        rects
+            .iter()
+            .fold(Self::new(), |region, rect| region.union(&Self::from_rect(rect)))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bands.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.bands.clear();
+    }
+
+    /// Non overlapping rectangles covering the region, top to bottom and left to right.
+    pub fn rects(&self) -> Vec<Rect> {
+        self.bands
+            .iter()
+            .flat_map(|band| {
+                band.spans.iter().map(move |(left, right)| {
+                    Rect::new(*left, band.top, right - left, band.bottom - band.top)
+                })
+            })
+            .collect()
+    }
+
+    /// Number of rectangles returned by `rects`.
+    pub fn len(&self) -> usize {
+        self.bands.iter().map(|band| band.spans.len()).sum()
+    }
+
+    pub fn bounds(&self) -> Option<Rect> {
+        let first = self.bands.first()?;
+        let last = self.bands.last()?;
+        let left = self
+            .bands
+            .iter()
+            .map(|band| band.spans[0].0)
+            .fold(f64::MAX, f64::min);
+        let right = self
+            .bands
+            .iter()
+            .map(|band| band.spans[band.spans.len() - 1].1)
+            .fold(f64::MIN, f64::max);
+        Some(Rect::new(left, first.top, right - left, last.bottom - first.top))
+    }
+
+    pub fn area(&self) -> f64 {
+        self.bands
+            .iter()
+            .map(|band| {
+                let width: f64 = band.spans.iter().map(|(left, right)| right - left).sum();
+                width * (band.bottom - band.top)
+            })
+            .sum()
+    }
+
+    pub fn contains_point(&self, pt: &Position) -> bool {
+        self.band_at(pt.top).is_some_and(|band| {
+            band.spans
+                .iter()
+                .any(|(left, right)| pt.left >= *left && pt.left < *right)
+        })
+    }
+
+    pub fn translate(&self, offset: &Position) -> Region {
+        Self {
+            bands: self
+                .bands
+                .iter()
+                .map(|band| Band {
+                    top: band.top + offset.top,
+                    bottom: band.bottom + offset.top,
+                    spans: band
+                        .spans
+                        .iter()
+                        .map(|(left, right)| (left + offset.left, right + offset.left))
+                        .collect(),
+                })
+                .collect(),
+        }
+    }
+
+    pub fn union(&self, other: &Region) -> Region {
+        self.combine(other, RegionOp::Union)
+    }
+    pub fn intersect(&self, other: &Region) -> Region {
+        self.combine(other, RegionOp::Intersect)
+    }
+    pub fn subtract(&self, other: &Region) -> Region {
+        self.combine(other, RegionOp::Subtract)
+    }
+
+    pub fn union_rect(&mut self, rect: &Rect) {
+        *self = self.union(&Self::from_rect(rect));
+    }
+    pub fn intersect_rect(&mut self, rect: &Rect) {
+        *self = self.intersect(&Self::from_rect(rect));
+    }
+    pub fn subtract_rect(&mut self, rect: &Rect) {
+        *self = self.subtract(&Self::from_rect(rect));
+    }
+
+    fn band_at(&self, y: f64) -> Option<&Band> {
+        let index = self.bands.partition_point(|band| band.bottom <= y);
+        self.bands.get(index).filter(|band| band.top <= y)
+    }
+
+    /// Walk every horizontal strip where neither region changes, combining the spans of both.
+    fn combine(&self, other: &Region, op: RegionOp) -> Region {
+        let mut edges: Vec<f64> = self
+            .bands
+            .iter()
+            .chain(other.bands.iter())
+            .flat_map(|band| [band.top, band.bottom])
+            .collect();
+        edges.sort_by(|a, b| a.total_cmp(b));
+        edges.dedup();
+
+        let mut result = Region::new();
+        let no_spans = vec![];
+        for pair in edges.windows(2) {
+            let (top, bottom) = (pair[0], pair[1]);
+            let a = self.band_at(top).map_or(&no_spans, |band| &band.spans);
+            let b = other.band_at(top).map_or(&no_spans, |band| &band.spans);
+            let spans = combine_spans(a, b, op);
+            if spans.is_empty() {
+                continue;
+            }
+            // Coalesce with the band above when nothing changes across the edge
+            if let Some(last) = result.bands.last_mut() {
+                if last.bottom == top && last.spans == spans {
+                    last.bottom = bottom;
+                    continue;
+                }
+            }
+            result.bands.push(Band { top, bottom, spans });
+        }
+        result
+    }
+}
+
+fn combine_spans(a: &[(f64, f64)], b: &[(f64, f64)], op: RegionOp) -> Vec<(f64, f64)> {
+    let mut edges: Vec<f64> = a
+        .iter()
+        .chain(b.iter())
+        .flat_map(|(left, right)| [*left, *right])
+        .collect();
+    edges.sort_by(|x, y| x.total_cmp(y));
+    edges.dedup();
+
+    let inside = |spans: &[(f64, f64)], x: f64| spans.iter().any(|(left, right)| x >= *left && x < *right);
+    let mut spans: Vec<(f64, f64)> = vec![];
+    for pair in edges.windows(2) {
+        let (left, right) = (pair[0], pair[1]);
+        if !op.apply(inside(a, left), inside(b, left)) {
+            continue;
+        }
+        match spans.last_mut() {
+            Some(last) if last.1 == left => last.1 = right,
+            _ => spans.push((left, right)),
+        }
+    }
+    spans
+}
+
+#[cfg(test)]
+mod tests {
+    use assert2::check;
+
+    use super::*;
+
+    const GRID: usize = 24;
+
+    /// Small xorshift generator, so the property tests are reproducible without extra dependencies.
+    struct XorShift(u64);
+
+    impl XorShift {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+        fn below(&mut self, n: usize) -> usize {
+            (self.next() % n as u64) as usize
+        }
+        fn rect(&mut self) -> Rect {
+            let left = self.below(GRID);
+            let top = self.below(GRID);
+            let width = self.below(GRID - left + 1);
+            let height = self.below(GRID - top + 1);
+            Rect::new(left as f64, top as f64, width as f64, height as f64)
+        }
+    }
+
+    fn mask_of(rect: &Rect) -> Vec<bool> {
+        (0..GRID * GRID)
+            .map(|i| rect.contains_point_f64((i % GRID) as f64, (i / GRID) as f64))
+            .collect()
+    }
+
+    fn check_matches(region: &Region, mask: &[bool]) {
+        for (i, inside) in mask.iter().enumerate() {
+            let pt = Position {
+                left: (i % GRID) as f64 + 0.5,
+                top: (i / GRID) as f64 + 0.5,
+            };
+            check!(region.contains_point(&pt) == *inside, "pixel {}", i);
+        }
+        check!(region.area() == mask.iter().filter(|m| **m).count() as f64);
+    }
+
+    fn check_invariants(region: &Region) {
+        for band in &region.bands {
+            check!(band.top < band.bottom);
+            check!(!band.spans.is_empty());
+            for span in &band.spans {
+                check!(span.0 < span.1);
+            }
+            for pair in band.spans.windows(2) {
+                check!(pair[0].1 < pair[1].0);
+            }
+        }
+        for pair in region.bands.windows(2) {
+            check!(pair[0].bottom <= pair[1].top);
+            check!(!(pair[0].bottom == pair[1].top && pair[0].spans == pair[1].spans));
+        }
+    }
+
+    #[test]
+    fn random_operations_match_pixel_mask() {
+        let mut rng = XorShift(0x2545_f491_4f6c_dd1d);
+        for _ in 0..200 {
+            let mut region = Region::new();
+            let mut mask = vec![false; GRID * GRID];
+            for _ in 0..8 {
+                let rect = rng.rect();
+                let rect_mask = mask_of(&rect);
+                match rng.below(3) {
+                    0 => {
+                        region.union_rect(&rect);
+                        mask.iter_mut().zip(&rect_mask).for_each(|(m, r)| *m |= *r);
+                    }
+                    1 => {
+                        region.subtract_rect(&rect);
+                        mask.iter_mut().zip(&rect_mask).for_each(|(m, r)| *m &= !*r);
+                    }
+                    _ => {
+                        // Intersect with a large rectangle, so regions don't vanish too often
+                        let rect = rect.union(&rng.rect());
+                        let rect_mask = mask_of(&rect);
+                        region.intersect_rect(&rect);
+                        mask.iter_mut().zip(&rect_mask).for_each(|(m, r)| *m &= *r);
+                    }
+                }
+                check_invariants(&region);
+                check_matches(&region, &mask);
+                // Rebuilding from the rects gives back the same canonical region
+                check!(Region::from_rects(&region.rects()) == region);
+            }
+        }
+    }
+
+    #[test]
+    fn region_of_region_operations() {
+        let mut rng = XorShift(0x9e37_79b9_7f4a_7c15);
+        for _ in 0..100 {
+            let a = Region::from_rects(&[rng.rect(), rng.rect(), rng.rect()]);
+            let b = Region::from_rects(&[rng.rect(), rng.rect(), rng.rect()]);
+            let union = a.union(&b);
+            let intersection = a.intersect(&b);
+            check_invariants(&union);
+            check_invariants(&intersection);
+            check!(union.area() == a.area() + b.area() - intersection.area());
+            check!(union.subtract(&b) == a.subtract(&b));
+            check!(a.subtract(&b).intersect(&b).is_empty());
+        }
+    }
+
+    #[test]
+    fn bounds_translate_and_coalesce() {
+        let region = Region::from_rects(&[
+            Rect::from_u32(0, 0, 4, 4),
+            Rect::from_u32(2, 2, 4, 4),
+            Rect::from_u32(0, 2, 3, 3),
+        ]);
+        let rects = region.rects();
+        check!(rects.len() == 3);
+        check!(rects[0] == (0, 0, 4, 2));
+        // (0,2)-(6,4) and (0,4)-(6,5) coalesce in one band
+        check!(rects[1] == (0, 2, 6, 3));
+        check!(rects[2] == (2, 5, 4, 1));
+        check!(region.bounds().unwrap() == (0, 0, 6, 6));
+
+        let moved = region.translate(&Position { left: 10.0, top: -2.0 });
+        check!(moved.bounds().unwrap() == (10, -2, 6, 6));
+        check!(moved.contains_point(&Position { left: 10.0, top: -2.0 }));
+        check!(!moved.contains_point(&Position { left: 10.0, top: 3.0 }));
+
+        // Two halves merge back into one rectangle
+        let halves = Region::from_rects(&[Rect::from_u32(0, 0, 5, 10), Rect::from_u32(5, 0, 5, 10)]);
+        check!(halves.len() == 1);
+        check!(Region::from_rect(&Rect::from_u32(3, 3, 0, 5)).is_empty());
+        check!(Region::new().bounds().is_none());
+
+        // Damage of NaN, as from a degenerate transform, doesn't panic
+        let mut region = Region::from_rect(&Rect::from_u32(0, 0, 4, 4));
+        region.union_rect(&Rect::new(f64::NAN, 0.0, 4.0, 4.0));
+        region.union_rect(&Rect::new(0.0, f64::NAN, 4.0, 4.0));
+    }
+}
@@ -0,0 +1,288 @@
+#![allow(dead_code)]
+#![allow(unused_imports)]
+
+use log::{debug, error, info, warn};
+use url::Url;
+
+use super::geometry::Position;
+use super::simple_error::SimpleError;
+
+/// One step of the history of a tab.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HistoryEntry {
+    pub url: Url,
+    /// Scroll position when the entry was left, restored when coming back
+    pub scroll: Position,
+    /// `history.state`, serialized
+    pub state: Option<String>,
+    /// Entries made by fragment navigation or `pushState` share the document of the entry they come from
+    document_id: u64,
+}
+
+/// The history of a tab, with a back-forward cache keeping the documents left behind alive.
+///   `D` is the document type.
+#[derive(Debug)]
+pub struct SessionHistory<D> {
+    entries: Vec<HistoryEntry>,
+    index: usize,
+    next_document_id: u64,
+    /// Documents by id, least recently cached first
+    bfcache: Vec<(u64, D)>,
+    bfcache_capacity: usize,
+}
+
+impl<D> SessionHistory<D> {
+    /// Keep at most `bfcache_capacity` documents for instant back and forward. Zero disables the cache.
+    pub fn new(bfcache_capacity: usize) -> Self {
+        Self {
+            entries: vec![],
+            index: 0,
+            next_document_id: 0,
+            bfcache: vec![],
+            bfcache_capacity,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    pub fn entry(&self, index: usize) -> Option<&HistoryEntry> {
+        self.entries.get(index)
+    }
+
+    pub fn current(&self) -> Option<&HistoryEntry> {
+        self.entries.get(self.index)
+    }
+
+    pub fn can_go_back(&self) -> bool {
+        self.index > 0
+    }
+
+    pub fn can_go_forward(&self) -> bool {
+        self.index + 1 < self.entries.len()
+    }
+
+    /// Index `delta` entries away from the current one, if it exists.
+    pub fn offset(&self, delta: isize) -> Option<usize> {
+        let index = self.index as isize + delta;
+        if index < 0 || index >= self.entries.len() as isize {
+            return None;
+        }
+        Some(index as usize)
+    }
+
+    /// Remember where the current entry is scrolled, before leaving it.
+    pub fn set_scroll(&mut self, scroll: Position) {
+        if let Some(entry) = self.entries.get_mut(self.index) {
+            entry.scroll = scroll;
+        }
+    }
+
+    /// Drop the entries after the current one, with their cached documents.
+    fn truncate_forward(&mut self) {
+        if self.entries.is_empty() {
+            return;
+        }
+        self.entries.truncate(self.index + 1);
+        let entries = &self.entries;
+        self.bfcache
+            .retain(|(id, _)| entries.iter().any(|entry| entry.document_id == *id));
+    }
+
+    fn append(&mut self, url: Url, state: Option<String>, document_id: u64) {
+        self.truncate_forward();
+        self.entries.push(HistoryEntry {
+            url,
+            scroll: Position { left: 0.0, top: 0.0 },
+            state,
+            document_id,
+        });
+        self.index = self.entries.len() - 1;
+    }
+
+    /// Navigation to a new document.
+    pub fn push(&mut self, url: Url) {
+        let document_id = self.next_document_id;
+        self.next_document_id += 1;
+        self.append(url, None, document_id);
+    }
+
+    /// Fragment navigation and `history.pushState`: a new entry for the current document.
+    ///   `url` must have the origin of the current entry.
+    pub fn push_same_document(&mut self, url: Url, state: Option<String>) -> Result<(), SimpleError> {
+        let current = self
+            .current()
+            .ok_or_else(|| SimpleError::new("No document to push a state for"))?;
+        check_same_origin(&current.url, &url)?;
+        let document_id = current.document_id;
+        self.append(url, state, document_id);
+        Ok(())
+    }
+
+    /// `history.replaceState`: change the URL and state of the current entry.
+    pub fn replace_current(&mut self, url: Url, state: Option<String>) -> Result<(), SimpleError> {
+        let current = self
+            .entries
+            .get_mut(self.index)
+            .ok_or_else(|| SimpleError::new("No document to replace the state of"))?;
+        check_same_origin(&current.url, &url)?;
+        current.url = url;
+        current.state = state;
+        Ok(())
+    }
+
+    /// True if going to `index` keeps the current document, as between fragments and pushed states.
+    pub fn is_same_document(&self, index: usize) -> bool {
+        match (self.current(), self.entries.get(index)) {
+            (Some(current), Some(entry)) => current.document_id == entry.document_id,
+            _ => false,
+        }
+    }
+
+    /// Make `index` the current entry. The caller swaps the documents.
+    pub fn go_to(&mut self, index: usize) {
+        if index < self.entries.len() {
+            self.index = index;
+        }
+    }
+
+    /// Keep the document of the current entry, which is being left, for a later traversal.
+    pub fn cache_current(&mut self, document: D) {
+        if self.bfcache_capacity == 0 {
+            return;
+        }
+        let id = match self.current() {
+            Some(entry) => entry.document_id,
+            None => return,
+        };
+        self.bfcache.retain(|(cached, _)| *cached != id);
+        self.bfcache.push((id, document));
+        while self.bfcache.len() > self.bfcache_capacity {
+            let (evicted, _) = self.bfcache.remove(0);
+            debug!("Evict document {} from the back-forward cache", evicted);
+        }
+    }
+
+    /// The cached document of the entry at `index`, if still alive.
+    pub fn take_cached(&mut self, index: usize) -> Option<D> {
+        let id = self.entries.get(index)?.document_id;
+        let position = self.bfcache.iter().position(|(cached, _)| *cached == id)?;
+        Some(self.bfcache.remove(position).1)
+    }
+
+    pub fn cached_count(&self) -> usize {
+        self.bfcache.len()
+    }
+}
+
+fn check_same_origin(current: &Url, url: &Url) -> Result<(), SimpleError> {
+    if current.origin() != url.origin() && current.scheme() != "file" {
+        return Err(SimpleError::new(
+            format!("{} cannot be pushed to the history of {}", url, current).as_str(),
+        ));
+    }
+    Ok(())
+}
+
+/// True if going from `current` to `target` only changes the fragment, so the document stays.
+pub fn is_fragment_navigation(current: &Url, target: &Url) -> bool {
+    if target.fragment().is_none() {
+        return false;
+    }
+    let mut current = current.clone();
+    let mut target = target.clone();
+    current.set_fragment(None);
+    target.set_fragment(None);
+    current == target
+}
+
+#[cfg(test)]
+mod tests {
+    use assert2::check;
+
+    use super::*;
+
+    fn url(text: &str) -> Url {
+        Url::parse(text).unwrap()
+    }
+
+    #[test]
+    fn back_and_forward() {
+        let mut history: SessionHistory<&str> = SessionHistory::new(2);
+        check!(!history.can_go_back());
+        history.push(url("https://a.com/"));
+        history.push(url("https://b.com/"));
+        history.push(url("https://c.com/"));
+        check!(history.index() == 2);
+        check!(history.offset(-2) == Some(0));
+        check!(history.offset(1) == None);
+        history.go_to(0);
+        check!(history.can_go_forward());
+        check!(history.current().unwrap().url.host_str() == Some("a.com"));
+        // A new navigation drops the forward entries
+        history.push(url("https://d.com/"));
+        check!(history.len() == 2);
+        check!(!history.can_go_forward());
+    }
+
+    #[test]
+    fn same_document_entries() {
+        let mut history: SessionHistory<&str> = SessionHistory::new(2);
+        history.push(url("https://a.com/page"));
+        history.push_same_document(url("https://a.com/page#part"), None).unwrap();
+        history
+            .push_same_document(url("https://a.com/other"), Some(String::from("{\"n\":1}")))
+            .unwrap();
+        check!(history.len() == 3);
+        check!(history.is_same_document(0));
+        check!(history.push_same_document(url("https://b.com/"), None).is_err());
+        history.replace_current(url("https://a.com/replaced"), None).unwrap();
+        check!(history.current().unwrap().url.path() == "/replaced");
+        check!(history.current().unwrap().state == None);
+        history.push(url("https://a.com/next"));
+        check!(!history.is_same_document(0));
+    }
+
+    #[test]
+    fn bfcache_keeps_documents_left() {
+        let mut history = SessionHistory::new(2);
+        history.push(url("https://a.com/"));
+        history.cache_current("a");
+        history.push(url("https://b.com/"));
+        history.push_same_document(url("https://b.com/#x"), None).unwrap();
+        history.cache_current("b");
+        history.push(url("https://c.com/"));
+        history.cache_current("c");
+        // Capacity 2: `a` was evicted
+        check!(history.cached_count() == 2);
+        check!(history.take_cached(0) == None);
+        check!(history.take_cached(1) == Some("b"));
+        check!(history.take_cached(1) == None);
+        // Going back then navigating drops the cached forward documents
+        history.go_to(0);
+        history.push(url("https://d.com/"));
+        check!(history.cached_count() == 0);
+
+        let mut disabled = SessionHistory::new(0);
+        disabled.push(url("https://a.com/"));
+        disabled.cache_current("a");
+        check!(disabled.cached_count() == 0);
+    }
+
+    #[test]
+    fn fragment_navigation() {
+        check!(is_fragment_navigation(&url("https://a.com/p?q"), &url("https://a.com/p?q#f")));
+        check!(is_fragment_navigation(&url("https://a.com/p#e"), &url("https://a.com/p#f")));
+        check!(!is_fragment_navigation(&url("https://a.com/p#f"), &url("https://a.com/p")));
+        check!(!is_fragment_navigation(&url("https://a.com/p"), &url("https://a.com/q#f")));
+    }
+}
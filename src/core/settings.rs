@@ -0,0 +1,295 @@
+#![allow(dead_code)]
+#![allow(unused_imports)]
+
+use std::path::{Path, PathBuf};
+
+use log::{debug, error, info, warn};
+use url::Url;
+
+use super::proxy::Proxy;
+use super::simple_error::SimpleError;
+use super::zoom;
+
+/// Which cookies pages may set and read.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CookiePolicy {
+    AcceptAll,
+    RejectThirdParty,
+    RejectAll,
+}
+
+impl CookiePolicy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CookiePolicy::AcceptAll => "accept-all",
+            CookiePolicy::RejectThirdParty => "reject-third-party",
+            CookiePolicy::RejectAll => "reject-all",
+        }
+    }
+
+    fn parse(text: &str) -> Result<Self, SimpleError> {
+        match text {
+            "accept-all" => Ok(CookiePolicy::AcceptAll),
+            "reject-third-party" => Ok(CookiePolicy::RejectThirdParty),
+            "reject-all" => Ok(CookiePolicy::RejectAll),
+            _ => Err(SimpleError::new(
+                format!(
+                    "Invalid cookie policy {:?}: expected accept-all, reject-third-party or reject-all",
+                    text
+                )
+                .as_str(),
+            )),
+        }
+    }
+}
+
+/// Type of a setting, for `about:config`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SettingKind {
+    Bool,
+    Number,
+    String,
+    /// One of a few words
+    Choice(&'static [&'static str]),
+}
+
+/// Every setting, in `about:config` order.
+pub const SETTING_KEYS: [(&str, SettingKind); 11] = [
+    ("browser.cache.size", SettingKind::Number),
+    ("browser.home_page", SettingKind::String),
+    ("font.default", SettingKind::String),
+    ("font.size.default", SettingKind::Number),
+    ("font.size.minimum", SettingKind::Number),
+    ("font.size.monospace", SettingKind::Number),
+    ("javascript.enabled", SettingKind::Bool),
+    (
+        "network.cookie.policy",
+        SettingKind::Choice(&["accept-all", "reject-third-party", "reject-all"]),
+    ),
+    ("network.proxy", SettingKind::String),
+    ("network.proxy.bypass", SettingKind::String),
+    ("zoom.default", SettingKind::Number),
+];
+
+pub fn setting_kind(key: &str) -> Option<SettingKind> {
+    SETTING_KEYS.iter().find(|(k, _)| *k == key).map(|(_, kind)| *kind)
+}
+
+/// User preferences, saved in the profile as `key = value` lines for the settings changed from their default.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Settings {
+    /// Largest size of the HTTP cache, in MiB
+    pub cache_size: f64,
+    pub home_page: Url,
+    pub default_font_family: String,
+    /// Sizes in CSS pixels
+    pub default_font_size: f64,
+    pub minimum_font_size: f64,
+    pub monospace_font_size: f64,
+    pub javascript_enabled: bool,
+    pub cookie_policy: CookiePolicy,
+    /// Proxy URL, empty for the proxies of the environment
+    pub proxy: String,
+    /// Comma-separated hosts reached without proxy, as `no_proxy`
+    pub proxy_bypass: String,
+    /// Zoom of the sites without a remembered level
+    pub default_zoom: f64,
+    /// Incremented on every change, so documents can tell they must restyle
+    revision: u64,
+    path: Option<PathBuf>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            cache_size: 256.0,
+            home_page: Url::parse("about:blank").unwrap(),
+            default_font_family: String::from("sans-serif"),
+            default_font_size: 16.0,
+            minimum_font_size: 0.0,
+            monospace_font_size: 13.0,
+            javascript_enabled: true,
+            cookie_policy: CookiePolicy::RejectThirdParty,
+            proxy: String::new(),
+            proxy_bypass: String::from("localhost, 127.0.0.1, [::1]"),
+            default_zoom: 1.0,
+            revision: 0,
+            path: None,
+        }
+    }
+}
+
+impl Settings {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load from `path` and save there on every change. A missing file is the default settings.
+    pub fn load(path: &Path) -> Result<Self, SimpleError> {
+        let mut settings = Self::new();
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(err) => return Err(SimpleError::new(format!("{:?}: {}", path, err).as_str())),
+        };
+        for line in text.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let result = match line.split_once('=') {
+                Some((key, value)) => settings.set_value(key.trim(), value.trim()),
+                None => Err(SimpleError::new("Expected key = value")),
+            };
+            if let Err(err) = result {
+                warn!("Ignoring setting {:?} in {:?}: {}", line, path, err);
+            }
+        }
+        settings.path = Some(path.to_path_buf());
+        Ok(settings)
+    }
+
+    /// `cache_size` in bytes.
+    pub fn cache_size_bytes(&self) -> u64 {
+        (self.cache_size * 1024.0 * 1024.0) as u64
+    }
+
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    /// Value of `key` as shown in `about:config`.
+    pub fn get(&self, key: &str) -> Option<String> {
+        let value = match key {
+            "browser.cache.size" => self.cache_size.to_string(),
+            "browser.home_page" => String::from(self.home_page.as_str()),
+            "font.default" => self.default_font_family.clone(),
+            "font.size.default" => self.default_font_size.to_string(),
+            "font.size.minimum" => self.minimum_font_size.to_string(),
+            "font.size.monospace" => self.monospace_font_size.to_string(),
+            "javascript.enabled" => self.javascript_enabled.to_string(),
+            "network.cookie.policy" => String::from(self.cookie_policy.as_str()),
+            "network.proxy" => self.proxy.clone(),
+            "network.proxy.bypass" => self.proxy_bypass.clone(),
+            "zoom.default" => self.default_zoom.to_string(),
+            _ => return None,
+        };
+        Some(value)
+    }
+
+    pub fn is_default(&self, key: &str) -> bool {
+        self.get(key) == Settings::default().get(key)
+    }
+
+    /// Parse `value` for `key` and save the settings.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), SimpleError> {
+        self.set_value(key, value.trim())?;
+        info!("Setting {} = {}", key, value.trim());
+        self.revision += 1;
+        self.save()
+    }
+
+    /// Back to the default value.
+    pub fn reset(&mut self, key: &str) -> Result<(), SimpleError> {
+        let value = Settings::default()
+            .get(key)
+            .ok_or_else(|| SimpleError::new(format!("Unknown setting {}", key).as_str()))?;
+        self.set(key, &value)
+    }
+
+    fn set_value(&mut self, key: &str, value: &str) -> Result<(), SimpleError> {
+        let number = |min: f64, max: f64| match value.parse::<f64>() {
+            Ok(number) if number >= min && number <= max => Ok(number),
+            _ => Err(SimpleError::new(
+                format!("{} must be a number from {} to {}, found {:?}", key, min, max, value).as_str(),
+            )),
+        };
+        match key {
+            "browser.cache.size" => self.cache_size = number(0.0, 16384.0)?,
+            "browser.home_page" => {
+                self.home_page = Url::parse(value).map_err(|err| {
+                    SimpleError::new(format!("{} must be a URL, found {:?}: {}", key, value, err).as_str())
+                })?
+            }
+            "font.default" if !value.is_empty() => self.default_font_family = String::from(value),
+            "font.size.default" => self.default_font_size = number(1.0, 72.0)?,
+            "font.size.minimum" => self.minimum_font_size = number(0.0, 72.0)?,
+            "font.size.monospace" => self.monospace_font_size = number(1.0, 72.0)?,
+            "javascript.enabled" => {
+                self.javascript_enabled = value.parse().map_err(|_| {
+                    SimpleError::new(format!("{} must be true or false, found {:?}", key, value).as_str())
+                })?
+            }
+            "network.cookie.policy" => self.cookie_policy = CookiePolicy::parse(value)?,
+            "network.proxy" => {
+                if !value.is_empty() {
+                    Proxy::parse(value)?;
+                }
+                self.proxy = String::from(value);
+            }
+            "network.proxy.bypass" => self.proxy_bypass = String::from(value),
+            "zoom.default" => {
+                let levels = &zoom::ZOOM_LEVELS;
+                self.default_zoom = number(levels[0], levels[levels.len() - 1])?
+            }
+            "font.default" => return Err(SimpleError::new("font.default cannot be empty")),
+            _ => return Err(SimpleError::new(format!("Unknown setting {}", key).as_str())),
+        }
+        Ok(())
+    }
+
+    fn save(&self) -> Result<(), SimpleError> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        let text: String = SETTING_KEYS
+            .iter()
+            .filter(|(key, _)| !self.is_default(key))
+            .map(|(key, _)| format!("{} = {}\n", key, self.get(key).unwrap_or_default()))
+            .collect();
+        std::fs::write(path, text).map_err(|err| SimpleError::new(format!("{:?}: {}", path, err).as_str()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert2::check;
+
+    use super::*;
+
+    #[test]
+    fn typed_values() {
+        let mut settings = Settings::new();
+        check!(SETTING_KEYS.iter().all(|(key, _)| settings.get(key).is_some() && settings.is_default(key)));
+        settings.set("font.size.default", " 20 ").unwrap();
+        check!(settings.default_font_size == 20.0);
+        check!(settings.revision() == 1);
+        check!(!settings.is_default("font.size.default"));
+        check!(settings.set("font.size.default", "200").is_err());
+        check!(settings.set("javascript.enabled", "yes").is_err());
+        check!(settings.set("network.cookie.policy", "sometimes").is_err());
+        check!(settings.set("no.such.key", "1").is_err());
+        check!(settings.set("network.proxy", "ftp://proxy.example").is_err());
+        check!(settings.revision() == 1);
+        settings.set("javascript.enabled", "false").unwrap();
+        check!(!settings.javascript_enabled);
+        settings.set("network.cookie.policy", "reject-all").unwrap();
+        check!(settings.cookie_policy == CookiePolicy::RejectAll);
+        settings.reset("font.size.default").unwrap();
+        check!(settings.is_default("font.size.default"));
+    }
+
+    #[test]
+    fn persistence() {
+        let path = std::env::temp_dir().join(format!("rowser-settings-{}", std::process::id()));
+        std::fs::write(&path, "# comment\nzoom.default = 1.25\nbogus\nfont.size.default = huge\n").unwrap();
+        let mut settings = Settings::load(&path).unwrap();
+        check!(settings.default_zoom == 1.25);
+        check!(settings.default_font_size == 16.0);
+        settings.set("browser.home_page", "https://example.com/").unwrap();
+        let text = std::fs::read_to_string(&path).unwrap();
+        check!(text == "browser.home_page = https://example.com/\nzoom.default = 1.25\n");
+        check!(Settings::load(&path).unwrap().home_page.as_str() == "https://example.com/");
+        std::fs::remove_file(&path).unwrap();
+    }
+}
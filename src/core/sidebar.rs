@@ -0,0 +1,181 @@
+#![allow(dead_code)]
+#![allow(unused_imports)]
+
+use log::{debug, error, info, warn};
+use url::Url;
+
+use crate::fixme;
+
+use super::bookmarks::{BookmarkFolder, BookmarkItem};
+use super::box_painter::paint_box;
+use super::color::Color;
+use super::geometry::{Position, Rect};
+use super::gfxbuffer::Surface;
+use super::style::BoxStyle;
+use super::tab_strip::{ACTIVE_TAB_COLOR, ICON_COLOR};
+use super::text::{draw_text, elide};
+
+/// Sizes are in CSS pixels, like the tab strip.
+pub const SIDEBAR_WIDTH: f64 = 240.0;
+const HEADER_HEIGHT: f64 = 28.0;
+const ROW_HEIGHT: f64 = 22.0;
+const INDENT: f64 = 14.0;
+const PADDING: f64 = 8.0;
+const FONT_SIZE: f64 = 11.0;
+const HEADER_FONT_SIZE: f64 = 12.0;
+
+const SIDEBAR_COLOR: Color = Color {
+    r: 0x29,
+    g: 0x2a,
+    b: 0x2d,
+    a: 0xff,
+};
+const FOLDER_COLOR: Color = Color {
+    r: 0xfd,
+    g: 0xd6,
+    b: 0x63,
+    a: 0xff,
+};
+
+/// A line of the bookmarks sidebar: a folder, or a bookmark with its URL.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SidebarRow {
+    pub depth: usize,
+    pub title: String,
+    pub url: Option<Url>,
+}
+
+/// The bookmarks tree flattened to rows, folders before their content.
+pub fn bookmark_rows(root: &BookmarkFolder) -> Vec<SidebarRow> {
+    fn add_rows(folder: &BookmarkFolder, depth: usize, rows: &mut Vec<SidebarRow>) {
+        for child in folder.children.iter() {
+            match child {
+                BookmarkItem::Bookmark(bookmark) => rows.push(SidebarRow {
+                    depth,
+                    title: if bookmark.title.is_empty() {
+                        String::from(bookmark.url.as_str())
+                    } else {
+                        bookmark.title.clone()
+                    },
+                    url: Some(bookmark.url.clone()),
+                }),
+                BookmarkItem::Folder(child) => {
+                    rows.push(SidebarRow {
+                        depth,
+                        title: child.title.clone(),
+                        url: None,
+                    });
+                    add_rows(child, depth + 1, rows);
+                }
+            }
+        }
+    }
+    let mut rows = vec![];
+    add_rows(root, 0, &mut rows);
+    rows
+}
+
+/// The bookmarks sidebar, on the left of the document, in CSS pixels of the window.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SidebarLayout {
+    pub bounds: Rect,
+}
+
+impl SidebarLayout {
+    pub fn new(top: f64, height: f64) -> Self {
+        Self {
+            bounds: Rect::new(0.0, top, SIDEBAR_WIDTH, height.max(0.0)),
+        }
+    }
+
+    fn row_bounds(&self, index: usize) -> Rect {
+        Rect::new(
+            self.bounds.left(),
+            self.bounds.top() + HEADER_HEIGHT + index as f64 * ROW_HEIGHT,
+            self.bounds.width(),
+            ROW_HEIGHT,
+        )
+    }
+
+    /// Index of the row under `pt`, among `count`.
+    pub fn row_at(&self, count: usize, pt: &Position) -> Option<usize> {
+        if !self.bounds.contains_point(pt) {
+            return None;
+        }
+        let offset = pt.top - self.bounds.top() - HEADER_HEIGHT;
+        if offset < 0.0 {
+            return None;
+        }
+        Some((offset / ROW_HEIGHT) as usize).filter(|index| *index < count)
+    }
+
+    /// Paint the sidebar, highlighting the row of `current`, the URL of the document.
+    pub fn paint(&self, surface: &mut Surface, rows: &[SidebarRow], current: Option<&Url>, scale: f64, clip: &Rect) {
+        let clip = match self.bounds.scale(scale).get_intersection_rect(clip) {
+            Some(clip) => clip,
+            None => return,
+        };
+        paint_box(surface, &self.bounds.scale(scale), &BoxStyle::with_background(SIDEBAR_COLOR), &clip);
+        let header = Position {
+            left: (self.bounds.left() + PADDING) * scale,
+            top: (self.bounds.top() + (HEADER_HEIGHT - HEADER_FONT_SIZE) / 2.0) * scale,
+        };
+        draw_text(surface, "Bookmarks", &header, HEADER_FONT_SIZE * scale, ICON_COLOR, &clip);
+        fixme!("Scroll the sidebar when the bookmarks do not fit");
+        for (i, row) in rows.iter().enumerate() {
+            let bounds = self.row_bounds(i);
+            if bounds.top() >= self.bounds.bottom() {
+                break;
+            }
+            if row.url.is_some() && row.url.as_ref() == current {
+                paint_box(surface, &bounds.scale(scale), &BoxStyle::with_background(ACTIVE_TAB_COLOR), &clip);
+            }
+            let left = bounds.left() + PADDING + row.depth as f64 * INDENT;
+            let title = elide(&row.title, FONT_SIZE, bounds.right() - PADDING - left);
+            let origin = Position {
+                left: left * scale,
+                top: (bounds.top() + (ROW_HEIGHT - FONT_SIZE) / 2.0) * scale,
+            };
+            let color = if row.url.is_some() { ICON_COLOR } else { FOLDER_COLOR };
+            draw_text(surface, &title, &origin, FONT_SIZE * scale, color, &clip);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert2::check;
+
+    use super::super::bookmarks::Bookmark;
+    use super::*;
+
+    #[test]
+    fn rows_and_hit_testing() {
+        let bookmark = |url: &str, title: &str| {
+            BookmarkItem::Bookmark(Bookmark {
+                title: String::from(title),
+                url: Url::parse(url).unwrap(),
+                tags: vec![],
+                keyword: None,
+                added: 0,
+            })
+        };
+        let mut folder = BookmarkFolder::new("Folder", 0);
+        folder.children.push(bookmark("https://b.com/", "B"));
+        let mut root = BookmarkFolder::new("", 0);
+        root.children.push(bookmark("https://a.com/", ""));
+        root.children.push(BookmarkItem::Folder(folder));
+        let rows = bookmark_rows(&root);
+        check!(rows.iter().map(|r| r.title.as_str()).collect::<Vec<_>>() == vec!["https://a.com/", "Folder", "B"]);
+        check!(rows.iter().map(|r| r.depth).collect::<Vec<_>>() == vec![0, 0, 1]);
+        check!(rows[1].url == None);
+
+        let layout = SidebarLayout::new(70.0, 400.0);
+        let at = |top: f64| Position { left: 20.0, top };
+        check!(layout.row_at(3, &at(75.0)) == None);
+        check!(layout.row_at(3, &at(70.0 + HEADER_HEIGHT + 1.0)) == Some(0));
+        check!(layout.row_at(3, &at(70.0 + HEADER_HEIGHT + ROW_HEIGHT * 2.5)) == Some(2));
+        check!(layout.row_at(3, &at(70.0 + HEADER_HEIGHT + ROW_HEIGHT * 3.5)) == None);
+        check!(layout.row_at(3, &Position { left: 300.0, top: 110.0 }) == None);
+    }
+}
@@ -19,4 +19,6 @@ impl fmt::Display for SimpleError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{}", self.message.as_str())
     }
-}
\ No newline at end of file
+}
+
+impl std::error::Error for SimpleError {}
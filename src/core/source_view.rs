@@ -0,0 +1,331 @@
+#![allow(dead_code)]
+#![allow(unused_imports)]
+
+use log::{debug, error, info, warn};
+use url::Url;
+
+use crate::fixme;
+
+use super::box_painter::paint_box;
+use super::color::Color;
+use super::geometry::{Position, Rect};
+use super::gfxbuffer::Surface;
+use super::http::Response;
+use super::style::BoxStyle;
+use super::tab_strip::ICON_COLOR;
+use super::text::{draw_text, text_width};
+
+/// Sizes are in CSS pixels at zoom 1, like the rest of the browser UI.
+const FONT_SIZE: f64 = 11.0;
+const LINE_HEIGHT: f64 = 16.0;
+const PADDING: f64 = 8.0;
+const TAB_WIDTH: usize = 4;
+
+const PAGE_COLOR: Color = Color {
+    r: 0x20,
+    g: 0x21,
+    b: 0x24,
+    a: 0xff,
+};
+const GUTTER_COLOR: Color = Color {
+    r: 0x29,
+    g: 0x2a,
+    b: 0x2d,
+    a: 0xff,
+};
+const MUTED_COLOR: Color = Color {
+    r: 0x9a,
+    g: 0xa0,
+    b: 0xa6,
+    a: 0xff,
+};
+const TAG_COLOR: Color = Color {
+    r: 0x8a,
+    g: 0xb4,
+    b: 0xf8,
+    a: 0xff,
+};
+const ATTRIBUTE_NAME_COLOR: Color = Color {
+    r: 0xfd,
+    g: 0xd6,
+    b: 0x63,
+    a: 0xff,
+};
+const ATTRIBUTE_VALUE_COLOR: Color = Color {
+    r: 0x81,
+    g: 0xc9,
+    b: 0x95,
+    a: 0xff,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SourceToken {
+    Text,
+    Tag,
+    AttributeName,
+    AttributeValue,
+    Comment,
+    Doctype,
+}
+
+impl SourceToken {
+    fn color(&self) -> Color {
+        match self {
+            SourceToken::Text => ICON_COLOR,
+            SourceToken::Tag => TAG_COLOR,
+            SourceToken::AttributeName => ATTRIBUTE_NAME_COLOR,
+            SourceToken::AttributeValue => ATTRIBUTE_VALUE_COLOR,
+            SourceToken::Comment | SourceToken::Doctype => MUTED_COLOR,
+        }
+    }
+}
+
+/// Spans of a line of source, with their token.
+pub type SourceLine = Vec<(SourceToken, String)>;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum State {
+    Text,
+    TagName,
+    InTag,
+    AttributeName,
+    BeforeValue,
+    Quoted(char),
+    Unquoted,
+    Comment,
+    Doctype,
+}
+
+/// Lines of HTML or XML, split in tags, attributes, comments and text.
+pub fn highlight_markup(text: &str) -> Vec<SourceLine> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut state = State::Text;
+    let mut tokens = Vec::with_capacity(chars.len());
+    for (i, c) in chars.iter().copied().enumerate() {
+        let next = chars.get(i + 1).copied().unwrap_or(' ');
+        let token = match state {
+            State::Text if c == '<' && chars[i..].starts_with(&['<', '!', '-', '-']) => {
+                state = State::Comment;
+                SourceToken::Comment
+            }
+            State::Text if c == '<' && next == '!' => {
+                state = State::Doctype;
+                SourceToken::Doctype
+            }
+            State::Text if c == '<' && (next.is_alphabetic() || next == '/' || next == '?') => {
+                state = State::TagName;
+                SourceToken::Tag
+            }
+            State::Text => SourceToken::Text,
+            State::Comment => {
+                if c == '>' && chars[..i].ends_with(&['-', '-']) {
+                    state = State::Text;
+                }
+                SourceToken::Comment
+            }
+            State::Doctype => {
+                if c == '>' {
+                    state = State::Text;
+                }
+                SourceToken::Doctype
+            }
+            State::TagName | State::InTag | State::AttributeName | State::BeforeValue | State::Unquoted if c == '>' => {
+                state = State::Text;
+                SourceToken::Tag
+            }
+            State::TagName | State::InTag | State::AttributeName | State::Unquoted if c.is_whitespace() => {
+                state = State::InTag;
+                SourceToken::Text
+            }
+            State::TagName => SourceToken::Tag,
+            State::InTag if c == '/' || c == '?' => SourceToken::Tag,
+            State::InTag | State::AttributeName if c == '=' => {
+                state = State::BeforeValue;
+                SourceToken::Tag
+            }
+            State::InTag | State::AttributeName => {
+                state = State::AttributeName;
+                SourceToken::AttributeName
+            }
+            State::BeforeValue if c.is_whitespace() => SourceToken::Text,
+            State::BeforeValue if c == '"' || c == '\'' => {
+                state = State::Quoted(c);
+                SourceToken::AttributeValue
+            }
+            State::BeforeValue => {
+                state = State::Unquoted;
+                SourceToken::AttributeValue
+            }
+            State::Quoted(quote) => {
+                if c == quote {
+                    state = State::InTag;
+                }
+                SourceToken::AttributeValue
+            }
+            State::Unquoted => SourceToken::AttributeValue,
+        };
+        tokens.push(token);
+    }
+    split_lines(chars.into_iter().zip(tokens))
+}
+
+/// Lines of text without highlighting.
+pub fn plain_lines(text: &str) -> Vec<SourceLine> {
+    split_lines(text.chars().map(|c| (c, SourceToken::Text)))
+}
+
+fn split_lines(chars: impl Iterator<Item = (char, SourceToken)>) -> Vec<SourceLine> {
+    let mut lines = vec![SourceLine::new()];
+    for (c, token) in chars {
+        let line = lines.last_mut().unwrap();
+        match c {
+            '\n' => lines.push(SourceLine::new()),
+            '\r' => (),
+            _ => {
+                if line.last().map(|(last, _)| *last) != Some(token) {
+                    line.push((token, String::new()));
+                }
+                let span = &mut line.last_mut().unwrap().1;
+                match c {
+                    '\t' => span.push_str(&" ".repeat(TAB_WIDTH)),
+                    _ => span.push(c),
+                }
+            }
+        }
+    }
+    lines
+}
+
+/// `view-source:` pages: the text of a response with numbered lines, painted by the browser.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceView {
+    pub url: Url,
+    lines: Vec<SourceLine>,
+    /// Distance scrolled from the top, in CSS pixels
+    scroll: f64,
+}
+
+impl SourceView {
+    /// The text of `response`, highlighted if it is HTML or XML.
+    pub fn new(response: &Response) -> Self {
+        let text = String::from_utf8_lossy(&response.body);
+        let markup = response
+            .mime_type()
+            .is_some_and(|mime_type| mime_type == "text/html" || mime_type.ends_with("xml"));
+        fixme!("Highlight CSS and JavaScript sources");
+        Self {
+            url: response.url.clone(),
+            lines: if markup {
+                highlight_markup(&text)
+            } else {
+                plain_lines(&text)
+            },
+            scroll: 0.0,
+        }
+    }
+
+    /// Scroll by `delta` CSS pixels, down when positive, keeping text in a view of `bounds`.
+    pub fn scroll_by(&mut self, delta: f64, bounds: &Rect) {
+        let height = self.lines.len() as f64 * LINE_HEIGHT + PADDING * 2.0;
+        self.scroll = (self.scroll + delta).clamp(0.0, (height - bounds.height()).max(0.0));
+    }
+
+    fn gutter_width(&self) -> f64 {
+        text_width(&self.lines.len().to_string(), FONT_SIZE) + PADDING * 2.0
+    }
+
+    /// Paint the page in `bounds`, in CSS pixels, `scale` device pixels per CSS pixel.
+    pub fn paint(&self, surface: &mut Surface, bounds: &Rect, scale: f64, clip: &Rect) {
+        let clip = match bounds.scale(scale).get_intersection_rect(clip) {
+            Some(clip) => clip,
+            None => return,
+        };
+        paint_box(
+            surface,
+            &bounds.scale(scale),
+            &BoxStyle::with_background(PAGE_COLOR),
+            &clip,
+        );
+        let gutter = Rect::new(bounds.left(), bounds.top(), self.gutter_width(), bounds.height());
+        paint_box(
+            surface,
+            &gutter.scale(scale),
+            &BoxStyle::with_background(GUTTER_COLOR),
+            &clip,
+        );
+        fixme!("Wrap long lines, scroll horizontally, select and copy");
+
+        let first = ((self.scroll - PADDING) / LINE_HEIGHT).floor().max(0.0) as usize;
+        let visible = (bounds.height() / LINE_HEIGHT).ceil() as usize + 1;
+        for (index, line) in self.lines.iter().enumerate().skip(first).take(visible) {
+            let top = bounds.top() + PADDING + index as f64 * LINE_HEIGHT - self.scroll;
+            let text_top = (top + (LINE_HEIGHT - FONT_SIZE) / 2.0) * scale;
+            let number = (index + 1).to_string();
+            let origin = Position {
+                left: (gutter.right() - PADDING - text_width(&number, FONT_SIZE)) * scale,
+                top: text_top,
+            };
+            draw_text(surface, &number, &origin, FONT_SIZE * scale, MUTED_COLOR, &clip);
+            let mut left = gutter.right() + PADDING;
+            for (token, span) in line.iter() {
+                let origin = Position {
+                    left: left * scale,
+                    top: text_top,
+                };
+                draw_text(surface, span, &origin, FONT_SIZE * scale, token.color(), &clip);
+                left += text_width(span, FONT_SIZE);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert2::check;
+
+    use super::*;
+
+    fn spans(line: &SourceLine) -> Vec<(SourceToken, &str)> {
+        line.iter().map(|(token, text)| (*token, text.as_str())).collect()
+    }
+
+    #[test]
+    fn highlighting_and_scrolling() {
+        let lines = highlight_markup("<!DOCTYPE html>\r\n<a href=\"/x\" hidden>a < b<!-- <c> --></a>\n\tx=1");
+        check!(lines.len() == 3);
+        check!(spans(&lines[0]) == vec![(SourceToken::Doctype, "<!DOCTYPE html>")]);
+        check!(
+            spans(&lines[1])
+                == vec![
+                    (SourceToken::Tag, "<a"),
+                    (SourceToken::Text, " "),
+                    (SourceToken::AttributeName, "href"),
+                    (SourceToken::Tag, "="),
+                    (SourceToken::AttributeValue, "\"/x\""),
+                    (SourceToken::Text, " "),
+                    (SourceToken::AttributeName, "hidden"),
+                    (SourceToken::Tag, ">"),
+                    (SourceToken::Text, "a < b"),
+                    (SourceToken::Comment, "<!-- <c> -->"),
+                    (SourceToken::Tag, "</a>"),
+                ]
+        );
+        check!(spans(&lines[2]) == vec![(SourceToken::Text, "    x=1")]);
+
+        let mut response = Response::new(
+            Url::parse("view-source:data:,x").unwrap(),
+            200,
+            "text/plain",
+            b"<b>\n".repeat(100),
+        );
+        check!(SourceView::new(&response).lines[0] == vec![(SourceToken::Text, String::from("<b>"))]);
+        response.headers.set("Content-Type", "application/xhtml+xml");
+        let mut view = SourceView::new(&response);
+        check!(view.lines[0] == vec![(SourceToken::Tag, String::from("<b>"))]);
+        let bounds = Rect::new(0.0, 70.0, 800.0, 500.0);
+        view.scroll_by(-50.0, &bounds);
+        check!(view.scroll == 0.0);
+        view.scroll_by(10000.0, &bounds);
+        check!(view.scroll == 101.0 * LINE_HEIGHT + PADDING * 2.0 - 500.0);
+    }
+}
@@ -0,0 +1,269 @@
+#![allow(dead_code)]
+#![allow(unused_imports)]
+
+use log::{debug, error, info, warn};
+use url::Url;
+
+use super::media::{MediaFeatures, MediaQueryList};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Descriptor {
+    /// `2x`
+    Density(f64),
+    /// `400w`
+    Width(u32),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImageCandidate {
+    pub url: Url,
+    pub descriptor: Descriptor,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SourceLength {
+    Px(f64),
+    /// Fraction of the viewport width, 1.0 is `100vw`
+    Vw(f64),
+}
+
+impl SourceLength {
+    fn parse(text: &str) -> Option<SourceLength> {
+        if let Some(number) = text.strip_suffix("px") {
+            number.parse().ok().map(SourceLength::Px)
+        } else if let Some(number) = text.strip_suffix("vw") {
+            number.parse::<f64>().ok().map(|vw| SourceLength::Vw(vw / 100.0))
+        } else {
+            None
+        }
+    }
+    fn resolve(&self, features: &MediaFeatures) -> f64 {
+        match self {
+            SourceLength::Px(px) => *px,
+            SourceLength::Vw(fraction) => features.viewport.width * fraction,
+        }
+    }
+}
+
+/// One entry of the `sizes` attribute.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceSize {
+    pub condition: Option<MediaQueryList>,
+    pub length: SourceLength,
+}
+
+/// The parsed `srcset` and `sizes` attributes of an `<img>`, with candidate URLs already resolved.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SrcSet {
+    pub candidates: Vec<ImageCandidate>,
+    pub sizes: Vec<SourceSize>,
+}
+
+impl SrcSet {
+    /// Invalid candidates and sizes are dropped, as browsers do.
+    pub fn parse(base: &Url, srcset: &str, sizes: Option<&str>) -> SrcSet {
+        let candidates = split_candidates(srcset)
+            .into_iter()
+            .filter_map(|(url, descriptors)| {
+                let descriptor = match parse_descriptor(&descriptors) {
+                    Some(descriptor) => descriptor,
+                    None => {
+                        warn!("Invalid srcset descriptor {:?} for {}", descriptors, url);
+                        return None;
+                    }
+                };
+                match base.join(&url) {
+                    Ok(url) => Some(ImageCandidate { url, descriptor }),
+                    Err(err) => {
+                        warn!("Invalid srcset url {}: {}", url, err);
+                        None
+                    }
+                }
+            })
+            .collect();
+        let sizes = sizes.map_or(vec![], parse_sizes);
+        SrcSet { candidates, sizes }
+    }
+
+    /// Width the image will be laid out at, in CSS pixels: the first matching `sizes` entry, or `100vw`.
+    pub fn source_size(&self, features: &MediaFeatures) -> f64 {
+        self.sizes
+            .iter()
+            .find(|size| size.condition.as_ref().is_none_or(|c| c.matches(features)))
+            .map_or(features.viewport.width, |size| size.length.resolve(features))
+    }
+
+    /// The candidate to load and its density: the smallest one dense enough for the device,
+    ///   or the densest available.
+    pub fn select(&self, features: &MediaFeatures) -> Option<(&ImageCandidate, f64)> {
+        let source_size = self.source_size(features);
+        let with_density = self.candidates.iter().map(|candidate| {
+            let density = match candidate.descriptor {
+                Descriptor::Density(density) => density,
+                Descriptor::Width(width) if source_size > 0.0 => width as f64 / source_size,
+                Descriptor::Width(_) => f64::INFINITY,
+            };
+            (candidate, density)
+        });
+        let mut best: Option<(&ImageCandidate, f64)> = None;
+        for (candidate, density) in with_density {
+            best = match best {
+                None => Some((candidate, density)),
+                Some((_, best_density)) => {
+                    let enough = density >= features.resolution;
+                    let best_enough = best_density >= features.resolution;
+                    let better = match (enough, best_enough) {
+                        (true, true) => density < best_density,
+                        (true, false) => true,
+                        (false, true) => false,
+                        (false, false) => density > best_density,
+                    };
+                    if better {
+                        Some((candidate, density))
+                    } else {
+                        best
+                    }
+                }
+            };
+        }
+        best
+    }
+}
+
+/// Split `srcset` into URLs and their descriptor text. URLs may contain commas, so they end at whitespace.
+fn split_candidates(srcset: &str) -> Vec<(String, String)> {
+    let mut candidates = vec![];
+    let mut chars = srcset.chars().peekable();
+    loop {
+        while chars.peek().is_some_and(|c| c.is_whitespace() || *c == ',') {
+            chars.next();
+        }
+        if chars.peek().is_none() {
+            break;
+        }
+        let mut url = String::new();
+        while let Some(c) = chars.peek().filter(|c| !c.is_whitespace()) {
+            url.push(*c);
+            chars.next();
+        }
+        let mut descriptors = String::new();
+        if url.ends_with(',') {
+            // `a.png,b.png`: no descriptors
+            url = String::from(url.trim_end_matches(','));
+        } else {
+            let mut depth = 0;
+            for c in chars.by_ref() {
+                match c {
+                    '(' => depth += 1,
+                    ')' => depth -= 1,
+                    ',' if depth == 0 => break,
+                    _ => (),
+                }
+                descriptors.push(c);
+            }
+        }
+        candidates.push((url, String::from(descriptors.trim())));
+    }
+    candidates
+}
+
+fn parse_descriptor(text: &str) -> Option<Descriptor> {
+    let mut words = text.split_whitespace();
+    let descriptor = match words.next() {
+        None => Descriptor::Density(1.0),
+        Some(word) => {
+            if let Some(density) = word.strip_suffix('x') {
+                Descriptor::Density(density.parse().ok().filter(|d: &f64| *d > 0.0)?)
+            } else if let Some(width) = word.strip_suffix('w') {
+                Descriptor::Width(width.parse().ok().filter(|w| *w > 0)?)
+            } else {
+                return None;
+            }
+        }
+    };
+    if words.next().is_some() {
+        // Height descriptors and multiple descriptors are not supported
+        return None;
+    }
+    Some(descriptor)
+}
+
+fn parse_sizes(sizes: &str) -> Vec<SourceSize> {
+    sizes
+        .split(',')
+        .filter_map(|entry| {
+            let entry = entry.trim();
+            let (condition, length) = match entry.rfind(char::is_whitespace) {
+                Some(split) => (Some(MediaQueryList::parse(&entry[..split])), entry[split..].trim()),
+                None => (None, entry),
+            };
+            match SourceLength::parse(length) {
+                Some(length) => Some(SourceSize { condition, length }),
+                None => {
+                    warn!("Invalid sizes entry {:?}", entry);
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use assert2::check;
+
+    use super::super::geometry::Size;
+    use super::*;
+
+    fn features(width: f64, resolution: f64) -> MediaFeatures {
+        MediaFeatures {
+            viewport: Size::new(width, 600.0),
+            resolution,
+            em: 16.0,
+        }
+    }
+
+    fn selected(srcset: &SrcSet, width: f64, resolution: f64) -> String {
+        let (candidate, _) = srcset.select(&features(width, resolution)).unwrap();
+        String::from(candidate.url.path())
+    }
+
+    #[test]
+    fn density_descriptors() {
+        let base = Url::parse("https://example.com/page/").unwrap();
+        let srcset = SrcSet::parse(&base, "a.png, b.png 2x,c.png 3x", None);
+        check!(srcset.candidates.len() == 3);
+        check!(srcset.candidates[0].descriptor == Descriptor::Density(1.0));
+        check!(selected(&srcset, 800.0, 1.0) == "/page/a.png");
+        check!(selected(&srcset, 800.0, 1.5) == "/page/b.png");
+        check!(selected(&srcset, 800.0, 2.0) == "/page/b.png");
+        check!(selected(&srcset, 800.0, 4.0) == "/page/c.png");
+    }
+
+    #[test]
+    fn width_descriptors_and_sizes() {
+        let base = Url::parse("https://example.com/").unwrap();
+        let srcset = SrcSet::parse(
+            &base,
+            "small.png 400w, large.png 800w, data:image/png;base64,AA== 1600w",
+            Some("(max-width: 600px) 100vw, 400px"),
+        );
+        check!(srcset.candidates.len() == 3);
+        check!(srcset.candidates[2].url.scheme() == "data");
+        check!(srcset.source_size(&features(1000.0, 1.0)) == 400.0);
+        check!(selected(&srcset, 1000.0, 1.0) == "/small.png");
+        check!(selected(&srcset, 1000.0, 2.0) == "/large.png");
+        // Narrow viewport: 100vw of 500px at 2x needs 1000 pixels
+        check!(srcset.source_size(&features(500.0, 2.0)) == 500.0);
+        check!(srcset.select(&features(500.0, 2.0)).unwrap().0.url.scheme() == "data");
+    }
+
+    #[test]
+    fn invalid_candidates_are_dropped() {
+        let base = Url::parse("https://example.com/").unwrap();
+        let srcset = SrcSet::parse(&base, "a.png 2q, b.png 1x 2x, c.png 0x, d.png 1.5x", None);
+        check!(srcset.candidates.len() == 1);
+        check!(srcset.candidates[0].descriptor == Descriptor::Density(1.5));
+        check!(SrcSet::parse(&base, "", None).select(&features(800.0, 1.0)).is_none());
+    }
+}
@@ -0,0 +1,463 @@
+#![allow(dead_code)]
+#![allow(unused_imports)]
+
+use std::rc::Rc;
+
+use log::{debug, error, info, warn};
+
+use super::bitmap::DecodedImage;
+use super::color::{Color, Colors};
+use super::geometry::{Position, Rect, Size, Transform, Transform3D};
+use super::gradient::Gradient;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BorderStyle {
+    None,
+    Solid,
+    Dashed,
+    Dotted,
+    Double,
+    Groove,
+    Ridge,
+    Inset,
+    Outset,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BorderSide {
+    pub width: f64,
+    pub style: BorderStyle,
+    pub color: Color,
+}
+
+impl BorderSide {
+    pub const NONE: BorderSide = BorderSide {
+        width: 0.0,
+        style: BorderStyle::None,
+        color: Colors::TRANSPARENT,
+    };
+
+    pub fn new(width: f64, style: BorderStyle, color: Color) -> Self {
+        Self {
+            width,
+            style,
+            color,
+        }
+    }
+
+    /// Used width: `border-style: none` computes the width to zero.
+    pub fn used_width(&self) -> f64 {
+        if self.style == BorderStyle::None {
+            0.0
+        } else {
+            self.width.max(0.0)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Borders {
+    pub top: BorderSide,
+    pub right: BorderSide,
+    pub bottom: BorderSide,
+    pub left: BorderSide,
+}
+
+impl Borders {
+    pub fn all(side: BorderSide) -> Self {
+        Self {
+            top: side,
+            right: side,
+            bottom: side,
+            left: side,
+        }
+    }
+    pub fn none() -> Self {
+        Self::all(BorderSide::NONE)
+    }
+}
+
+/// Elliptical radii of each corner, `width` horizontal and `height` vertical.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CornerRadii {
+    pub top_left: Size,
+    pub top_right: Size,
+    pub bottom_right: Size,
+    pub bottom_left: Size,
+}
+
+impl CornerRadii {
+    pub fn uniform(radius: f64) -> Self {
+        let size = Size::new(radius, radius);
+        Self {
+            top_left: size,
+            top_right: size,
+            bottom_right: size,
+            bottom_left: size,
+        }
+    }
+    pub fn is_zero(&self) -> bool {
+        [
+            self.top_left,
+            self.top_right,
+            self.bottom_right,
+            self.bottom_left,
+        ]
+        .iter()
+        .all(|r| r.width <= 0.0 || r.height <= 0.0)
+    }
+}
+
+impl Default for CornerRadii {
+    fn default() -> Self {
+        Self::uniform(0.0)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoxShadow {
+    pub offset: Position,
+    pub blur: f64,
+    pub spread: f64,
+    pub color: Color,
+    pub inset: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Outline {
+    pub side: BorderSide,
+    pub offset: f64,
+}
+
+#[derive(Debug, Clone)]
+pub enum BackgroundImage {
+    Gradient(Gradient),
+    Image(Rc<DecodedImage>),
+}
+
+impl PartialEq for BackgroundImage {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (BackgroundImage::Gradient(a), BackgroundImage::Gradient(b)) => a == b,
+            (BackgroundImage::Image(a), BackgroundImage::Image(b)) => Rc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LengthPercentage {
+    Px(f64),
+    /// Fraction, 1.0 is 100%
+    Percent(f64),
+}
+
+impl LengthPercentage {
+    pub fn resolve(&self, reference: f64) -> f64 {
+        match self {
+            LengthPercentage::Px(px) => *px,
+            LengthPercentage::Percent(fraction) => reference * fraction,
+        }
+    }
+    /// Lengths are multiplied, percentages stay relative.
+    pub fn scaled(&self, factor: f64) -> Self {
+        match self {
+            LengthPercentage::Px(px) => LengthPercentage::Px(px * factor),
+            LengthPercentage::Percent(fraction) => LengthPercentage::Percent(*fraction),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BackgroundSize {
+    Cover,
+    Contain,
+    /// Width and height, `None` is `auto`
+    Explicit(Option<LengthPercentage>, Option<LengthPercentage>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BackgroundRepeat {
+    Repeat,
+    NoRepeat,
+    Space,
+    Round,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BackgroundBox {
+    Border,
+    Padding,
+    Content,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BackgroundLayer {
+    pub image: BackgroundImage,
+    pub size: BackgroundSize,
+    /// `background-position` x and y: percentages align the same point of the image and of the area.
+    pub position: (LengthPercentage, LengthPercentage),
+    pub repeat: (BackgroundRepeat, BackgroundRepeat),
+    pub clip: BackgroundBox,
+    pub origin: BackgroundBox,
+}
+
+impl BackgroundLayer {
+    /// A layer with the CSS initial values of every other background property.
+    pub fn new(image: BackgroundImage) -> Self {
+        Self {
+            image,
+            size: BackgroundSize::Explicit(None, None),
+            position: (LengthPercentage::Percent(0.0), LengthPercentage::Percent(0.0)),
+            repeat: (BackgroundRepeat::Repeat, BackgroundRepeat::Repeat),
+            clip: BackgroundBox::Border,
+            origin: BackgroundBox::Padding,
+        }
+    }
+}
+
+/// One function of the CSS `transform` list. Angles are in degrees.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TransformFunction {
+    /// Percentages refer to the border box.
+    Translate(LengthPercentage, LengthPercentage),
+    TranslateZ(f64),
+    Scale(f64, f64),
+    /// Around the z axis, clockwise on screen.
+    Rotate(f64),
+    RotateX(f64),
+    RotateY(f64),
+    Skew(f64, f64),
+    /// `matrix(a, b, c, d, e, f)`
+    Matrix(Transform),
+    Matrix3D(Transform3D),
+    Perspective(f64),
+}
+
+impl TransformFunction {
+    pub fn scaled(&self, factor: f64) -> Self {
+        match self {
+            TransformFunction::Translate(x, y) => TransformFunction::Translate(x.scaled(factor), y.scaled(factor)),
+            TransformFunction::TranslateZ(z) => TransformFunction::TranslateZ(z * factor),
+            TransformFunction::Matrix(matrix) => {
+                let mut matrix = *matrix;
+                matrix.e *= factor;
+                matrix.f *= factor;
+                TransformFunction::Matrix(matrix)
+            }
+            TransformFunction::Matrix3D(matrix) => {
+                // Conjugate by the scale: lengths in, lengths out
+                let scale = Transform3D::scale(factor, factor, factor);
+                let unscale = Transform3D::scale(1.0 / factor, 1.0 / factor, 1.0 / factor);
+                TransformFunction::Matrix3D(unscale.then(matrix).then(&scale))
+            }
+            TransformFunction::Perspective(distance) => TransformFunction::Perspective(distance * factor),
+            other => *other,
+        }
+    }
+
+    pub fn to_matrix(self, reference: &Size) -> Transform3D {
+        match self {
+            TransformFunction::Translate(x, y) => Transform3D::translate(
+                x.resolve(reference.width),
+                y.resolve(reference.height),
+                0.0,
+            ),
+            TransformFunction::TranslateZ(z) => Transform3D::translate(0.0, 0.0, z),
+            TransformFunction::Scale(x, y) => Transform3D::scale(x, y, 1.0),
+            TransformFunction::Rotate(angle) => Transform::rotate(angle.to_radians()).into(),
+            TransformFunction::RotateX(angle) => Transform3D::rotate(1.0, 0.0, 0.0, angle.to_radians()),
+            TransformFunction::RotateY(angle) => Transform3D::rotate(0.0, 1.0, 0.0, angle.to_radians()),
+            TransformFunction::Skew(x, y) => Transform::skew(x.to_radians(), y.to_radians()).into(),
+            TransformFunction::Matrix(matrix) => matrix.into(),
+            TransformFunction::Matrix3D(matrix) => matrix,
+            TransformFunction::Perspective(distance) => Transform3D::perspective(distance),
+        }
+    }
+}
+
+/// Painting related properties of an element box. `bounds` of the element is its border box.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BoxStyle {
+    pub background_color: Option<Color>,
+    /// In CSS order: the first layer is painted on top.
+    pub background_layers: Vec<BackgroundLayer>,
+    pub borders: Borders,
+    pub border_radius: CornerRadii,
+    /// In CSS order: the first shadow is painted on top.
+    pub box_shadows: Vec<BoxShadow>,
+    pub outline: Option<Outline>,
+    /// In CSS order: the last function is applied first.
+    pub transform: Vec<TransformFunction>,
+    /// Relative to the border box.
+    pub transform_origin: (LengthPercentage, LengthPercentage),
+}
+
+impl Default for BoxStyle {
+    fn default() -> Self {
+        Self {
+            background_color: None,
+            background_layers: vec![],
+            borders: Borders::none(),
+            border_radius: CornerRadii::default(),
+            box_shadows: vec![],
+            outline: None,
+            transform: vec![],
+            transform_origin: (LengthPercentage::Percent(0.5), LengthPercentage::Percent(0.5)),
+        }
+    }
+}
+
+impl BoxStyle {
+    pub fn with_background(color: Color) -> Self {
+        Self {
+            background_color: Some(color),
+            ..Self::default()
+        }
+    }
+
+    /// The same style with every length multiplied by `factor`, as from CSS pixels to device pixels.
+    pub fn scaled(&self, factor: f64) -> Self {
+        if factor == 1.0 {
+            return self.clone();
+        }
+        let side = |side: &BorderSide| BorderSide {
+            width: side.width * factor,
+            ..*side
+        };
+        let radius = |size: &Size| Size::new(size.width * factor, size.height * factor);
+        Self {
+            background_color: self.background_color,
+            background_layers: self
+                .background_layers
+                .iter()
+                .map(|layer| {
+                    let size = match (&layer.size, &layer.image) {
+                        // Images are sized in CSS pixels: make the intrinsic size explicit
+                        (BackgroundSize::Explicit(None, None), BackgroundImage::Image(image)) => {
+                            let intrinsic = image.intrinsic_size();
+                            BackgroundSize::Explicit(
+                                Some(LengthPercentage::Px(intrinsic.width * factor)),
+                                Some(LengthPercentage::Px(intrinsic.height * factor)),
+                            )
+                        }
+                        (BackgroundSize::Explicit(width, height), _) => BackgroundSize::Explicit(
+                            width.map(|w| w.scaled(factor)),
+                            height.map(|h| h.scaled(factor)),
+                        ),
+                        (other, _) => *other,
+                    };
+                    BackgroundLayer {
+                        size,
+                        position: (layer.position.0.scaled(factor), layer.position.1.scaled(factor)),
+                        ..layer.clone()
+                    }
+                })
+                .collect(),
+            borders: Borders {
+                top: side(&self.borders.top),
+                right: side(&self.borders.right),
+                bottom: side(&self.borders.bottom),
+                left: side(&self.borders.left),
+            },
+            border_radius: CornerRadii {
+                top_left: radius(&self.border_radius.top_left),
+                top_right: radius(&self.border_radius.top_right),
+                bottom_right: radius(&self.border_radius.bottom_right),
+                bottom_left: radius(&self.border_radius.bottom_left),
+            },
+            box_shadows: self
+                .box_shadows
+                .iter()
+                .map(|shadow| BoxShadow {
+                    offset: Position {
+                        left: shadow.offset.left * factor,
+                        top: shadow.offset.top * factor,
+                    },
+                    blur: shadow.blur * factor,
+                    spread: shadow.spread * factor,
+                    ..*shadow
+                })
+                .collect(),
+            outline: self.outline.map(|outline| Outline {
+                side: side(&outline.side),
+                offset: outline.offset * factor,
+            }),
+            transform: self.transform.iter().map(|f| f.scaled(factor)).collect(),
+            transform_origin: (
+                self.transform_origin.0.scaled(factor),
+                self.transform_origin.1.scaled(factor),
+            ),
+        }
+    }
+
+    /// Matrix mapping the untransformed `border_box` to where it is painted, `None` without `transform`.
+    pub fn transform_matrix(&self, border_box: &Rect) -> Option<Transform3D> {
+        if self.transform.is_empty() {
+            return None;
+        }
+        let size = border_box.size;
+        let origin_x = border_box.left() + self.transform_origin.0.resolve(size.width);
+        let origin_y = border_box.top() + self.transform_origin.1.resolve(size.height);
+        let matrix = self
+            .transform
+            .iter()
+            .rev()
+            .fold(Transform3D::translate(-origin_x, -origin_y, 0.0), |matrix, function| {
+                matrix.then(&function.to_matrix(&size))
+            });
+        Some(matrix.then(&Transform3D::translate(origin_x, origin_y, 0.0)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert2::check;
+
+    use super::*;
+
+    #[test]
+    fn transform_around_origin() {
+        let mut style = BoxStyle {
+            transform: vec![TransformFunction::Rotate(90.0)],
+            ..BoxStyle::default()
+        };
+        let border_box = Rect::from_u32(10, 10, 20, 20);
+        let matrix = style.transform_matrix(&border_box).unwrap();
+        // Rotating around the center moves the top left corner to the top right
+        let corner = matrix.map_point(&Position { left: 10.0, top: 10.0 }).unwrap();
+        check!((corner.left - 30.0).abs() < 1e-9);
+        check!((corner.top - 10.0).abs() < 1e-9);
+
+        style.transform = vec![
+            TransformFunction::Translate(LengthPercentage::Percent(0.5), LengthPercentage::Px(0.0)),
+            TransformFunction::Scale(2.0, 2.0),
+        ];
+        style.transform_origin = (LengthPercentage::Px(0.0), LengthPercentage::Px(0.0));
+        let matrix = style.transform_matrix(&border_box).unwrap();
+        // Scaled first, then translated by half the width
+        let corner = matrix.map_point(&Position { left: 30.0, top: 30.0 }).unwrap();
+        check!((corner.left - 60.0).abs() < 1e-9);
+        check!((corner.top - 50.0).abs() < 1e-9);
+        check!(BoxStyle::default().transform_matrix(&border_box).is_none());
+    }
+
+    #[test]
+    fn scaled_transform_commutes_with_scale() {
+        let style = BoxStyle {
+            transform: vec![
+                TransformFunction::Perspective(200.0),
+                TransformFunction::RotateY(30.0),
+                TransformFunction::Translate(LengthPercentage::Px(5.0), LengthPercentage::Percent(0.5)),
+            ],
+            ..BoxStyle::default()
+        };
+        let border_box = Rect::from_u32(10, 10, 20, 20);
+        let css = style.transform_matrix(&border_box).unwrap();
+        let device = style.scaled(2.0).transform_matrix(&border_box.scale(2.0)).unwrap();
+        let pt = css.map_point(&Position { left: 12.0, top: 17.0 }).unwrap();
+        let device_pt = device.map_point(&Position { left: 24.0, top: 34.0 }).unwrap();
+        check!((device_pt.left - pt.left * 2.0).abs() < 1e-9);
+        check!((device_pt.top - pt.top * 2.0).abs() < 1e-9);
+    }
+}
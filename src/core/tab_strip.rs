@@ -0,0 +1,203 @@
+#![allow(dead_code)]
+#![allow(unused_imports)]
+
+use log::{debug, error, info, warn};
+
+use crate::fixme;
+
+use super::box_painter::paint_box;
+use super::color::Color;
+use super::geometry::{Position, Rect, Size, Transform};
+use super::gfxbuffer::Surface;
+use super::path::{LineCap, Path, StrokeStyle};
+use super::rasterizer;
+use super::style::{BoxStyle, CornerRadii};
+use super::text::{draw_text, elide};
+
+/// Sizes of the browser UI are in CSS pixels at zoom 1, scaled by the window scale factor.
+pub const TAB_STRIP_HEIGHT: f64 = 34.0;
+const TAB_MAX_WIDTH: f64 = 220.0;
+const TAB_MIN_WIDTH: f64 = 48.0;
+const TAB_GAP: f64 = 2.0;
+const TAB_TOP_MARGIN: f64 = 4.0;
+const CLOSE_BUTTON_SIZE: f64 = 16.0;
+const NEW_TAB_BUTTON_SIZE: f64 = 26.0;
+const TITLE_FONT_SIZE: f64 = 11.0;
+const TITLE_PADDING: f64 = 10.0;
+
+const STRIP_COLOR: Color = Color {
+    r: 0x20,
+    g: 0x21,
+    b: 0x24,
+    a: 0xff,
+};
+const TAB_COLOR: Color = Color {
+    r: 0x35,
+    g: 0x36,
+    b: 0x3a,
+    a: 0xff,
+};
+pub const ACTIVE_TAB_COLOR: Color = Color {
+    r: 0x50,
+    g: 0x52,
+    b: 0x58,
+    a: 0xff,
+};
+pub const ICON_COLOR: Color = Color {
+    r: 0xe8,
+    g: 0xea,
+    b: 0xed,
+    a: 0xff,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TabStripHit {
+    Tab(usize),
+    Close(usize),
+    NewTab,
+}
+
+/// Where every tab of the strip goes, in CSS pixels.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TabStripLayout {
+    pub tabs: Vec<Rect>,
+    pub new_tab: Rect,
+}
+
+impl TabStripLayout {
+    /// Tabs share `width` evenly, between their minimum and maximum width.
+    pub fn new(count: usize, width: f64) -> Self {
+        let available = width - NEW_TAB_BUTTON_SIZE - TAB_GAP * 2.0;
+        let tab_width = if count == 0 {
+            0.0
+        } else {
+            (available / count as f64 - TAB_GAP).clamp(TAB_MIN_WIDTH, TAB_MAX_WIDTH)
+        };
+        let tab_height = TAB_STRIP_HEIGHT - TAB_TOP_MARGIN;
+        let tabs: Vec<Rect> = (0..count)
+            .map(|i| Rect::new(TAB_GAP + i as f64 * (tab_width + TAB_GAP), TAB_TOP_MARGIN, tab_width, tab_height))
+            .collect();
+        let new_tab_left = tabs.last().map_or(TAB_GAP, |r| r.right() + TAB_GAP);
+        let new_tab = Rect::new(
+            new_tab_left,
+            (TAB_STRIP_HEIGHT - NEW_TAB_BUTTON_SIZE + TAB_TOP_MARGIN) / 2.0,
+            NEW_TAB_BUTTON_SIZE,
+            NEW_TAB_BUTTON_SIZE,
+        );
+        Self { tabs, new_tab }
+    }
+
+    pub fn close_button(tab: &Rect) -> Rect {
+        Rect::new(
+            tab.right() - CLOSE_BUTTON_SIZE - 6.0,
+            tab.top() + (tab.height() - CLOSE_BUTTON_SIZE) / 2.0,
+            CLOSE_BUTTON_SIZE,
+            CLOSE_BUTTON_SIZE,
+        )
+    }
+
+    pub fn hit(&self, pt: &Position) -> Option<TabStripHit> {
+        if self.new_tab.contains_point(pt) {
+            return Some(TabStripHit::NewTab);
+        }
+        let index = self.tabs.iter().position(|tab| tab.contains_point(pt))?;
+        if Self::close_button(&self.tabs[index]).contains_point(pt) {
+            Some(TabStripHit::Close(index))
+        } else {
+            Some(TabStripHit::Tab(index))
+        }
+    }
+
+    /// Paint the strip at the top of `surface`, `scale` device pixels per CSS pixel.
+    ///   `titles` has one entry per tab.
+    pub fn paint(&self, surface: &mut Surface, titles: &[String], active: usize, scale: f64, clip: &Rect) {
+        let strip = Rect::new(0.0, 0.0, surface.width as f64, TAB_STRIP_HEIGHT * scale);
+        paint_box(surface, &strip, &BoxStyle::with_background(STRIP_COLOR), clip);
+
+        let mut tab_style = BoxStyle::with_background(TAB_COLOR);
+        tab_style.border_radius = CornerRadii {
+            bottom_left: Size::new(0.0, 0.0),
+            bottom_right: Size::new(0.0, 0.0),
+            ..CornerRadii::uniform(6.0 * scale)
+        };
+        let transform = Transform::scale(scale, scale);
+        let mut icon = StrokeStyle::new(1.5);
+        icon.cap = LineCap::Round;
+        for (i, tab) in self.tabs.iter().enumerate() {
+            tab_style.background_color = Some(if i == active { ACTIVE_TAB_COLOR } else { TAB_COLOR });
+            paint_box(surface, &tab.scale(scale), &tab_style, clip);
+            fixme!("Draw tab favicons");
+            let close = Self::close_button(tab);
+            if let Some(title) = titles.get(i) {
+                let width = close.left() - tab.left() - TITLE_PADDING - 4.0;
+                let title = elide(title, TITLE_FONT_SIZE, width);
+                let origin = Position {
+                    left: (tab.left() + TITLE_PADDING) * scale,
+                    top: (tab.top() + (tab.height() - TITLE_FONT_SIZE) / 2.0) * scale,
+                };
+                let title_clip = Rect::new(tab.left(), tab.top(), close.left() - tab.left(), tab.height()).scale(scale);
+                if let Some(title_clip) = title_clip.get_intersection_rect(clip) {
+                    draw_text(surface, &title, &origin, TITLE_FONT_SIZE * scale, ICON_COLOR, &title_clip);
+                }
+            }
+            let mut cross = Path::new();
+            cross
+                .move_to(close.left() + 4.0, close.top() + 4.0)
+                .line_to(close.right() - 4.0, close.bottom() - 4.0)
+                .move_to(close.right() - 4.0, close.top() + 4.0)
+                .line_to(close.left() + 4.0, close.bottom() - 4.0);
+            rasterizer::stroke_path(surface, &cross, &transform, &icon, ICON_COLOR, clip);
+        }
+        let plus_box = &self.new_tab;
+        let (cx, cy) = (plus_box.left() + plus_box.width() / 2.0, plus_box.top() + plus_box.height() / 2.0);
+        let mut plus = Path::new();
+        plus.move_to(cx - 6.0, cy)
+            .line_to(cx + 6.0, cy)
+            .move_to(cx, cy - 6.0)
+            .line_to(cx, cy + 6.0);
+        rasterizer::stroke_path(surface, &plus, &transform, &icon, ICON_COLOR, clip);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert2::check;
+
+    use super::*;
+
+    #[test]
+    fn tabs_share_the_width() {
+        let layout = TabStripLayout::new(3, 1000.0);
+        check!(layout.tabs.len() == 3);
+        check!(layout.tabs[0].width() == TAB_MAX_WIDTH);
+        check!(layout.new_tab.left() == layout.tabs[2].right() + TAB_GAP);
+
+        let crowded = TabStripLayout::new(10, 800.0);
+        check!(crowded.tabs[0].width() < TAB_MAX_WIDTH);
+        check!(crowded.new_tab.right() <= 800.0 + 1e-9);
+        check!(TabStripLayout::new(100, 500.0).tabs[0].width() == TAB_MIN_WIDTH);
+    }
+
+    #[test]
+    fn hit_testing() {
+        let layout = TabStripLayout::new(2, 1000.0);
+        let tab = layout.tabs[1];
+        let center = Position {
+            left: tab.left() + 20.0,
+            top: tab.top() + tab.height() / 2.0,
+        };
+        check!(layout.hit(&center) == Some(TabStripHit::Tab(1)));
+        let close = TabStripLayout::close_button(&tab);
+        let on_close = Position {
+            left: close.left() + 1.0,
+            top: close.top() + 1.0,
+        };
+        check!(layout.hit(&on_close) == Some(TabStripHit::Close(1)));
+        let on_plus = Position {
+            left: layout.new_tab.left() + 1.0,
+            top: layout.new_tab.top() + 1.0,
+        };
+        check!(layout.hit(&on_plus) == Some(TabStripHit::NewTab));
+        check!(layout.hit(&Position { left: 999.0, top: 10.0 }) == None);
+    }
+}
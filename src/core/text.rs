@@ -0,0 +1,154 @@
+#![allow(dead_code)]
+#![allow(unused_imports)]
+
+use font8x8::{UnicodeFonts, BASIC_FONTS, BLOCK_FONTS, BOX_FONTS, GREEK_FONTS, LATIN_FONTS};
+use log::{debug, error, info, warn};
+
+use super::color::Color;
+use super::geometry::{Position, Rect};
+use super::gfxbuffer::Surface;
+
+/// Glyphs are `GLYPH_CELL` x `GLYPH_CELL` bits, drawn scaled to the font size.
+const GLYPH_CELL: usize = 8;
+
+const ELLIPSIS: &str = "...";
+
+/// Bits of `c`, row by row, leftmost pixel in the lowest bit. Unknown characters are drawn as `?`.
+fn glyph(c: char) -> [u8; 8] {
+    BASIC_FONTS
+        .get(c)
+        .or_else(|| LATIN_FONTS.get(c))
+        .or_else(|| GREEK_FONTS.get(c))
+        .or_else(|| BOX_FONTS.get(c))
+        .or_else(|| BLOCK_FONTS.get(c))
+        .or_else(|| BASIC_FONTS.get('?'))
+        .unwrap_or([0; 8])
+}
+
+/// Every character advances by `size`: the font is monospaced and its cells are square.
+pub fn text_width(text: &str, size: f64) -> f64 {
+    text.chars().count() as f64 * size
+}
+
+/// Character boundary closest to `x`, measured from the start of the text.
+pub fn char_index_at(text: &str, size: f64, x: f64) -> usize {
+    if size <= 0.0 {
+        return 0;
+    }
+    ((x / size).round().max(0.0) as usize).min(text.chars().count())
+}
+
+/// `text` shortened with an ellipsis to fit in `max_width`.
+pub fn elide(text: &str, size: f64, max_width: f64) -> String {
+    if text_width(text, size) <= max_width {
+        return String::from(text);
+    }
+    let fits = (max_width / size).floor() as usize;
+    if fits <= ELLIPSIS.len() {
+        return text.chars().take(fits).collect();
+    }
+    let mut elided: String = text.chars().take(fits - ELLIPSIS.len()).collect();
+    elided.push_str(ELLIPSIS);
+    elided
+}
+
+/// Draw `text` with its top left corner at `origin`, `size` device pixels high.
+///   Pixels partially covered by a glyph bit are blended by the covered area.
+pub fn draw_text(surface: &mut Surface, text: &str, origin: &Position, size: f64, color: Color, clip: &Rect) {
+    if size <= 0.0 {
+        return;
+    }
+    let rgba = color.as_u8_ref();
+    let bit = size / GLYPH_CELL as f64;
+    for (i, c) in text.chars().enumerate() {
+        if c.is_whitespace() {
+            continue;
+        }
+        let cell = Rect::new(origin.left + i as f64 * size, origin.top, size, size);
+        let (x_begin, x_end, y_begin, y_end) = match surface.pixel_span(&cell, clip) {
+            Some(span) => span,
+            None => continue,
+        };
+        let rows = glyph(c);
+        for y in y_begin..y_end {
+            let v0 = (y as f64 - cell.top()) / bit;
+            for x in x_begin..x_end {
+                let u0 = (x as f64 - cell.left()) / bit;
+                let coverage = cell_coverage(&rows, u0, v0, 1.0 / bit);
+                if coverage > 0.0 {
+                    surface.blend(x, y, &rgba, coverage);
+                }
+            }
+        }
+    }
+}
+
+/// Fraction of the square `[u0, u0 + extent) x [v0, v0 + extent)`, in glyph bits, covered by set bits.
+fn cell_coverage(rows: &[u8; 8], u0: f64, v0: f64, extent: f64) -> f64 {
+    let overlap = |begin: f64, cell: usize| (begin + extent).min(cell as f64 + 1.0) - begin.max(cell as f64);
+    let first = |begin: f64| begin.floor().max(0.0) as usize;
+    let last = |begin: f64| ((begin + extent).ceil() as usize).min(GLYPH_CELL);
+    let mut covered = 0.0;
+    for (row, &bits) in rows.iter().enumerate().take(last(v0)).skip(first(v0)) {
+        let height = overlap(v0, row);
+        if height <= 0.0 || bits == 0 {
+            continue;
+        }
+        for column in first(u0)..last(u0) {
+            if bits & (1 << column) != 0 {
+                covered += height * overlap(u0, column).max(0.0);
+            }
+        }
+    }
+    (covered / (extent * extent)).min(1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use assert2::check;
+
+    use super::*;
+
+    #[test]
+    fn measuring() {
+        check!(text_width("abc", 12.0) == 36.0);
+        check!(text_width("àé", 10.0) == 20.0);
+        check!(char_index_at("abc", 10.0, 14.0) == 1);
+        check!(char_index_at("abc", 10.0, 16.0) == 2);
+        check!(char_index_at("abc", 10.0, 100.0) == 3);
+        check!(char_index_at("abc", 10.0, -5.0) == 0);
+        check!(elide("abcdefghij", 10.0, 100.0) == "abcdefghij");
+        check!(elide("abcdefghij", 10.0, 75.0) == "abcd...");
+        check!(elide("abcdefghij", 10.0, 25.0) == "ab");
+    }
+
+    #[test]
+    fn draws_glyph_bits() {
+        let mut data = vec![0; 16 * 8 * 4];
+        let mut surface = Surface::new(&mut data, 16, 8);
+        let clip = Rect::new(0.0, 0.0, 16.0, 8.0);
+        let white = Color::new(0xff, 0xff, 0xff, 0xff);
+        draw_text(&mut surface, "| ", &Position { left: 0.0, top: 0.0 }, 8.0, white, &clip);
+        let rows = glyph('|');
+        for (y, bits) in rows.iter().enumerate() {
+            for x in 0..8 {
+                let set = bits & (1 << x) != 0;
+                check!((surface.pixel(x, y)[3] == 0xff) == set, "pixel {},{}", x, y);
+            }
+        }
+        // The space draws nothing
+        check!((8..16).all(|x| (0..8).all(|y| surface.pixel(x, y) == [0, 0, 0, 0])));
+    }
+
+    #[test]
+    fn scaled_glyphs_are_blended_by_coverage() {
+        // A vertical bar in the fourth column
+        let rows = [0x08; 8];
+        // A pixel twice as large as a bit, half on the bar
+        check!(cell_coverage(&rows, 2.0, 2.0, 2.0) == 0.5);
+        check!(cell_coverage(&rows, 3.0, 0.0, 1.0) == 1.0);
+        check!(cell_coverage(&rows, 3.5, 0.0, 1.0) == 0.5);
+        check!(cell_coverage(&[0xff; 8], 0.0, 0.0, 8.0) == 1.0);
+        check!(cell_coverage(&[0; 8], 0.0, 0.0, 8.0) == 0.0);
+    }
+}
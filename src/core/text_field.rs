@@ -0,0 +1,194 @@
+#![allow(dead_code)]
+#![allow(unused_imports)]
+
+use log::{debug, error, info, warn};
+
+use super::dom::window_events::{Key, Modifiers};
+
+/// Single line of editable text, with a caret and an optional selection. Positions are in characters.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TextField {
+    text: String,
+    cursor: usize,
+    /// Other end of the selection, the caret being one end
+    anchor: Option<usize>,
+}
+
+impl TextField {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    /// Replace the text, with the caret at the end.
+    pub fn set_text(&mut self, text: &str) {
+        self.text = String::from(text);
+        self.cursor = self.len();
+        self.anchor = None;
+    }
+
+    fn len(&self) -> usize {
+        self.text.chars().count()
+    }
+
+    fn byte_offset(&self, index: usize) -> usize {
+        self.text.char_indices().nth(index).map_or(self.text.len(), |(offset, _)| offset)
+    }
+
+    /// Selected character range, empty ranges are no selection.
+    pub fn selection(&self) -> Option<(usize, usize)> {
+        let anchor = self.anchor?;
+        match anchor.cmp(&self.cursor) {
+            std::cmp::Ordering::Less => Some((anchor, self.cursor)),
+            std::cmp::Ordering::Greater => Some((self.cursor, anchor)),
+            std::cmp::Ordering::Equal => None,
+        }
+    }
+
+    pub fn selected_text(&self) -> &str {
+        match self.selection() {
+            Some((begin, end)) => &self.text[self.byte_offset(begin)..self.byte_offset(end)],
+            None => "",
+        }
+    }
+
+    pub fn select_all(&mut self) {
+        self.anchor = Some(0);
+        self.cursor = self.len();
+    }
+
+    /// Move the caret to `index`, extending the selection when `select` is set.
+    pub fn move_to(&mut self, index: usize, select: bool) {
+        if select {
+            self.anchor.get_or_insert(self.cursor);
+        } else {
+            self.anchor = None;
+        }
+        self.cursor = index.min(self.len());
+    }
+
+    fn delete_selection(&mut self) -> bool {
+        let (begin, end) = match self.selection() {
+            Some(range) => range,
+            None => return false,
+        };
+        let range = self.byte_offset(begin)..self.byte_offset(end);
+        self.text.replace_range(range, "");
+        self.cursor = begin;
+        self.anchor = None;
+        true
+    }
+
+    /// Type `text` at the caret, replacing the selection. Line breaks and control characters are dropped.
+    pub fn insert(&mut self, text: &str) {
+        self.delete_selection();
+        let text: String = text.chars().filter(|c| !c.is_control()).collect();
+        let offset = self.byte_offset(self.cursor);
+        self.text.insert_str(offset, &text);
+        self.cursor += text.chars().count();
+    }
+
+    pub fn backspace(&mut self) {
+        if self.delete_selection() || self.cursor == 0 {
+            return;
+        }
+        self.move_to(self.cursor - 1, true);
+        self.delete_selection();
+    }
+
+    pub fn delete(&mut self) {
+        if self.delete_selection() || self.cursor == self.len() {
+            return;
+        }
+        self.move_to(self.cursor + 1, true);
+        self.delete_selection();
+    }
+
+    /// Editing and caret keys. Returns false for keys the field does not use.
+    pub fn on_key(&mut self, key: &Key, modifiers: &Modifiers) -> bool {
+        let select = modifiers.shift;
+        match key {
+            Key::Left => match self.selection() {
+                Some((begin, _)) if !select => self.move_to(begin, false),
+                _ => self.move_to(self.cursor.saturating_sub(1), select),
+            },
+            Key::Right => match self.selection() {
+                Some((_, end)) if !select => self.move_to(end, false),
+                _ => self.move_to(self.cursor + 1, select),
+            },
+            Key::Home => self.move_to(0, select),
+            Key::End => self.move_to(self.len(), select),
+            Key::Backspace => self.backspace(),
+            Key::Delete => self.delete(),
+            Key::Char('a') if modifiers.primary() => self.select_all(),
+            _ => return false,
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert2::check;
+
+    use super::*;
+
+    fn shift() -> Modifiers {
+        Modifiers {
+            shift: true,
+            ..Modifiers::default()
+        }
+    }
+
+    #[test]
+    fn typing_and_deleting() {
+        let mut field = TextField::new();
+        field.insert("exampl.com");
+        check!(field.cursor() == 10);
+        field.move_to(6, false);
+        field.insert("e");
+        check!(field.text() == "example.com");
+        field.backspace();
+        field.backspace();
+        check!(field.text() == "examp.com");
+        field.delete();
+        check!(field.text() == "exampcom");
+        field.move_to(0, false);
+        field.backspace();
+        check!(field.text() == "exampcom");
+        field.insert("a\nb");
+        check!(field.text() == "abexampcom");
+    }
+
+    #[test]
+    fn selection() {
+        let mut field = TextField::new();
+        field.set_text("héllo world");
+        field.on_key(&Key::Home, &Modifiers::default());
+        for _ in 0..5 {
+            field.on_key(&Key::Right, &shift());
+        }
+        check!(field.selection() == Some((0, 5)));
+        check!(field.selected_text() == "héllo");
+        field.insert("goodbye");
+        check!(field.text() == "goodbye world");
+        check!(field.selection() == None);
+
+        field.select_all();
+        check!(field.selected_text() == "goodbye world");
+        // Left collapses the selection to its start
+        field.on_key(&Key::Left, &Modifiers::default());
+        check!(field.cursor() == 0);
+        check!(field.selection() == None);
+        field.on_key(&Key::End, &shift());
+        field.on_key(&Key::Delete, &Modifiers::default());
+        check!(field.text() == "");
+    }
+}
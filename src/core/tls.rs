@@ -0,0 +1,289 @@
+#![allow(dead_code)]
+#![allow(unused_imports)]
+
+use std::collections::HashMap;
+use std::net::TcpStream;
+use std::sync::{Arc, Mutex};
+
+use log::{debug, error, info, warn};
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::client::WebPkiServerVerifier;
+use rustls::crypto::{self, CryptoProvider};
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{
+    CertificateError, ClientConfig, ClientConnection, DigitallySignedStruct, RootCertStore, SignatureScheme,
+    StreamOwned,
+};
+
+use crate::fixme;
+
+use super::http::Stream;
+use super::simple_error::SimpleError;
+
+/// Protocols offered with ALPN: the client only speaks HTTP/1.1.
+const ALPN_PROTOCOLS: [&[u8]; 1] = [b"http/1.1"];
+
+impl Stream for StreamOwned<ClientConnection, TcpStream> {}
+
+/// Why the certificate of a host was refused, and the certificate, to trust it anyway.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CertificateProblem {
+    pub host: String,
+    pub port: u16,
+    pub reason: String,
+    certificate: CertificateDer<'static>,
+}
+
+/// Opens TLS sessions, verifying certificates against the system trust store.
+#[derive(Debug)]
+pub struct TlsConnector {
+    /// Trusted with the system certificates, as the one of a local test server
+    extra_roots: Vec<CertificateDer<'static>>,
+    use_system_roots: bool,
+    /// Certificates trusted for a host and port despite failing verification, by explicit choice of the user
+    exceptions: Vec<(String, u16, CertificateDer<'static>)>,
+    /// Last certificate refused per host and port, filled by the verifiers
+    problems: Arc<Mutex<HashMap<(String, u16), CertificateProblem>>>,
+    /// Loaded on the first connection: loading the system certificates takes a while
+    roots: Option<Arc<RootCertStore>>,
+    /// Per port, as the verifier only sees the host name
+    configs: HashMap<u16, Arc<ClientConfig>>,
+}
+
+impl Default for TlsConnector {
+    fn default() -> Self {
+        Self {
+            extra_roots: vec![],
+            use_system_roots: true,
+            exceptions: vec![],
+            problems: Arc::new(Mutex::new(HashMap::new())),
+            roots: None,
+            configs: HashMap::new(),
+        }
+    }
+}
+
+impl TlsConnector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Trust only the certificates added with `add_root_certificate`.
+    pub fn without_system_roots() -> Self {
+        Self {
+            use_system_roots: false,
+            ..Self::default()
+        }
+    }
+
+    /// Trust certificates issued by `certificate`, given in DER.
+    pub fn add_root_certificate(&mut self, certificate: &[u8]) {
+        self.extra_roots.push(CertificateDer::from(certificate.to_vec()));
+        self.roots = None;
+        self.configs.clear();
+    }
+
+    /// Why the last connection to `host` on `port` failed verification, if it did.
+    pub fn certificate_problem(&self, host: &str, port: u16) -> Option<CertificateProblem> {
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        self.problems.lock().unwrap().get(&(String::from(host), port)).cloned()
+    }
+
+    /// Trust the certificate last refused for `host` on `port`, and only that one, there only.
+    ///   Returns false if none was.
+    pub fn allow_certificate(&mut self, host: &str, port: u16) -> bool {
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        let problem = match self.problems.lock().unwrap().remove(&(String::from(host), port)) {
+            Some(problem) => problem,
+            None => return false,
+        };
+        warn!(
+            "Trusting the certificate of {}:{} despite: {}",
+            host, port, problem.reason
+        );
+        self.exceptions.push((String::from(host), port, problem.certificate));
+        self.configs.remove(&port);
+        true
+    }
+
+    /// Complete a TLS handshake with `host` on `port` over `stream`.
+    pub fn connect(&mut self, host: &str, port: u16, mut stream: TcpStream) -> Result<Box<dyn Stream>, SimpleError> {
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        let server_name = ServerName::try_from(String::from(host))
+            .map_err(|err| SimpleError::new(format!("Invalid TLS server name {}: {}", host, err).as_str()))?;
+        // Sends SNI for host names, not for IP addresses
+        let mut connection = ClientConnection::new(self.config(port)?, server_name)
+            .map_err(|err| SimpleError::new(format!("{}: {}", host, err).as_str()))?;
+        while connection.is_handshaking() {
+            if let Err(err) = connection.complete_io(&mut stream) {
+                return Err(match self.certificate_problem(host, port) {
+                    Some(problem) => SimpleError::new(
+                        format!("The certificate of {} is not trusted: {}", host, problem.reason).as_str(),
+                    ),
+                    None => SimpleError::new(format!("TLS handshake with {} failed: {}", host, err).as_str()),
+                });
+            }
+        }
+        self.problems.lock().unwrap().remove(&(String::from(host), port));
+        debug!(
+            "TLS {:?} with {}, ALPN {:?}",
+            connection.protocol_version(),
+            host,
+            connection.alpn_protocol().map(String::from_utf8_lossy)
+        );
+        Ok(Box::new(StreamOwned::new(connection, stream)))
+    }
+
+    fn roots(&mut self) -> Arc<RootCertStore> {
+        if let Some(roots) = &self.roots {
+            return roots.clone();
+        }
+        let mut roots = RootCertStore::empty();
+        if self.use_system_roots {
+            let native = rustls_native_certs::load_native_certs();
+            for err in native.errors.iter() {
+                warn!("Error while loading the system certificates: {}", err);
+            }
+            let (added, ignored) = roots.add_parsable_certificates(native.certs);
+            info!("Loaded {} system certificates, ignored {}", added, ignored);
+        }
+        roots.add_parsable_certificates(self.extra_roots.iter().cloned());
+        if roots.is_empty() {
+            error!("No trusted root certificates: every certificate will be refused");
+        }
+        let roots = Arc::new(roots);
+        self.roots = Some(roots.clone());
+        roots
+    }
+
+    /// The configuration of connections to `port`, trusting the exceptions made for it.
+    fn config(&mut self, port: u16) -> Result<Arc<ClientConfig>, SimpleError> {
+        if let Some(config) = self.configs.get(&port) {
+            return Ok(config.clone());
+        }
+        let provider = Arc::new(crypto::ring::default_provider());
+        let roots = self.roots();
+        let webpki = if roots.is_empty() {
+            None
+        } else {
+            let verifier = WebPkiServerVerifier::builder_with_provider(roots, provider.clone())
+                .build()
+                .map_err(|err| SimpleError::new(format!("Cannot verify certificates: {}", err).as_str()))?;
+            Some(verifier)
+        };
+        let verifier = Verifier {
+            webpki,
+            port,
+            exceptions: self
+                .exceptions
+                .iter()
+                .filter(|exception| exception.1 == port)
+                .map(|(host, _, certificate)| (host.clone(), certificate.clone()))
+                .collect(),
+            problems: self.problems.clone(),
+            provider: provider.clone(),
+        };
+        let mut config = ClientConfig::builder_with_provider(provider)
+            .with_safe_default_protocol_versions()
+            .map_err(|err| SimpleError::new(format!("Cannot configure TLS: {}", err).as_str()))?
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(verifier))
+            .with_no_client_auth();
+        config.alpn_protocols = ALPN_PROTOCOLS.iter().map(|protocol| protocol.to_vec()).collect();
+        let config = Arc::new(config);
+        self.configs.insert(port, config.clone());
+        Ok(config)
+    }
+}
+
+/// WebPKI verification, except for the certificates the user chose to trust, remembering what it refuses.
+#[derive(Debug)]
+struct Verifier {
+    webpki: Option<Arc<WebPkiServerVerifier>>,
+    /// Port of the connections verified
+    port: u16,
+    /// Certificates trusted per host on `port`
+    exceptions: Vec<(String, CertificateDer<'static>)>,
+    problems: Arc<Mutex<HashMap<(String, u16), CertificateProblem>>>,
+    provider: Arc<CryptoProvider>,
+}
+
+fn describe(err: &rustls::Error, host: &str) -> String {
+    match err {
+        rustls::Error::InvalidCertificate(problem) => match problem {
+            CertificateError::UnknownIssuer => String::from("it is issued by an unknown authority"),
+            CertificateError::Expired | CertificateError::ExpiredContext { .. } => String::from("it has expired"),
+            CertificateError::NotValidYet | CertificateError::NotValidYetContext { .. } => {
+                String::from("it is not valid yet")
+            }
+            CertificateError::NotValidForName | CertificateError::NotValidForNameContext { .. } => {
+                format!("it is not valid for {}", host)
+            }
+            CertificateError::Revoked => String::from("it has been revoked"),
+            CertificateError::BadSignature => String::from("its signature is invalid"),
+            other => format!("{:?}", other),
+        },
+        other => other.to_string(),
+    }
+}
+
+impl ServerCertVerifier for Verifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let host = server_name.to_str();
+        let allowed = self
+            .exceptions
+            .iter()
+            .any(|(exception, certificate)| *exception == host && certificate == end_entity);
+        if allowed {
+            debug!("Certificate of {} trusted by exception", host);
+            return Ok(ServerCertVerified::assertion());
+        }
+        let result = match &self.webpki {
+            Some(webpki) => webpki.verify_server_cert(end_entity, intermediates, server_name, ocsp_response, now),
+            None => Err(rustls::Error::InvalidCertificate(CertificateError::UnknownIssuer)),
+        };
+        if let Err(err) = &result {
+            warn!("Refusing the certificate of {}: {}", host, err);
+            let problem = CertificateProblem {
+                host: String::from(host.as_ref()),
+                port: self.port,
+                reason: describe(err, &host),
+                certificate: end_entity.clone().into_owned(),
+            };
+            self.problems
+                .lock()
+                .unwrap()
+                .insert((String::from(host.as_ref()), self.port), problem);
+        }
+        result
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        crypto::verify_tls12_signature(message, cert, dss, &self.provider.signature_verification_algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        crypto::verify_tls13_signature(message, cert, dss, &self.provider.signature_verification_algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider.signature_verification_algorithms.supported_schemes()
+    }
+}
@@ -0,0 +1,236 @@
+#![allow(dead_code)]
+#![allow(unused_imports)]
+
+use std::cell::RefCell;
+use std::error::Error;
+use std::rc::Rc;
+use std::time::Instant;
+
+use log::{debug, error, info, warn};
+use url::Url;
+
+use crate::fixme;
+
+use super::browser::Browser;
+use super::dom::window_events::{Event, EventHandler};
+use super::dom::{self, DomRoot};
+use super::geometry::{IntSize, Rect};
+use super::gfxbuffer::Surface;
+use super::http::{Request, Response};
+use super::http_cache::CacheMode;
+use super::loader::Loader;
+use super::settings::Settings;
+use super::simple_error::SimpleError;
+use super::zoom::ZoomMap;
+
+/// A document without a window, for other programs to embed: it loads URLs and HTML, takes input events
+///   and renders into an RGBA buffer of the caller.
+#[derive(Debug)]
+pub struct WebView {
+    document: DomRoot,
+    loader: Rc<RefCell<Loader>>,
+    settings: Rc<RefCell<Settings>>,
+    zoom_map: Rc<RefCell<ZoomMap>>,
+    /// Size of the buffer rendered into, in device pixels
+    size: IntSize,
+    scale_factor: f64,
+    last_frame: Option<Instant>,
+}
+
+impl WebView {
+    /// An empty view of `width` x `height` device pixels, loading with the network and schemes of `browser`.
+    pub fn new(browser: &Browser, width: u32, height: u32) -> Self {
+        let shared = browser.shared();
+        let scale_factor = 1.0;
+        let document = DomRoot::new(
+            Rect::from_u32(0, 0, width, height),
+            scale_factor,
+            None,
+            1.0,
+            shared.settings.clone(),
+            shared.loader.clone(),
+        );
+        Self {
+            document,
+            loader: shared.loader.clone(),
+            settings: shared.settings.clone(),
+            zoom_map: shared.zoom_map.clone(),
+            size: IntSize::new(width, height),
+            scale_factor,
+            last_frame: None,
+        }
+    }
+
+    /// Load `url` and show it. On failure, as for schemes without handler, the view keeps the document it had.
+    ///   Documents are not built from HTML yet: only the `<title>` of HTML responses is read.
+    pub fn load_url(&mut self, url: Url) -> Result<(), Box<dyn Error>> {
+        info!("Load {}", url);
+        if url.scheme() == "about" {
+            self.show(url, None);
+            return Ok(());
+        }
+        fixme!("Load without blocking the caller");
+        let mut request = Request::get(url);
+        request.top_level = true;
+        let result = self.loader.borrow().load(request, CacheMode::Default);
+        let response = result?;
+        debug!("{} {} ({} bytes)", response.url, response.status, response.body.len());
+        let title = dom::response_title(&response);
+        self.show(response.url, title);
+        Ok(())
+    }
+
+    /// Show `html`, its relative URLs resolved against `base_url`. Without one the document is `about:blank`.
+    ///   Not public until documents are built from HTML: only the `<title>` is read.
+    pub(crate) fn load_html(&mut self, html: &str, base_url: Option<Url>) {
+        let url = base_url.unwrap_or_else(|| Url::parse("about:blank").unwrap());
+        self.show(url, dom::html_title(html));
+    }
+
+    /// Replace the document with an empty one at `url`, titled `title`.
+    fn show(&mut self, url: Url, title: Option<String>) {
+        // The document isn't built from the HTML yet, as in browser windows
+        let zoom = self.zoom_map.borrow().get(&url);
+        let mut document = DomRoot::new(
+            Rect::from_u32(0, 0, self.size.width, self.size.height),
+            self.scale_factor,
+            Some(url.clone()),
+            zoom,
+            self.settings.clone(),
+            self.loader.clone(),
+        );
+        if let Some(fragment) = url.fragment() {
+            document.scroll_to_fragment(fragment);
+        }
+        document.set_title(title);
+        document.redraw_requested();
+        self.document = document;
+    }
+
+    pub fn url(&self) -> Option<&Url> {
+        self.document.url()
+    }
+
+    /// The `<title>` of the document, without extra whitespace. None if it has none.
+    pub fn title(&self) -> Option<&str> {
+        self.document.title()
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        (self.size.width, self.size.height)
+    }
+
+    /// Render into buffers of `width` x `height` device pixels from now on.
+    pub fn resize(&mut self, width: u32, height: u32) {
+        let size = IntSize::new(width, height);
+        if size == self.size {
+            return;
+        }
+        debug!("Resize view to {}x{}", width, height);
+        self.size = size;
+        self.document.set_viewport(Rect::from_u32(0, 0, width, height));
+    }
+
+    /// Device pixels per CSS pixel, e.g. 2 on high density screens.
+    pub fn set_scale_factor(&mut self, scale_factor: f64) {
+        self.scale_factor = scale_factor;
+        self.document.set_scale_factor(scale_factor);
+    }
+
+    /// Handle an input event. Positions are in device pixels of the view. A clicked link is loaded.
+    pub fn handle_event(&mut self, event: &Event) {
+        if let Event::ScaleFactorChanged { scale_factor } = event {
+            self.scale_factor = *scale_factor;
+        }
+        self.document.on_window_event(event);
+        if let Some(link) = self.document.take_followed_link() {
+            fixme!("Let the application open links to new windows");
+            if let Err(err) = self.load_url(link.url.clone()) {
+                error!("Error while loading {}: {}", link.url, err);
+            }
+        }
+    }
+
+    /// Paint into `buffer`, RGBA rows of the size of the view, what changed since the last render.
+    ///   Returns true if something was painted. Call `redraw` first to paint a new buffer entirely.
+    ///   Buffers smaller than the view are refused.
+    pub fn render(&mut self, buffer: &mut [u8]) -> Result<bool, Box<dyn Error>> {
+        let (width, height) = (self.size.width as usize, self.size.height as usize);
+        let needed = width.checked_mul(height).and_then(|pixels| pixels.checked_mul(4));
+        if needed.is_none_or(|needed| buffer.len() < needed) {
+            return Err(Box::new(SimpleError::new(
+                format!("Buffer of {} bytes for a view of {}x{}", buffer.len(), width, height).as_str(),
+            )));
+        }
+        let now = Instant::now();
+        let elapsed = self
+            .last_frame
+            .map_or(0.0, |last| now.duration_since(last).as_secs_f64());
+        self.last_frame = Some(now);
+        let mut surface = Surface::new(buffer, width, height);
+        Ok(self.document.on_frame(&mut surface, elapsed))
+    }
+
+    /// Paint everything on the next render.
+    pub fn redraw(&mut self) {
+        self.document.redraw_requested();
+    }
+
+    /// True while animations need more renders.
+    pub fn needs_render(&mut self) -> bool {
+        self.document.is_animating()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert2::check;
+
+    use super::super::app_scheme::SchemeResponse;
+    use super::*;
+
+    #[test]
+    fn loading_and_rendering() {
+        let browser = Browser::new(None);
+        browser
+            .register_scheme("app", |request| match request.url.path() {
+                "/index.html" => SchemeResponse::ok("text/html", "<title>App</title>"),
+                _ => SchemeResponse::not_found(),
+            })
+            .unwrap();
+        let mut view = WebView::new(&browser, 80, 60);
+        check!(view.url() == None);
+
+        check!(view.load_url(Url::parse("app://main/index.html").unwrap()).is_ok());
+        check!(view.url().map(Url::as_str) == Some("app://main/index.html"));
+        check!(view.title() == Some("App"));
+        check!(view.load_url(Url::parse("about:blank").unwrap()).is_ok());
+        check!(view.title() == None);
+        check!(view.load_url(Url::parse("unknown://x").unwrap()).is_err());
+        let missing = Url::parse("file:///nonexistent/rowser").unwrap();
+        check!(view.load_url(missing).is_err());
+        check!(view.url().map(Url::as_str) == Some("about:blank"));
+        view.load_html("<title>Inline</title>", Some(Url::parse("app://main/").unwrap()));
+        check!(view.url().map(Url::as_str) == Some("app://main/"));
+        check!(view.title() == Some("Inline"));
+
+        // The placeholder document: a white box at 30,50
+        let mut buffer = vec![0; 80 * 60 * 4];
+        check!(view.render(&mut buffer).unwrap());
+        let pixel = (55 * 80 + 40) * 4;
+        check!(buffer[pixel..pixel + 4] == [0xff, 0xff, 0xff, 0xff]);
+        check!(!view.render(&mut buffer).unwrap());
+        view.redraw();
+        check!(view.render(&mut buffer).unwrap());
+
+        // A buffer of the size before a resize is refused rather than overrun
+        view.resize(100, 60);
+        check!(view.render(&mut buffer).is_err());
+        check!(view.render(&mut []).is_err());
+        view.resize(40, 60);
+        let mut buffer = vec![0; 40 * 60 * 4];
+        check!(view.render(&mut buffer).unwrap());
+        let pixel = (55 * 40 + 35) * 4;
+        check!(buffer[pixel..pixel + 4] == [0xff, 0xff, 0xff, 0xff]);
+    }
+}
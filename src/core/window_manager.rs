@@ -0,0 +1,185 @@
+#![allow(dead_code)]
+#![allow(unused_imports)]
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::Path;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+
+use log::{debug, error, info, warn};
+use url::Url;
+use winit::event_loop::EventLoopWindowTarget;
+use winit::window::{WindowBuilder, WindowId};
+
+use super::bookmarks::Bookmarks;
+use super::browser_window::BrowserWindow;
+use super::cookies::CookieJar;
+use super::dom::window_events::{Event, EventHandler, Modifiers};
+use super::history_db::HistoryDb;
+use super::http::HttpClient;
+use super::http_cache::HttpCache;
+use super::loader::Loader;
+use super::profile::Profile;
+use super::proxy::ProxyConfig;
+use super::settings::Settings;
+use super::simple_error::SimpleError;
+use super::zoom::ZoomMap;
+
+/// What all the windows share, loaded from and saved to the profile.
+#[derive(Debug, Clone)]
+pub struct SharedState {
+    pub zoom_map: Rc<RefCell<ZoomMap>>,
+    pub history: Rc<RefCell<HistoryDb>>,
+    pub bookmarks: Rc<RefCell<Bookmarks>>,
+    pub settings: Rc<RefCell<Settings>>,
+    /// Locked by the threads loading URLs for the whole of each request
+    pub http: Arc<Mutex<HttpClient>>,
+    /// Loads every URL, `http` ones with `http`
+    pub loader: Rc<RefCell<Loader>>,
+}
+
+impl SharedState {
+    /// Without a profile nothing is remembered between runs. Unreadable files are logged and start empty.
+    pub fn load(profile: Option<&Profile>) -> Self {
+        let settings: Settings = load_file(profile, "settings", Settings::load);
+        let mut zoom_map: ZoomMap = load_file(profile, "zoom_levels", ZoomMap::load);
+        zoom_map.set_default_level(settings.default_zoom);
+        let mut cache: HttpCache = load_file(profile, "cache", HttpCache::open);
+        cache.set_max_size(settings.cache_size_bytes());
+        let mut cookies: CookieJar = load_file(profile, "cookies", CookieJar::load);
+        cookies.policy = settings.cookie_policy;
+        let mut http = HttpClient::new();
+        http.set_cache(Some(cache));
+        http.set_cookies(cookies);
+        http.set_proxies(ProxyConfig::from_settings(&settings));
+        let http = Arc::new(Mutex::new(http));
+        Self {
+            zoom_map: Rc::new(RefCell::new(zoom_map)),
+            history: Rc::new(RefCell::new(load_file(profile, "history", HistoryDb::load))),
+            bookmarks: Rc::new(RefCell::new(load_file(profile, "bookmarks.html", Bookmarks::load))),
+            settings: Rc::new(RefCell::new(settings)),
+            loader: Rc::new(RefCell::new(Loader::new(http.clone()))),
+            http,
+        }
+    }
+
+    /// Save what is kept in memory between changes, before exiting.
+    pub fn flush(&self) {
+        self.http.lock().unwrap().cookies_mut().flush();
+    }
+}
+
+fn load_file<T: Default>(profile: Option<&Profile>, name: &str, load: fn(&Path) -> Result<T, SimpleError>) -> T {
+    let profile = match profile {
+        Some(profile) => profile,
+        None => return T::default(),
+    };
+    match profile.file(name).and_then(|path| load(&path)) {
+        Ok(loaded) => loaded,
+        Err(err) => {
+            error!("Error while loading {}: {}", name, err);
+            T::default()
+        }
+    }
+}
+
+/// A top-level window and the keyboard modifiers last reported for it.
+#[derive(Debug)]
+struct ManagedWindow {
+    browser: BrowserWindow,
+    modifiers: Modifiers,
+}
+
+/// All the top-level windows of the browser, sharing one event loop.
+#[derive(Debug)]
+pub struct WindowManager {
+    windows: HashMap<WindowId, ManagedWindow>,
+    shared: SharedState,
+}
+
+impl WindowManager {
+    pub fn new(shared: SharedState) -> Self {
+        Self {
+            windows: HashMap::new(),
+            shared,
+        }
+    }
+
+    pub fn open_window<T>(&mut self, target: &EventLoopWindowTarget<T>, url: Option<Url>) -> Result<WindowId, SimpleError> {
+        let window = WindowBuilder::new()
+            .with_title("Rowser")
+            .build(target)
+            .map_err(|err| SimpleError::new(format!("Cannot create window: {}", err).as_str()))?;
+        let id = window.id();
+        debug!("Open window {:?} with {:?}", id, url);
+        let browser = BrowserWindow::new(Rc::new(window), url, self.shared.clone());
+        self.windows.insert(
+            id,
+            ManagedWindow {
+                browser,
+                modifiers: Modifiers::default(),
+            },
+        );
+        Ok(id)
+    }
+
+    /// True once the last window is closed.
+    pub fn is_empty(&self) -> bool {
+        self.windows.is_empty()
+    }
+
+    pub fn window_count(&self) -> usize {
+        self.windows.len()
+    }
+
+    pub fn modifiers(&self, id: WindowId) -> Modifiers {
+        self.windows.get(&id).map(|w| w.modifiers).unwrap_or_default()
+    }
+
+    pub fn set_modifiers(&mut self, id: WindowId, modifiers: Modifiers) {
+        if let Some(window) = self.windows.get_mut(&id) {
+            window.modifiers = modifiers;
+        }
+    }
+
+    /// Send `event` to the window it happened in. Events of windows already closed are dropped.
+    pub fn on_window_event(&mut self, id: WindowId, event: &Event) {
+        match self.windows.get_mut(&id) {
+            Some(window) => window.browser.on_window_event(event),
+            None => debug!("Event {:?} for closed window {:?}", event, id),
+        }
+    }
+
+    pub fn redraw_requested(&mut self, id: WindowId) {
+        if let Some(window) = self.windows.get_mut(&id) {
+            window.browser.redraw_requested();
+        }
+    }
+
+    /// Paint every window, close the ones that asked for it and open the requested ones.
+    pub fn on_frame<T>(&mut self, target: &EventLoopWindowTarget<T>, elapsed: f64) {
+        let mut requests = vec![];
+        for window in self.windows.values_mut() {
+            window.browser.on_frame(elapsed);
+            requests.append(&mut window.browser.take_window_requests());
+        }
+        // Dropping the browser window drops the platform window
+        self.windows.retain(|id, window| {
+            if window.browser.close_requested() {
+                debug!("Close window {:?}", id);
+            }
+            !window.browser.close_requested()
+        });
+        for url in requests {
+            if let Err(err) = self.open_window(target, url) {
+                error!("{}", err);
+            }
+        }
+    }
+
+    /// True while any window needs frames.
+    pub fn is_animating(&mut self) -> bool {
+        self.windows.values_mut().any(|w| w.browser.is_animating())
+    }
+}
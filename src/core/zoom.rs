@@ -0,0 +1,170 @@
+#![allow(dead_code)]
+#![allow(unused_imports)]
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use log::{debug, error, info, warn};
+use url::Url;
+
+use super::simple_error::SimpleError;
+
+/// Zoom steps of Ctrl+plus and Ctrl+minus.
+pub const ZOOM_LEVELS: [f64; 17] = [
+    0.25, 0.33, 0.5, 0.67, 0.75, 0.8, 0.9, 1.0, 1.1, 1.25, 1.5, 1.75, 2.0, 2.5, 3.0, 4.0, 5.0,
+];
+
+pub fn zoom_in(level: f64) -> f64 {
+    ZOOM_LEVELS
+        .iter()
+        .copied()
+        .find(|l| *l > level + 1e-6)
+        .unwrap_or(ZOOM_LEVELS[ZOOM_LEVELS.len() - 1])
+}
+
+pub fn zoom_out(level: f64) -> f64 {
+    ZOOM_LEVELS
+        .iter()
+        .rev()
+        .copied()
+        .find(|l| *l < level - 1e-6)
+        .unwrap_or(ZOOM_LEVELS[0])
+}
+
+/// Zoom level remembered for every origin, saved as `origin<TAB>level` lines.
+#[derive(Debug)]
+pub struct ZoomMap {
+    levels: BTreeMap<String, f64>,
+    /// Level of the origins without one, not remembered
+    default_level: f64,
+    path: Option<PathBuf>,
+}
+
+impl Default for ZoomMap {
+    fn default() -> Self {
+        Self {
+            levels: BTreeMap::new(),
+            default_level: 1.0,
+            path: None,
+        }
+    }
+}
+
+impl ZoomMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load from `path` and save there on every change. A missing file is an empty map.
+    pub fn load(path: &Path) -> Result<Self, SimpleError> {
+        let mut map = Self {
+            path: Some(path.to_path_buf()),
+            ..Self::default()
+        };
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(map),
+            Err(err) => return Err(SimpleError::new(format!("{:?}: {}", path, err).as_str())),
+        };
+        for line in text.lines().filter(|l| !l.trim().is_empty()) {
+            match line.split_once('\t').and_then(|(o, l)| Some((o, l.trim().parse::<f64>().ok()?))) {
+                Some((origin, level)) if level > 0.0 => {
+                    map.levels.insert(String::from(origin), level);
+                }
+                _ => warn!("Ignoring zoom entry {:?} in {:?}", line, path),
+            }
+        }
+        Ok(map)
+    }
+
+    /// Origin key: scheme, host and port. Local files share one entry.
+    fn key(url: &Url) -> String {
+        match url.origin() {
+            url::Origin::Tuple(..) => url.origin().ascii_serialization(),
+            url::Origin::Opaque(_) => format!("{}:", url.scheme()),
+        }
+    }
+
+    pub fn get(&self, url: &Url) -> f64 {
+        self.levels.get(&Self::key(url)).copied().unwrap_or(self.default_level)
+    }
+
+    pub fn default_level(&self) -> f64 {
+        self.default_level
+    }
+
+    /// The `zoom.default` setting.
+    pub fn set_default_level(&mut self, level: f64) {
+        self.default_level = level;
+    }
+
+    pub fn set(&mut self, url: &Url, level: f64) {
+        if (level - self.default_level).abs() < 1e-6 {
+            self.levels.remove(&Self::key(url));
+        } else {
+            self.levels.insert(Self::key(url), level);
+        }
+        if let Err(err) = self.save() {
+            error!("Error while saving zoom levels: {}", err);
+        }
+    }
+
+    fn save(&self) -> Result<(), SimpleError> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        let text: String = self
+            .levels
+            .iter()
+            .map(|(origin, level)| format!("{}\t{}\n", origin, level))
+            .collect();
+        std::fs::write(path, text).map_err(|err| SimpleError::new(format!("{:?}: {}", path, err).as_str()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert2::check;
+
+    use super::*;
+
+    #[test]
+    fn steps() {
+        check!(zoom_in(1.0) == 1.1);
+        check!(zoom_out(1.0) == 0.9);
+        check!(zoom_in(5.0) == 5.0);
+        check!(zoom_out(0.25) == 0.25);
+        // Levels off the list snap to the next step
+        check!(zoom_in(1.05) == 1.1);
+        check!(zoom_out(1.05) == 1.0);
+    }
+
+    #[test]
+    fn per_origin_roundtrip() {
+        let path = std::env::temp_dir().join(format!("rowser-zoom-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let page = Url::parse("https://example.com/a/b").unwrap();
+        let other_page = Url::parse("https://example.com:443/c").unwrap();
+        let other_origin = Url::parse("http://example.com/").unwrap();
+        {
+            let mut map = ZoomMap::load(&path).unwrap();
+            map.set(&page, 1.5);
+            map.set(&other_origin, 0.8);
+            map.set(&other_origin, 1.0);
+        }
+        let map = ZoomMap::load(&path).unwrap();
+        check!(map.get(&other_page) == 1.5);
+        check!(map.get(&other_origin) == 1.0);
+        check!(std::fs::read_to_string(&path).unwrap() == "https://example.com\t1.5\n");
+
+        // Levels are remembered when they differ from the default
+        let mut map = ZoomMap::load(&path).unwrap();
+        map.set_default_level(1.5);
+        check!(map.get(&other_origin) == 1.5);
+        map.set(&other_origin, 1.0);
+        map.set(&page, 1.5);
+        check!(std::fs::read_to_string(&path).unwrap() == "http://example.com\t1\n");
+        std::fs::remove_file(&path).unwrap();
+    }
+}
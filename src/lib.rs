@@ -0,0 +1,15 @@
+//! Rowser as a library, to embed the browser in other programs.
+//!
+//! A `Browser` opens windows sharing a profile, and loads the URLs of the schemes an application
+//!   registers, as `app://`, with its own callbacks. A `WebView` shows a document of the browser
+//!   without a window: the application feeds it input events and renders it into its own buffers.
+//!   Documents are not built from HTML yet: of an HTML page, only the title is read.
+
+mod core;
+
+pub use crate::core::app_scheme::{SchemeCallback, SchemeRequest, SchemeResponse};
+pub use crate::core::browser::Browser;
+pub use crate::core::dom::window_events::{DeviceId, Event, Key, Modifiers, MouseButton, WheelDelta};
+pub use crate::core::geometry::Position;
+pub use crate::core::web_view::WebView;
+pub use url::Url;
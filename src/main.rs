@@ -1,7 +1,8 @@
-#![feature(type_name_of_val)]
+use std::path::PathBuf;
+
 use clap::Parser;
 
-//use url::{ParseError, Url};
+use rowser::{Browser, Url};
 
 /// Full browser experience
 #[derive(Parser, Debug)]
@@ -10,15 +11,36 @@ struct Cli {
     //URL to browse
     #[clap(default_value_t)]
     url: String,
+    /// Directory of the profile: settings, history, bookmarks. Defaults to $XDG_DATA_HOME/rowser
+    #[clap(long, value_name = "DIR")]
+    profile: Option<PathBuf>,
+    /// Import bookmarks from a bookmark HTML file exported by another browser
+    #[clap(long, value_name = "FILE")]
+    import_bookmarks: Option<PathBuf>,
+    /// Export the bookmarks to a bookmark HTML file and exit
+    #[clap(long, value_name = "FILE")]
+    export_bookmarks: Option<PathBuf>,
+    /// Load pages only from the HTTP cache, never from the network
+    #[clap(long)]
+    offline: bool,
 }
 
 #[allow(unused_imports)]
 use log::{debug, error, info, warn};
 
-//#[path = "core/platform_window/mod.rs"]
-//mod platform_window;
-mod core;
-use crate::core::platform_window::MainWindow;
+/// Import and export bookmarks of the profile. Returns true if the browser should not start.
+fn transfer_bookmarks(args: &Cli, browser: &Browser) -> Result<bool, Box<dyn std::error::Error>> {
+    if let Some(path) = &args.import_bookmarks {
+        let count = browser.import_bookmarks(path)?;
+        info!("Imported {} bookmarks from {:?}", count, path);
+    }
+    if let Some(path) = &args.export_bookmarks {
+        browser.export_bookmarks(path)?;
+        info!("Exported bookmarks to {:?}", path);
+        return Ok(true);
+    }
+    Ok(false)
+}
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     env_logger::init();
@@ -27,11 +49,22 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     warn!("This is a Warning!");
     error!("This is an Error!");*/
     debug!("Args are {:?}", args);
-    //let url = Url::parse(&args.url).map_err(|err| CriticalError(format!("{:?}", err)))?;
-    //info!("Url {:?}", url.as_str());
-    let main_window = MainWindow {};
+    let profile = args.profile.clone().or_else(Browser::default_profile_dir);
+    if profile.is_none() {
+        warn!("Set HOME, XDG_DATA_HOME or --profile to remember settings, history and bookmarks");
+    }
+    let browser = Browser::new(profile.as_deref());
+    if transfer_bookmarks(&args, &browser)? {
+        return Ok(());
+    }
+    browser.set_offline(args.offline);
+    let url = if args.url.is_empty() {
+        browser.home_page()
+    } else {
+        Some(Url::parse(&args.url)?)
+    };
 
-    main_window.run()?;
+    browser.run(url)?;
 
     debug!("Application exit");
 